@@ -0,0 +1,211 @@
+//! Generates the event structs and discriminators of the bundled Anchor IDLs.
+//!
+//! Every IDL becomes a module in `$OUT_DIR/idl.rs` (named after `metadata.name`), included
+//! by `types::idl`. Updating an IDL file is enough to pick up new events or fields.
+
+use std::{collections::BTreeSet, env, fmt::Write, fs, path::Path};
+
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+const IDLS: [&str; 2] = ["pump-fun-idl.json", "pump_amm_idl.json"];
+
+fn main() {
+    let mut out = String::from("// @generated by build.rs from the Anchor IDLs, do not edit\n");
+    for path in IDLS {
+        println!("cargo:rerun-if-changed={}", path);
+        let idl: Value = serde_json::from_str(
+            &fs::read_to_string(path).unwrap_or_else(|e| panic!("read {} error: {}", path, e)),
+        )
+        .unwrap_or_else(|e| panic!("parse {} error: {}", path, e));
+        out.push_str(&generate(path, &idl));
+    }
+    let dest = Path::new(&env::var("OUT_DIR").unwrap()).join("idl.rs");
+    fs::write(dest, out).unwrap();
+}
+
+fn generate(path: &str, idl: &Value) -> String {
+    let module = idl["metadata"]["name"]
+        .as_str()
+        .or_else(|| idl["name"].as_str())
+        .unwrap_or_else(|| panic!("{} has no name", path));
+    let types = idl["types"].as_array().cloned().unwrap_or_default();
+    let find_type = |name: &str| types.iter().find(|t| t["name"] == name).cloned();
+
+    let mut out = String::new();
+    writeln!(out, "\n/// Events of `{}`", path).unwrap();
+    writeln!(out, "pub mod {} {{", module).unwrap();
+    writeln!(out, "    #![allow(unused_imports)]").unwrap();
+    writeln!(out, "    use borsh::{{BorshDeserialize, BorshSerialize}};").unwrap();
+    writeln!(out, "    use serde::{{Deserialize, Serialize}};").unwrap();
+    writeln!(out, "    use solana_sdk::pubkey::Pubkey;").unwrap();
+
+    // events, then the types they refer to
+    let mut pending: Vec<String> = vec![];
+    for event in idl["events"].as_array().cloned().unwrap_or_default() {
+        let name = event["name"].as_str().unwrap().to_string();
+        let discriminator = match event["discriminator"].as_array() {
+            Some(bytes) => bytes.iter().map(|b| b.as_u64().unwrap() as u8).collect::<Vec<_>>(),
+            None => Sha256::digest(format!("event:{}", name))[..8].to_vec(),
+        };
+        writeln!(
+            out,
+            "\n    pub const {}_DISCRIMINATOR: [u8; 8] = {:?};",
+            screaming_snake(&name),
+            discriminator
+        )
+        .unwrap();
+        pending.push(name);
+    }
+
+    let mut generated = BTreeSet::new();
+    while let Some(name) = pending.pop() {
+        if !generated.insert(name.clone()) {
+            continue;
+        }
+        // legacy IDLs keep the event fields on the event itself
+        let def = find_type(&name)
+            .map(|t| t["type"].clone())
+            .or_else(|| {
+                idl["events"].as_array().and_then(|events| {
+                    events
+                        .iter()
+                        .find(|e| e["name"] == name.as_str() && e.get("fields").is_some())
+                        .map(|e| serde_json::json!({ "kind": "struct", "fields": e["fields"] }))
+                })
+            })
+            .unwrap_or_else(|| panic!("{}: type {} not found", path, name));
+        out.push_str(&generate_type(&name, &def, &mut pending, &find_type));
+    }
+    out.push_str("}\n");
+    out
+}
+
+fn generate_type(
+    name: &str,
+    def: &Value,
+    pending: &mut Vec<String>,
+    find_type: &dyn Fn(&str) -> Option<Value>,
+) -> String {
+    let mut out = String::new();
+    match def["kind"].as_str() {
+        Some("struct") => {
+            let fields = def["fields"].as_array().cloned().unwrap_or_default();
+            let copy = fields.iter().all(|f| is_copy(&f["type"], find_type));
+            writeln!(
+                out,
+                "\n    #[derive(Debug, Clone, {}BorshSerialize, BorshDeserialize, Serialize, Deserialize)]",
+                if copy { "Copy, " } else { "" }
+            )
+            .unwrap();
+            writeln!(out, "    pub struct {} {{", name).unwrap();
+            for field in fields {
+                if field["type"] == "pubkey" || field["type"] == "publicKey" {
+                    writeln!(out, "        #[serde(with = \"crate::types::pubkey_b58\")]").unwrap();
+                }
+                writeln!(
+                    out,
+                    "        pub {}: {},",
+                    snake(field["name"].as_str().unwrap()),
+                    rust_type(&field["type"], pending)
+                )
+                .unwrap();
+            }
+            writeln!(out, "    }}").unwrap();
+        }
+        Some("enum") => {
+            writeln!(
+                out,
+                "\n    #[derive(Debug, Clone, Copy, PartialEq, Eq, BorshSerialize, BorshDeserialize, Serialize, Deserialize)]"
+            )
+            .unwrap();
+            writeln!(out, "    pub enum {} {{", name).unwrap();
+            for variant in def["variants"].as_array().cloned().unwrap_or_default() {
+                if variant.get("fields").is_some() {
+                    panic!("enum {} has variants with fields, not supported", name);
+                }
+                writeln!(out, "        {},", variant["name"].as_str().unwrap()).unwrap();
+            }
+            writeln!(out, "    }}").unwrap();
+        }
+        kind => panic!("type {} has unsupported kind {:?}", name, kind),
+    }
+    out
+}
+
+fn rust_type(ty: &Value, pending: &mut Vec<String>) -> String {
+    if let Some(name) = ty.as_str() {
+        return match name {
+            "bool" | "u8" | "i8" | "u16" | "i16" | "u32" | "i32" | "u64" | "i64" | "u128" | "i128" | "f32"
+            | "f64" => name.to_string(),
+            "string" => "String".to_string(),
+            "bytes" => "Vec<u8>".to_string(),
+            "pubkey" | "publicKey" => "Pubkey".to_string(),
+            other => panic!("unsupported idl type {}", other),
+        };
+    }
+    if let Some(inner) = ty.get("option") {
+        return format!("Option<{}>", rust_type(inner, pending));
+    }
+    if let Some(inner) = ty.get("vec") {
+        return format!("Vec<{}>", rust_type(inner, pending));
+    }
+    if let Some(array) = ty.get("array").and_then(|a| a.as_array()) {
+        return format!("[{}; {}]", rust_type(&array[0], pending), array[1]);
+    }
+    if let Some(defined) = ty.get("defined") {
+        // `{"defined": "Name"}` in legacy IDLs, `{"defined": {"name": "Name"}}` since anchor 0.30
+        let name = defined.as_str().or_else(|| defined["name"].as_str()).unwrap().to_string();
+        pending.push(name.clone());
+        return name;
+    }
+    panic!("unsupported idl type {}", ty)
+}
+
+fn is_copy(ty: &Value, find_type: &dyn Fn(&str) -> Option<Value>) -> bool {
+    if let Some(name) = ty.as_str() {
+        return !matches!(name, "string" | "bytes");
+    }
+    if ty.get("vec").is_some() {
+        return false;
+    }
+    if let Some(inner) = ty.get("option") {
+        return is_copy(inner, find_type);
+    }
+    if let Some(array) = ty.get("array").and_then(|a| a.as_array()) {
+        return is_copy(&array[0], find_type);
+    }
+    if let Some(defined) = ty.get("defined") {
+        let name = defined.as_str().or_else(|| defined["name"].as_str()).unwrap_or_default();
+        return match find_type(name) {
+            Some(def) if def["type"]["kind"] == "enum" => true,
+            Some(def) => def["type"]["fields"]
+                .as_array()
+                .map(|fields| fields.iter().all(|f| is_copy(&f["type"], find_type)))
+                .unwrap_or(false),
+            None => false,
+        };
+    }
+    false
+}
+
+// bondingCurve -> bonding_curve, already snake_case names are kept
+fn snake(name: &str) -> String {
+    let mut out = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if c.is_ascii_uppercase() {
+            if i > 0 {
+                out.push('_');
+            }
+            out.push(c.to_ascii_lowercase());
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+// TradeEvent -> TRADE_EVENT
+fn screaming_snake(name: &str) -> String {
+    snake(name).to_ascii_uppercase()
+}
@@ -1,18 +1,70 @@
-use reqwest::Client;
+use reqwest::{Client, StatusCode};
 use serde::{Deserialize, Serialize};
-use std::error::Error;
+use std::{error::Error, time::Duration};
+use tokio::time::sleep;
+use tracing::warn;
+
+use crate::{config::AiConfig, constants::SECONDS, utils::backoff_with_jitter};
+
+/// Error of an AI request, `Send` so a retry can wait across it
+pub type AiError = Box<dyn Error + Send + Sync>;
 
 /// Struct to hold detailed token information
 pub struct TokenInfo {
     pub name: String,
     pub symbol: String,
     pub url: String,
+    /// excerpts of the top tweets, one per line (see `x::tweet_excerpts`)
     pub x_content: String,
+    /// engagement and tone over those tweets (see `x::TweetAggregate::summary`)
+    pub x_summary: String,
+}
+
+/// The model's read of a token, asked for as JSON
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct TokenSummary {
+    /// two sentences on the token
+    pub summary: String,
+    /// 0 (safe) to 100 (almost surely a rug or scam), `None` when the answer wasn't JSON
+    pub risk_score: Option<u8>,
+    /// short lowercase tags, e.g. `meme`, `ai`, `animal`
+    pub categories: Vec<String>,
+    /// how sure the model is, 0.0-1.0
+    pub confidence: Option<f32>,
+}
+
+impl TokenSummary {
+    /// Parse the model's answer; text that isn't the JSON asked for is taken as the summary
+    /// alone, without a score
+    pub fn parse(text: &str) -> Self {
+        // 有时会包在 ```json 里
+        let json = text.trim().trim_start_matches("```json").trim_start_matches("```").trim_end_matches("```").trim();
+        match serde_json::from_str::<TokenSummary>(json) {
+            Ok(mut summary) if !summary.summary.is_empty() => {
+                summary.risk_score = summary.risk_score.map(|score| score.min(100));
+                summary.confidence = summary.confidence.map(|confidence| confidence.clamp(0.0, 1.0));
+                summary.categories.retain(|category| !category.trim().is_empty());
+                summary
+            }
+            _ => Self { summary: text.trim().to_string(), ..Default::default() },
+        }
+    }
 }
 
 #[derive(Serialize)]
 struct GeminiRequest {
     contents: Vec<GeminiContent>,
+    #[serde(rename = "generationConfig")]
+    generation_config: GenerationConfig,
+}
+
+// JSON mode, the answer follows `response_schema`
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GenerationConfig {
+    response_mime_type: &'static str,
+    response_schema: serde_json::Value,
 }
 
 #[derive(Serialize)]
@@ -45,29 +97,131 @@ struct GeminiResponsePart {
     text: String,
 }
 
-pub async fn generate_token_summary(token: &TokenInfo) -> Result<String, Box<dyn Error>> {
-    let client = Client::new();
+/// Gemini api client
+#[derive(Debug, Clone)]
+pub struct AiClient {
+    http: Client,
+    api_key: String,
+    max_retries: u8,
+    // milliseconds before the second try and the most between two tries
+    retry_backoff: u64,
+    max_retry_backoff: u64,
+}
+
+impl AiClient {
+    pub fn new(api_key: &str) -> Self {
+        Self {
+            http: Client::builder()
+                .timeout(Duration::from_secs(30))
+                .build()
+                .expect("Failed to create HTTP client"),
+            api_key: api_key.to_string(),
+            max_retries: 3,
+            retry_backoff: SECONDS,
+            max_retry_backoff: 10 * SECONDS,
+        }
+    }
+
+    /// Set how long one request may take (milliseconds), a slower one fails and is tried again
+    pub fn with_timeout(mut self, timeout_ms: u64) -> Self {
+        self.http = Client::builder()
+            .timeout(Duration::from_millis(timeout_ms))
+            .build()
+            .expect("Failed to create HTTP client");
+        self
+    }
+
+    /// Set the maximum number of tries
+    pub fn with_max_retries(mut self, max_retries: u8) -> Self {
+        self.max_retries = max_retries.max(1);
+        self
+    }
+
+    /// Set the wait before the second try and the longest wait between tries (milliseconds)
+    pub fn with_retry_backoff(mut self, initial_ms: u64, max_ms: u64) -> Self {
+        self.retry_backoff = initial_ms;
+        self.max_retry_backoff = max_ms;
+        self
+    }
+
+    /// The summary of `token`; a timeout, network error, 429 or 5xx is tried again up to
+    /// `max_retries` times, other errors fail at once
+    pub async fn generate_token_summary(&self, token: &TokenInfo) -> Result<TokenSummary, AiError> {
+        let mut attempt: u32 = 0;
+        loop {
+            let error = match generate_token_summary(&self.http, &self.api_key, token).await {
+                Ok(summary) => return Ok(summary),
+                Err(e) => e,
+            };
+            attempt += 1;
+            if !is_retryable(&error) || attempt >= self.max_retries as u32 {
+                return Err(error);
+            }
+            let wait = backoff_with_jitter(attempt, self.retry_backoff, self.max_retry_backoff);
+            warn!("ai summary of {} error, try {} in {}ms: {}", token.symbol, attempt + 1, wait, error);
+            sleep(Duration::from_millis(wait)).await;
+        }
+    }
+}
+
+/// Create a client with the settings of `config`
+pub fn ai_client(config: &AiConfig) -> AiClient {
+    AiClient::new(&config.api_key)
+        .with_timeout(config.timeout)
+        .with_max_retries(config.max_retries)
+        .with_retry_backoff(config.retry_backoff, config.max_retry_backoff)
+}
+
+// 超时、断网、限流和 5xx 才值得重试, a bad key or a blocked prompt fails the same way again
+fn is_retryable(error: &AiError) -> bool {
+    match error.downcast_ref::<reqwest::Error>() {
+        Some(e) => {
+            e.is_timeout()
+                || e.is_connect()
+                || e.status().is_some_and(|status| status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error())
+        }
+        None => false,
+    }
+}
+
+async fn generate_token_summary(client: &Client, api_key: &str, token: &TokenInfo) -> Result<TokenSummary, AiError> {
     let api_url = "https://generativelanguage.googleapis.com/v1beta/models/gemini-2.0-flash:generateContent";
-    let api_key = std::env::var("AI_API_KEY").expect("AI_API_KEY not found");
     
     // Create a flexible prompt that can work with or without X content
-    let prompt = if token.x_content.is_empty() {
+    let analysis = if token.x_content.is_empty() {
         format!(
-            "Provide a two-sentence investment analysis of the '{}' ({}) token based solely on its ticker symbol characteristics. Offer a concise, objective perspective on its potential market positioning and dynamics without mentioning investment risks.",
+            "Analyse the '{}' ({}) token based solely on its ticker symbol characteristics, for its potential market positioning and dynamics.",
             token.name, token.symbol
         )
     } else {
         format!(
-            "Provide a two-sentence investment analysis of the '{}' ({}) token, using both its ticker symbol and X (Twitter) content: '{}'. Offer a concise, objective perspective on its brand positioning and market dynamics without including risk disclaimers.",
-            token.name, token.symbol, token.x_content
+            "Analyse the '{}' ({}) token using both its ticker symbol and its X (Twitter) activity, weighing the engagement and tone of the tweets.\nX activity: {}\nTop tweets:\n{}",
+            token.name, token.symbol, token.x_summary, token.x_content
         )
     };
-    
+    let prompt = format!(
+        "{}\nAnswer as JSON: `summary` is a concise, objective two-sentence analysis of its brand positioning and market dynamics without risk disclaimers, `risk_score` from 0 (low) to 100 (almost surely a rug or scam), `categories` up to 3 short lowercase tags (e.g. meme, ai, animal, celebrity, political), `confidence` from 0.0 to 1.0 in this read.",
+        analysis
+    );
+
     // Prepare the request
     let request = GeminiRequest {
         contents: vec![GeminiContent {
             parts: vec![GeminiPart { text: prompt }],
         }],
+        generation_config: GenerationConfig {
+            response_mime_type: "application/json",
+            response_schema: serde_json::json!({
+                "type": "OBJECT",
+                "properties": {
+                    "summary": { "type": "STRING" },
+                    "risk_score": { "type": "INTEGER" },
+                    "categories": { "type": "ARRAY", "items": { "type": "STRING" } },
+                    "confidence": { "type": "NUMBER" }
+                },
+                "required": ["summary", "risk_score", "categories", "confidence"]
+            }),
+        },
     };
     
     // Make the API call
@@ -76,13 +230,14 @@ pub async fn generate_token_summary(token: &TokenInfo) -> Result<String, Box<dyn
         .json(&request)
         .send()
         .await?
+        .error_for_status()?
         .json::<GeminiResponse>()
         .await?;
     
     // Extract and return the summary
     if let Some(candidate) = response.candidates.first() {
         if let Some(part) = candidate.content.parts.first() {
-            return Ok(part.text.trim().to_string());
+            return Ok(TokenSummary::parse(&part.text));
         }
     }
     
@@ -96,16 +251,19 @@ mod tests {
     #[tokio::test]
     async fn test_generate_token_summary_real_request() {
         dotenv::dotenv().ok();
+        let config = crate::config::Config::load().unwrap();
+        let client = AiClient::new(&config.ai.api_key);
         // Create a test token
         let token = TokenInfo {
             name: "PEPE".to_string(),
             symbol: "PEPE".to_string(),
             url: "https://pepe.pump.fun".to_string(),
-            x_content: "PEPE is a token, it target to fire 1 M".to_string(),
+            x_content: "- @pepe (120 likes): PEPE is a token, it target to fire 1 M".to_string(),
+            x_summary: "1 tweets from 1 accounts, 120 likes, 30 retweets, 12 replies; tone neutral (0 bullish, 0 bearish, score +0.00)".to_string(),
         };
 
         // Call the actual function with a real API request
-        let result = generate_token_summary(&token).await;
+        let result = client.generate_token_summary(&token).await;
 
         // Verify the result is Ok and contains some text
         assert!(result.is_ok(), "API request failed: {:?}", result.err());
@@ -113,11 +271,28 @@ mod tests {
         let summary = result.unwrap();
 
         // Basic validation of the response
-        assert!(!summary.is_empty(), "Summary should not be empty");
-        println!("Generated summary: {}", summary);
+        assert!(!summary.summary.is_empty(), "Summary should not be empty");
+        println!("Generated summary: {:?}", summary);
 
         // Optional: Additional assertions about the content
-        assert!(summary.contains("PEPE") || summary.contains("pepe"),
+        assert!(summary.summary.contains("PEPE") || summary.summary.contains("pepe"),
                 "Summary should mention the token name");
     }
+
+    #[test]
+    fn parse_token_summary_test() {
+        let json = r#"{"summary": "A frog meme.", "risk_score": 140, "categories": ["meme", " "], "confidence": 0.8}"#;
+        assert_eq!(
+            TokenSummary::parse(json),
+            TokenSummary {
+                summary: "A frog meme.".to_string(),
+                risk_score: Some(100),
+                categories: vec!["meme".to_string()],
+                confidence: Some(0.8),
+            }
+        );
+        assert_eq!(TokenSummary::parse(&format!("```json\n{}\n```", json)).summary, "A frog meme.");
+        // not the JSON asked for
+        assert_eq!(TokenSummary::parse(" Plain text. "), TokenSummary { summary: "Plain text.".to_string(), ..Default::default() });
+    }
 }
\ No newline at end of file
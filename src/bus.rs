@@ -0,0 +1,43 @@
+use std::sync::Arc;
+
+use tokio::sync::broadcast;
+
+use crate::{handler::EventContext, types::TargetEvent};
+
+/// A parsed event together with its transaction context
+#[derive(Debug, Clone)]
+pub struct BusEvent {
+    pub ctx: EventContext,
+    pub event: TargetEvent,
+}
+
+/// Broadcast channel every parsed event is published on.
+///
+/// Subscribers run independently of the gRPC loop. A subscriber that falls more
+/// than `capacity` events behind gets `RecvError::Lagged` and skips the oldest ones,
+/// publishing never waits for slow subscribers.
+#[derive(Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<Arc<BusEvent>>,
+}
+
+impl EventBus {
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity.max(1));
+        Self { sender }
+    }
+
+    /// Publish an event, returns the number of subscribers that will see it
+    pub fn publish(&self, ctx: EventContext, event: TargetEvent) -> usize {
+        // no subscribers is not an error
+        self.sender.send(Arc::new(BusEvent { ctx, event })).unwrap_or(0)
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<BusEvent>> {
+        self.sender.subscribe()
+    }
+
+    pub fn subscriber_count(&self) -> usize {
+        self.sender.receiver_count()
+    }
+}
@@ -1,178 +1,1922 @@
-use std::collections::HashMap;
+use std::{collections::{HashMap, HashSet}, str::FromStr, sync::Arc};
 
-use redis::{aio::MultiplexedConnection, AsyncCommands, RedisResult};
-use solana_sdk::timing::timestamp;
-use tracing::{debug, info};
+use dashmap::DashMap;
+use once_cell::sync::{Lazy, OnceCell};
+
+use redis::{streams::StreamRangeReply, AsyncCommands, RedisResult};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use solana_sdk::{native_token::LAMPORTS_PER_SOL, pubkey::Pubkey, timing::timestamp};
+use tracing::{debug, info, warn};
+
+use crate::{ai::{AiClient, TokenInfo, TokenSummary}, candles::{merge_closed, Candle, ClosedCandle, INTERVALS}, config::AlertConfig, constants::{MINUTES, TOKEN_2022_PROGRAM_ID}, handler::EventContext, pubsub::{publish_alert, ALERTS_HELD_CHANNEL, ALERTS_REMOVED_CHANNEL, ALERTS_SENT_CHANNEL}, redis_conn::RedisConn, social::SocialChain, stats::FeedLatency, storage::TokenStore, tg_bot::{image::social_link, notifier::Notifier, tg_bot::{FollowupAlert, SentAlert, TokenDetails}}, types::{CreateEvent, ParseFailure, Program}, utils::{associated_token_address, find_boop_bonding_curve, format_duration_ms, format_timestamp_to_et, parse_token_program, PumpfunParams}, x::{tweet_excerpts, x_handle, Tweet, TweetAggregate}};
+
+// 多实例共用同一个redis时的key前缀 (`redis.key_prefix`), set once on startup
+static KEY_PREFIX: OnceCell<String> = OnceCell::new();
+
+/// Prefix every key of this module with `prefix`, call before the first redis access;
+/// later calls are ignored
+pub fn set_key_prefix(prefix: &str) {
+    let _ = KEY_PREFIX.set(prefix.to_string());
+}
+
+fn key_prefix() -> &'static str {
+    KEY_PREFIX.get().map(String::as_str).unwrap_or_default()
+}
+
+/// `name` with the key prefix
+pub fn redis_key(name: &str) -> String {
+    format!("{}{}", key_prefix(), name)
+}
+
+// per-token key, e.g. `<prefix>volume:<mint>`
+fn mint_key(prefix: &str, mint: &str) -> String {
+    format!("{}{}{}", key_prefix(), prefix, mint)
+}
+
+// 单机部署的本地存储 (`storage.backend`), set once on startup; the token records, pool index
+// and alert state then live in the store, the rest of the data stays in redis
+static TOKEN_STORE: OnceCell<Arc<dyn TokenStore>> = OnceCell::new();
+
+/// Keep the token records, pool index and alert state in `store` instead of redis, call before
+/// the first redis access; later calls are ignored
+pub fn set_token_store(store: Arc<dyn TokenStore>) {
+    let _ = TOKEN_STORE.set(store);
+}
+
+fn token_store() -> Option<&'static Arc<dyn TokenStore>> {
+    TOKEN_STORE.get()
+}
+
+// store errors pass through the redis results of this module
+fn store_error(e: anyhow::Error) -> redis::RedisError {
+    redis::RedisError::from((redis::ErrorKind::IoError, "token store error", format!("{:#}", e)))
+}
 
-use crate::{ai::{generate_token_summary, TokenInfo}, constants::{MARKET_CAP, NEW_COIN_MAX_TIME, NEW_COIN_MIN_TIME}, tg_bot::{tg_bot::TokenDetails, tg_bot_type::BotInstance}, types::CreateEvent, utils::format_timestamp_to_et, x::{Tweet, XClient}};
 const TOKEN_SET_KEY: &str = "token_info_set";
+// written by versions before the checkpoint hash, still read on startup
+const LAST_SLOT_KEY: &str = "last_processed_slot";
+const CHECKPOINT_KEY: &str = "checkpoint";
+// runtime threshold overrides, e.g. `hset config:thresholds market_cap 80000`
+const THRESHOLDS_KEY: &str = "config:thresholds";
+// mints and creators whose tokens are never alerted, set with the admin commands
+const MUTED_MINTS_KEY: &str = "config:muted_mints";
+const BLACKLISTED_CREATORS_KEY: &str = "config:blacklisted_creators";
+// chat id -> JSON `Subscription` of users who subscribed to alerts in a DM with the bot
+const SUBSCRIBERS_KEY: &str = "subscribers";
+// PumpSwap pool -> mint of tracked tokens, so pool events skip the scan over all records
+const POOL_INDEX_KEY: &str = "pool_index";
+// tracked tokens ranked by current market cap
+const MARKET_CAP_RANK_KEY: &str = "market_cap_rank";
+// tokens sold by each moonshot / boop curve, their trade events do not carry the reserves
+const CURVE_SOLD_KEY: &str = "curve_tokens_sold";
+// moonshot curve -> mint of tracked tokens, its trade events only name the curve
+const CURVE_INDEX_KEY: &str = "curve_index";
+// per token: set of holder wallets, list of the first buyers as `FirstBuyer` JSON
+const HOLDERS_KEY_PREFIX: &str = "token_holders:";
+const FIRST_BUYERS_KEY_PREFIX: &str = "token_first_buyers:";
+// per token: HyperLogLog of the wallets that bought it
+const BUYERS_KEY_PREFIX: &str = "token_buyers:";
+// per token: creator's balance in raw token units
+const DEV_BALANCE_KEY_PREFIX: &str = "dev_balance:";
+// running parse failure totals per `program:kind`, and when each was last seen (unix ms)
+const PARSE_FAILURES_KEY: &str = "parse_failures";
+const PARSE_FAILURES_SEEN_KEY: &str = "parse_failures:last_seen";
+// `feed<index>:p50_micros` / `:p99_micros` / `:samples` of the last stats window
+const FEED_LATENCY_KEY: &str = "feed_latency";
+// pump.fun global parameters from the latest SetParams event
+const PUMPFUN_PARAMS_KEY: &str = "pumpfun_params";
+// per token: compute unit prices of the latest buys, newest first
+const PRIORITY_FEES_KEY_PREFIX: &str = "priority_fees:";
+// per token: SOL volume per minute, `<minute>:buy` / `<minute>:sell` fields in lamports
+const VOLUME_KEY_PREFIX: &str = "volume:";
+// per interval and token: closed candles as JSON scored by their open time, `candles:1m:<mint>`
+const CANDLES_KEY_PREFIX: &str = "candles:";
+// per token: stream of price and market cap points, trimmed to `engine.price_history`
+const PRICE_HISTORY_KEY_PREFIX: &str = "price_history:";
+// per token: `AlertState` as JSON, expiring with the record
+const ALERT_STATE_KEY_PREFIX: &str = "alert_state:";
+// per token and tier (or per token, from the single threshold of the first versions): alert
+// markers of older versions, folded into the alert state on startup
+const LEGACY_ALERT_SENT_KEY_PREFIX: &str = "token_alert_sent:";
+// per token: `AlertMessages` as JSON, the sent alerts edited with `alert.live_updates` and
+// replied to with follow-ups
+const ALERT_MESSAGES_KEY_PREFIX: &str = "alert_messages:";
+// per token: set of the follow-up kinds sent (`2x`, `graduated`, ...)
+const FOLLOWUPS_KEY_PREFIX: &str = "alert_followups:";
+// mint -> `DigestEntry` as JSON, tokens alerted since the last digest (`alert.digest_interval`)
+const DIGEST_KEY: &str = "digest:tokens";
+// mints already reported as graduated in a digest
+const DIGEST_GRADUATED_KEY: &str = "digest:graduated";
+// per route: mint -> tier of the alerts held in the route's quiet hours, `quiet_held:<route>`
+const QUIET_HELD_KEY_PREFIX: &str = "quiet_held:";
+// coin alerts that never reached a route, newest first as JSON `DeadLetter`s
+const DEAD_LETTERS_KEY: &str = "dead_letters";
+// the dead letter list is cut to this length
+const DEAD_LETTERS_MAX: isize = 1000;
+// route name -> id of the route's pinned trending message (`telegram.trending_interval`)
+const TRENDING_MESSAGES_KEY: &str = "trending_messages";
+// per token: the tweets found for its AI summary as JSON, kept for `x.search_cache_ttl`
+const X_TWEETS_KEY_PREFIX: &str = "x_tweets:";
+// per token: set when its tweets link to the website in its metadata (`x::same_site`)
+const SOCIALS_VERIFIED_KEY_PREFIX: &str = "socials_verified:";
+// KOL account -> id of its newest tweet read by the KOL scanner (`x.kol_accounts`)
+const KOL_LAST_SEEN_KEY: &str = "kol:last_seen";
+// per token: set of the KOL accounts whose mention of it was sent
+const KOL_MENTIONS_KEY_PREFIX: &str = "kol_mentions:";
 
 // ! blockhash
-pub async fn get_block_hash_str(conn: &mut MultiplexedConnection) -> RedisResult<String> {
-    redis::cmd("get").arg("blockhash").query_async(conn).await
+const BLOCKHASH_KEY: &str = "blockhash";
+const BLOCKHASH_INFO_KEY: &str = "blockhash_info";
+
+/// A blockhash is accepted for 150 blocks after the block that produced it
+pub const MAX_BLOCKHASH_AGE: u64 = 150;
+// about 400ms per block
+const BLOCKHASH_LIFETIME_MS: u64 = MAX_BLOCKHASH_AGE * 400;
+
+/// Latest blockhash with what is needed to tell whether it can still be used
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BlockhashInfo {
+    pub blockhash: String,
+    pub slot: u64,
+    pub block_height: u64,
+    /// transactions using the blockhash fail once the chain is past this height
+    pub last_valid_block_height: u64,
+    /// estimated wall-clock expiry, unix milliseconds
+    pub expires_at: u64,
+}
+
+impl BlockhashInfo {
+    /// `produced_at` is the block time in unix milliseconds
+    pub fn new(blockhash: String, slot: u64, block_height: u64, produced_at: u64) -> Self {
+        Self {
+            blockhash,
+            slot,
+            block_height,
+            last_valid_block_height: block_height + MAX_BLOCKHASH_AGE,
+            expires_at: produced_at + BLOCKHASH_LIFETIME_MS,
+        }
+    }
+
+    /// Usable by a transaction sent now, by the estimated expiry
+    pub fn is_fresh(&self, now_ms: u64) -> bool {
+        now_ms < self.expires_at
+    }
+
+    /// Usable at `block_height`, exact when the current height is known
+    pub fn is_valid_at(&self, block_height: u64) -> bool {
+        block_height <= self.last_valid_block_height
+    }
+}
+
+pub async fn get_block_hash_str(conn: &mut RedisConn) -> RedisResult<String> {
+    redis::cmd("get").arg(redis_key(BLOCKHASH_KEY)).query_async(conn).await
+}
+
+/// Store the blockhash, the plain `blockhash` key is kept for existing readers
+pub async fn set_block_hash(conn: &mut RedisConn, info: &BlockhashInfo) -> RedisResult<()> {
+    redis::pipe()
+        .atomic()
+        .set(redis_key(BLOCKHASH_KEY), &info.blockhash)
+        .ignore()
+        .hset_multiple(
+            redis_key(BLOCKHASH_INFO_KEY),
+            &[
+                ("blockhash", info.blockhash.clone()),
+                ("slot", info.slot.to_string()),
+                ("block_height", info.block_height.to_string()),
+                ("last_valid_block_height", info.last_valid_block_height.to_string()),
+                ("expires_at", info.expires_at.to_string()),
+            ],
+        )
+        .ignore()
+        .exec_async(conn)
+        .await
+}
+
+pub async fn get_block_hash_info(conn: &mut RedisConn) -> RedisResult<Option<BlockhashInfo>> {
+    let fields: HashMap<String, String> = conn.hgetall(redis_key(BLOCKHASH_INFO_KEY)).await?;
+    let number = |name: &str| fields.get(name).and_then(|v| v.parse::<u64>().ok());
+    match (fields.get("blockhash"), number("slot"), number("block_height"), number("last_valid_block_height"), number("expires_at")) {
+        (Some(blockhash), Some(slot), Some(block_height), Some(last_valid_block_height), Some(expires_at)) => {
+            Ok(Some(BlockhashInfo {
+                blockhash: blockhash.clone(),
+                slot,
+                block_height,
+                last_valid_block_height,
+                expires_at,
+            }))
+        }
+        _ => Ok(None),
+    }
+}
+
+// ! signature dedup
+/// Remember a transaction signature, returns false if it was already seen within `ttl_secs`
+pub async fn mark_signature_seen(conn: &mut RedisConn, signature: &str, ttl_secs: u64) -> RedisResult<bool> {
+    let result: Option<String> = redis::cmd("set")
+        .arg(redis_key(&format!("tx_seen:{}", signature)))
+        .arg(1)
+        .arg("NX")
+        .arg("EX")
+        .arg(ttl_secs.max(1))
+        .query_async(conn)
+        .await?;
+    Ok(result.is_some())
+}
+
+// ! checkpoint
+/// Last fully processed slot and the last transaction handled in it
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Checkpoint {
+    pub slot: u64,
+    pub signature: String,
+}
+
+pub async fn set_checkpoint(conn: &mut RedisConn, checkpoint: &Checkpoint) -> RedisResult<()> {
+    conn.hset_multiple(
+        redis_key(CHECKPOINT_KEY),
+        &[("slot", checkpoint.slot.to_string()), ("signature", checkpoint.signature.clone())],
+    )
+    .await
+}
+
+pub async fn get_checkpoint(conn: &mut RedisConn) -> RedisResult<Option<Checkpoint>> {
+    let fields: HashMap<String, String> = conn.hgetall(redis_key(CHECKPOINT_KEY)).await?;
+    if let Some(slot) = fields.get("slot").and_then(|slot| slot.parse().ok()) {
+        return Ok(Some(Checkpoint {
+            slot,
+            signature: fields.get("signature").cloned().unwrap_or_default(),
+        }));
+    }
+    let last_slot: Option<u64> = conn.get(redis_key(LAST_SLOT_KEY)).await?;
+    Ok(last_slot.map(|slot| Checkpoint { slot, signature: String::new() }))
+}
+
+/// Remove tokens with their records and per-token keys
+pub async fn remove_tokens(conn: &mut RedisConn, mints: &[String]) -> RedisResult<()> {
+    if mints.is_empty() {
+        return Ok(());
+    }
+    let holder_keys: Vec<String> = mints
+        .iter()
+        .flat_map(|mint| {
+            [HOLDERS_KEY_PREFIX, FIRST_BUYERS_KEY_PREFIX, BUYERS_KEY_PREFIX, DEV_BALANCE_KEY_PREFIX, PRIORITY_FEES_KEY_PREFIX, PRICE_HISTORY_KEY_PREFIX, VOLUME_KEY_PREFIX, ALERT_STATE_KEY_PREFIX, ALERT_MESSAGES_KEY_PREFIX, FOLLOWUPS_KEY_PREFIX]
+                .map(|prefix| mint_key(prefix, mint))
+                .into_iter()
+                .chain(INTERVALS.map(|interval| candles_key(interval, mint)))
+        })
+        .collect();
+    conn.del::<_, ()>(holder_keys).await?;
+    conn.hdel::<_, _, ()>(redis_key(DIGEST_KEY), mints).await?;
+    conn.srem::<_, _, ()>(redis_key(DIGEST_GRADUATED_KEY), mints).await?;
+    for mint in mints {
+        ALERT_LOCKS.remove(mint);
+    }
+    remove_token_records(conn, mints).await
+}
+
+// ! holders
+// 持有人和买家数写回记录, the counts are kept in the record next to the market cap so
+// alerts read them with it; the record is decoded in redis like in `UPDATE_MARKET_CAPS`.
+// KEYS: records, holder set; ARGV: mint, owner, balance. Returns 1 when the owner just
+// became a holder, untracked tokens are skipped.
+static UPDATE_HOLDER: Lazy<redis::Script> = Lazy::new(|| {
+    redis::Script::new(
+        r#"
+local raw = redis.call('HGET', KEYS[1], ARGV[1])
+local ok, record = false, nil
+if raw then
+    ok, record = pcall(cjson.decode, raw)
+end
+if not ok or type(record) ~= 'table' then
+    return 0
+end
+local changed
+if tonumber(ARGV[3]) > 0 then
+    changed = redis.call('SADD', KEYS[2], ARGV[2])
+else
+    redis.call('SREM', KEYS[2], ARGV[2])
+    changed = 0
+end
+local holders = redis.call('SCARD', KEYS[2])
+if holders ~= tonumber(record.holders) then
+    record.holders = holders
+    redis.call('HSET', KEYS[1], ARGV[1], cjson.encode(record))
+end
+return changed
+"#,
+    )
+});
+
+// KEYS: records, buyer HyperLogLog; ARGV: mint, wallet. Returns the buyer estimate,
+// -1 for an untracked token.
+static ADD_BUYER: Lazy<redis::Script> = Lazy::new(|| {
+    redis::Script::new(
+        r#"
+local raw = redis.call('HGET', KEYS[1], ARGV[1])
+local ok, record = false, nil
+if raw then
+    ok, record = pcall(cjson.decode, raw)
+end
+if not ok or type(record) ~= 'table' then
+    return -1
+end
+if redis.call('PFADD', KEYS[2], ARGV[2]) == 0 then
+    return tonumber(record.buyers) or 0
+end
+local buyers = redis.call('PFCOUNT', KEYS[2])
+record.buyers = buyers
+redis.call('HSET', KEYS[1], ARGV[1], cjson.encode(record))
+return buyers
+"#,
+    )
+});
+
+/// Keep `owner` in the holders of `mint` while it has a balance and the holder count in its
+/// record; true when it just became a holder
+pub async fn update_holder(conn: &mut RedisConn, mint: &str, owner: &str, balance: u64) -> RedisResult<bool> {
+    if let Some(store) = token_store() {
+        if store.token(mint).await.map_err(store_error)?.is_none() {
+            return Ok(false);
+        }
+        let key = mint_key(HOLDERS_KEY_PREFIX, mint);
+        let added: u64 = if balance > 0 {
+            conn.sadd(&key, owner).await?
+        } else {
+            conn.srem::<_, _, ()>(&key, owner).await?;
+            0
+        };
+        let holders: u64 = conn.scard(&key).await?;
+        store.update_token(mint, Box::new(move |record| record.holders = holders)).await.map_err(store_error)?;
+        return Ok(added > 0);
+    }
+    let added: u64 = UPDATE_HOLDER
+        .key(redis_key(TOKEN_SET_KEY))
+        .key(mint_key(HOLDERS_KEY_PREFIX, mint))
+        .arg(mint)
+        .arg(owner)
+        .arg(balance)
+        .invoke_async(conn)
+        .await?;
+    Ok(added > 0)
+}
+
+/// Count `wallet` as a buyer of `mint` and store the estimate in its record;
+/// returns the estimate, `None` for an untracked token
+pub async fn add_buyer(conn: &mut RedisConn, mint: &str, wallet: &str) -> RedisResult<Option<u64>> {
+    if let Some(store) = token_store() {
+        let Some(record) = store.token(mint).await.map_err(store_error)? else {
+            return Ok(None);
+        };
+        let key = mint_key(BUYERS_KEY_PREFIX, mint);
+        if !conn.pfadd::<_, _, bool>(&key, wallet).await? {
+            return Ok(Some(record.buyers));
+        }
+        let buyers: u64 = conn.pfcount(&key).await?;
+        store.update_token(mint, Box::new(move |record| record.buyers = buyers)).await.map_err(store_error)?;
+        return Ok(Some(buyers));
+    }
+    let buyers: i64 = ADD_BUYER
+        .key(redis_key(TOKEN_SET_KEY))
+        .key(mint_key(BUYERS_KEY_PREFIX, mint))
+        .arg(mint)
+        .arg(wallet)
+        .invoke_async(conn)
+        .await?;
+    Ok(u64::try_from(buyers).ok())
+}
+
+// The balance stays in its own key, raw amounts pass 2^53 and would come back from cjson
+// as floats; the record only gets the share.
+// KEYS: records, dev balance; ARGV: mint, wallet, `add` or `set`, amount, total supply, now,
+// sold (1/0). Returns 1 when the wallet is the creator of a tracked token.
+static UPDATE_DEV_BALANCE: Lazy<redis::Script> = Lazy::new(|| {
+    redis::Script::new(
+        r#"
+local raw = redis.call('HGET', KEYS[1], ARGV[1])
+local ok, record = false, nil
+if raw then
+    ok, record = pcall(cjson.decode, raw)
+end
+if not ok or type(record) ~= 'table' or record.creator ~= ARGV[2] then
+    return 0
+end
+local balance
+if ARGV[3] == 'set' then
+    redis.call('SET', KEYS[2], ARGV[4])
+    balance = tonumber(ARGV[4])
+else
+    balance = redis.call('INCRBY', KEYS[2], ARGV[4])
+    if balance < 0 then
+        redis.call('SET', KEYS[2], 0)
+        balance = 0
+    end
+end
+local supply = tonumber(ARGV[5])
+if supply > 0 then
+    record.dev_percent = balance / supply * 100
+end
+if ARGV[7] == '1' then
+    record.dev_sold = true
+end
+record.dev_updated = tonumber(ARGV[6])
+redis.call('HSET', KEYS[1], ARGV[1], cjson.encode(record))
+return 1
+"#,
+    )
+});
+
+/// Change in the creator's balance of a token, from a trade, a transfer or an RPC lookup
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DevBalance {
+    /// tokens bought (positive) or sold (negative)
+    Change(i64),
+    /// balance after a transfer or read over RPC
+    Balance(u64),
+}
+
+/// Apply a change of `wallet`'s balance of `mint` when it is the creator, storing its share of
+/// `total_supply` in the record; `sold` marks the record as sold by the dev. True when applied
+pub async fn update_dev_balance(
+    conn: &mut RedisConn,
+    mint: &str,
+    wallet: &str,
+    balance: DevBalance,
+    total_supply: u64,
+    sold: bool,
+) -> RedisResult<bool> {
+    if let Some(store) = token_store() {
+        if !store.token(mint).await.map_err(store_error)?.is_some_and(|record| record.creator == wallet) {
+            return Ok(false);
+        }
+        let key = mint_key(DEV_BALANCE_KEY_PREFIX, mint);
+        let amount = match balance {
+            DevBalance::Balance(amount) => {
+                conn.set::<_, _, ()>(&key, amount).await?;
+                amount
+            }
+            DevBalance::Change(change) => {
+                let amount: i64 = conn.incr(&key, change).await?;
+                if amount < 0 {
+                    conn.set::<_, _, ()>(&key, 0).await?;
+                }
+                amount.max(0) as u64
+            }
+        };
+        let now = timestamp();
+        store
+            .update_token(
+                mint,
+                Box::new(move |record| {
+                    if total_supply > 0 {
+                        record.dev_percent = (amount as f64 / total_supply as f64 * 100.0) as f32;
+                    }
+                    if sold {
+                        record.dev_sold = true;
+                    }
+                    record.dev_updated = now;
+                }),
+            )
+            .await
+            .map_err(store_error)?;
+        return Ok(true);
+    }
+    let mut invocation = UPDATE_DEV_BALANCE.key(redis_key(TOKEN_SET_KEY));
+    invocation.key(mint_key(DEV_BALANCE_KEY_PREFIX, mint)).arg(mint).arg(wallet);
+    match balance {
+        DevBalance::Change(amount) => invocation.arg("add").arg(amount),
+        DevBalance::Balance(amount) => invocation.arg("set").arg(amount),
+    };
+    let applied: u64 = invocation.arg(total_supply).arg(timestamp()).arg(if sold { "1" } else { "0" }).invoke_async(conn).await?;
+    Ok(applied > 0)
+}
+
+/// Creator's balance of `mint` in raw token units, 0 when unknown
+pub async fn dev_balance(conn: &mut RedisConn, mint: &str) -> RedisResult<u64> {
+    let balance: Option<u64> = conn.get(mint_key(DEV_BALANCE_KEY_PREFIX, mint)).await?;
+    Ok(balance.unwrap_or_default())
+}
+
+/// Distinct buyers of `mint`, a HyperLogLog estimate (about 1% off)
+pub async fn buyer_count(conn: &mut RedisConn, mint: &str) -> RedisResult<u64> {
+    conn.pfcount(mint_key(BUYERS_KEY_PREFIX, mint)).await
+}
+
+pub async fn holder_count(conn: &mut RedisConn, mint: &str) -> RedisResult<u64> {
+    conn.scard(mint_key(HOLDERS_KEY_PREFIX, mint)).await
+}
+
+// KEYS: records, first buyers; ARGV: mint, wallet, slot, limit. Returns 1 when the wallet
+// was added, wallets already in the list and untracked tokens are skipped.
+static ADD_FIRST_BUYER: Lazy<redis::Script> = Lazy::new(|| {
+    redis::Script::new(
+        r#"
+local raw = redis.call('HGET', KEYS[1], ARGV[1])
+local ok, record = false, nil
+if raw then
+    ok, record = pcall(cjson.decode, raw)
+end
+if not ok or type(record) ~= 'table' then
+    return 0
+end
+if redis.call('LLEN', KEYS[2]) >= tonumber(ARGV[4]) then
+    return 0
+end
+for _, entry in ipairs(redis.call('LRANGE', KEYS[2], 0, -1)) do
+    local decoded, buyer = pcall(cjson.decode, entry)
+    if entry == ARGV[2] or (decoded and type(buyer) == 'table' and buyer.wallet == ARGV[2]) then
+        return 0
+    end
+end
+local slot = tonumber(ARGV[3])
+local create_slot = tonumber(record.create_slot) or 0
+local same_block = create_slot > 0 and slot == create_slot
+redis.call('RPUSH', KEYS[2], cjson.encode({ wallet = ARGV[2], slot = slot, same_block = same_block }))
+record.first_buyers = (tonumber(record.first_buyers) or 0) + 1
+if same_block then
+    record.same_block_buyers = (tonumber(record.same_block_buyers) or 0) + 1
+end
+redis.call('HSET', KEYS[1], ARGV[1], cjson.encode(record))
+return 1
+"#,
+    )
+});
+
+/// Early buyer of a token, for sniper and bundle detection
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FirstBuyer {
+    pub wallet: String,
+    /// slot of the buy, 0 for entries of older versions
+    pub slot: u64,
+    /// bought in the slot the token was created in
+    pub same_block: bool,
+}
+
+impl FirstBuyer {
+    /// JSON, or the plain wallet written by older versions
+    pub fn decode(value: &str) -> Self {
+        serde_json::from_str(value).unwrap_or_else(|_| Self { wallet: value.to_string(), ..Default::default() })
+    }
+}
+
+/// Append `wallet` to the first buyers of `mint` until there are `limit`, counting it in the
+/// record; true when it was added
+pub async fn add_first_buyer(conn: &mut RedisConn, mint: &str, wallet: &str, slot: u64, limit: usize) -> RedisResult<bool> {
+    if let Some(store) = token_store() {
+        let Some(record) = store.token(mint).await.map_err(store_error)? else {
+            return Ok(false);
+        };
+        let key = mint_key(FIRST_BUYERS_KEY_PREFIX, mint);
+        let entries: Vec<String> = conn.lrange(&key, 0, -1).await?;
+        if entries.len() >= limit || entries.iter().any(|entry| FirstBuyer::decode(entry).wallet == wallet) {
+            return Ok(false);
+        }
+        let same_block = record.create_slot > 0 && slot == record.create_slot;
+        let buyer = FirstBuyer { wallet: wallet.to_string(), slot, same_block };
+        conn.rpush::<_, _, ()>(&key, serde_json::to_string(&buyer).unwrap_or_default()).await?;
+        let update = move |record: &mut TokenRecord| {
+            record.first_buyers += 1;
+            if same_block {
+                record.same_block_buyers += 1;
+            }
+        };
+        store.update_token(mint, Box::new(update)).await.map_err(store_error)?;
+        return Ok(true);
+    }
+    let added: u64 = ADD_FIRST_BUYER
+        .key(redis_key(TOKEN_SET_KEY))
+        .key(mint_key(FIRST_BUYERS_KEY_PREFIX, mint))
+        .arg(mint)
+        .arg(wallet)
+        .arg(slot)
+        .arg(limit)
+        .invoke_async(conn)
+        .await?;
+    Ok(added > 0)
+}
+
+/// First buyers of `mint` in buy order
+pub async fn first_buyers(conn: &mut RedisConn, mint: &str) -> RedisResult<Vec<FirstBuyer>> {
+    let entries: Vec<String> = conn.lrange(mint_key(FIRST_BUYERS_KEY_PREFIX, mint), 0, -1).await?;
+    Ok(entries.iter().map(|entry| FirstBuyer::decode(entry)).collect())
+}
+
+// ! priority fees
+/// Priority fee levels buyers of a token paid, in micro-lamports per compute unit
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PriorityFeeStats {
+    pub samples: usize,
+    pub avg: u64,
+    pub p50: u64,
+    pub p90: u64,
+    pub p99: u64,
+}
+
+impl PriorityFeeStats {
+    pub fn from_samples(mut samples: Vec<u64>) -> Self {
+        if samples.is_empty() {
+            return Self::default();
+        }
+        samples.sort_unstable();
+        let percentile = |p: usize| samples[(samples.len() - 1) * p / 100];
+        Self {
+            samples: samples.len(),
+            avg: samples.iter().sum::<u64>() / samples.len() as u64,
+            p50: percentile(50),
+            p90: percentile(90),
+            p99: percentile(99),
+        }
+    }
+
+    /// Median and p90 for alerts, e.g. `p50 1200, p90 8000 µlamports/CU (40 buys)`, empty without samples
+    pub fn summary(&self) -> String {
+        if self.samples == 0 {
+            return String::new();
+        }
+        format!("p50 {}, p90 {} µlamports/CU ({} buys)", self.p50, self.p90, self.samples)
+    }
+}
+
+/// Record the compute unit price of a buy, keeping the latest `limit`
+pub async fn add_priority_fee(conn: &mut RedisConn, mint: &str, unit_price: u64, limit: usize) -> RedisResult<()> {
+    let key = mint_key(PRIORITY_FEES_KEY_PREFIX, mint);
+    redis::pipe()
+        .lpush(&key, unit_price)
+        .ignore()
+        .ltrim(&key, 0, limit as isize - 1)
+        .ignore()
+        .query_async(conn)
+        .await
+}
+
+/// Levels of the kept compute unit prices of `mint`, for alerts
+pub async fn priority_fee_stats(conn: &mut RedisConn, mint: &str) -> RedisResult<PriorityFeeStats> {
+    let samples: Vec<u64> = conn.lrange(mint_key(PRIORITY_FEES_KEY_PREFIX, mint), 0, -1).await?;
+    Ok(PriorityFeeStats::from_samples(samples))
+}
+
+// ! price history
+/// Price and market cap of a token at a point in time
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PricePoint {
+    /// unix milliseconds
+    pub timestamp: u64,
+    pub price: f64,
+    pub market_cap: f64,
+}
+
+impl PricePoint {
+    /// Market cap change from the first to the last point, in percent
+    pub fn change(points: &[PricePoint]) -> Option<f64> {
+        let (first, last) = (points.first()?, points.last()?);
+        if first.market_cap <= 0.0 {
+            return None;
+        }
+        Some((last.market_cap - first.market_cap) / first.market_cap * 100.0)
+    }
+}
+
+/// Append a point to the history of `mint`, dropping points older than `retention_ms`
+pub async fn add_price_point(
+    conn: &mut RedisConn,
+    mint: &str,
+    price: f64,
+    market_cap: f64,
+    retention_ms: u64,
+) -> RedisResult<()> {
+    let mut pipe = redis::pipe();
+    queue_price_point(&mut pipe, mint, price, market_cap, retention_ms);
+    pipe.query_async(conn).await
+}
+
+fn queue_price_point(pipe: &mut redis::Pipeline, mint: &str, price: f64, market_cap: f64, retention_ms: u64) {
+    let key = mint_key(PRICE_HISTORY_KEY_PREFIX, mint);
+    // stream ids start with the unix ms time, `MINID ~` trims by age
+    let min_id = timestamp().saturating_sub(retention_ms);
+    pipe.cmd("XADD")
+        .arg(&key)
+        .arg("MINID")
+        .arg("~")
+        .arg(min_id)
+        .arg("*")
+        .arg("price")
+        .arg(price)
+        .arg("market_cap")
+        .arg(market_cap)
+        .ignore()
+        .pexpire(&key, retention_ms as i64)
+        .ignore();
+}
+
+/// Points of `mint` since `since_ms` (unix milliseconds), oldest first
+pub async fn price_history(conn: &mut RedisConn, mint: &str, since_ms: u64) -> RedisResult<Vec<PricePoint>> {
+    let reply: StreamRangeReply = conn.xrange(mint_key(PRICE_HISTORY_KEY_PREFIX, mint), since_ms, "+").await?;
+    Ok(reply
+        .ids
+        .iter()
+        .filter_map(|entry| {
+            Some(PricePoint {
+                timestamp: entry.id.split('-').next()?.parse().ok()?,
+                price: entry.get("price")?,
+                market_cap: entry.get("market_cap")?,
+            })
+        })
+        .collect())
+}
+
+/// Market cap change of `mint` over the last `window_ms` in percent, `None` without history
+pub async fn price_change(conn: &mut RedisConn, mint: &str, window_ms: u64) -> RedisResult<Option<f64>> {
+    let points = price_history(conn, mint, timestamp().saturating_sub(window_ms)).await?;
+    Ok(PricePoint::change(&points))
+}
+
+// ! candles
+fn candles_key(interval: u64, mint: &str) -> String {
+    format!("{}{}{}:{}", key_prefix(), CANDLES_KEY_PREFIX, format_duration_ms(interval), mint)
+}
+
+/// Store closed candles, dropping the ones older than `retention_ms`
+pub async fn add_candles(conn: &mut RedisConn, candles: &[ClosedCandle], retention_ms: u64) -> RedisResult<()> {
+    if candles.is_empty() {
+        return Ok(());
+    }
+    // one member per open time: a trade that arrives after its interval was stored is merged
+    // into the stored candle
+    let candles = merge_closed(candles);
+    let mut lookup = redis::pipe();
+    for (mint, interval, candle) in &candles {
+        lookup.zrangebyscore(candles_key(*interval, mint), candle.open_time, candle.open_time);
+    }
+    let stored: Vec<Vec<String>> = lookup.query_async(conn).await?;
+
+    let oldest = timestamp().saturating_sub(retention_ms);
+    let mut pipe = redis::pipe();
+    pipe.atomic();
+    for ((mint, interval, candle), stored) in candles.iter().zip(stored) {
+        let key = candles_key(*interval, mint);
+        let stored = stored.iter().filter_map(|value| serde_json::from_str::<Candle>(value).ok()).reduce(|mut first, next| {
+            first.merge(&next);
+            first
+        });
+        let candle = match stored {
+            Some(mut stored) => {
+                stored.merge(candle);
+                stored
+            }
+            None => *candle,
+        };
+        let Ok(value) = serde_json::to_string(&candle) else {
+            continue;
+        };
+        pipe.zrembyscore(&key, candle.open_time, candle.open_time)
+            .ignore()
+            .zadd(&key, value, candle.open_time)
+            .ignore()
+            .zrembyscore(&key, 0, oldest.saturating_sub(1))
+            .ignore()
+            .pexpire(&key, retention_ms as i64)
+            .ignore();
+    }
+    pipe.query_async(conn).await
+}
+
+/// Closed candles of `mint` for `interval` (milliseconds, see `candles::INTERVALS`) since `since_ms`, oldest first
+pub async fn candles(conn: &mut RedisConn, mint: &str, interval: u64, since_ms: u64) -> RedisResult<Vec<Candle>> {
+    let values: Vec<String> = conn.zrangebyscore(candles_key(interval, mint), since_ms, "+inf").await?;
+    Ok(values.iter().filter_map(|value| serde_json::from_str(value).ok()).collect())
+}
+
+// ! volume
+/// SOL bought and sold in lamports
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TokenVolume {
+    pub buy: u64,
+    pub sell: u64,
+}
+
+impl TokenVolume {
+    pub fn total(&self) -> u64 {
+        self.buy + self.sell
+    }
+
+    /// Share of the volume that was bought, 0 without trades
+    pub fn buy_ratio(&self) -> f64 {
+        if self.total() == 0 {
+            return 0.0;
+        }
+        self.buy as f64 / self.total() as f64
+    }
+}
+
+/// Volume of a token over the last minute, 5 minutes and hour; a window of n minutes
+/// covers the current minute and the n - 1 before it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct VolumeWindows {
+    pub m1: TokenVolume,
+    pub m5: TokenVolume,
+    pub h1: TokenVolume,
+}
+
+impl VolumeWindows {
+    /// Sum the `<minute>:buy|sell` buckets, returns the windows and the fields older than an hour
+    pub fn from_buckets(buckets: &HashMap<String, u64>, now_minute: u64) -> (Self, Vec<String>) {
+        let mut windows = Self::default();
+        let mut stale = vec![];
+        for (field, lamports) in buckets {
+            let Some((minute, side)) = field.split_once(':') else {
+                stale.push(field.clone());
+                continue;
+            };
+            let Ok(minute) = minute.parse::<u64>() else {
+                stale.push(field.clone());
+                continue;
+            };
+            let age = now_minute.saturating_sub(minute);
+            if age >= 60 {
+                stale.push(field.clone());
+                continue;
+            }
+            for (window, volume) in [(1, &mut windows.m1), (5, &mut windows.m5), (60, &mut windows.h1)] {
+                if age < window {
+                    match side {
+                        "buy" => volume.buy += lamports,
+                        _ => volume.sell += lamports,
+                    }
+                }
+            }
+        }
+        (windows, stale)
+    }
+}
+
+/// Add a trade of `lamports` to the current minute of `mint`
+pub async fn add_volume(conn: &mut RedisConn, mint: &str, is_buy: bool, lamports: u64) -> RedisResult<()> {
+    let key = mint_key(VOLUME_KEY_PREFIX, mint);
+    let field = format!("{}:{}", timestamp() / MINUTES, if is_buy { "buy" } else { "sell" });
+    redis::pipe()
+        .hincr(&key, field, lamports)
+        .ignore()
+        // idle tokens lose their buckets on their own
+        .pexpire(&key, (61 * MINUTES) as i64)
+        .ignore()
+        .query_async(conn)
+        .await
+}
+
+/// Rolling volume of `mint`, buckets older than an hour are removed on the way
+pub async fn token_volume(conn: &mut RedisConn, mint: &str) -> RedisResult<VolumeWindows> {
+    let key = mint_key(VOLUME_KEY_PREFIX, mint);
+    let buckets: HashMap<String, u64> = conn.hgetall(&key).await?;
+    let (windows, stale) = VolumeWindows::from_buckets(&buckets, timestamp() / MINUTES);
+    if !stale.is_empty() {
+        conn.hdel::<_, _, ()>(&key, stale).await?;
+    }
+    Ok(windows)
+}
+
+/// Whether `mint` has a token record
+pub async fn is_tracked(conn: &mut RedisConn, mint: &str) -> RedisResult<bool> {
+    if let Some(store) = token_store() {
+        return Ok(store.token(mint).await.map_err(store_error)?.is_some());
+    }
+    conn.hexists(redis_key(TOKEN_SET_KEY), mint).await
+}
+
+// ! parse failures
+/// Add the failures of a stats window to the running totals
+pub async fn record_parse_failures(
+    conn: &mut RedisConn,
+    failures: &[(Program, ParseFailure, u64)],
+) -> RedisResult<()> {
+    let now = timestamp();
+    let mut pipe = redis::pipe();
+    for (program, kind, count) in failures {
+        let field = format!("{}:{}", program, kind);
+        pipe.hincr(redis_key(PARSE_FAILURES_KEY), &field, *count)
+            .ignore()
+            .hset(redis_key(PARSE_FAILURES_SEEN_KEY), &field, now)
+            .ignore();
+    }
+    pipe.query_async(conn).await
+}
+
+/// Feed latency percentiles of the last stats window, next to the parse failures
+pub async fn record_feed_latency(conn: &mut RedisConn, feeds: &[FeedLatency]) -> RedisResult<()> {
+    let mut fields = Vec::new();
+    for feed in feeds {
+        fields.push((format!("feed{}:p50_micros", feed.feed), feed.p50_micros));
+        fields.push((format!("feed{}:p99_micros", feed.feed), feed.p99_micros));
+        fields.push((format!("feed{}:samples", feed.feed), feed.samples));
+    }
+    if fields.is_empty() {
+        return Ok(());
+    }
+    conn.hset_multiple(redis_key(FEED_LATENCY_KEY), &fields).await
+}
+
+/// Running totals per `program:kind`
+pub async fn get_parse_failures(conn: &mut RedisConn) -> RedisResult<HashMap<String, u64>> {
+    conn.hgetall(redis_key(PARSE_FAILURES_KEY)).await
+}
+
+// ! pump.fun params
+pub async fn set_pumpfun_params(conn: &mut RedisConn, params: &PumpfunParams) -> RedisResult<()> {
+    conn.hset_multiple(
+        redis_key(PUMPFUN_PARAMS_KEY),
+        &[
+            ("fee_recipient", params.fee_recipient.to_string()),
+            ("initial_virtual_token_reserves", params.initial_virtual_token_reserves.to_string()),
+            ("initial_virtual_sol_reserves", params.initial_virtual_sol_reserves.to_string()),
+            ("initial_real_token_reserves", params.initial_real_token_reserves.to_string()),
+            ("token_total_supply", params.token_total_supply.to_string()),
+            ("fee_basis_points", params.fee_basis_points.to_string()),
+        ],
+    )
+    .await
+}
+
+/// Stored parameters, `None` before the first `SetParams` event or for an incomplete hash
+pub async fn get_pumpfun_params(conn: &mut RedisConn) -> RedisResult<Option<PumpfunParams>> {
+    let fields: HashMap<String, String> = conn.hgetall(redis_key(PUMPFUN_PARAMS_KEY)).await?;
+    let number = |name: &str| fields.get(name).and_then(|v| v.parse::<u64>().ok());
+    let params = (|| {
+        Some(PumpfunParams {
+            fee_recipient: Pubkey::from_str(fields.get("fee_recipient")?).ok()?,
+            initial_virtual_token_reserves: number("initial_virtual_token_reserves")?,
+            initial_virtual_sol_reserves: number("initial_virtual_sol_reserves")?,
+            initial_real_token_reserves: number("initial_real_token_reserves")?,
+            token_total_supply: number("token_total_supply")?,
+            fee_basis_points: number("fee_basis_points")?,
+        })
+    })();
+    Ok(params)
+}
+
+// ! thresholds
+pub async fn get_threshold_overrides(conn: &mut RedisConn) -> RedisResult<HashMap<String, String>> {
+    conn.hgetall(redis_key(THRESHOLDS_KEY)).await
+}
+
+// ! blocklist
+/// Muted mints and blacklisted creators, no alert, follow-up or digest entry goes out for their tokens
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Blocklist {
+    pub mints: HashSet<String>,
+    pub creators: HashSet<String>,
+}
+
+impl Blocklist {
+    pub fn blocks(&self, record: &TokenRecord) -> bool {
+        self.mints.contains(&record.mint) || self.creators.contains(&record.creator)
+    }
+}
+
+pub async fn blocklist(conn: &mut RedisConn) -> RedisResult<Blocklist> {
+    let (mints, creators): (HashSet<String>, HashSet<String>) =
+        redis::pipe().smembers(redis_key(MUTED_MINTS_KEY)).smembers(redis_key(BLACKLISTED_CREATORS_KEY)).query_async(conn).await?;
+    Ok(Blocklist { mints, creators })
+}
+
+/// Mute `mint`, false when it was muted before
+pub async fn mute_mint(conn: &mut RedisConn, mint: &str) -> RedisResult<bool> {
+    let added: u32 = conn.sadd(redis_key(MUTED_MINTS_KEY), mint).await?;
+    Ok(added > 0)
+}
+
+/// Blacklist `creator`, false when it was blacklisted before
+pub async fn blacklist_creator(conn: &mut RedisConn, creator: &str) -> RedisResult<bool> {
+    let added: u32 = conn.sadd(redis_key(BLACKLISTED_CREATORS_KEY), creator).await?;
+    Ok(added > 0)
+}
+
+/// Unmute the mint or unblacklist the creator `key`, false when it was neither
+pub async fn unblock(conn: &mut RedisConn, key: &str) -> RedisResult<bool> {
+    let (mints, creators): (u32, u32) =
+        redis::pipe().srem(redis_key(MUTED_MINTS_KEY), key).srem(redis_key(BLACKLISTED_CREATORS_KEY), key).query_async(conn).await?;
+    Ok(mints + creators > 0)
+}
+
+// ! subscriptions
+/// Filters of a user's DM subscription, `/subscribe min_mcap=80000 socials=yes launchpad=pumpfun`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Subscription {
+    /// lowest alert tier the user gets, 0 for every tier
+    pub min_market_cap: f32,
+    /// only tokens whose metadata links a website, X or Telegram
+    pub socials: bool,
+    /// launchpads (`pumpfun`, `moonshot`, `boop`) the token must come from, empty for any
+    pub launchpads: Vec<String>,
+}
+
+impl Subscription {
+    /// Whether an alert at `tier` for `record` goes to the subscriber, the socials are checked
+    /// when the alert is sent
+    pub fn accepts(&self, tier: f32, record: &TokenRecord) -> bool {
+        tier >= self.min_market_cap
+            && (self.launchpads.is_empty() || self.launchpads.iter().any(|launchpad| launchpad == record.launchpad().as_str()))
+    }
+}
+
+/// Subscriptions by chat id, unreadable ones are skipped
+pub async fn subscribers(conn: &mut RedisConn) -> RedisResult<HashMap<i64, Subscription>> {
+    let result: HashMap<i64, String> = conn.hgetall(redis_key(SUBSCRIBERS_KEY)).await?;
+    Ok(result
+        .into_iter()
+        .filter_map(|(chat_id, value)| Some((chat_id, serde_json::from_str(&value).ok()?)))
+        .collect())
+}
+
+/// Subscription of the chat `chat_id`, `None` when it has none
+pub async fn get_subscription(conn: &mut RedisConn, chat_id: i64) -> RedisResult<Option<Subscription>> {
+    let value: Option<String> = conn.hget(redis_key(SUBSCRIBERS_KEY), chat_id).await?;
+    Ok(value.and_then(|value| serde_json::from_str(&value).ok()))
+}
+
+/// Subscribe the chat `chat_id` with `subscription`, replacing its filters
+pub async fn subscribe(conn: &mut RedisConn, chat_id: i64, subscription: &Subscription) -> RedisResult<()> {
+    let value = serde_json::to_string(subscription).unwrap_or_default();
+    conn.hset(redis_key(SUBSCRIBERS_KEY), chat_id, value).await
+}
+
+/// Drop the subscription of the chat `chat_id`, false when it had none
+pub async fn unsubscribe(conn: &mut RedisConn, chat_id: i64) -> RedisResult<bool> {
+    let removed: u32 = conn.hdel(redis_key(SUBSCRIBERS_KEY), chat_id).await?;
+    Ok(removed > 0)
+}
+
+// ! token records
+/// Tracked token, stored as JSON in `token_info_set` under its mint
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TokenRecord {
+    pub mint: String,
+    pub market_cap: f32,
+    /// unix milliseconds
+    pub create_time: u64,
+    pub name: String,
+    pub symbol: String,
+    pub uri: String,
+    pub creator: String,
+    pub bonding_curve: String,
+    /// PumpSwap pool after graduation, empty before
+    pub pool: String,
+    /// highest alert tier reached
+    pub tier: f32,
+    /// create transaction
+    pub signature: String,
+    /// token program id of the mint, empty for SPL Token
+    pub token_program: String,
+    /// highest market cap seen and when (unix milliseconds)
+    pub ath_market_cap: f32,
+    pub ath_time: u64,
+    /// wallets holding the token, kept with `engine.spl_transfers`
+    pub holders: u64,
+    /// distinct wallets that bought the token (estimate), kept with `engine.buyers`
+    pub buyers: u64,
+    /// slot of the create transaction, 0 for records of older versions
+    pub create_slot: u64,
+    /// first buyers captured with `engine.first_buyers` and how many of them bought in `create_slot`
+    pub first_buyers: u64,
+    pub same_block_buyers: u64,
+    /// creator's share of the supply in percent, kept with `engine.dev_holdings`
+    pub dev_percent: f32,
+    /// the creator sold some of the token
+    pub dev_sold: bool,
+    /// last dev balance update, unix milliseconds, 0 when the dev is not tracked
+    pub dev_updated: u64,
+    /// launchpad the token was created on, `None` for records of older versions
+    pub program: Option<Program>,
+}
+
+impl TokenRecord {
+    pub fn new(ctx: &EventContext, program: Program, create: &CreateEvent) -> Self {
+        Self {
+            mint: create.mint.to_string(),
+            create_time: timestamp(),
+            name: create.name.clone(),
+            symbol: create.symbol.clone(),
+            uri: create.uri.clone(),
+            creator: create.user.to_string(),
+            bonding_curve: create.bonding_curve.to_string(),
+            signature: ctx.signature.clone(),
+            token_program: ctx.token_program.clone(),
+            create_slot: ctx.slot,
+            program: Some(program),
+            ..Default::default()
+        }
+    }
+
+    /// JSON, or the `mint|mk|create_time|name|symbol|uri|user|bonding_curve|pool[|tier|signature|token_program]`
+    /// values written by older versions
+    pub fn decode(value: &str) -> Option<Self> {
+        if value.starts_with('{') {
+            return serde_json::from_str(value).ok();
+        }
+        let splits: Vec<_> = value.split('|').collect();
+        // the lengths the pipe writers produced: 9 fields, then the tier (10), the signature (11)
+        // and the token program (12). A `|` in the name or symbol shifted the fields, the base58
+        // pool or curve then sits where the tier is and does not parse.
+        let tier = match splits.len() {
+            9 => 0.0,
+            10..=12 => splits[9].parse().ok()?,
+            _ => return None,
+        };
+        let field = |index: usize| splits.get(index).copied().unwrap_or("").to_string();
+        Some(Self {
+            mint: field(0),
+            market_cap: splits[1].parse().ok()?,
+            create_time: splits[2].parse().ok()?,
+            name: field(3),
+            symbol: field(4),
+            uri: field(5),
+            creator: field(6),
+            bonding_curve: field(7),
+            pool: field(8),
+            tier,
+            signature: field(10),
+            token_program: field(11),
+            ..Default::default()
+        })
+    }
+
+    pub fn encode(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+
+    pub fn token_program(&self) -> Pubkey {
+        parse_token_program(&self.token_program)
+    }
+
+    /// Launchpad the token was created on; older records are told apart by the boop.fun bonding curve
+    pub fn launchpad(&self) -> Program {
+        if let Some(program) = self.program {
+            return program;
+        }
+        match Pubkey::from_str(&self.mint) {
+            Ok(mint) if find_boop_bonding_curve(&mint).to_string() == self.bonding_curve => Program::Boop,
+            _ => Program::Pumpfun,
+        }
+    }
+
+    /// Set the current market cap, raising the all-time high when it is exceeded
+    pub fn update_market_cap(&mut self, market_cap: f32, now: u64) {
+        self.market_cap = market_cap;
+        if market_cap > self.ath_market_cap {
+            self.ath_market_cap = market_cap;
+            self.ath_time = now;
+        }
+    }
+
+    /// How far the market cap is below its all-time high, in percent
+    pub fn drawdown(&self) -> f32 {
+        if self.ath_market_cap <= 0.0 {
+            return 0.0;
+        }
+        ((self.ath_market_cap - self.market_cap) / self.ath_market_cap * 100.0).max(0.0)
+    }
+
+    /// All-time high for alerts, e.g. `420 SOL, -25% from ATH`, empty at the high
+    pub fn ath(&self) -> String {
+        if self.ath_market_cap > self.market_cap {
+            format!("{} SOL, -{:.0}% from ATH", self.ath_market_cap, self.drawdown())
+        } else {
+            String::new()
+        }
+    }
+
+    /// Buyer and holder counts for alerts, e.g. `120 buyers, 85 holders`, empty when neither is kept
+    pub fn traders(&self) -> String {
+        match (self.buyers, self.holders) {
+            (0, 0) => String::new(),
+            (buyers, 0) => format!("{} buyers", buyers),
+            (0, holders) => format!("{} holders", holders),
+            (buyers, holders) => format!("{} buyers, {} holders", buyers, holders),
+        }
+    }
+
+    /// Dev holdings for alerts, e.g. `holds 4.2%` or `sold, holds 0.0%`, empty when not tracked
+    pub fn dev_holdings(&self) -> String {
+        match (self.dev_updated, self.dev_sold) {
+            (0, _) => String::new(),
+            (_, false) => format!("holds {:.1}%", self.dev_percent),
+            (_, true) => format!("sold, holds {:.1}%", self.dev_percent),
+        }
+    }
+
+    /// Early buyers in the create block for alerts, e.g. `3 of first 20 in the create block`,
+    /// empty when first buyers are not captured
+    pub fn same_block(&self) -> String {
+        if self.first_buyers == 0 {
+            return String::new();
+        }
+        format!("{} of first {} in the create block", self.same_block_buyers, self.first_buyers)
+    }
+
+    /// The bonding curve or the pool, accounts that hold the unsold supply
+    pub fn is_reserve(&self, owner: &str) -> bool {
+        owner == self.bonding_curve || (!self.pool.is_empty() && owner == self.pool)
+    }
+}
+
+pub async fn add_token_info(
+    conn: &mut RedisConn, 
+    ctx: &EventContext,
+    program: Program,
+    create: &CreateEvent,
+) -> RedisResult<()> {
+    let record = TokenRecord::new(ctx, program, create);
+
+    info!("create token info: {} | {} | {} | {} | {} ", record.mint, record.create_time, record.name, record.symbol, record.creator);  
+
+    if program != Program::Moonshot {
+        return set_token_record(conn, &record).await;
+    }
+    // moonshot trades are counted per curve from the create on
+    let mut pipe = redis::pipe();
+    pipe.atomic();
+    match token_store() {
+        // the curve index and counts stay in redis
+        Some(store) => store.set_token(&record).await.map_err(store_error)?,
+        None => {
+            pipe.hset(redis_key(TOKEN_SET_KEY), &record.mint, record.encode()).ignore();
+        }
+    }
+    pipe.hset(redis_key(CURVE_INDEX_KEY), &record.bonding_curve, &record.mint)
+        .ignore()
+        .hset(redis_key(CURVE_SOLD_KEY), &record.bonding_curve, 0)
+        .ignore();
+    pipe.query_async(conn).await
+}
+
+pub async fn set_token_record(conn: &mut RedisConn, record: &TokenRecord) -> RedisResult<()> {
+    if let Some(store) = token_store() {
+        return store.set_token(record).await.map_err(store_error);
+    }
+    conn.hset(redis_key(TOKEN_SET_KEY), &record.mint, record.encode()).await
+}
+
+/// Record of `mint`, `None` for an untracked token or an unreadable value
+pub async fn get_token_record(conn: &mut RedisConn, mint: &str) -> RedisResult<Option<TokenRecord>> {
+    if let Some(store) = token_store() {
+        return store.token(mint).await.map_err(store_error);
+    }
+    let info: Option<String> = conn.hget(redis_key(TOKEN_SET_KEY), mint).await?;
+    Ok(info.as_deref().and_then(TokenRecord::decode))
+}
+
+/// All readable records by mint
+pub async fn token_records(conn: &mut RedisConn) -> RedisResult<HashMap<String, TokenRecord>> {
+    if let Some(store) = token_store() {
+        let records = store.tokens().await.map_err(store_error)?;
+        return Ok(records.into_iter().map(|record| (record.mint.clone(), record)).collect());
+    }
+    let result = conn.hgetall::<_, HashMap<String, String>>(redis_key(TOKEN_SET_KEY)).await?;
+    Ok(result
+        .into_iter()
+        .filter_map(|(mint, info)| Some((mint, TokenRecord::decode(&info)?)))
+        .collect())
+}
+
+/// Rewrite the pipe-delimited records of older versions as JSON, returns how many were converted;
+/// nothing to do with a token store
+pub async fn migrate_token_records(conn: &mut RedisConn) -> RedisResult<usize> {
+    if token_store().is_some() {
+        return Ok(0);
+    }
+    let result = conn.hgetall::<_, HashMap<String, String>>(redis_key(TOKEN_SET_KEY)).await?;
+    let mut migrated = 0;
+    for (mint, info) in result {
+        if info.starts_with('{') {
+            continue;
+        }
+        match TokenRecord::decode(&info) {
+            Some(record) => {
+                conn.hset::<_, _, _, ()>(redis_key(TOKEN_SET_KEY), &mint, record.encode()).await?;
+                migrated += 1;
+            }
+            None => debug!("skip unreadable token record {}: {}", mint, info),
+        }
+    }
+    Ok(migrated)
+}
+
+/// Rebuild the pool and curve indexes and the market cap ranking from all records,
+/// for records written before they existed; returns the number of records
+pub async fn rebuild_token_indexes(conn: &mut RedisConn) -> RedisResult<usize> {
+    let records = token_records(conn).await?;
+    if token_store().is_some() {
+        // the store keeps its own pool index and ranks by the records, only the curves are in redis
+        let curves: Vec<(&str, &str)> = records
+            .iter()
+            .filter(|(_, record)| record.launchpad() == Program::Moonshot)
+            .map(|(mint, record)| (record.bonding_curve.as_str(), mint.as_str()))
+            .collect();
+        if !curves.is_empty() {
+            conn.hset_multiple::<_, _, _, ()>(redis_key(CURVE_INDEX_KEY), &curves).await?;
+        }
+        return Ok(records.len());
+    }
+    let pools: Vec<(&str, &str)> = records
+        .iter()
+        .filter(|(_, record)| !record.pool.is_empty())
+        .map(|(mint, record)| (record.pool.as_str(), mint.as_str()))
+        .collect();
+    let curves: Vec<(&str, &str)> = records
+        .iter()
+        .filter(|(_, record)| record.launchpad() == Program::Moonshot)
+        .map(|(mint, record)| (record.bonding_curve.as_str(), mint.as_str()))
+        .collect();
+    let ranks: Vec<(f32, &str)> = records.iter().map(|(mint, record)| (record.market_cap, mint.as_str())).collect();
+    let mut pipe = redis::pipe();
+    pipe.atomic().del(redis_key(MARKET_CAP_RANK_KEY)).ignore();
+    if !pools.is_empty() {
+        pipe.hset_multiple(redis_key(POOL_INDEX_KEY), &pools).ignore();
+    }
+    if !curves.is_empty() {
+        pipe.hset_multiple(redis_key(CURVE_INDEX_KEY), &curves).ignore();
+    }
+    if !ranks.is_empty() {
+        pipe.zadd_multiple(redis_key(MARKET_CAP_RANK_KEY), &ranks).ignore();
+    }
+    pipe.query_async::<()>(conn).await?;
+    Ok(records.len())
+}
+
+/// Tracked tokens with the highest market caps, highest first
+pub async fn top_tokens(conn: &mut RedisConn, limit: usize) -> RedisResult<Vec<TokenRecord>> {
+    if limit == 0 {
+        return Ok(vec![]);
+    }
+    if let Some(store) = token_store() {
+        let mut records = store.tokens().await.map_err(store_error)?;
+        records.sort_by(|a, b| b.market_cap.total_cmp(&a.market_cap));
+        records.truncate(limit);
+        return Ok(records);
+    }
+    let mints: Vec<String> = conn.zrevrange(redis_key(MARKET_CAP_RANK_KEY), 0, limit as isize - 1).await?;
+    if mints.is_empty() {
+        return Ok(vec![]);
+    }
+    let records: Vec<Option<String>> = redis::cmd("HMGET").arg(redis_key(TOKEN_SET_KEY)).arg(&mints).query_async(conn).await?;
+    Ok(records.iter().filter_map(|info| TokenRecord::decode(info.as_deref()?)).collect())
 }
 
-pub async fn add_token_info(
-    conn: &mut MultiplexedConnection, 
-    create: &CreateEvent,
-) -> RedisResult<()> {
-    // info = mint|mk|create_time|token_name|token_symbol|token_uri|user|bonding_curve|pool
-    let info = format!("{}|{}|{}|{}|{}|{}|{}|{}|{}", create.mint, 0, timestamp(), create.name, create.symbol, create.uri, create.user.to_string(), create.bonding_curve.to_string(), "".to_string());
-    let mint = format!("{}", create.mint.to_string());
-
-    info!("create token info: {} | {} | {} | {} | {} ", mint,  timestamp(), create.name, create.symbol, create.user.to_string());  
+/// Mint of the token traded in `pool`, empty when the pool is not tracked
+pub async fn from_pool_query_token_mint(conn: &mut RedisConn, pool: &str) -> RedisResult<String> {
+    if let Some(store) = token_store() {
+        return Ok(store.token_by_pool(pool).await.map_err(store_error)?.unwrap_or_default());
+    }
+    let mint: Option<String> = conn.hget(redis_key(POOL_INDEX_KEY), pool).await?;
+    Ok(mint.unwrap_or_default())
+}
 
-    conn.hset(TOKEN_SET_KEY, mint, info)
-        .await
+// store `record` with `pool`, keeping the pool index in step
+async fn set_record_pool(conn: &mut RedisConn, mut record: TokenRecord, pool: &str) -> RedisResult<()> {
+    // the store keeps the pool index with the record
+    if record.pool == pool || token_store().is_some() {
+        record.pool = pool.to_string();
+        return set_token_record(conn, &record).await;
+    }
+    let mut pipe = redis::pipe();
+    pipe.atomic();
+    queue_record_pool(&mut pipe, &mut record, pool);
+    pipe.hset(redis_key(TOKEN_SET_KEY), &record.mint, record.encode()).ignore();
+    pipe.query_async(conn).await
 }
 
-pub async fn query_token_info(conn: &mut MultiplexedConnection, mint: &str) -> RedisResult<String> {
-    match conn.hget::<_, _, String>(TOKEN_SET_KEY, mint).await {
-        Ok(info) => Ok(info),
-        Err(e) => Err(e),
+// move `record` to `pool` in the pool index
+fn queue_record_pool(pipe: &mut redis::Pipeline, record: &mut TokenRecord, pool: &str) {
+    if record.pool == pool {
+        return;
+    }
+    if !record.pool.is_empty() {
+        pipe.hdel(redis_key(POOL_INDEX_KEY), &record.pool).ignore();
     }
+    if !pool.is_empty() {
+        pipe.hset(redis_key(POOL_INDEX_KEY), pool, &record.mint).ignore();
+    }
+    record.pool = pool.to_string();
 }
 
-pub async fn from_pool_query_token_mint(conn: &mut MultiplexedConnection, pool: &str) -> RedisResult<String> {
-    
-    match conn.hgetall::<_, HashMap<String, String>>(TOKEN_SET_KEY).await {
-        Ok(result) => {
-            for (mint, info) in result {
-                let splits: Vec<_> = info.split("|").collect();
-                if splits.len() > 8 && splits[8] == pool.to_string() {
-                    return Ok(mint.to_string());
-                } 
-            }
-            Ok("".to_string())
-        },
-        Err(e) => Err(e),
+// remove the records of `mints` together with their pool index and curve entries
+async fn remove_token_records(conn: &mut RedisConn, mints: &[String]) -> RedisResult<()> {
+    if mints.is_empty() {
+        return Ok(());
+    }
+    let records = match token_store() {
+        // the store drops its pool index with the records
+        Some(store) => store.remove_tokens(mints).await.map_err(store_error)?,
+        None => {
+            // explicit HMGET, `hget` sends a single field as HGET
+            let records: Vec<Option<String>> =
+                redis::cmd("HMGET").arg(redis_key(TOKEN_SET_KEY)).arg(mints).query_async(conn).await?;
+            records.iter().filter_map(|info| TokenRecord::decode(info.as_deref()?)).collect()
+        }
+    };
+    let pools: Vec<&str> = records.iter().map(|record| record.pool.as_str()).filter(|pool| !pool.is_empty()).collect();
+    if !pools.is_empty() && token_store().is_none() {
+        conn.hdel::<_, _, ()>(redis_key(POOL_INDEX_KEY), pools).await?;
+    }
+    // moonshot counts the tokens sold per curve, boop per mint
+    let curves: Vec<&str> = records.iter().map(|record| record.bonding_curve.as_str()).collect();
+    conn.hdel::<_, _, ()>(redis_key(CURVE_SOLD_KEY), mints).await?;
+    conn.zrem::<_, _, ()>(redis_key(MARKET_CAP_RANK_KEY), mints).await?;
+    if !curves.is_empty() {
+        conn.hdel::<_, _, ()>(redis_key(CURVE_INDEX_KEY), &curves).await?;
+        conn.hdel::<_, _, ()>(redis_key(CURVE_SOLD_KEY), curves).await?;
     }
+    conn.hdel(redis_key(TOKEN_SET_KEY), mints).await
+}
+
+/// Mint of the tracked Moonshot token traded on `curve`, empty when the curve is not tracked
+pub async fn from_curve_query_token_mint(conn: &mut RedisConn, curve: &str) -> RedisResult<String> {
+    let mint: Option<String> = conn.hget(redis_key(CURVE_INDEX_KEY), curve).await?;
+    Ok(mint.unwrap_or_default())
 }
 
+/// Move the tokens sold by a curve by `delta`, returns the new total
+pub async fn add_tokens_sold(conn: &mut RedisConn, curve: &str, delta: i64) -> RedisResult<u64> {
+    let sold: i64 = conn.hincr(redis_key(CURVE_SOLD_KEY), curve, delta).await?;
+    Ok(sold.max(0) as u64)
+}
 
+/// Store a token's market cap, false when the token is not tracked
 pub async fn update_mk(
-    conn: &mut MultiplexedConnection,
+    conn: &mut RedisConn,
     mint: &str,
     market_cap: f64,
     pool: &str,
-) -> RedisResult<()> { 
-    match conn.hget::<_, _, String>(TOKEN_SET_KEY, mint).await {
-        Ok(old_info) => {
-            let splits: Vec<_> = old_info.split("|").collect();
+) -> RedisResult<bool> {
+    let update = MarketCapUpdate::new(mint, 0.0, market_cap, pool, timestamp());
+    Ok(!update_market_caps(conn, &[update], 0).await?.is_empty())
+}
+
+// 在redis内读改写, the record is read and written inside redis so a concurrent
+// writer can't clobber it and a record removed by `check_mk` stays removed.
+// KEYS: records, pool index, rank; ARGV: mint, market cap, high, high time, pool per update,
+// an empty pool keeps the stored one. Returns the mints that have a record.
+static UPDATE_MARKET_CAPS: Lazy<redis::Script> = Lazy::new(|| {
+    redis::Script::new(
+        r#"
+local updated = {}
+for i = 1, #ARGV, 5 do
+    local mint = ARGV[i]
+    local raw = redis.call('HGET', KEYS[1], mint)
+    local ok, record = false, nil
+    if raw then
+        ok, record = pcall(cjson.decode, raw)
+    end
+    if ok and type(record) == 'table' then
+        local market_cap = tonumber(ARGV[i + 1])
+        local high = tonumber(ARGV[i + 2])
+        if high > (tonumber(record.ath_market_cap) or 0) then
+            record.ath_market_cap = high
+            record.ath_time = tonumber(ARGV[i + 3])
+        end
+        record.market_cap = market_cap
+        local pool = ARGV[i + 4]
+        local old = record.pool or ''
+        if pool ~= '' and old ~= pool then
+            if old ~= '' then
+                redis.call('HDEL', KEYS[2], old)
+            end
+            redis.call('HSET', KEYS[2], pool, mint)
+            record.pool = pool
+        end
+        redis.call('HSET', KEYS[1], mint, cjson.encode(record))
+        redis.call('ZADD', KEYS[3], market_cap, mint)
+        updated[#updated + 1] = mint
+    end
+end
+return updated
+"#,
+    )
+});
+
+/// Market cap updates of one token collected between two writes
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct MarketCapUpdate {
+    pub mint: String,
+    /// latest price and market cap
+    pub price: f64,
+    pub market_cap: f64,
+    pub pool: String,
+    /// highest market cap of the batch and when it was seen, for the all-time high
+    pub high: f64,
+    pub high_time: u64,
+}
+
+impl MarketCapUpdate {
+    pub fn new(mint: &str, price: f64, market_cap: f64, pool: &str, now: u64) -> Self {
+        Self { mint: mint.to_string(), price, market_cap, pool: pool.to_string(), high: market_cap, high_time: now }
+    }
+
+    /// Fold a later update of the same token into this one
+    pub fn merge(&mut self, later: MarketCapUpdate) {
+        if later.high > self.high {
+            self.high = later.high;
+            self.high_time = later.high_time;
+        }
+        self.price = later.price;
+        self.market_cap = later.market_cap;
+        // bonding curve trades carry no pool, keep the one seen before
+        if !later.pool.is_empty() {
+            self.pool = later.pool;
+        }
+    }
+
+    /// Apply the update to `record` like `UPDATE_MARKET_CAPS` does in redis
+    pub fn apply(&self, record: &mut TokenRecord) {
+        if self.high as f32 > record.ath_market_cap {
+            record.ath_market_cap = self.high as f32;
+            record.ath_time = self.high_time;
+        }
+        record.market_cap = self.market_cap as f32;
+        if !self.pool.is_empty() {
+            record.pool = self.pool.clone();
+        }
+    }
+}
+
+/// Apply a batch of updates in one script call, untracked tokens are skipped; returns the
+/// mints updated. `price_history` is the retention of their price points, 0 writes none
+pub async fn update_market_caps(
+    conn: &mut RedisConn,
+    updates: &[MarketCapUpdate],
+    price_history: u64,
+) -> RedisResult<Vec<String>> {
+    if updates.is_empty() {
+        return Ok(vec![]);
+    }
+    let updated: Vec<String> = match token_store() {
+        Some(store) => store.update_market_caps(updates).await.map_err(store_error)?,
+        None => {
+            let mut invocation = UPDATE_MARKET_CAPS.key(redis_key(TOKEN_SET_KEY));
+            invocation.key(redis_key(POOL_INDEX_KEY)).key(redis_key(MARKET_CAP_RANK_KEY));
+            for update in updates {
+                invocation
+                    .arg(&update.mint)
+                    .arg(update.market_cap as f32)
+                    .arg(update.high as f32)
+                    .arg(update.high_time)
+                    .arg(&update.pool);
+            }
+            invocation.invoke_async(conn).await?
+        }
+    };
+    if price_history > 0 && !updated.is_empty() {
+        let mut pipe = redis::pipe();
+        for update in updates.iter().filter(|update| updated.contains(&update.mint)) {
+            queue_price_point(&mut pipe, &update.mint, update.price, update.market_cap, price_history);
+        }
+        pipe.query_async::<()>(conn).await?;
+    }
+    Ok(updated)
+}
 
-            let (mint, create_time) = (splits[0], splits[2]);
-            let new_info = format!("{}|{}|{}|{}|{}|{}|{}|{}|{}", mint, market_cap.to_string(), create_time, splits[3], splits[4], splits[5], splits[6], splits[7], pool.to_string());
-            conn.hset(TOKEN_SET_KEY, mint, new_info).await
-        } 
-        Err(_) => Ok(()), 
+/// Point a token's record at the PumpSwap pool it migrated to, so pool events find the mint
+pub async fn set_token_pool(conn: &mut RedisConn, mint: &str, pool: &str) -> RedisResult<()> {
+    if let Some(store) = token_store() {
+        let pool = pool.to_string();
+        store.update_token(mint, Box::new(move |record| record.pool = pool)).await.map_err(store_error)?;
+        return Ok(());
     }
+    let Some(record) = get_token_record(conn, mint).await? else {
+        return Ok(());
+    };
+    set_record_pool(conn, record, pool).await
 }
 
-pub async fn check_mk(conn: &mut MultiplexedConnection, instance: BotInstance, x_instance: XClient) -> RedisResult<()> {
-    match conn
-        .hgetall::<'_, _, HashMap<String, String>>(TOKEN_SET_KEY)
-        .await
-    {
+// ! tiers
+/// Store the highest tier a token has reached in its record
+pub async fn update_tier(conn: &mut RedisConn, mint: &str, tier: f32) -> RedisResult<()> {
+    let Some(mut record) = get_token_record(conn, mint).await? else {
+        return Ok(());
+    };
+    if record.tier >= tier {
+        return Ok(());
+    }
+    record.tier = tier;
+    set_token_record(conn, &record).await
+}
+
+/// `publish` also sends every alert decision to the `alerts:*` Pub/Sub channels
+pub async fn check_mk(conn: &mut RedisConn, notifier: Notifier, social: SocialChain, ai: AiClient, alert: &AlertConfig, publish: bool) -> RedisResult<()> {
+    match token_records(conn).await {
         Ok(result) => {
+            // the numbers of this check, the alert edits of a later one win over them
+            let checked_at = timestamp();
             let mut tokens_to_exist = result.clone();
-            for (_, info) in result {
-                let splits: Vec<_> = info.split("|").collect();
-                if splits.len() != 9 {
+            let mut expired = Vec::new();
+            // 归零的币, alerted tokens that collapsed get a last follow-up
+            let mut rugs = Vec::new();
+            for (mint, record) in result {
+                let (mk, create_time) = (record.market_cap, record.create_time);
+
+                if alert.record_ttl > 0 && create_time + alert.record_ttl <= timestamp() {
+                    tokens_to_exist.remove(&mint);
+                    expired.push(mint);
                     continue;
                 }
-                let (mint, mk, create_time, _, _, _, _, _, _pool) = (
-                    splits[0], 
-                    splits[1].parse::<f32>().unwrap(),
-                    splits[2].parse::<u64>().unwrap(),  
-                    splits[3], 
-                    splits[4],
-                    splits[5],
-                    splits[6],
-                    splits[7],
-                    splits[8],
-                ); 
                 
-                // 只在NEW_COIN_MIN_TIME和NEW_COIN_MAX_TIME之间检查市值
+                // 只在min_age和max_age之间检查市值
                 let is_mid_age_coin = 
-                    create_time + NEW_COIN_MIN_TIME <= timestamp() && 
-                    create_time + NEW_COIN_MAX_TIME > timestamp();
+                    create_time + alert.min_age <= timestamp() && 
+                    create_time + alert.max_age > timestamp();
                 
-                let has_enough_market_cap = mk >= *MARKET_CAP;
+                let has_enough_market_cap = mk >= alert.market_cap;
 
                 if !has_enough_market_cap {
                     if is_mid_age_coin {
+                        // before its alerts are removed with it
+                        rugs.extend(rug_followup(conn, alert, &record).await?);
                         // Remove token from Redis hash set
-                        conn.hdel(TOKEN_SET_KEY, mint).await?;
+                        remove_tokens(conn, std::slice::from_ref(&mint)).await?;
                         
                         // Remove from local tracking collection
-                        tokens_to_exist.remove(&mint.to_string());
+                        tokens_to_exist.remove(&mint);
                         
                         info!("Remove token from Redis: {} | {} | {}", mint, timestamp(), mk);
+                        if publish {
+                            let message = json!({ "mint": mint, "reason": "low_market_cap", "market_cap": mk });
+                            publish_alert(conn, ALERTS_REMOVED_CHANNEL, &message).await?;
+                        }
+                    }
+                }
+            }
+
+            if !expired.is_empty() {
+                remove_tokens(conn, &expired).await?;
+                info!("Removed {} expired tokens from Redis", expired.len());
+                if publish {
+                    for mint in &expired {
+                        publish_alert(conn, ALERTS_REMOVED_CHANNEL, &json!({ "mint": mint, "reason": "expired" })).await?;
                     }
                 }
             }
 
             // Prepare tokens to process
+            let tiers = notifier.tiers(alert);
+            let blocklist = blocklist(conn).await?;
+            let all_subscribers = if notifier.subscriptions() { subscribers(conn).await? } else { HashMap::new() };
             let mut tokens_to_process = Vec::new();
+            // 已发过的 alert 原地更新, tokens with a tier were alerted before
+            let mut updates = Vec::new();
+            let mut fired = Vec::new();
+            let alerted: Vec<(String, TokenRecord)> = if alert.live_updates || alert.followups {
+                tokens_to_exist.iter().filter(|(_, record)| record.tier > 0.0).map(|(mint, record)| (mint.clone(), record.clone())).collect()
+            } else {
+                Vec::new()
+            };
             
-            for (mint, info) in tokens_to_exist { 
-                let splits: Vec<_> = info.as_str().split("|").collect();
-                if splits.len() != 9 {
+            for (mint, record) in tokens_to_exist { 
+                let (mk, create_time) = (record.market_cap, record.create_time);
+                if mk > 0.0 {
+                    info!("checking ======> mint: {} | create_time: {} | mk: {}", mint, create_time, mk);
+                }
+
+                if !tiers.iter().any(|tier| mk >= *tier) {
+                    continue;
+                }
+                // muted or from a blacklisted creator, its tiers stay open for an unmute
+                if blocklist.blocks(&record) {
+                    if publish {
+                        let message = json!({ "mint": mint, "reason": "blocked", "market_cap": mk, "creator": record.creator });
+                        publish_alert(conn, ALERTS_HELD_CHANNEL, &message).await?;
+                    }
                     continue;
                 }
-                let (_, _, create_time, _, _, _, _, _, _) = (
-                    splits[0], 
-                    splits[1].parse::<f32>().unwrap(),
-                    splits[2].parse::<u64>().unwrap(), 
-                    splits[3],
-                    splits[4],
-                    splits[5],
-                    splits[6],
-                    splits[7],
-                    splits[8],
-                ); 
-                if splits[1].parse::<f32>().unwrap() > 0.0 {
-                    info!("checking ======> mint: {} | create_time: {} | mk: {}", mint, create_time, splits[1]);
+                let volume = token_volume(conn, &mint).await?;
+                // volume rules hold the alert back without using up its tier
+                if !alert.volume_ok(&volume.m5) {
+                    if publish {
+                        let message = json!({
+                            "mint": mint,
+                            "reason": "volume",
+                            "market_cap": mk,
+                            "volume_5m": volume.m5.total(),
+                            "buy_ratio_5m": volume.m5.buy_ratio(),
+                        });
+                        publish_alert(conn, ALERTS_HELD_CHANNEL, &message).await?;
+                    }
+                    continue;
+                }
+
+                // Every tier crossed is marked as fired; when several tiers are crossed
+                // in one cycle only the highest one is announced
+                let now = timestamp();
+                let mut state = alert_state(conn, &mint).await?;
+                let new_tier = state.next_tier(&tiers, mk);
+                if new_tier.is_some() && state.in_cooldown(now) {
+                    // 冷却期内不发, the tier stays open and fires once the cooldown is over
+                    if publish {
+                        let message = json!({
+                            "mint": mint,
+                            "reason": "cooldown",
+                            "market_cap": mk,
+                            "cooldown_until": state.cooldown_until,
+                        });
+                        publish_alert(conn, ALERTS_HELD_CHANNEL, &message).await?;
+                    }
+                    continue;
                 }
-                // Check if token alert has already been sent
-                let mint_warning = format!("token_alert_sent:{}", mint);
-                if !is_token_alert_sent(conn, &mint_warning).await? && splits[1].parse::<f32>().unwrap() > *MARKET_CAP {
-                    // Mark as sent
-                    mark_token_alert_sent(conn, &mint_warning).await?;
+
+                if let Some(tier) = new_tier {
+                    // no chat takes the alert yet, the tier stays open like with the volume rules
+                    let mut routes = notifier.routes_for(tier, &record, &volume.m5);
+                    let subscribers: Vec<(i64, Subscription)> = all_subscribers
+                        .iter()
+                        .filter(|(_, subscription)| subscription.accepts(tier, &record))
+                        .map(|(chat_id, subscription)| (*chat_id, subscription.clone()))
+                        .collect();
+                    if routes.is_empty() && subscribers.is_empty() {
+                        if publish {
+                            let message = json!({ "mint": mint, "reason": "routes", "market_cap": mk, "tier": tier });
+                            publish_alert(conn, ALERTS_HELD_CHANNEL, &message).await?;
+                        }
+                        continue;
+                    }
+                    state.fire(&tiers, mk, now, alert.cooldown);
+                    set_alert_state(conn, &mint, &state, alert.record_ttl).await?;
+                    if tier > record.tier {
+                        update_tier(conn, &mint, tier).await?;
+                    }
+                    let change = if alert.change_window > 0 { price_change(conn, &mint, alert.change_window).await? } else { None };
+                    if publish {
+                        let message = json!({
+                            "mint": mint,
+                            "name": record.name,
+                            "symbol": record.symbol,
+                            "tier": tier,
+                            "market_cap": mk,
+                            "price_change": change,
+                            "buyers": record.buyers,
+                            "holders": record.holders,
+                            "first_buyers": record.first_buyers,
+                            "same_block_buyers": record.same_block_buyers,
+                            "dev_percent": record.dev_percent,
+                            "dev_sold": record.dev_sold,
+                            "routes": routes.iter().map(|(route, _)| route.name.as_str()).collect::<Vec<_>>(),
+                            "subscribers": subscribers.len(),
+                        });
+                        publish_alert(conn, ALERTS_SENT_CHANNEL, &message).await?;
+                    }
+                    // 汇总模式不单独发, the token waits for the next digest; subscribers still get it now
+                    if alert.digest_interval > 0 {
+                        if !routes.is_empty() {
+                            let names = routes.iter().map(|(route, _)| route.name.clone()).collect();
+                            add_digest_token(conn, &mint, tier, names).await?;
+                        }
+                        if subscribers.is_empty() {
+                            continue;
+                        }
+                        routes.clear();
+                    }
+                    // 静默时段暂存, chats in quiet hours with `quiet_hold` get the alert when they end
+                    let (held, mut routes): (Vec<_>, Vec<_>) =
+                        routes.into_iter().partition(|(route, _)| route.filter.quiet_hold && route.filter.silences(tier, now));
+                    for (route, _) in &held {
+                        hold_quiet_alert(conn, &route.name, &mint, tier).await?;
+                    }
+                    if routes.is_empty() && subscribers.is_empty() {
+                        continue;
+                    }
+                    // chats that already have an alert get it edited, the others a new one
+                    if alert.live_updates {
+                        if let Some(messages) = alert_messages(conn, &mint).await? {
+                            routes.retain(|(route, _)| !messages.messages.iter().any(|(name, _)| name == &route.name));
+                            updates.push(AlertUpdate { record: record.clone(), tier: Some(tier), followup: None, edit: true });
+                            fired.push(mint.clone());
+                            if routes.is_empty() && subscribers.is_empty() {
+                                continue;
+                            }
+                        }
+                    }
                     // Add to processing list
-                    tokens_to_process.push((mint, info));
+                    tokens_to_process.push((mint, record, tier, change, volume, routes, subscribers));
+                }
+            }
+
+            for (mint, record) in alerted {
+                if fired.contains(&mint) || blocklist.blocks(&record) {
+                    continue;
+                }
+                if let Some(rug) = rug_followup(conn, alert, &record).await? {
+                    rugs.push(rug);
+                    continue;
+                }
+                let Some(messages) = alert_messages(conn, &mint).await? else {
+                    continue;
+                };
+                // every multiple reached is marked, the highest one is announced
+                let mut reached = None;
+                if alert.followups {
+                    for multiple in messages.multiples_reached(record.market_cap, &alert.followup_multiples) {
+                        let followup = FollowupAlert::Multiple { multiple, market_cap: record.market_cap, alert_market_cap: messages.alert_market_cap };
+                        if mark_followup(conn, &mint, &followup.kind(), alert.record_ttl).await? {
+                            reached = Some(followup);
+                        }
+                    }
+                }
+                let edit = alert.live_updates && messages.needs_update(record.market_cap, alert.update_min_change);
+                if reached.is_some() || edit {
+                    updates.push(AlertUpdate { record, tier: None, followup: reached, edit });
                 }
             }
 
-            if !tokens_to_process.is_empty() {
+            if !tokens_to_process.is_empty() || !updates.is_empty() || !rugs.is_empty() {
+                let change_window = format_duration_ms(alert.change_window);
+                let record_ttl = alert.record_ttl;
+                let rug_cleanup = alert.rug_cleanup;
+                let mut conn = conn.clone();
                 tokio::spawn(async move {
-                    for (mint, info) in tokens_to_process {
-                        let splits: Vec<_> = info.split("|").collect();
-                        let (_mint, mk, create_time, name, symbol, uri, user, _bonding_curve) = (
-                            splits[0],
-                            splits[1].parse::<f32>().unwrap(),
-                            splits[2].parse::<u64>().unwrap(),
-                            splits[3],
-                            splits[4],
-                            splits[5],
-                            splits[6],
-                            splits[7],
-                        );
+                    for (messages, followup) in rugs {
+                        let _lock = lock_alert_messages(&messages.details.mint_address).await;
+                        notifier.send_rug_notice(&messages.messages, &followup, &messages.details, rug_cleanup).await;
+                    }
+                    for AlertUpdate { record, tier, followup, edit } in updates {
+                        // one update of a token's messages at a time, read again under the lock as
+                        // an earlier check still editing them may have saved since
+                        let _lock = lock_alert_messages(&record.mint).await;
+                        let mut messages = match alert_messages(&mut conn, &record.mint).await {
+                            Ok(Some(messages)) => messages,
+                            Ok(None) => continue,
+                            Err(e) => {
+                                warn!("read alert messages of {} error: {}", record.mint, e);
+                                continue;
+                            }
+                        };
+                        if let Some(followup) = followup {
+                            notifier.send_followup(&messages.messages, &followup, &messages.details).await;
+                        }
+                        // a later check already showed newer numbers
+                        if !edit || messages.checked_at > checked_at {
+                            continue;
+                        }
+                        messages.refresh(&record, tier);
+                        messages.checked_at = checked_at;
+                        // 互动数不必每次都刷, the X counts are fetched every `x.engagement_interval`
+                        let interval = social.engagement_interval();
+                        if interval > 0 && timestamp() >= messages.x_refreshed + interval {
+                            if let Some(engagement) = social.tweet_engagement(&messages.details.ai_from_x_url).await {
+                                messages.details.x_engagement = engagement;
+                            }
+                            messages.x_refreshed = timestamp();
+                        }
+                        notifier.edit_coin_alert(&messages.messages, &messages.details).await;
+                        if let Err(e) = set_alert_messages(&mut conn, &record.mint, &messages, record_ttl).await {
+                            warn!("save alert messages of {} error: {}", record.mint, e);
+                        }
+                    }
+
+                    for (mint, record, tier, change, volume, routes, subscribers) in tokens_to_process {
+                        let token_program = record.token_program();
+                        let ath = record.ath();
+                        let traders = record.traders();
+                        let same_block = record.same_block();
+                        let dev = record.dev_holdings();
+                        let launchpad = record.launchpad();
+                        let priority_fees = match priority_fee_stats(&mut conn, &record.mint).await {
+                            Ok(stats) => stats.summary(),
+                            Err(e) => {
+                                warn!("read priority fees of {} error: {}", record.mint, e);
+                                String::new()
+                            }
+                        };
+                        let TokenRecord { market_cap: mk, create_time, name, symbol, uri, creator: user, signature, .. } = record;
                         
-                        // get token x info
-                        let x_info = if let Ok(x_infos) = x_instance.search_tweets(&mint, None, Some("Top")).await {
-                            x_infos.tweets.first().unwrap().clone()
-                        } else {
-                            Tweet::default()
+                        // get token x info, from the token's own account when its metadata links one;
+                        // no tweet when nothing is found or the X quota is used up
+                        let metadata = notifier.token_metadata(&uri).await;
+                        let account = metadata.as_ref().and_then(|metadata| social_link(metadata, "twitter")).and_then(x_handle);
+                        let tweets = token_tweets(&mut conn, &social, &mint, &symbol, &name, account.as_deref()).await;
+                        // the linked tweet, none when nothing was found; the alert says so
+                        let tweet: Option<&Tweet> = tweets.first();
+                        // 推文链接到官网才算 verified
+                        let socials_verified = match metadata.as_ref().and_then(|metadata| social_link(metadata, "website")) {
+                            Some(website) => verify_socials(&mut conn, &social, &mint, website, &tweets, record_ttl).await,
+                            None => false,
                         };
 
-                        // get token ai summary
-                        let summary = generate_token_summary(&TokenInfo {
+                        // get token ai summary; when the AI keeps failing the alert still goes out, the
+                        // templates show the analysis as unavailable
+                        let summary = match ai.generate_token_summary(&TokenInfo {
                             url: uri.to_string(),
                             name: name.to_string(),
                             symbol: symbol.to_string(),
-                            x_content: x_info.text,
-                        }).await.expect("Failed to get token summary");
+                            // 多条推文的摘录加上整体的互动和情绪
+                            x_content: tweet_excerpts(&tweets),
+                            x_summary: TweetAggregate::new(&tweets).summary(),
+                        }).await {
+                            Ok(summary) => summary,
+                            Err(e) => {
+                                warn!("ai summary of {} error, alerting without it: {}", mint, e);
+                                TokenSummary::default()
+                            }
+                        };
                        
                         // send coin alert
                         let token_details = TokenDetails {
@@ -180,15 +1924,78 @@ pub async fn check_mk(conn: &mut MultiplexedConnection, instance: BotInstance, x
                             name: name.to_string(),
                             symbol: symbol.to_string(),
                             url: uri.to_string(),
-                            ai_analysis: summary,
-                            ai_from_x_url: x_info.tweet_id,
+                            launchpad: launchpad.name().to_string(),
+                            chart_url: launchpad.chart_url(&mint),
+                            ai_analysis: summary.summary,
+                            risk_score: summary.risk_score.map(|score| format!("{}/100", score)).unwrap_or_default(),
+                            categories: summary.categories.join(", "),
+                            confidence: summary.confidence.map(|confidence| format!("{:.0}%", confidence * 100.0)).unwrap_or_default(),
+                            ai_from_x_url: tweet.map(|tweet| tweet.tweet_id.clone()).unwrap_or_default(),
+                            x_engagement: tweet.map(Tweet::engagement).unwrap_or_default(),
+                            socials_verified,
                             market_cap: mk.to_string(),
+                            price_change: change.map(|change| format!("{:+.0}% in {}", change, change_window)).unwrap_or_default(),
+                            volume: if volume.h1.total() == 0 {
+                                "".to_string()
+                            } else {
+                                format!(
+                                    "5m {:.2} SOL ({:.0}% buys), 1h {:.2} SOL",
+                                    volume.m5.total() as f64 / LAMPORTS_PER_SOL as f64,
+                                    volume.m5.buy_ratio() * 100.0,
+                                    volume.h1.total() as f64 / LAMPORTS_PER_SOL as f64,
+                                )
+                            },
+                            ath,
+                            traders,
+                            same_block,
+                            dev,
+                            priority_fees,
+                            tier: tier.to_string(),
                             creator: user.to_string(),
                             launch_time: format_timestamp_to_et(create_time),
+                            signature: signature.to_string(),
+                            token_2022: token_program == TOKEN_2022_PROGRAM_ID,
+                            creator_token_account: match (Pubkey::from_str(&user), Pubkey::from_str(&mint)) {
+                                (Ok(creator), Ok(mint)) => associated_token_address(&creator, &mint, &token_program).to_string(),
+                                _ => "".to_string(),
+                            },
+                            updated: "".to_string(),
                         };
                         
                         // Directly send message, no need to check again
-                        let _ = instance.send_coin_alert(&token_details).await;
+                        let delivery = notifier.send_coin_alert(&routes, &subscribers, &token_details, metadata.as_ref(), tier).await;
+                        for (route, error) in delivery.failed {
+                            let letter = DeadLetter {
+                                mint: mint.clone(),
+                                route,
+                                tier,
+                                error: error.msg,
+                                time: timestamp(),
+                                details: token_details.clone(),
+                            };
+                            if let Err(e) = add_dead_letter(&mut conn, &letter).await {
+                                warn!("save dead letter of {} error: {}", mint, e);
+                            }
+                        }
+                        let sent = delivery.sent;
+                        if !sent.is_empty() {
+                            // chats added to a token alerted before keep the earlier messages
+                            let _lock = lock_alert_messages(&mint).await;
+                            let mut messages = match alert_messages(&mut conn, &mint).await {
+                                Ok(Some(messages)) => messages,
+                                _ => AlertMessages { alert_market_cap: mk, ..Default::default() },
+                            };
+                            messages.messages.extend(sent);
+                            if messages.checked_at <= checked_at {
+                                messages.x_refreshed = timestamp();
+                                messages.market_cap = mk;
+                                messages.details = token_details;
+                                messages.checked_at = checked_at;
+                            }
+                            if let Err(e) = set_alert_messages(&mut conn, &mint, &messages, record_ttl).await {
+                                warn!("save alert messages of {} error: {}", mint, e);
+                            }
+                        }
                     }
                 });
             }
@@ -200,15 +2007,510 @@ pub async fn check_mk(conn: &mut MultiplexedConnection, instance: BotInstance, x
 }
 
 
+// the follow-up and the edit of one token's alerts in a check, sent together under its lock
+struct AlertUpdate {
+    record: TokenRecord,
+    /// tier crossed in this check, shown by the edit
+    tier: Option<f32>,
+    followup: Option<FollowupAlert>,
+    edit: bool,
+}
+
+// ! alert messages
+/// Alerts sent for a token, edited with live numbers when `alert.live_updates` is on and
+/// replied to by follow-ups
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AlertMessages {
+    /// the alert as last sent or edited
+    pub details: TokenDetails,
+    /// route name and first message of the alert per chat
+    pub messages: Vec<(String, SentAlert)>,
+    /// market cap the messages show
+    pub market_cap: f32,
+    /// market cap at the first alert
+    pub alert_market_cap: f32,
+    /// when the X post's counts were last fetched, unix milliseconds
+    pub x_refreshed: u64,
+    /// start of the market cap check whose numbers the messages show, unix milliseconds
+    pub checked_at: u64,
+}
+
+impl AlertMessages {
+    /// `multiples` of the first alert's market cap that `market_cap` reached, ascending
+    pub fn multiples_reached(&self, market_cap: f32, multiples: &[f32]) -> Vec<f32> {
+        let mut reached: Vec<f32> = multiples
+            .iter()
+            .copied()
+            .filter(|multiple| *multiple > 1.0 && self.alert_market_cap > 0.0 && market_cap >= self.alert_market_cap * multiple)
+            .collect();
+        reached.sort_by(|a, b| a.total_cmp(b));
+        reached
+    }
+
+    /// Whether `market_cap` moved at least `min_change` percent from the one shown
+    pub fn needs_update(&self, market_cap: f32, min_change: f32) -> bool {
+        self.market_cap > 0.0 && ((market_cap - self.market_cap) / self.market_cap * 100.0).abs() >= min_change
+    }
+
+    /// Show the current numbers of `record`, and `tier` when it crossed a new one
+    pub fn refresh(&mut self, record: &TokenRecord, tier: Option<f32>) {
+        self.details.market_cap = record.market_cap.to_string();
+        self.details.ath = record.ath();
+        self.details.traders = record.traders();
+        self.details.dev = record.dev_holdings();
+        if let Some(tier) = tier {
+            self.details.tier = tier.to_string();
+        }
+        self.details.updated = format_timestamp_to_et(timestamp());
+        self.market_cap = record.market_cap;
+    }
+}
+
+// per token: held while its alert messages are edited and saved, so the sends of overlapping
+// market cap checks do not overwrite each other
+static ALERT_LOCKS: Lazy<DashMap<String, Arc<tokio::sync::Mutex<()>>>> = Lazy::new(DashMap::new);
+
+/// Wait for the other updates of the alerts of `mint`, the next one starts when the guard drops
+pub async fn lock_alert_messages(mint: &str) -> tokio::sync::OwnedMutexGuard<()> {
+    let lock = ALERT_LOCKS.entry(mint.to_string()).or_default().clone();
+    lock.lock_owned().await
+}
+
+/// Forget the alerts of `mint`, no more edits or follow-ups go out for them
+pub async fn remove_alert_messages(conn: &mut RedisConn, mint: &str) -> RedisResult<()> {
+    conn.del(mint_key(ALERT_MESSAGES_KEY_PREFIX, mint)).await
+}
+
+pub async fn alert_messages(conn: &mut RedisConn, mint: &str) -> RedisResult<Option<AlertMessages>> {
+    let messages: Option<String> = conn.get(mint_key(ALERT_MESSAGES_KEY_PREFIX, mint)).await?;
+    Ok(messages.and_then(|messages| serde_json::from_str(&messages).ok()))
+}
+
+/// Save the alerts of `mint`, forgotten after `ttl_ms` like the record (0 keeps them)
+pub async fn set_alert_messages(conn: &mut RedisConn, mint: &str, messages: &AlertMessages, ttl_ms: u64) -> RedisResult<()> {
+    let key = mint_key(ALERT_MESSAGES_KEY_PREFIX, mint);
+    let value = serde_json::to_string(messages).unwrap_or_default();
+    if ttl_ms == 0 {
+        return conn.set(key, value).await;
+    }
+    conn.pset_ex(key, value, ttl_ms).await
+}
+
+/// The rug follow-up of an alerted `record` whose market cap fell below `alert.rug_floor_percent`
+/// of its alert market cap, once; its alerts are forgotten
+async fn rug_followup(conn: &mut RedisConn, alert: &AlertConfig, record: &TokenRecord) -> RedisResult<Option<(AlertMessages, FollowupAlert)>> {
+    if !alert.followups || alert.rug_floor_percent <= 0.0 || record.tier <= 0.0 {
+        return Ok(None);
+    }
+    let Some(messages) = alert_messages(conn, &record.mint).await? else {
+        return Ok(None);
+    };
+    if record.market_cap >= messages.alert_market_cap * alert.rug_floor_percent / 100.0 {
+        return Ok(None);
+    }
+    let followup = FollowupAlert::Rugged { market_cap: record.market_cap, alert_market_cap: messages.alert_market_cap, liquidity_pulled: false };
+    if !mark_followup(conn, &record.mint, &followup.kind(), alert.record_ttl).await? {
+        return Ok(None);
+    }
+    remove_alert_messages(conn, &record.mint).await?;
+    Ok(Some((messages, followup)))
+}
+
+/// Remember that the `kind` follow-up of `mint` was sent, false when it was before
+pub async fn mark_followup(conn: &mut RedisConn, mint: &str, kind: &str, ttl_ms: u64) -> RedisResult<bool> {
+    let key = mint_key(FOLLOWUPS_KEY_PREFIX, mint);
+    let added: u32 = conn.sadd(&key, kind).await?;
+    if added > 0 && ttl_ms > 0 {
+        conn.pexpire::<_, ()>(&key, ttl_ms as i64).await?;
+    }
+    Ok(added > 0)
+}
+
+// ! digest
+/// A token alerted since the last digest
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DigestEntry {
+    /// highest tier crossed
+    pub tier: f32,
+    /// routes that took the alert
+    pub routes: Vec<String>,
+}
+
+/// Hold the alert of `mint` at `tier` for the next digest, a higher tier replaces a lower one
+pub async fn add_digest_token(conn: &mut RedisConn, mint: &str, tier: f32, routes: Vec<String>) -> RedisResult<()> {
+    let key = redis_key(DIGEST_KEY);
+    let known: Option<String> = conn.hget(&key, mint).await?;
+    let mut entry: DigestEntry = known.and_then(|entry| serde_json::from_str(&entry).ok()).unwrap_or_default();
+    entry.tier = entry.tier.max(tier);
+    for route in routes {
+        if !entry.routes.contains(&route) {
+            entry.routes.push(route);
+        }
+    }
+    conn.hset(key, mint, serde_json::to_string(&entry).unwrap_or_default()).await
+}
+
+/// Tokens alerted since the last digest, they stay until `settle_digest_tokens`
+pub async fn digest_tokens(conn: &mut RedisConn) -> RedisResult<HashMap<String, DigestEntry>> {
+    let entries: HashMap<String, String> = conn.hgetall(redis_key(DIGEST_KEY)).await?;
+    Ok(entries
+        .into_iter()
+        .filter_map(|(mint, entry)| Some((mint, serde_json::from_str(&entry).ok()?)))
+        .collect())
+}
+
+// KEYS: digest hash; ARGV: mint, entry as read, entry to keep ('' removes it), ... An entry an
+// alert changed since it was read is left for the next digest.
+static SETTLE_DIGEST_TOKENS: Lazy<redis::Script> = Lazy::new(|| {
+    redis::Script::new(
+        r#"
+for i = 1, #ARGV, 3 do
+    if redis.call('HGET', KEYS[1], ARGV[i]) == ARGV[i + 1] then
+        if ARGV[i + 2] == '' then
+            redis.call('HDEL', KEYS[1], ARGV[i])
+        else
+            redis.call('HSET', KEYS[1], ARGV[i], ARGV[i + 2])
+        end
+    end
+end
+return 0
+"#,
+    )
+});
+
+/// Drop the `sent` digest tokens once the digest went out, keeping each for the routes in
+/// `failed` it was meant for so the next digest retries them
+pub async fn settle_digest_tokens(
+    conn: &mut RedisConn,
+    sent: &HashMap<String, DigestEntry>,
+    failed: &[String],
+) -> RedisResult<()> {
+    if sent.is_empty() {
+        return Ok(());
+    }
+    let mut invocation = SETTLE_DIGEST_TOKENS.key(redis_key(DIGEST_KEY));
+    for (mint, entry) in sent {
+        let routes: Vec<String> = entry.routes.iter().filter(|route| failed.contains(route)).cloned().collect();
+        let keep = if routes.is_empty() {
+            String::new()
+        } else {
+            serde_json::to_string(&DigestEntry { tier: entry.tier, routes }).unwrap_or_default()
+        };
+        invocation.arg(mint).arg(serde_json::to_string(entry).unwrap_or_default()).arg(keep);
+    }
+    invocation.invoke_async::<()>(conn).await
+}
+
+/// The mints of `mints` not reported as graduated before, they are marked as reported
+pub async fn mark_digest_graduated(conn: &mut RedisConn, mints: &[String]) -> RedisResult<Vec<String>> {
+    if mints.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut pipe = redis::pipe();
+    for mint in mints {
+        pipe.sadd(redis_key(DIGEST_GRADUATED_KEY), mint);
+    }
+    let added: Vec<u32> = pipe.query_async(conn).await?;
+    Ok(mints.iter().zip(added).filter(|(_, added)| *added > 0).map(|(mint, _)| mint.clone()).collect())
+}
+
+// ! dead letters
+/// A coin alert that failed to send to a route after all retries
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DeadLetter {
+    pub mint: String,
+    pub route: String,
+    pub tier: f32,
+    pub error: String,
+    /// unix milliseconds
+    pub time: u64,
+    /// the alert as it would have been sent
+    pub details: TokenDetails,
+}
+
+/// Keep `letter` in the dead letter list, the oldest fall off past `DEAD_LETTERS_MAX`
+pub async fn add_dead_letter(conn: &mut RedisConn, letter: &DeadLetter) -> RedisResult<()> {
+    let key = redis_key(DEAD_LETTERS_KEY);
+    redis::pipe()
+        .lpush(&key, serde_json::to_string(letter).unwrap_or_default())
+        .ignore()
+        .ltrim(&key, 0, DEAD_LETTERS_MAX - 1)
+        .ignore()
+        .query_async(conn)
+        .await
+}
+
+// ! trending
+/// Pinned trending message id per route name
+pub async fn trending_messages(conn: &mut RedisConn) -> RedisResult<HashMap<String, i64>> {
+    conn.hgetall(redis_key(TRENDING_MESSAGES_KEY)).await
+}
+
+/// Remember `message_id` as the trending message of `route`
+pub async fn set_trending_message(conn: &mut RedisConn, route: &str, message_id: i64) -> RedisResult<()> {
+    conn.hset(redis_key(TRENDING_MESSAGES_KEY), route, message_id).await
+}
+
+/// Hold the alert of `mint` at `tier` for the end of the quiet hours of `route`
+pub async fn hold_quiet_alert(conn: &mut RedisConn, route: &str, mint: &str, tier: f32) -> RedisResult<()> {
+    conn.hset(redis_key(&format!("{}{}", QUIET_HELD_KEY_PREFIX, route)), mint, tier).await
+}
+
+/// Alerts held for `route` by mint with their tier, kept until `settle_quiet_alerts`
+pub async fn quiet_alerts(conn: &mut RedisConn, route: &str) -> RedisResult<HashMap<String, f32>> {
+    conn.hgetall(redis_key(&format!("{}{}", QUIET_HELD_KEY_PREFIX, route))).await
+}
+
+// KEYS: held alerts of a route; ARGV: mint, tier pairs. Drops each mint still held at that tier,
+// one held again at a higher tier meanwhile stays for the next release.
+static SETTLE_QUIET_ALERTS: Lazy<redis::Script> = Lazy::new(|| {
+    redis::Script::new(
+        r#"
+for i = 1, #ARGV, 2 do
+    if tonumber(redis.call('HGET', KEYS[1], ARGV[i])) == tonumber(ARGV[i + 1]) then
+        redis.call('HDEL', KEYS[1], ARGV[i])
+    end
+end
+return 0
+"#,
+    )
+});
+
+/// Drop the `released` alerts of `route` once their summary went out
+pub async fn settle_quiet_alerts(conn: &mut RedisConn, route: &str, released: &HashMap<String, f32>) -> RedisResult<()> {
+    if released.is_empty() {
+        return Ok(());
+    }
+    let mut invocation = SETTLE_QUIET_ALERTS.key(redis_key(&format!("{}{}", QUIET_HELD_KEY_PREFIX, route)));
+    for (mint, tier) in released {
+        invocation.arg(mint).arg(*tier);
+    }
+    invocation.invoke_async::<()>(conn).await
+}
+
+// ! alert state
+/// Alerts sent for a token so far
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AlertState {
+    /// tiers crossed and alerted, ascending
+    pub tiers: Vec<f32>,
+    /// last alert, unix milliseconds, 0 before the first
+    pub last_alert: u64,
+    /// no alert is sent before this time (unix milliseconds)
+    pub cooldown_until: u64,
+}
+
+impl AlertState {
+    pub fn has_fired(&self, tier: f32) -> bool {
+        self.tiers.contains(&tier)
+    }
+
+    /// Highest tier of `tiers` the market cap crossed that has not fired yet
+    pub fn next_tier(&self, tiers: &[f32], market_cap: f32) -> Option<f32> {
+        tiers.iter().copied().filter(|tier| market_cap >= *tier && !self.has_fired(*tier)).reduce(f32::max)
+    }
+
+    pub fn in_cooldown(&self, now: u64) -> bool {
+        now < self.cooldown_until
+    }
+
+    /// Mark every tier the market cap crossed as fired and start the cooldown (`cooldown` 0 has none)
+    pub fn fire(&mut self, tiers: &[f32], market_cap: f32, now: u64, cooldown: u64) {
+        for tier in tiers.iter().copied().filter(|tier| market_cap >= *tier) {
+            if !self.has_fired(tier) {
+                self.tiers.push(tier);
+            }
+        }
+        self.tiers.sort_by(|a, b| a.total_cmp(b));
+        self.last_alert = now;
+        self.cooldown_until = now + cooldown;
+    }
+
+    /// Mark the tier of a legacy `token_alert_sent` marker as fired, every tier of `tiers` for a
+    /// tierless one; no cooldown is started
+    pub fn fire_legacy(&mut self, tier: Option<f32>, tiers: &[f32]) {
+        let fired: Vec<f32> = match tier {
+            Some(tier) => vec![tier],
+            None => tiers.to_vec(),
+        };
+        for tier in fired {
+            if !self.has_fired(tier) {
+                self.tiers.push(tier);
+            }
+        }
+        self.tiers.sort_by(|a, b| a.total_cmp(b));
+    }
+}
+
+/// Alert state of `mint`, the default when nothing was sent yet
+pub async fn alert_state(conn: &mut RedisConn, mint: &str) -> RedisResult<AlertState> {
+    if let Some(store) = token_store() {
+        return store.alert_state(mint).await.map_err(store_error);
+    }
+    let state: Option<String> = conn.get(mint_key(ALERT_STATE_KEY_PREFIX, mint)).await?;
+    Ok(state.and_then(|state| serde_json::from_str(&state).ok()).unwrap_or_default())
+}
+
+/// Store the alert state of `mint`, kept as long as the token record (`ttl_ms` 0 keeps it)
+pub async fn set_alert_state(conn: &mut RedisConn, mint: &str, state: &AlertState, ttl_ms: u64) -> RedisResult<()> {
+    if let Some(store) = token_store() {
+        return store.set_alert_state(mint, state, ttl_ms).await.map_err(store_error);
+    }
+    let key = mint_key(ALERT_STATE_KEY_PREFIX, mint);
+    let value = serde_json::to_string(state).unwrap_or_default();
+    if ttl_ms == 0 {
+        return conn.set(key, value).await;
+    }
+    conn.pset_ex(key, value, ttl_ms).await
+}
+
+// mint and tier of a `token_alert_sent:<mint>:<tier>` marker, without the key prefix; the tier is
+// `None` for the tierless `token_alert_sent:<mint>` markers of the first versions
+fn legacy_alert_marker(key: &str) -> Option<(&str, Option<f32>)> {
+    let marker = key.strip_prefix(LEGACY_ALERT_SENT_KEY_PREFIX)?;
+    match marker.rsplit_once(':') {
+        Some((mint, tier)) => Some((mint, Some(tier.parse().ok()?))),
+        None if !marker.is_empty() => Some((marker, None)),
+        None => None,
+    }
+}
+
+/// Fold the `token_alert_sent:<mint>:<tier>` markers of older versions into the alert state of
+/// their token, so tiers alerted before the upgrade do not fire again; a tierless marker counts
+/// as every tier of `tiers` fired. Returns how many markers were converted, others are kept
+pub async fn migrate_alert_markers(conn: &mut RedisConn, tiers: &[f32]) -> RedisResult<usize> {
+    let pattern = format!("{}*", redis_key(LEGACY_ALERT_SENT_KEY_PREFIX));
+    let mut keys = vec![];
+    let mut cursor = 0u64;
+    loop {
+        let (next, batch): (u64, Vec<String>) =
+            redis::cmd("SCAN").arg(cursor).arg("MATCH").arg(&pattern).arg("COUNT").arg(1000).query_async(conn).await?;
+        keys.extend(batch);
+        if next == 0 {
+            break;
+        }
+        cursor = next;
+    }
+
+    // marker tiers and remaining lifetime per mint, `None` for markers kept without expiry
+    let mut markers: HashMap<String, (Vec<Option<f32>>, Option<u64>)> = HashMap::new();
+    let mut folded = vec![];
+    for key in &keys {
+        let Some((mint, tier)) = legacy_alert_marker(&key[key_prefix().len()..]) else {
+            continue;
+        };
+        let ttl: i64 = conn.pttl(key).await?;
+        if ttl == -2 {
+            continue;
+        }
+        let (marker_tiers, expiry) = markers.entry(mint.to_string()).or_insert((vec![], Some(0)));
+        marker_tiers.push(tier);
+        *expiry = match (*expiry, ttl) {
+            (None, _) | (_, -1) => None,
+            (Some(current), ttl) => Some(current.max(ttl as u64)),
+        };
+        folded.push(key.clone());
+    }
+
+    for (mint, (marker_tiers, expiry)) in &markers {
+        let mut state = alert_state(conn, mint).await?;
+        for tier in marker_tiers {
+            state.fire_legacy(*tier, tiers);
+        }
+        set_alert_state(conn, mint, &state, expiry.unwrap_or(0)).await?;
+    }
+    if !folded.is_empty() {
+        conn.del::<_, ()>(&folded).await?;
+    }
+    Ok(folded.len())
+}
+
+// ! x
+/// Tweets found for `mint` within `x.search_cache_ttl`, `None` when they have to be fetched
+pub async fn cached_tweets(conn: &mut RedisConn, mint: &str) -> RedisResult<Option<Vec<Tweet>>> {
+    let tweets: Option<String> = conn.get(mint_key(X_TWEETS_KEY_PREFIX, mint)).await?;
+    Ok(tweets.and_then(|tweets| serde_json::from_str(&tweets).ok()))
+}
+
+/// Keep the tweets found for `mint` for `ttl_ms`
+pub async fn cache_tweets(conn: &mut RedisConn, mint: &str, tweets: &[Tweet], ttl_ms: u64) -> RedisResult<()> {
+    let value = serde_json::to_string(tweets).unwrap_or_default();
+    conn.pset_ex(mint_key(X_TWEETS_KEY_PREFIX, mint), value, ttl_ms).await
+}
+
+/// Whether the tweets of `mint` were found to link to the website in its metadata
+pub async fn socials_verified(conn: &mut RedisConn, mint: &str) -> RedisResult<bool> {
+    conn.exists(mint_key(SOCIALS_VERIFIED_KEY_PREFIX, mint)).await
+}
+
+/// Remember for `ttl_ms` (0 keeps it) that `mint`'s socials are verified
+pub async fn set_socials_verified(conn: &mut RedisConn, mint: &str, ttl_ms: u64) -> RedisResult<()> {
+    let key = mint_key(SOCIALS_VERIFIED_KEY_PREFIX, mint);
+    if ttl_ms == 0 {
+        return conn.set(key, 1).await;
+    }
+    conn.pset_ex(key, 1, ttl_ms).await
+}
+
+/// Whether `tweets` of `mint` link to `website` from its metadata; once they did the token
+/// stays verified and its later alerts don't resolve the links again
+async fn verify_socials(conn: &mut RedisConn, social: &SocialChain, mint: &str, website: &str, tweets: &[Tweet], ttl_ms: u64) -> bool {
+    match socials_verified(conn, mint).await {
+        Ok(true) => return true,
+        Ok(false) => {}
+        Err(e) => warn!("read socials verified of {} error: {}", mint, e),
+    }
+    if !social.links_to(tweets, website).await {
+        return false;
+    }
+    if let Err(e) = set_socials_verified(conn, mint, ttl_ms).await {
+        warn!("save socials verified of {} error: {}", mint, e);
+    }
+    true
+}
+
+/// Newest tweet id read per KOL account
+pub async fn kol_last_seen(conn: &mut RedisConn) -> RedisResult<HashMap<String, u64>> {
+    conn.hgetall(redis_key(KOL_LAST_SEEN_KEY)).await
+}
+
+pub async fn set_kol_last_seen(conn: &mut RedisConn, account: &str, tweet_id: u64) -> RedisResult<()> {
+    conn.hset(redis_key(KOL_LAST_SEEN_KEY), account, tweet_id).await
+}
 
-// Store token alert status in Redis
-pub async fn mark_token_alert_sent(conn: &mut MultiplexedConnection, mint: &str) -> RedisResult<()> {
-    conn.set(mint, 1).await  
+/// Remember that the mention of `mint` by `account` was sent, false when it was before
+pub async fn mark_kol_mention(conn: &mut RedisConn, mint: &str, account: &str, ttl_ms: u64) -> RedisResult<bool> {
+    let key = mint_key(KOL_MENTIONS_KEY_PREFIX, mint);
+    let added: u32 = conn.sadd(&key, account).await?;
+    if added > 0 && ttl_ms > 0 {
+        conn.pexpire::<_, ()>(&key, ttl_ms as i64).await?;
+    }
+    Ok(added > 0)
 }
 
-pub async fn is_token_alert_sent(conn: &mut MultiplexedConnection, mint: &str) -> RedisResult<bool> {
-    // Check if token alert has already been sent
-    conn.exists(mint).await
+/// Tweets for the AI summary of `mint` (see `SocialChain::token_tweets`), from the cache when they
+/// were fetched within `x.search_cache_ttl`. Nothing found is not cached, a failed or throttled
+/// search is tried again with the next alert
+async fn token_tweets(conn: &mut RedisConn, social: &SocialChain, mint: &str, symbol: &str, name: &str, account: Option<&str>) -> Vec<Tweet> {
+    let ttl = social.search_cache_ttl();
+    if ttl > 0 {
+        match cached_tweets(conn, mint).await {
+            Ok(Some(tweets)) => return tweets,
+            Ok(None) => {}
+            Err(e) => warn!("read cached tweets of {} error: {}", mint, e),
+        }
+    }
+    let tweets = social.token_tweets(mint, symbol, name, account).await;
+    if ttl > 0 && !tweets.is_empty() {
+        if let Err(e) = cache_tweets(conn, mint, &tweets, ttl).await {
+            warn!("cache tweets of {} error: {}", mint, e);
+        }
+    }
+    tweets
 }
 
 #[cfg(test)]
@@ -218,19 +2520,21 @@ mod test {
     use solana_sdk::pubkey::Pubkey;
 
     use crate::{
-        cache::{add_token_info, check_mk, update_mk}, constants::REDIS_URL, tg_bot::tg_bot::get_instance, types::CreateEvent, x::get_x_instance
+        ai::AiClient, cache::{add_token_info, check_mk, legacy_alert_marker, update_mk, AlertMessages, AlertState, BlockhashInfo, FirstBuyer, MarketCapUpdate, PricePoint, PriorityFeeStats, TokenRecord, TokenVolume, VolumeWindows}, config::Config, handler::EventContext, redis_conn::RedisConn, tg_bot::{notifier::Notifier, registry::BotRegistry}, social::social_chain, types::{CreateEvent, Program}
     };
 
     #[tokio::test]
     async fn alert_test() -> anyhow::Result<()> {
         dotenv::dotenv().ok();
-        let instance = get_instance();
-        let redis = redis::Client::open(REDIS_URL.to_string())?;
-        let mut con = redis.get_multiplexed_async_connection().await?;
+        let config = Config::load()?;
+        let notifier = Notifier::new(&config.telegram, BotRegistry::from_config(&config.telegram)?);
+        let mut con = RedisConn::connect(&config.redis).await?;
         // 1. Add a token info
         let mint = Pubkey::new_unique();
         add_token_info(
             &mut con,
+            &EventContext::default(),
+            Program::Pumpfun,
             &CreateEvent {
                 name: "".to_string(),
                 symbol: "".to_string(),
@@ -238,6 +2542,7 @@ mod test {
                 mint,
                 user: Pubkey::new_unique(),
                 bonding_curve: Pubkey::new_unique(),
+                creator: Pubkey::new_unique(),
             },
         )
         .await?;
@@ -249,9 +2554,205 @@ mod test {
 
         // 3. Pause and check
         sleep(Duration::from_secs(11));
-        check_mk(&mut con, instance, get_x_instance()).await?;
+        check_mk(&mut con, notifier, social_chain(&config.x), AiClient::new(&config.ai.api_key), &config.alert, config.pubsub.alerts).await?;
 
         Ok(())
     }
-}
 
+    #[test]
+    fn blockhash_info_test() {
+        let info = BlockhashInfo::new("hash".to_string(), 100, 90, 1_000_000);
+        assert_eq!(info.last_valid_block_height, 240);
+        assert!(info.is_valid_at(240));
+        assert!(!info.is_valid_at(241));
+        assert!(info.is_fresh(1_000_000 + 59_999));
+        assert!(!info.is_fresh(1_000_000 + 60_000));
+    }
+
+    #[test]
+    fn token_record_test() {
+        let record = TokenRecord::decode("mint|1200.5|1700000000000|name|sym|uri|user|curve|").unwrap();
+        assert_eq!(record.market_cap, 1200.5);
+        assert_eq!(record.create_time, 1_700_000_000_000);
+        assert_eq!((record.bonding_curve.as_str(), record.pool.as_str(), record.tier), ("curve", "", 0.0));
+        assert!(record.is_reserve("curve"));
+        assert!(!record.is_reserve(""));
+
+        let record = TokenRecord::decode("mint|0|1|name|sym|uri|user|curve|pool|30000|sig|prog").unwrap();
+        assert_eq!((record.tier, record.signature.as_str(), record.token_program.as_str()), (30000.0, "sig", "prog"));
+        assert_eq!(TokenRecord::decode(&record.encode()), Some(record));
+
+        let record = TokenRecord::decode("mint|0|1|name|sym|uri|user|curve|pool|50000").unwrap();
+        assert_eq!((record.pool.as_str(), record.tier), ("pool", 50000.0));
+
+        // a `|` in the name shifts the fields
+        assert!(TokenRecord::decode("mint|0|1|na|me|sym|uri|user|curve|pool|0|sig|prog").is_none());
+        assert!(TokenRecord::decode("mint|0|1|na|me|sym|uri|user|curve|").is_none());
+        assert!(TokenRecord::decode("mint|0|1|na|me|sym|uri|user|curve|pool").is_none());
+        assert!(TokenRecord::decode("mint|0|1|na|me|sym|uri|user|curve|pool|50000").is_none());
+        assert!(TokenRecord::decode("mint|0|1").is_none());
+    }
+
+    #[test]
+    fn token_record_ath_test() {
+        let mut record = TokenRecord::default();
+        assert_eq!(record.drawdown(), 0.0);
+        record.update_market_cap(400.0, 1);
+        record.update_market_cap(300.0, 2);
+        assert_eq!((record.ath_market_cap, record.ath_time), (400.0, 1));
+        assert_eq!(record.drawdown(), 25.0);
+        record.update_market_cap(500.0, 3);
+        assert_eq!((record.ath_market_cap, record.ath_time, record.drawdown()), (500.0, 3, 0.0));
+    }
+
+    #[test]
+    fn token_record_traders_test() {
+        let record = |buyers, holders| TokenRecord { buyers, holders, ..Default::default() };
+        assert_eq!(record(0, 0).traders(), "");
+        assert_eq!(record(120, 0).traders(), "120 buyers");
+        assert_eq!(record(0, 85).traders(), "85 holders");
+        assert_eq!(record(120, 85).traders(), "120 buyers, 85 holders");
+    }
+
+    #[test]
+    fn first_buyer_test() {
+        let buyer = FirstBuyer { wallet: "wallet".to_string(), slot: 7, same_block: true };
+        assert_eq!(FirstBuyer::decode(&serde_json::to_string(&buyer).unwrap()), buyer);
+        assert_eq!(FirstBuyer::decode("wallet"), FirstBuyer { wallet: "wallet".to_string(), ..Default::default() });
+
+        let record = |first_buyers, same_block_buyers| TokenRecord { first_buyers, same_block_buyers, ..Default::default() };
+        assert_eq!(record(0, 0).same_block(), "");
+        assert_eq!(record(20, 3).same_block(), "3 of first 20 in the create block");
+    }
+
+    #[test]
+    fn dev_holdings_test() {
+        let mut record = TokenRecord::default();
+        assert_eq!(record.dev_holdings(), "");
+        record.dev_updated = 1;
+        record.dev_percent = 4.2;
+        assert_eq!(record.dev_holdings(), "holds 4.2%");
+        record.dev_sold = true;
+        record.dev_percent = 0.0;
+        assert_eq!(record.dev_holdings(), "sold, holds 0.0%");
+    }
+
+    #[test]
+    fn alert_state_test() {
+        let tiers = [100.0, 200.0, 400.0];
+        let mut state = AlertState::default();
+        assert_eq!(state.next_tier(&tiers, 50.0), None);
+        assert_eq!(state.next_tier(&tiers, 250.0), Some(200.0));
+
+        state.fire(&tiers, 250.0, 1_000, 60_000);
+        assert_eq!(state.tiers, vec![100.0, 200.0]);
+        assert_eq!((state.last_alert, state.cooldown_until), (1_000, 61_000));
+        assert_eq!(state.next_tier(&tiers, 300.0), None);
+        assert!(state.in_cooldown(60_999));
+        assert!(!state.in_cooldown(61_000));
+
+        // 升级, crossing a higher tier opens it again
+        assert_eq!(state.next_tier(&tiers, 500.0), Some(400.0));
+        state.fire(&tiers, 500.0, 70_000, 0);
+        assert_eq!(state.tiers, vec![100.0, 200.0, 400.0]);
+        assert!(!state.in_cooldown(70_000));
+    }
+
+    #[test]
+    fn alert_messages_test() {
+        let mut messages = AlertMessages { market_cap: 100.0, ..Default::default() };
+        assert!(!messages.needs_update(105.0, 10.0));
+        assert!(messages.needs_update(110.0, 10.0));
+        assert!(messages.needs_update(80.0, 10.0));
+
+        let record = TokenRecord { market_cap: 120.0, ath_market_cap: 150.0, buyers: 40, ..Default::default() };
+        messages.refresh(&record, Some(100.0));
+        assert_eq!(messages.details.market_cap, "120");
+        assert_eq!(messages.details.ath, "150 SOL, -20% from ATH");
+        assert_eq!(messages.details.tier, "100");
+        assert_eq!(messages.details.traders, "40 buyers");
+        assert!(!messages.details.updated.is_empty());
+        assert!(!messages.needs_update(120.0, 10.0));
+
+        assert!(messages.multiples_reached(500.0, &[2.0]).is_empty());
+        messages.alert_market_cap = 100.0;
+        assert_eq!(messages.multiples_reached(520.0, &[10.0, 2.0, 5.0, 1.0]), vec![2.0, 5.0]);
+    }
+
+    #[test]
+    fn legacy_alert_marker_test() {
+        let mint = "6p6xgHyF7AeE6TZkSmFsko444wqoP15icUSqi2jfGiPN";
+        assert_eq!(legacy_alert_marker(&format!("token_alert_sent:{}:100000", mint)), Some((mint, Some(100_000.0))));
+        assert_eq!(legacy_alert_marker(&format!("token_alert_sent:{}:2.5", mint)), Some((mint, Some(2.5))));
+        assert_eq!(legacy_alert_marker(&format!("token_alert_sent:{}:top", mint)), None);
+        assert_eq!(legacy_alert_marker(&format!("alert_state:{}", mint)), None);
+        assert_eq!(legacy_alert_marker("token_alert_sent:"), None);
+
+        // 第一版只有一个阈值, the tierless marker of the first versions fires every tier
+        let (marker_mint, tier) = legacy_alert_marker(&format!("token_alert_sent:{}", mint)).unwrap();
+        assert_eq!((marker_mint, tier), (mint, None));
+        let tiers = [100.0, 200.0, 400.0];
+        let mut state = AlertState::default();
+        state.fire_legacy(tier, &tiers);
+        assert_eq!(state.tiers, vec![100.0, 200.0, 400.0]);
+        assert_eq!(state.next_tier(&tiers, 1_000.0), None);
+        assert!(!state.in_cooldown(0));
+
+        let mut state = AlertState::default();
+        state.fire_legacy(Some(200.0), &tiers);
+        state.fire_legacy(Some(100.0), &tiers);
+        assert_eq!(state.tiers, vec![100.0, 200.0]);
+        assert_eq!(state.next_tier(&tiers, 1_000.0), Some(400.0));
+    }
+
+    #[test]
+    fn price_change_test() {
+        let point = |market_cap| PricePoint { market_cap, ..Default::default() };
+        assert_eq!(PricePoint::change(&[]), None);
+        assert_eq!(PricePoint::change(&[point(0.0), point(10.0)]), None);
+        assert_eq!(PricePoint::change(&[point(100.0), point(80.0), point(440.0)]), Some(340.0));
+        assert_eq!(PricePoint::change(&[point(100.0), point(50.0)]), Some(-50.0));
+    }
+
+    #[test]
+    fn market_cap_update_merge_test() {
+        let mut update = MarketCapUpdate::new("mint", 1.0, 100.0, "", 1);
+        update.merge(MarketCapUpdate::new("mint", 3.0, 300.0, "", 2));
+        update.merge(MarketCapUpdate::new("mint", 2.0, 200.0, "pool", 3));
+        assert_eq!((update.price, update.market_cap, update.pool.as_str()), (2.0, 200.0, "pool"));
+        assert_eq!((update.high, update.high_time), (300.0, 2));
+
+        // a later update without a pool keeps it
+        update.merge(MarketCapUpdate::new("mint", 4.0, 400.0, "", 4));
+        assert_eq!((update.price, update.market_cap, update.pool.as_str()), (4.0, 400.0, "pool"));
+        assert_eq!((update.high, update.high_time), (400.0, 4));
+    }
+
+    #[test]
+    fn volume_windows_test() {
+        let buckets = [("100:buy", 5), ("100:sell", 1), ("97:buy", 3), ("50:sell", 10), ("40:buy", 7), ("junk", 1)]
+            .into_iter()
+            .map(|(field, lamports)| (field.to_string(), lamports))
+            .collect();
+        let (windows, mut stale) = VolumeWindows::from_buckets(&buckets, 100);
+        assert_eq!(windows.m1, TokenVolume { buy: 5, sell: 1 });
+        assert_eq!(windows.m5, TokenVolume { buy: 8, sell: 1 });
+        assert_eq!(windows.h1, TokenVolume { buy: 8, sell: 11 });
+        stale.sort();
+        assert_eq!(stale, vec!["40:buy".to_string(), "junk".to_string()]);
+        assert_eq!(windows.m5.buy_ratio(), 8.0 / 9.0);
+        assert_eq!(TokenVolume::default().buy_ratio(), 0.0);
+    }
+
+    #[test]
+    fn priority_fee_stats_test() {
+        assert_eq!(PriorityFeeStats::from_samples(vec![]), PriorityFeeStats::default());
+
+        let stats = PriorityFeeStats::from_samples((1..=100).rev().collect());
+        assert_eq!(stats.samples, 100);
+        assert_eq!(stats.avg, 50);
+        assert_eq!((stats.p50, stats.p90, stats.p99), (50, 90, 99));
+        assert_eq!(stats.summary(), "p50 50, p90 90 µlamports/CU (100 buys)");
+        assert_eq!(PriorityFeeStats::default().summary(), "");
+    }
+}
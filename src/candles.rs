@@ -0,0 +1,305 @@
+//! OHLCV candles per mint, built from the trade events on the event bus.
+//!
+//! Prices are the execution price of each trade in SOL per token. Only tokens with a record are
+//! followed; open candles are kept in memory and stored once their interval is over, see
+//! `cache::candles`. Candles are keyed by the block time of their trades, a trade that arrives
+//! after its interval was stored is merged into the stored candle; it only becomes the open or
+//! close when its block time is before the first or after the last trade of the candle.
+
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use solana_sdk::{native_token::LAMPORTS_PER_SOL, pubkey::Pubkey, timing::timestamp};
+use tokio::{
+    sync::{broadcast, watch},
+    time::{interval, MissedTickBehavior},
+};
+use tracing::warn;
+
+use crate::{
+    bus::BusEvent,
+    cache::{add_candles, from_curve_query_token_mint, from_pool_query_token_mint, is_tracked},
+    config::CandlesConfig,
+    constants::{BOOP_CURVE, MINUTES, MOONSHOT_CURVE, SECONDS},
+    redis_conn::RedisConn,
+    types::TargetEvent,
+    utils::TOKEN_DECIMALS,
+};
+
+/// Candle intervals in milliseconds
+pub const INTERVALS: [u64; 2] = [MINUTES, 5 * MINUTES];
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Candle {
+    /// start of the interval, unix milliseconds
+    pub open_time: u64,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    /// SOL traded in lamports
+    pub volume: u64,
+    pub trades: u32,
+    /// block time of the opening trade, unix milliseconds; 0 for candles of older versions
+    #[serde(default)]
+    pub first_trade: u64,
+    /// block time of the closing trade, unix milliseconds; 0 for candles of older versions
+    #[serde(default)]
+    pub last_trade: u64,
+}
+
+impl Candle {
+    fn new(open_time: u64, time: u64, price: f64, volume: u64) -> Self {
+        Self { open_time, open: price, high: price, low: price, close: price, volume, trades: 1, first_trade: time, last_trade: time }
+    }
+
+    fn add(&mut self, time: u64, price: f64, volume: u64) {
+        self.merge(&Candle::new(self.open_time, time, price, volume));
+    }
+
+    /// Take in the trades of `other`, a candle of the same interval; its open and close only win
+    /// when its trades came before or after these
+    pub fn merge(&mut self, other: &Candle) {
+        if other.first_trade < self.first_trade {
+            self.open = other.open;
+            self.first_trade = other.first_trade;
+        }
+        if other.last_trade >= self.last_trade {
+            self.close = other.close;
+            self.last_trade = other.last_trade;
+        }
+        self.high = self.high.max(other.high);
+        self.low = self.low.min(other.low);
+        self.volume += other.volume;
+        self.trades += other.trades;
+    }
+}
+
+/// A candle that is complete, ready to be stored
+pub type ClosedCandle = (String, u64, Candle);
+
+/// `candles` with those of the same mint, interval and open time merged, in their first order
+pub fn merge_closed(candles: &[ClosedCandle]) -> Vec<ClosedCandle> {
+    let mut merged: Vec<ClosedCandle> = Vec::with_capacity(candles.len());
+    let mut index: HashMap<(&str, u64, u64), usize> = HashMap::new();
+    for (mint, interval, candle) in candles {
+        match index.get(&(mint.as_str(), *interval, candle.open_time)) {
+            Some(&i) => merged[i].2.merge(candle),
+            None => {
+                index.insert((mint.as_str(), *interval, candle.open_time), merged.len());
+                merged.push((mint.clone(), *interval, *candle));
+            }
+        }
+    }
+    merged
+}
+
+/// Open candles of every mint and interval
+#[derive(Debug, Default)]
+pub struct CandleBuilder {
+    open: HashMap<(String, u64), Candle>,
+}
+
+impl CandleBuilder {
+    /// Add a trade at `time` (unix milliseconds), returns the candles it closed
+    pub fn add(&mut self, mint: &str, time: u64, price: f64, volume: u64) -> Vec<ClosedCandle> {
+        let mut closed = vec![];
+        for interval in INTERVALS {
+            let open_time = time - time % interval;
+            match self.open.get_mut(&(mint.to_string(), interval)) {
+                Some(candle) if candle.open_time == open_time => candle.add(time, price, volume),
+                // a trade from an older block than the open candle goes out on its own,
+                // `cache::add_candles` merges it into the stored candle of its interval
+                Some(candle) if candle.open_time > open_time => {
+                    closed.push((mint.to_string(), interval, Candle::new(open_time, time, price, volume)));
+                }
+                Some(candle) => {
+                    closed.push((mint.to_string(), interval, *candle));
+                    *candle = Candle::new(open_time, time, price, volume);
+                }
+                None => {
+                    self.open.insert((mint.to_string(), interval), Candle::new(open_time, time, price, volume));
+                }
+            }
+        }
+        closed
+    }
+
+    /// Whether `mint` has an open candle
+    pub fn is_open(&self, mint: &str) -> bool {
+        INTERVALS.iter().any(|interval| self.open.contains_key(&(mint.to_string(), *interval)))
+    }
+
+    /// Remove and return the candles whose interval ended before `now`
+    pub fn close_before(&mut self, now: u64) -> Vec<ClosedCandle> {
+        let ended: Vec<(String, u64)> = self
+            .open
+            .iter()
+            .filter(|((_, interval), candle)| candle.open_time + interval <= now)
+            .map(|(key, _)| key.clone())
+            .collect();
+        ended
+            .into_iter()
+            .filter_map(|key| {
+                let candle = self.open.remove(&key)?;
+                Some((key.0, key.1, candle))
+            })
+            .collect()
+    }
+
+    /// Remove and return every open candle
+    pub fn drain(&mut self) -> Vec<ClosedCandle> {
+        self.open.drain().map(|((mint, interval), candle)| (mint, interval, candle)).collect()
+    }
+}
+
+// where the mint of a trade comes from
+enum TradeMint {
+    Mint(Pubkey),
+    Pool(Pubkey),
+    Curve(Pubkey),
+}
+
+// mint, lamports, tokens and token decimals of a trade event
+fn trade_of(event: &TargetEvent) -> Option<(TradeMint, u64, u64, u8)> {
+    match event {
+        TargetEvent::PumpfunBuy(trade) | TargetEvent::PumpfunSell(trade) => {
+            Some((TradeMint::Mint(trade.mint), trade.sol_amount, trade.token_amount, TOKEN_DECIMALS))
+        }
+        TargetEvent::PumpammBuy(buy) => {
+            Some((TradeMint::Pool(buy.pool), buy.quote_amount_in, buy.base_amount_out, TOKEN_DECIMALS))
+        }
+        TargetEvent::PumpammSell(sell) => {
+            Some((TradeMint::Pool(sell.pool), sell.quote_amount_out, sell.base_amount_in, TOKEN_DECIMALS))
+        }
+        TargetEvent::MoonshotBuy(trade) | TargetEvent::MoonshotSell(trade) => {
+            Some((TradeMint::Curve(trade.curve), trade.collateral_amount, trade.amount, MOONSHOT_CURVE.token_decimals))
+        }
+        TargetEvent::BoopBuy(trade) => Some((TradeMint::Mint(trade.mint), trade.amount_in, trade.amount_out, BOOP_CURVE.token_decimals)),
+        TargetEvent::BoopSell(trade) => Some((TradeMint::Mint(trade.mint), trade.amount_out, trade.amount_in, BOOP_CURVE.token_decimals)),
+        _ => None,
+    }
+}
+
+/// SOL per whole token
+pub fn execution_price(lamports: u64, tokens: u64, decimals: u8) -> Option<f64> {
+    if tokens == 0 {
+        return None;
+    }
+    Some((lamports as f64 / LAMPORTS_PER_SOL as f64) / (tokens as f64 / 10f64.powi(decimals as i32)))
+}
+
+/// Build candles from the bus until shutdown, the open candles are stored on the way out
+pub async fn run_candles(
+    mut events: broadcast::Receiver<Arc<BusEvent>>,
+    mut conn: RedisConn,
+    config: CandlesConfig,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let mut builder = CandleBuilder::default();
+    let mut ticker = interval(Duration::from_millis(SECONDS));
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        let closed = tokio::select! {
+            biased;
+            _ = shutdown.changed() => break,
+            _ = ticker.tick() => builder.close_before(timestamp()),
+            event = events.recv() => match event {
+                Ok(event) => {
+                    let Some((mint, lamports, tokens, decimals)) = trade_of(&event.event) else {
+                        continue;
+                    };
+                    let Some(price) = execution_price(lamports, tokens, decimals) else {
+                        continue;
+                    };
+                    // pool and curve lookups only find tracked tokens
+                    let mint = match mint {
+                        TradeMint::Mint(mint) => {
+                            let mint = mint.to_string();
+                            if builder.is_open(&mint) {
+                                Ok(mint)
+                            } else {
+                                is_tracked(&mut conn, &mint).await.map(|tracked| if tracked { mint } else { String::new() })
+                            }
+                        }
+                        TradeMint::Pool(pool) => from_pool_query_token_mint(&mut conn, &pool.to_string()).await,
+                        TradeMint::Curve(curve) => from_curve_query_token_mint(&mut conn, &curve.to_string()).await,
+                    };
+                    match mint {
+                        Ok(mint) if !mint.is_empty() => {
+                            let time = event.ctx.block_time.max(0) as u64 * SECONDS;
+                            builder.add(&mint, time, price, lamports)
+                        }
+                        Ok(_) => continue,
+                        Err(e) => {
+                            warn!("candle mint lookup error: {}", e);
+                            continue;
+                        }
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    warn!("candle builder lagged, {} events skipped", skipped);
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+        };
+        if !closed.is_empty() {
+            if let Err(e) = add_candles(&mut conn, &closed, config.retention).await {
+                warn!("store candles error: {}", e);
+            }
+        }
+    }
+    if let Err(e) = add_candles(&mut conn, &builder.drain(), config.retention).await {
+        warn!("store candles error: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn candle_builder_test() {
+        let mut builder = CandleBuilder::default();
+        assert!(builder.add("mint", 60_000, 1.0, 10).is_empty());
+        assert!(builder.add("mint", 70_000, 3.0, 5).is_empty());
+        assert!(builder.add("mint", 80_000, 0.5, 5).is_empty());
+        // next minute closes the 1m candle, the 5m one stays open
+        let closed = builder.add("mint", 125_000, 2.0, 1);
+        assert_eq!(closed.len(), 1);
+        let (mint, interval, candle) = &closed[0];
+        assert_eq!((mint.as_str(), *interval), ("mint", MINUTES));
+        assert_eq!(
+            *candle,
+            Candle { open_time: 60_000, open: 1.0, high: 3.0, low: 0.5, close: 0.5, volume: 20, trades: 3, first_trade: 60_000, last_trade: 80_000 }
+        );
+        // late trade of an older minute is passed on for its stored candle, inside the interval
+        // it changes neither the open nor the close
+        let late = builder.add("mint", 61_000, 9.0, 1);
+        assert_eq!(late, vec![("mint".to_string(), MINUTES, Candle::new(60_000, 61_000, 9.0, 1))]);
+        let merged = merge_closed(&[closed[0].clone(), late[0].clone()]);
+        assert_eq!(merged.len(), 1);
+        assert_eq!(
+            merged[0].2,
+            Candle { open_time: 60_000, open: 1.0, high: 9.0, low: 0.5, close: 0.5, volume: 21, trades: 4, first_trade: 60_000, last_trade: 80_000 }
+        );
+        // 更早或更晚的成交才换开盘/收盘价
+        let mut candle = Candle::new(60_000, 70_000, 2.0, 1);
+        candle.merge(&Candle::new(60_000, 65_000, 1.5, 1));
+        candle.merge(&Candle::new(60_000, 90_000, 2.5, 1));
+        assert_eq!((candle.open, candle.close), (1.5, 2.5));
+        assert_eq!((candle.first_trade, candle.last_trade), (65_000, 90_000));
+
+        let closed = builder.close_before(300_000);
+        assert_eq!(closed.len(), 2);
+        assert!(builder.close_before(300_000).is_empty());
+    }
+
+    #[test]
+    fn execution_price_test() {
+        // 1 SOL for 1,000,000 tokens of 6 decimals
+        assert_eq!(execution_price(LAMPORTS_PER_SOL, 1_000_000_000_000, 6), Some(0.000001));
+        assert_eq!(execution_price(1, 0, 6), None);
+    }
+}
@@ -1,14 +1,25 @@
-use anyhow::Result;
-use futures_util::Stream;
-use std::{collections::HashMap, time::Duration};
-use yellowstone_grpc_client::{ClientTlsConfig, GeyserGrpcClient};
+use anyhow::{anyhow, Result};
+use futures_util::{stream, Stream};
+use serde::Deserialize;
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::sync::mpsc;
 use yellowstone_grpc_proto::{
     geyser::{
-        CommitmentLevel, SubscribeRequest, SubscribeRequestFilterAccounts,
-        SubscribeRequestFilterBlocks, SubscribeRequestFilterBlocksMeta,
-        SubscribeRequestFilterTransactions, SubscribeUpdate,
+        geyser_client::GeyserClient, CommitmentLevel, GetLatestBlockhashRequest, SubscribeRequest,
+        SubscribeRequestFilterAccounts, SubscribeRequestFilterBlocks, SubscribeRequestFilterBlocksMeta,
+        SubscribeRequestFilterSlots, SubscribeRequestFilterTransactions, SubscribeRequestPing, SubscribeUpdate,
+    },
+    tonic::{
+        codec::{CompressionEncoding, Streaming},
+        metadata::AsciiMetadataValue,
+        service::{interceptor::InterceptedService, Interceptor},
+        transport::{Channel, ClientTlsConfig, Endpoint},
+        Request, Status,
     },
-    tonic::Status,
 };
 
 /// transactions filter map 
@@ -21,15 +32,267 @@ type BlocksFilterMap = HashMap<String, SubscribeRequestFilterBlocks>;
 type AccountFilterMap = HashMap<String, SubscribeRequestFilterAccounts>;
 /// blockhash filter map
 type BlockMetaFilterMap = HashMap<String, SubscribeRequestFilterBlocksMeta>;
+/// slot status filter map
+type SlotsFilterMap = HashMap<String, SubscribeRequestFilterSlots>;
 
-/// grpc structure, parameters only url
-pub struct GrpcClient {
+/// 在已打开的订阅上更新过滤规则, update the filters of an open subscription.
+///
+/// Yellowstone replaces every filter of the stream with each request it receives,
+/// so the handle keeps the whole request and resends it after each change.
+#[derive(Clone)]
+pub struct SubscriptionHandle {
+    request: Arc<Mutex<SubscribeRequest>>,
+    updates: mpsc::UnboundedSender<SubscribeRequest>,
+}
+
+impl SubscriptionHandle {
+    fn new(updates: mpsc::UnboundedSender<SubscribeRequest>, mut request: SubscribeRequest) -> Self {
+        // replay only applies to the first request
+        request.from_slot = None;
+        Self { request: Arc::new(Mutex::new(request)), updates }
+    }
+
+    /// Also receive transactions mentioning `accounts`
+    pub fn add_accounts(&self, accounts: &[String]) -> Result<()> {
+        self.update(|request| {
+            for filter in request.transactions.values_mut() {
+                for account in accounts {
+                    if !filter.account_include.contains(account) {
+                        filter.account_include.push(account.clone());
+                    }
+                }
+            }
+        })
+    }
+
+    pub fn remove_accounts(&self, accounts: &[String]) -> Result<()> {
+        self.update(|request| {
+            for filter in request.transactions.values_mut() {
+                filter.account_include.retain(|account| !accounts.contains(account));
+            }
+        })
+    }
+
+    /// Ping the server, answers a server ping or keeps load balancers from closing an idle stream.
+    /// The filters of the stream are not changed.
+    pub fn ping(&self, id: i32) -> Result<()> {
+        self.updates
+            .send(SubscribeRequest {
+                ping: Some(SubscribeRequestPing { id }),
+                ..Default::default()
+            })
+            .map_err(|_| anyhow!("subscription closed"))
+    }
+
+    /// Change the request and send it over the stream
+    pub fn update(&self, change: impl FnOnce(&mut SubscribeRequest)) -> Result<()> {
+        let mut request = self.request.lock().map_err(|_| anyhow!("subscription request lock poisoned"))?;
+        change(&mut request);
+        self.updates
+            .send(request.clone())
+            .map_err(|_| anyhow!("subscription closed"))
+    }
+}
+
+/// Accounts watched in addition to the configured programs.
+///
+/// Kept across reconnects and pushed to the current subscription without reconnecting.
+#[derive(Clone, Default)]
+pub struct AccountWatchlist {
+    inner: Arc<Mutex<Watchlist>>,
+}
+
+#[derive(Default)]
+struct Watchlist {
+    accounts: BTreeSet<String>,
+    subscriptions: Vec<SubscriptionHandle>,
+}
+
+impl AccountWatchlist {
+    pub fn watch(&self, account: &str) -> Result<()> {
+        let mut inner = self.inner.lock().map_err(|_| anyhow!("watchlist lock poisoned"))?;
+        if inner.accounts.insert(account.to_string()) {
+            // a closed subscription is replaced on reconnect, which reads the list again
+            for subscription in &inner.subscriptions {
+                let _ = subscription.add_accounts(&[account.to_string()]);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn unwatch(&self, account: &str) -> Result<()> {
+        let mut inner = self.inner.lock().map_err(|_| anyhow!("watchlist lock poisoned"))?;
+        if inner.accounts.remove(account) {
+            for subscription in &inner.subscriptions {
+                let _ = subscription.remove_accounts(&[account.to_string()]);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn accounts(&self) -> Vec<String> {
+        self.inner
+            .lock()
+            .map(|inner| inner.accounts.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Subscriptions that receive future changes, empty while disconnected
+    pub fn attach(&self, subscriptions: Vec<SubscriptionHandle>) {
+        if let Ok(mut inner) = self.inner.lock() {
+            inner.subscriptions = subscriptions;
+        }
+    }
+}
+
+/// gRPC 消息压缩, message compression
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    Gzip,
+    Zstd,
+}
+
+impl From<Compression> for CompressionEncoding {
+    fn from(compression: Compression) -> Self {
+        match compression {
+            Compression::Gzip => CompressionEncoding::Gzip,
+            Compression::Zstd => CompressionEncoding::Zstd,
+        }
+    }
+}
+
+/// Connection settings for `GrpcClient`
+#[derive(Debug, Clone)]
+pub struct GrpcClientBuilder {
     endpoint: String,
+    x_token: Option<String>,
+    connect_timeout: Duration,
+    timeout: Duration,
+    keepalive_interval: Duration,
+    keepalive_timeout: Duration,
+    max_decoding_message_size: usize,
+    compression: Option<Compression>,
 }
 
-impl GrpcClient {
+impl GrpcClientBuilder {
     pub fn new(endpoint: String) -> Self {
-        Self { endpoint }
+        Self {
+            endpoint,
+            x_token: None,
+            connect_timeout: Duration::from_secs(10),
+            timeout: Duration::from_secs(60),
+            keepalive_interval: Duration::from_secs(10),
+            keepalive_timeout: Duration::from_secs(20),
+            // block updates are often larger than tonic's 4 MiB default
+            max_decoding_message_size: 64 * 1024 * 1024,
+            compression: None,
+        }
+    }
+
+    /// Set the `x-token` sent with every request
+    pub fn x_token(mut self, x_token: Option<String>) -> Self {
+        self.x_token = x_token;
+        self
+    }
+
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    /// Timeout of unary requests
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// HTTP/2 keepalive, so idle connections are not dropped by proxies
+    pub fn keepalive(mut self, interval: Duration, timeout: Duration) -> Self {
+        self.keepalive_interval = interval;
+        self.keepalive_timeout = timeout;
+        self
+    }
+
+    /// Largest message accepted from the server
+    pub fn max_decoding_message_size(mut self, size: usize) -> Self {
+        self.max_decoding_message_size = size;
+        self
+    }
+
+    /// Compress requests and accept compressed responses
+    pub fn compression(mut self, compression: Option<Compression>) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Create the client, the channel connects on first use and reconnects by itself.
+    /// Must be called inside the tokio runtime.
+    pub fn build(self) -> Result<GrpcClient> {
+        let mut endpoint = Endpoint::from_shared(self.endpoint.clone())?
+            .connect_timeout(self.connect_timeout)
+            .timeout(self.timeout)
+            .http2_keep_alive_interval(self.keepalive_interval)
+            .keep_alive_timeout(self.keepalive_timeout)
+            .keep_alive_while_idle(true);
+        if self.endpoint.starts_with("https") {
+            endpoint = endpoint.tls_config(ClientTlsConfig::new().with_native_roots())?;
+        }
+        let x_token = self.x_token.map(|token| token.parse::<AsciiMetadataValue>()).transpose()?;
+
+        let mut geyser = GeyserClient::with_interceptor(endpoint.connect_lazy(), XToken(x_token))
+            .max_decoding_message_size(self.max_decoding_message_size);
+        if let Some(compression) = self.compression {
+            geyser = geyser
+                .send_compressed(compression.into())
+                .accept_compressed(compression.into());
+        }
+        Ok(GrpcClient { geyser })
+    }
+}
+
+/// Adds the `x-token` header to every request
+#[derive(Clone)]
+struct XToken(Option<AsciiMetadataValue>);
+
+impl Interceptor for XToken {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        if let Some(x_token) = &self.0 {
+            request.metadata_mut().insert("x-token", x_token.clone());
+        }
+        Ok(request)
+    }
+}
+
+/// grpc structure, one channel shared by every subscription and request.
+///
+/// Cloning is cheap, clones use the same channel.
+#[derive(Clone)]
+pub struct GrpcClient {
+    geyser: GeyserClient<InterceptedService<Channel, XToken>>,
+}
+
+impl GrpcClient {
+    /// Client with default settings
+    pub fn new(endpoint: String) -> Result<Self> {
+        GrpcClientBuilder::new(endpoint).build()
+    }
+
+    pub fn builder(endpoint: String) -> GrpcClientBuilder {
+        GrpcClientBuilder::new(endpoint)
+    }
+
+    // 打开订阅流, open a subscription, later requests go through the returned handle
+    async fn subscribe(&self, request: SubscribeRequest) -> Result<(SubscriptionHandle, Streaming<SubscribeUpdate>)> {
+        let (updates, receiver) = mpsc::unbounded_channel();
+        updates
+            .send(request.clone())
+            .map_err(|_| anyhow!("subscription closed"))?;
+        let requests = stream::unfold(receiver, |mut receiver| async move {
+            receiver.recv().await.map(|request| (request, receiver))
+        });
+        let response = self.geyser.clone().subscribe(requests).await?;
+        Ok((SubscriptionHandle::new(updates, request), response.into_inner()))
     }
 
     /// 订阅区块
@@ -40,14 +303,8 @@ impl GrpcClient {
         include_transactions: Option<bool>, // 是否包含所有交易, whether to include all transactions
         include_accounts: Option<bool>,     // 是否包含所有账户更新, whether to include all account updates
         include_entries: Option<bool>,      // 默认false, default false
+        from_slot: Option<u64>,             // 从该 slot 开始重放, replay from this slot
     ) -> Result<impl Stream<Item = Result<SubscribeUpdate, Status>>> {
-        // 创建client
-        let mut client = GeyserGrpcClient::build_from_shared(self.endpoint.clone())?
-            .tls_config(ClientTlsConfig::new().with_native_roots())?
-            .connect_timeout(Duration::from_secs(10))
-            .timeout(Duration::from_secs(60))
-            .connect()
-            .await?;
         // 过滤规则
         let mut blocks: BlocksFilterMap = HashMap::new();
         blocks.insert(
@@ -65,14 +322,13 @@ impl GrpcClient {
         let subscribe_request = SubscribeRequest {
             blocks,
             commitment: Some(CommitmentLevel::Confirmed.into()),
+            from_slot,
             ..Default::default()
         };
 
         // 返回流
         // return stream
-        let (_, stream) = client
-            .subscribe_with_request(Some(subscribe_request))
-            .await?;
+        let (_, stream) = self.subscribe(subscribe_request).await?;
         Ok(stream)
     }
 
@@ -83,23 +339,18 @@ impl GrpcClient {
         account_include: Vec<String>,  // 包含在内的地址相关交易都会收到, include addresses
         account_exclude: Vec<String>,  // 不包含这些地址的相关交易都会收到, exclude addresses
         account_required: Vec<String>, // 必须要包含的地址, required addresses
+        failed: Option<bool>,          // 失败交易过滤, None = all, Some(true) = only failed, Some(false) = none, see `include_filter`
+        vote: Option<bool>,            // 投票交易过滤, None = all, Some(true) = only votes, Some(false) = none
+        from_slot: Option<u64>,        // 从该 slot 开始重放, replay from this slot
         commitment: CommitmentLevel,   // 确认级别, commitment level
-    ) -> Result<impl Stream<Item = Result<SubscribeUpdate, Status>>> {
-        // client
-        let mut client = GeyserGrpcClient::build_from_shared(self.endpoint.clone())?
-            .tls_config(ClientTlsConfig::new().with_native_roots())?
-            .connect_timeout(Duration::from_secs(10))
-            .timeout(Duration::from_secs(60))
-            .connect()
-            .await?;
-
+    ) -> Result<(SubscriptionHandle, impl Stream<Item = Result<SubscribeUpdate, Status>>)> {
         // filter rules
         let mut transactions: TransactionsFilterMap = HashMap::new();
         transactions.insert(
             "client".to_string(),
             SubscribeRequestFilterTransactions {
-                vote: None,
-                failed: None,
+                vote,
+                failed,
                 signature: None,
                 account_include,
                 account_exclude,
@@ -109,19 +360,30 @@ impl GrpcClient {
 
         let mut metas: BlockMetaFilterMap = HashMap::new();
         metas.insert("client".to_string(), SubscribeRequestFilterBlocksMeta {});
+
+        // processed 订阅同时接收 slot 状态, 用于确认交易
+        // at processed also receive slot status updates, used to confirm transactions
+        let mut slots: SlotsFilterMap = HashMap::new();
+        if commitment == CommitmentLevel::Processed {
+            slots.insert(
+                "client".to_string(),
+                SubscribeRequestFilterSlots {
+                    filter_by_commitment: Some(false),
+                    ..Default::default()
+                },
+            );
+        }
         // request
         let subscribe_request = SubscribeRequest {
             transactions,
             blocks_meta: metas,
+            slots,
             commitment: Some(commitment.into()),
+            from_slot,
             ..Default::default()
         };
 
-        let (_, stream) = client
-            .subscribe_with_request(Some(subscribe_request))
-            .await?;
-
-        Ok(stream)
+        self.subscribe(subscribe_request).await
     }
 
     pub async fn subscribe_account_updates(
@@ -129,14 +391,6 @@ impl GrpcClient {
         account: Vec<String>,
         commitment: CommitmentLevel,
     ) -> Result<impl Stream<Item = Result<SubscribeUpdate, Status>>> {
-        // client
-        let mut client = GeyserGrpcClient::build_from_shared(self.endpoint.clone())?
-            .tls_config(ClientTlsConfig::new().with_native_roots())?
-            .connect_timeout(Duration::from_secs(10))
-            .timeout(Duration::from_secs(60))
-            .connect()
-            .await?;
-
         // filter rules
         let mut accounts: AccountFilterMap = HashMap::new();
         accounts.insert(
@@ -157,22 +411,39 @@ impl GrpcClient {
         };
 
         // return stream
-        let (_, stream) = client
-            .subscribe_with_request(Some(subscribe_request))
-            .await?;
-
+        let (_, stream) = self.subscribe(subscribe_request).await?;
         Ok(stream)
     }
 
     /// Get latest blockhash
     pub async fn get_latest_blockhash(&self) -> Result<String> {
-        let mut client = GeyserGrpcClient::build_from_shared(self.endpoint.clone())?
-            .tls_config(ClientTlsConfig::new().with_native_roots())?
-            .connect_timeout(Duration::from_secs(10))
-            .timeout(Duration::from_secs(60))
-            .connect()
+        let response = self
+            .geyser
+            .clone()
+            .get_latest_blockhash(GetLatestBlockhashRequest { commitment: None })
             .await?;
-        let response = client.get_latest_blockhash(None).await?;
-        Ok(response.blockhash)
+        Ok(response.into_inner().blockhash)
+    }
+}
+
+/// Transaction filter value for `failed` / `vote` that receives those transactions alongside
+/// the others when `include` is set; Yellowstone reads `Some(true)` as "only these"
+pub fn include_filter(include: bool) -> Option<bool> {
+    if include {
+        None
+    } else {
+        Some(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn include_filter_test() {
+        // including failed or vote transactions must not drop the normal ones
+        assert_eq!(include_filter(true), None);
+        assert_eq!(include_filter(false), Some(false));
     }
 }
@@ -0,0 +1,1757 @@
+use std::{collections::HashMap, env, fs, path::Path, str::FromStr, time::Duration};
+
+use anyhow::{bail, Context, Result};
+use chrono_tz::Tz;
+use serde::{de, Deserialize, Deserializer};
+use solana_sdk::{native_token::LAMPORTS_PER_SOL, pubkey::Pubkey};
+use url::Url;
+use yellowstone_grpc_proto::geyser::CommitmentLevel;
+
+use crate::{
+    cache::{TokenRecord, TokenVolume},
+    client::{Compression, GrpcClient},
+    constants::{
+        CHECK_INTERVAL, MINUTES, NEW_COIN_MAX_TIME, NEW_COIN_MIN_TIME, BOOP_PROGRAM_ID, MOONSHOT_PROGRAM_ID, PUMPAMM_PROGRAM_ID, PUMPFUN_PROGRAM_ID, SECONDS,
+    },
+    secrets::{open_store, SecretStore, AI_API_KEY, GRPC_X_TOKEN, TG_BOT_TOKEN, X_API_KEY, X_BEARER_TOKEN},
+    tg_bot::{
+        locale::{self, DEFAULT_LANGUAGE},
+        registry::BotPurpose,
+    },
+    types::Program,
+    utils::{in_time_window, parse_time_window},
+};
+
+/// 默认配置文件路径, default config file path
+pub const DEFAULT_CONFIG_PATH: &str = "config.toml";
+
+/// table holding the named profiles, e.g. `[profiles.dev.grpc]`
+const PROFILES_KEY: &str = "profiles";
+
+/// Typed runtime configuration.
+///
+/// Loaded from a TOML file (see `config.example.toml`), then overridden by
+/// environment variables so existing `.env` deployments keep working.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// selected profile name, if any
+    #[serde(skip)]
+    pub profile: Option<String>,
+    pub grpc: GrpcConfig,
+    pub rpc: RpcConfig,
+    pub redis: RedisConfig,
+    pub programs: ProgramsConfig,
+    pub engine: EngineConfig,
+    pub alert: AlertConfig,
+    pub candles: CandlesConfig,
+    pub pubsub: PubSubConfig,
+    pub event_log: EventLogConfig,
+    pub storage: StorageConfig,
+    pub telegram: TelegramConfig,
+    pub x: XConfig,
+    pub ai: AiConfig,
+    pub secrets: SecretsConfig,
+}
+
+/// Yellowstone gRPC endpoint
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct GrpcConfig {
+    pub url: String,
+    /// `x-token` header required by some providers (secret `GRPC_X_TOKEN`)
+    pub x_token: Option<String>,
+    /// also receive failed transactions, off by default
+    pub include_failed: bool,
+    /// also receive vote transactions, off by default
+    pub include_vote: bool,
+    /// subscription commitment, `processed` handles events before confirmation
+    pub commitment: Commitment,
+    /// at `processed`, events wait for their slot to be confirmed before they are handled;
+    /// a slot not confirmed once the chain is this many slots past it is dropped with its fork
+    pub confirm_timeout_slots: u64,
+    /// reconnect and tell the admin when no transaction arrives for this long
+    /// (milliseconds, `"60s"` in the file), 0 disables the watchdog
+    #[serde(deserialize_with = "de_duration_ms")]
+    pub idle_timeout: u64,
+    /// HTTP/2 keepalive ping interval (milliseconds, `"10s"` in the file)
+    #[serde(deserialize_with = "de_duration_ms")]
+    pub keepalive_interval: u64,
+    /// close the connection when a keepalive ping is not acked in time
+    #[serde(deserialize_with = "de_duration_ms")]
+    pub keepalive_timeout: u64,
+    /// how often the client pings over the subscription stream, 0 only answers server pings
+    #[serde(deserialize_with = "de_duration_ms")]
+    pub ping_interval: u64,
+    #[serde(deserialize_with = "de_duration_ms")]
+    pub connect_timeout: u64,
+    /// timeout of unary requests such as `get_latest_blockhash`
+    #[serde(deserialize_with = "de_duration_ms")]
+    pub request_timeout: u64,
+    /// largest message accepted from the server, in bytes
+    pub max_message_size: usize,
+    /// `gzip` or `zstd`, off when unset
+    pub compression: Option<Compression>,
+    /// more providers subscribed in parallel, the first to deliver a transaction wins
+    pub extra_feeds: Vec<FeedConfig>,
+    pub reconnect: ReconnectConfig,
+}
+
+/// Another Yellowstone endpoint raced against `grpc.url`
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct FeedConfig {
+    pub url: String,
+    pub x_token: Option<String>,
+}
+
+impl GrpcConfig {
+    /// Client for the primary endpoint
+    pub fn client(&self) -> Result<GrpcClient> {
+        self.client_for(&self.url, self.x_token.clone())
+    }
+
+    /// Clients for the primary endpoint and every extra feed
+    pub fn clients(&self) -> Result<Vec<GrpcClient>> {
+        let mut clients = vec![self.client()?];
+        for feed in &self.extra_feeds {
+            clients.push(self.client_for(&feed.url, feed.x_token.clone())?);
+        }
+        Ok(clients)
+    }
+
+    fn client_for(&self, url: &str, x_token: Option<String>) -> Result<GrpcClient> {
+        GrpcClient::builder(url.to_string())
+            .x_token(x_token)
+            .connect_timeout(Duration::from_millis(self.connect_timeout))
+            .timeout(Duration::from_millis(self.request_timeout))
+            .keepalive(
+                Duration::from_millis(self.keepalive_interval),
+                Duration::from_millis(self.keepalive_timeout),
+            )
+            .max_decoding_message_size(self.max_message_size)
+            .compression(self.compression)
+            .build()
+    }
+}
+
+impl Default for GrpcConfig {
+    fn default() -> Self {
+        Self {
+            url: String::new(),
+            x_token: None,
+            include_failed: false,
+            include_vote: false,
+            commitment: Commitment::Confirmed,
+            confirm_timeout_slots: 32,
+            idle_timeout: MINUTES,
+            keepalive_interval: 10 * SECONDS,
+            keepalive_timeout: 20 * SECONDS,
+            ping_interval: 15 * SECONDS,
+            connect_timeout: 10 * SECONDS,
+            request_timeout: MINUTES,
+            max_message_size: 64 * 1024 * 1024,
+            compression: None,
+            extra_feeds: vec![],
+            reconnect: ReconnectConfig::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Commitment {
+    /// lowest latency, alerts wait until the create transaction is confirmed
+    Processed,
+    #[default]
+    Confirmed,
+}
+
+impl From<Commitment> for CommitmentLevel {
+    fn from(commitment: Commitment) -> Self {
+        match commitment {
+            Commitment::Processed => CommitmentLevel::Processed,
+            Commitment::Confirmed => CommitmentLevel::Confirmed,
+        }
+    }
+}
+
+/// Reconnect backoff for the gRPC stream
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ReconnectConfig {
+    /// first retry delay (milliseconds, `"1s"` in the file)
+    #[serde(deserialize_with = "de_duration_ms")]
+    pub initial_delay: u64,
+    /// upper bound of the retry delay
+    #[serde(deserialize_with = "de_duration_ms")]
+    pub max_delay: u64,
+    /// notify the admin chat after this many consecutive failures
+    pub notify_after: u32,
+}
+
+impl Default for ReconnectConfig {
+    fn default() -> Self {
+        Self {
+            initial_delay: SECONDS,
+            max_delay: MINUTES,
+            notify_after: 5,
+        }
+    }
+}
+
+/// Solana RPC endpoint
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct RpcConfig {
+    pub url: String,
+}
+
+/// Redis connection
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct RedisConfig {
+    pub mode: RedisMode,
+    /// server url, for the `single` mode
+    pub url: String,
+    /// sentinel urls for `sentinel`, node urls for `cluster`
+    pub nodes: Vec<String>,
+    /// master group name, for `sentinel`
+    pub master_name: String,
+    /// database index, overrides the one in `url`
+    pub db: Option<i64>,
+    /// prepended to every key, e.g. `"mon1:"`, so instances sharing a database keep apart
+    pub key_prefix: String,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RedisMode {
+    /// one server at `url`, reconnected after a restart
+    #[default]
+    Single,
+    /// master of the `master_name` group, looked up again after a failover
+    Sentinel,
+    Cluster,
+}
+
+impl RedisConfig {
+    /// Url used to connect, with `db` applied
+    pub fn connection_url(&self) -> String {
+        match (self.db, Url::parse(&self.url)) {
+            (Some(db), Ok(mut url)) => {
+                url.set_path(&format!("/{}", db));
+                url.to_string()
+            }
+            _ => self.url.clone(),
+        }
+    }
+}
+
+/// Monitored program ids (base58) and which of them are subscribed
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ProgramsConfig {
+    pub pumpfun: String,
+    pub pumpamm: String,
+    pub moonshot: String,
+    pub boop: String,
+    /// programs to subscribe to, events of the others are ignored
+    pub enabled: Vec<Program>,
+}
+
+impl Default for ProgramsConfig {
+    fn default() -> Self {
+        Self {
+            pumpfun: PUMPFUN_PROGRAM_ID.to_string(),
+            pumpamm: PUMPAMM_PROGRAM_ID.to_string(),
+            moonshot: MOONSHOT_PROGRAM_ID.to_string(),
+            boop: BOOP_PROGRAM_ID.to_string(),
+            enabled: vec![Program::Pumpfun, Program::Pumpamm],
+        }
+    }
+}
+
+impl ProgramsConfig {
+    pub fn id(&self, program: Program) -> &str {
+        match program {
+            Program::Pumpfun => &self.pumpfun,
+            Program::Pumpamm => &self.pumpamm,
+            Program::Moonshot => &self.moonshot,
+            Program::Boop => &self.boop,
+        }
+    }
+
+    /// Enabled program with the base58 id `id`
+    pub fn program_of(&self, id: &str) -> Option<Program> {
+        self.enabled.iter().copied().find(|program| self.id(*program) == id)
+    }
+
+    pub fn is_enabled(&self, program: Program) -> bool {
+        self.enabled.contains(&program)
+    }
+
+    /// Enabled programs by decoded id, invalid ids are left out
+    pub fn enabled_keys(&self) -> Vec<(Pubkey, Program)> {
+        self.enabled
+            .iter()
+            .filter_map(|program| Some((Pubkey::from_str(self.id(*program)).ok()?, *program)))
+            .collect()
+    }
+
+    /// Program ids of the enabled programs, for the subscription filter
+    pub fn enabled_ids(&self) -> Vec<String> {
+        self.enabled.iter().map(|program| self.id(*program).to_string()).collect()
+    }
+}
+
+/// Event processing
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct EngineConfig {
+    /// number of worker tasks handling parsed events
+    pub workers: usize,
+    /// bounded queue size per worker
+    pub queue_size: usize,
+    /// transaction deduplication by signature
+    pub dedup: DedupMode,
+    /// signatures remembered by the `memory` dedup
+    pub dedup_capacity: usize,
+    /// how long the `redis` dedup remembers a signature (milliseconds, `"10m"` in the file)
+    #[serde(deserialize_with = "de_duration_ms")]
+    pub dedup_ttl: u64,
+    /// events buffered for each event bus subscriber before it starts lagging
+    pub bus_capacity: usize,
+    /// how often lag and queue stats are logged (milliseconds, `"1m"` in the file), 0 disables
+    #[serde(deserialize_with = "de_duration_ms")]
+    pub stats_interval: u64,
+    /// continue from the saved checkpoint on restart and reconnect
+    pub resume: bool,
+    /// largest gap replayed by the gRPC node, older checkpoints are skipped
+    pub max_replay_slots: u64,
+    /// ask the gRPC node to replay from this slot on startup instead of the checkpoint (`--from-slot`)
+    pub from_slot: Option<u64>,
+    /// pass cpi events with unknown discriminators of the monitored programs on as `TargetEvent::Unknown`
+    pub unknown_events: bool,
+    /// append unknown events as JSON lines to this file, empty disables
+    pub unknown_events_dump: String,
+    /// decode SPL Token transfers and mints inside monitored instructions to track holders
+    pub spl_transfers: bool,
+    /// first buyer wallets kept per token from buy trades, with whether they bought in the create block; 0 disables
+    pub first_buyers: usize,
+    /// compute unit prices kept per token from buy transactions, 0 disables
+    pub priority_fee_samples: usize,
+    /// keep a price and market cap history per tracked token this long (milliseconds, `"1h"` in the file), 0 disables
+    #[serde(deserialize_with = "de_duration_ms")]
+    pub price_history: u64,
+    /// aggregate the SOL buy/sell volume of tracked tokens per minute (`cache::token_volume`)
+    pub volume: bool,
+    /// count the distinct buyer wallets of tracked tokens into their records (`TokenRecord::buyers`)
+    pub buyers: bool,
+    /// follow the creator's balance of tracked tokens from trades, or from transfers with `spl_transfers`
+    pub dev_holdings: bool,
+    /// also read the creator balance of tokens past the first alert tier over rpc this often
+    /// (milliseconds, `"1m"` in the file), 0 disables
+    #[serde(deserialize_with = "de_duration_ms")]
+    pub dev_balance_refresh: u64,
+    /// parse geyser transactions straight from the protobuf bytes, the UI-type conversion stays as fallback
+    pub raw_parsing: bool,
+    /// buffer market cap updates per mint and write them in one transaction this often
+    /// (milliseconds, `"250ms"` in the file), 0 writes every update directly
+    #[serde(deserialize_with = "de_duration_ms")]
+    pub write_batch_interval: u64,
+    /// write the buffer early once this many mints are waiting, 0 only flushes on the interval
+    pub write_batch_size: usize,
+    /// with `write_batch_interval`, token records stay in memory until unused this long
+    /// (milliseconds, `"5m"` in the file), untracked mints and pools are looked up again after it
+    #[serde(deserialize_with = "de_duration_ms")]
+    pub hot_record_ttl: u64,
+}
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        Self {
+            workers: 4,
+            queue_size: 1024,
+            dedup: DedupMode::Memory,
+            dedup_capacity: 100_000,
+            dedup_ttl: 10 * MINUTES,
+            bus_capacity: 4096,
+            stats_interval: MINUTES,
+            resume: true,
+            max_replay_slots: 300,
+            from_slot: None,
+            unknown_events: false,
+            unknown_events_dump: String::new(),
+            spl_transfers: false,
+            first_buyers: 20,
+            priority_fee_samples: 0,
+            price_history: 60 * MINUTES,
+            volume: false,
+            buyers: false,
+            dev_holdings: false,
+            dev_balance_refresh: 0,
+            raw_parsing: true,
+            write_batch_interval: 0,
+            write_batch_size: 500,
+            hot_record_ttl: 5 * MINUTES,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DedupMode {
+    Off,
+    /// in-process set of recent signatures
+    #[default]
+    Memory,
+    /// `SET NX` with ttl, shared between instances using the same redis
+    Redis,
+}
+
+/// Market cap thresholds, the age window in which tokens are checked and the check cadence
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct AlertConfig {
+    /// minimum market cap a token needs inside the age window to stay tracked
+    pub market_cap: f32,
+    /// alert tiers, one alert per tier crossed; empty means a single tier at `market_cap`
+    pub tiers: Vec<f32>,
+    /// tokens younger than this are not checked yet (milliseconds, `"10m"` / `"2h"` in the file)
+    #[serde(alias = "new_coin_min_time", deserialize_with = "de_duration_ms")]
+    pub min_age: u64,
+    /// tokens older than this are no longer removed for a low market cap (milliseconds)
+    #[serde(alias = "new_coin_max_time", deserialize_with = "de_duration_ms")]
+    pub max_age: u64,
+    /// how often the market cap check runs (milliseconds, `"40s"` in the file)
+    #[serde(deserialize_with = "de_duration_ms")]
+    pub check_interval: u64,
+    /// alerts show the market cap change over this window from the price history (milliseconds), 0 hides it
+    #[serde(deserialize_with = "de_duration_ms")]
+    pub change_window: u64,
+    /// tokens are only alerted with at least this much SOL volume over 5 minutes, needs `engine.volume`; 0 disables
+    pub min_volume: f64,
+    /// tokens are only alerted when at least this share of the 5 minute volume is buys (0.0-1.0), 0 disables
+    pub min_buy_ratio: f64,
+    /// tokens older than this are forgotten with all their keys (milliseconds, `"24h"` in the file), 0 keeps them
+    #[serde(deserialize_with = "de_duration_ms")]
+    pub record_ttl: u64,
+    /// no further alert for a token this long after its last one (milliseconds, `"5m"` in the file),
+    /// tiers crossed meanwhile fire afterwards; 0 disables
+    #[serde(deserialize_with = "de_duration_ms")]
+    pub cooldown: u64,
+    /// edit sent alerts with the live market cap and ATH, later tiers edit them instead of sending new ones
+    pub live_updates: bool,
+    /// alerts are edited once the market cap moved this many percent from the one shown
+    pub update_min_change: f32,
+    /// reply to sent alerts when the token reaches a `followup_multiples` multiple of its alert
+    /// market cap, graduates or has its PumpSwap liquidity withdrawn
+    pub followups: bool,
+    pub followup_multiples: Vec<f32>,
+    /// share of the pool's liquidity (percent) one withdrawal needs to take for a follow-up
+    pub followup_withdraw_percent: f32,
+    /// a "rugged" follow-up when the market cap falls below this share (percent) of the alert
+    /// market cap, needs `followups`; 0 disables
+    pub rug_floor_percent: f32,
+    /// a withdrawal of at least this share of the pool's liquidity (percent) is a rug instead of a
+    /// liquidity follow-up; 0 disables
+    pub rug_withdraw_percent: f32,
+    /// what happens to the original alert of a rugged token
+    pub rug_cleanup: RugCleanup,
+    /// send a summary this often instead of one alert per token (milliseconds, `"15m"` in the file), 0 disables
+    #[serde(deserialize_with = "de_duration_ms")]
+    pub digest_interval: u64,
+    /// tokens per digest section
+    pub digest_size: usize,
+}
+
+/// Original alert of a rugged token
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RugCleanup {
+    /// left as it is, the follow-up replies to it
+    #[default]
+    Keep,
+    /// replaced with a struck-through "rugged" notice
+    Edit,
+    /// deleted from the chat
+    Delete,
+}
+
+impl Default for AlertConfig {
+    fn default() -> Self {
+        Self {
+            market_cap: 50000.0,
+            tiers: vec![],
+            min_age: NEW_COIN_MIN_TIME,
+            max_age: NEW_COIN_MAX_TIME,
+            check_interval: CHECK_INTERVAL,
+            change_window: 10 * MINUTES,
+            min_volume: 0.0,
+            min_buy_ratio: 0.0,
+            record_ttl: 24 * 60 * MINUTES,
+            cooldown: 0,
+            live_updates: false,
+            update_min_change: 10.0,
+            followups: false,
+            followup_multiples: vec![2.0],
+            followup_withdraw_percent: 50.0,
+            rug_floor_percent: 0.0,
+            rug_withdraw_percent: 0.0,
+            rug_cleanup: RugCleanup::Keep,
+            digest_interval: 0,
+            digest_size: 10,
+        }
+    }
+}
+
+impl AlertConfig {
+    /// Whether the 5 minute volume of a token passes `min_volume` and `min_buy_ratio`
+    pub fn volume_ok(&self, volume: &TokenVolume) -> bool {
+        volume.total() as f64 / LAMPORTS_PER_SOL as f64 >= self.min_volume && volume.buy_ratio() >= self.min_buy_ratio
+    }
+
+    /// Alert tiers in ascending order
+    pub fn tiers(&self) -> Vec<f32> {
+        let mut tiers: Vec<f32> = if self.tiers.is_empty() {
+            vec![self.market_cap]
+        } else {
+            self.tiers.iter().copied().filter(|t| t.is_finite()).collect()
+        };
+        tiers.sort_by(|a, b| a.total_cmp(b));
+        tiers.dedup();
+        tiers
+    }
+
+    /// Overlay runtime overrides (field name -> value) on top of this config.
+    /// Unknown fields and unparsable values are ignored; when the merged thresholds
+    /// are out of range the overrides are rejected as a whole.
+    pub fn with_overrides(&self, overrides: &HashMap<String, String>) -> Result<AlertConfig> {
+        let mut alert = self.clone();
+        for (field, value) in overrides {
+            let value = value.trim();
+            match field.as_str() {
+                "market_cap" => {
+                    if let Ok(v) = value.parse() {
+                        alert.market_cap = v;
+                    }
+                }
+                // comma separated, e.g. `50000,150000,500000`
+                "tiers" => {
+                    let tiers: Result<Vec<f32>, _> = value
+                        .split(',')
+                        .filter(|t| !t.trim().is_empty())
+                        .map(|t| t.trim().parse::<f32>())
+                        .collect();
+                    if let Ok(tiers) = tiers {
+                        alert.tiers = tiers;
+                    }
+                }
+                "min_age" | "new_coin_min_time" => {
+                    if let Some(v) = parse_duration_ms(value) {
+                        alert.min_age = v;
+                    }
+                }
+                "max_age" | "new_coin_max_time" => {
+                    if let Some(v) = parse_duration_ms(value) {
+                        alert.max_age = v;
+                    }
+                }
+                _ => {}
+            }
+        }
+        let mut problems = Vec::new();
+        alert.check_thresholds(&mut problems);
+        if !problems.is_empty() {
+            bail!("invalid alert overrides:\n  - {}", problems.join("\n  - "));
+        }
+        Ok(alert)
+    }
+
+    /// Thresholds and age window checks shared by `Config::validate` and the runtime overrides
+    fn check_thresholds(&self, problems: &mut Vec<String>) {
+        if self.market_cap.is_nan() || self.market_cap <= 0.0 {
+            problems.push(format!("alert.market_cap must be positive, got {}", self.market_cap));
+        }
+        if self.tiers.iter().any(|t| t.is_nan() || *t <= 0.0) {
+            problems.push(format!("alert.tiers must all be positive, got {:?}", self.tiers));
+        }
+        if self.min_age >= self.max_age {
+            problems.push(format!(
+                "alert.min_age ({}ms) must be less than alert.max_age ({}ms)",
+                self.min_age, self.max_age
+            ));
+        }
+        if self.record_ttl != 0 && self.record_ttl <= self.max_age {
+            problems.push(format!(
+                "alert.record_ttl ({}ms) must be 0 or greater than alert.max_age ({}ms)",
+                self.record_ttl, self.max_age
+            ));
+        }
+    }
+}
+
+/// Telegram bots.
+///
+/// `bot_token`/`chat_id` configure the alerts bot; `[telegram.bots.<purpose>]`
+/// adds bots for other purposes (admin, errors). A bot without its own
+/// `bot_token` uses the top-level one.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct TelegramConfig {
+    pub bot_token: String,
+    pub chat_id: String,
+    pub bots: HashMap<String, BotConfig>,
+    /// send coin alerts as a photo of the token image with the alert as caption, text when the image can't be fetched
+    pub alert_images: bool,
+    /// gateways IPFS images and metadata are tried on after the one in the link
+    pub ipfs_gateways: Vec<String>,
+    /// alert destinations by name, each with its own minimum tier and filters; empty sends
+    /// every alert to the alerts bot
+    pub routes: HashMap<String, RouteConfig>,
+    /// least time between two sends to one chat (milliseconds, `"1s"` in the file)
+    #[serde(deserialize_with = "de_duration_ms")]
+    pub chat_send_interval: u64,
+    /// sends per second over all chats, 0 leaves it to Telegram's 429 answers
+    pub max_sends_per_second: u32,
+    /// a send Telegram rate limits is repeated after its `retry_after` at most this often
+    pub send_retries: u32,
+    /// directory with a `coin_alert.md` / `coin_alert.html` Tera template replacing the built-in alert layout, empty for the built-in one
+    pub templates_dir: String,
+    /// parse mode coin alerts are rendered and sent in
+    pub alert_format: AlertFormat,
+    /// language of the alert text (`en`, `zh` or a catalog in `templates_dir/locales`), routes default to it
+    pub language: String,
+    /// take `/mute`, `/blacklist_creator` and `/unblacklist` in the admin chat
+    pub admin_commands: bool,
+    /// user ids allowed to send admin commands, empty for everyone in the admin chat
+    pub admin_users: Vec<i64>,
+    /// users subscribe to alerts with their own filters in a DM with the alerts bot
+    pub subscriptions: bool,
+    /// edit a pinned message with the top tokens by market cap in every route this often
+    /// (milliseconds, `"5m"` in the file), 0 for none
+    #[serde(deserialize_with = "de_duration_ms")]
+    pub trending_interval: u64,
+    /// tokens in the trending message
+    pub trending_size: usize,
+    /// a coin alert that fails to send for other reasons than Telegram refusing it is tried again
+    /// this often, waiting `alert_retry_backoff` doubled per try (milliseconds, `"1s"` in the file)
+    pub alert_retries: u32,
+    #[serde(deserialize_with = "de_duration_ms")]
+    pub alert_retry_backoff: u64,
+    /// tell the admin when this many coin alerts in a row failed to send, 0 never
+    pub failure_notify_after: u32,
+}
+
+/// Telegram parse mode of coin alerts
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertFormat {
+    /// MarkdownV2 from `coin_alert.md`
+    #[default]
+    Markdown,
+    /// HTML from `coin_alert.html`, no escaping pitfalls with links and special characters
+    Html,
+}
+
+impl Default for TelegramConfig {
+    fn default() -> Self {
+        Self {
+            bot_token: String::new(),
+            chat_id: String::new(),
+            bots: HashMap::new(),
+            alert_images: true,
+            ipfs_gateways: vec!["https://ipfs.io/ipfs/".to_string(), "https://dweb.link/ipfs/".to_string()],
+            routes: HashMap::new(),
+            chat_send_interval: SECONDS,
+            max_sends_per_second: 30,
+            send_retries: 3,
+            templates_dir: String::new(),
+            alert_format: AlertFormat::Markdown,
+            language: DEFAULT_LANGUAGE.to_string(),
+            admin_commands: false,
+            admin_users: vec![],
+            subscriptions: false,
+            trending_interval: 0,
+            trending_size: 10,
+            alert_retries: 2,
+            alert_retry_backoff: SECONDS,
+            failure_notify_after: 3,
+        }
+    }
+}
+
+/// A chat coin alerts are routed to, `[telegram.routes.<name>]`
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct RouteConfig {
+    /// defaults to `telegram.bot_token`
+    pub bot_token: String,
+    pub chat_id: String,
+    /// lowest alert tier sent to this chat, also added to the alert tiers; 0 takes every tier
+    pub market_cap: f32,
+    /// minimum SOL volume over 5 minutes, needs `engine.volume`; 0 disables
+    pub min_volume: f64,
+    /// minimum share of buys in the 5 minute volume (0.0-1.0); 0 disables
+    pub min_buy_ratio: f64,
+    /// minimum distinct buyers, needs `engine.buyers`; 0 disables
+    pub min_buyers: u64,
+    /// maximum share of the supply the creator holds (percent), needs `engine.dev_holdings`; 0 disables
+    pub max_dev_percent: f32,
+    /// skip tokens whose creator sold, needs `engine.dev_holdings`
+    pub skip_dev_sold: bool,
+    /// forum topic (`message_thread_id`) alerts are posted in, 0 for the General topic
+    pub topic: i64,
+    /// topic for tokens that graduated to PumpSwap, defaults to `topic`
+    pub amm_topic: i64,
+    /// language of the alerts in this chat, defaults to `telegram.language`
+    pub language: String,
+    /// daily window alerts go out without a notification sound, e.g. `"23:00-07:00"`; empty for none
+    pub quiet_hours: String,
+    /// IANA time zone of `quiet_hours`, e.g. `"Asia/Shanghai"`; UTC when empty
+    pub quiet_timezone: String,
+    /// hold the alerts of quiet hours and send them as one summary when the quiet hours end
+    pub quiet_hold: bool,
+    /// alerts at this tier or above notify even in quiet hours; 0 keeps every alert quiet
+    pub priority_tier: f32,
+    /// only tokens whose X posts link to the website in their metadata
+    pub verified_socials: bool,
+}
+
+impl RouteConfig {
+    /// Whether an alert at `tier` for `record` with this 5 minute `volume` goes to the chat
+    pub fn accepts(&self, tier: f32, record: &TokenRecord, volume: &TokenVolume) -> bool {
+        tier >= self.market_cap
+            && volume.total() as f64 / LAMPORTS_PER_SOL as f64 >= self.min_volume
+            && volume.buy_ratio() >= self.min_buy_ratio
+            && record.buyers >= self.min_buyers
+            && (self.max_dev_percent <= 0.0 || record.dev_percent <= self.max_dev_percent)
+            && !(self.skip_dev_sold && record.dev_sold)
+    }
+
+    /// Whether `timestamp_ms` is in the chat's quiet hours
+    pub fn is_quiet_at(&self, timestamp_ms: u64) -> bool {
+        let (Some(window), Ok(tz)) = (parse_time_window(&self.quiet_hours), self.quiet_tz()) else {
+            return false;
+        };
+        in_time_window(timestamp_ms, tz, window)
+    }
+
+    /// Whether an alert at `tier` goes out quietly (or is held) at `timestamp_ms`, priority
+    /// tiers always notify
+    pub fn silences(&self, tier: f32, timestamp_ms: u64) -> bool {
+        !(self.priority_tier > 0.0 && tier >= self.priority_tier) && self.is_quiet_at(timestamp_ms)
+    }
+
+    fn quiet_tz(&self) -> Result<Tz, chrono_tz::ParseError> {
+        if self.quiet_timezone.is_empty() {
+            return Ok(Tz::UTC);
+        }
+        self.quiet_timezone.parse()
+    }
+
+    /// Forum topic the alert for `record` goes to, `None` for the General topic
+    pub fn topic_for(&self, record: &TokenRecord) -> Option<i64> {
+        let topic = if !record.pool.is_empty() && self.amm_topic > 0 { self.amm_topic } else { self.topic };
+        (topic > 0).then_some(topic)
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct BotConfig {
+    pub bot_token: String,
+    pub chat_id: String,
+}
+
+impl TelegramConfig {
+    /// Bots keyed by purpose name with the default token filled in
+    pub fn resolved_bots(&self) -> Vec<(String, BotConfig)> {
+        let mut bots: Vec<(String, BotConfig)> = self
+            .bots
+            .iter()
+            .map(|(name, bot)| {
+                let mut bot = bot.clone();
+                if bot.bot_token.is_empty() {
+                    bot.bot_token = self.bot_token.clone();
+                }
+                (name.clone(), bot)
+            })
+            .collect();
+
+        if !self.bots.contains_key("alerts") && (!self.bot_token.is_empty() || !self.chat_id.is_empty()) {
+            bots.push((
+                "alerts".to_string(),
+                BotConfig {
+                    bot_token: self.bot_token.clone(),
+                    chat_id: self.chat_id.clone(),
+                },
+            ));
+        }
+        bots.sort_by(|a, b| a.0.cmp(&b.0));
+        bots
+    }
+
+    /// Alert routes sorted by name with the default token and language filled in
+    pub fn resolved_routes(&self) -> Vec<(String, RouteConfig)> {
+        let mut routes: Vec<(String, RouteConfig)> = self
+            .routes
+            .iter()
+            .map(|(name, route)| {
+                let mut route = route.clone();
+                if route.bot_token.is_empty() {
+                    route.bot_token = self.bot_token.clone();
+                }
+                if route.language.is_empty() {
+                    route.language = self.language.clone();
+                }
+                (name.clone(), route)
+            })
+            .collect();
+        routes.sort_by(|a, b| a.0.cmp(&b.0));
+        routes
+    }
+}
+
+/// 1m/5m OHLCV candles per tracked token, built from the event bus
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct CandlesConfig {
+    pub enabled: bool,
+    /// how long closed candles are kept (milliseconds, `"24h"` in the file)
+    #[serde(deserialize_with = "de_duration_ms")]
+    pub retention: u64,
+}
+
+impl Default for CandlesConfig {
+    fn default() -> Self {
+        Self { enabled: false, retention: 24 * 60 * MINUTES }
+    }
+}
+
+/// Redis Pub/Sub output, see the `pubsub` module
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct PubSubConfig {
+    /// publish every parsed event on `events:<program>:<kind>`
+    pub events: bool,
+    /// publish the alert decisions of the market cap check on `alerts:*`
+    pub alerts: bool,
+}
+
+/// Capped Redis Stream of every parsed event, see the `event_log` module
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct EventLogConfig {
+    pub enabled: bool,
+    /// stream key
+    pub key: String,
+    /// approximate number of events kept
+    pub max_len: usize,
+    /// consumer groups created on startup, so they see every event written from then on
+    pub groups: Vec<String>,
+}
+
+impl Default for EventLogConfig {
+    fn default() -> Self {
+        Self { enabled: false, key: "events".to_string(), max_len: 100_000, groups: vec![] }
+    }
+}
+
+/// Where the token records, the pool index and the alert state are kept, see `storage`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct StorageConfig {
+    pub backend: StorageBackend,
+    /// database file, for the `sqlite` backend
+    pub path: String,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self { backend: StorageBackend::Redis, path: "monitor.db".to_string() }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StorageBackend {
+    #[default]
+    Redis,
+    /// local SQLite file, needs the `sqlite` cargo feature
+    Sqlite,
+}
+
+/// X (Twitter) api, the key comes from the secret store (`X_API_KEY`, or `X_BEARER_TOKEN` for
+/// the official api)
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct XConfig {
+    pub provider: XProvider,
+    /// providers asked in this order when `provider` fails, the alert goes out without social
+    /// context when all of them do
+    pub fallback: Vec<XProvider>,
+    /// endpoint of `provider`, empty for its default; the fallbacks use their defaults
+    pub base_url: String,
+    pub api_key: String,
+    pub bearer_token: String,
+    /// tries of a request that fails with a network error, 408, 429 or 5xx
+    pub max_retries: u8,
+    /// wait before the second try, doubled for each further one with jitter (milliseconds,
+    /// `"500ms"` in the file)
+    #[serde(deserialize_with = "de_duration_ms")]
+    pub retry_backoff: u64,
+    /// longest wait between tries; a `Retry-After` beyond it fails the request instead
+    #[serde(deserialize_with = "de_duration_ms")]
+    pub max_retry_backoff: u64,
+    /// requests per minute over all X calls (retries included), 0 for no limit
+    pub requests_per_minute: u32,
+    /// requests that may go out at once after a quiet spell
+    pub burst: u32,
+    /// longest a request queues for the quota (milliseconds, `"10s"` in the file), the alert
+    /// goes out without the tweet after that
+    #[serde(deserialize_with = "de_duration_ms")]
+    pub max_queue_wait: u64,
+    /// latest tweets of a token's own X account (the `twitter` link in its metadata) the AI
+    /// summary reads instead of the search for the mint, 0 for the search only
+    pub account_tweets: usize,
+    /// merged results of the searches for the mint, `$SYMBOL` and the name the AI summary reads
+    /// when the token has no account tweets, the best ranked one is linked in the alert
+    pub search_tweets: usize,
+    /// search results matching one of these regular expressions (case-insensitive) are spam
+    pub spam_patterns: Vec<String>,
+    /// search results containing one of these words (case-insensitive) are spam
+    pub spam_keywords: Vec<String>,
+    /// search results of accounts with fewer followers are spam
+    pub min_followers: i32,
+    /// the same text from this many accounts is a shill template and spam, 0 for no check
+    pub template_accounts: usize,
+    /// like/retweet counts of an alert's X post are fetched again for live updates and digests
+    /// at most this often (milliseconds, `"5m"` in the file), 0 never
+    #[serde(deserialize_with = "de_duration_ms")]
+    pub engagement_interval: u64,
+    /// tweets found for a token are kept in redis this long (milliseconds, `"10m"` in the file)
+    /// and reused by its next alerts instead of searching again, 0 for no cache
+    #[serde(deserialize_with = "de_duration_ms")]
+    pub search_cache_ttl: u64,
+    /// influencer accounts whose tweets are scanned for tracked mints, a mention is sent to
+    /// the routes; empty for no scanner
+    pub kol_accounts: Vec<String>,
+    /// how often the KOL timelines are read (milliseconds, `"2m"` in the file)
+    #[serde(deserialize_with = "de_duration_ms")]
+    pub kol_interval: u64,
+}
+
+impl Default for XConfig {
+    fn default() -> Self {
+        Self {
+            provider: XProvider::Apidance,
+            fallback: Vec::new(),
+            base_url: String::new(),
+            api_key: String::new(),
+            bearer_token: String::new(),
+            max_retries: 3,
+            retry_backoff: 500,
+            max_retry_backoff: 30 * SECONDS,
+            requests_per_minute: 30,
+            burst: 5,
+            max_queue_wait: 10 * SECONDS,
+            account_tweets: 5,
+            search_tweets: 5,
+            spam_patterns: Vec::new(),
+            spam_keywords: Vec::new(),
+            min_followers: 1,
+            template_accounts: 3,
+            engagement_interval: 5 * MINUTES,
+            search_cache_ttl: 10 * MINUTES,
+            kol_accounts: Vec::new(),
+            kol_interval: 2 * MINUTES,
+        }
+    }
+}
+
+impl XConfig {
+    /// `provider` followed by the fallbacks, each once
+    pub fn providers(&self) -> Vec<XProvider> {
+        let mut providers = vec![self.provider];
+        for provider in &self.fallback {
+            if !providers.contains(provider) {
+                providers.push(*provider);
+            }
+        }
+        providers
+    }
+
+    /// `base_url` for `provider`, its default for the fallbacks
+    pub fn base_url_of(&self, provider: XProvider) -> &str {
+        if provider == self.provider && !self.base_url.is_empty() {
+            &self.base_url
+        } else {
+            provider.default_base_url()
+        }
+    }
+
+    /// Key of `provider`
+    pub fn credential_of(&self, provider: XProvider) -> &str {
+        match provider {
+            XProvider::Apidance => &self.api_key,
+            XProvider::Official => &self.bearer_token,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum XProvider {
+    /// apidance.pro, key `X_API_KEY`
+    #[default]
+    Apidance,
+    /// official X API v2 (`tweets/search/recent`), bearer token `X_BEARER_TOKEN`
+    Official,
+}
+
+impl XProvider {
+    pub fn default_base_url(self) -> &'static str {
+        match self {
+            XProvider::Apidance => "https://api.apidance.pro/sapi",
+            XProvider::Official => "https://api.x.com/2",
+        }
+    }
+}
+
+/// Gemini api, the key comes from the secret store (`AI_API_KEY`)
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct AiConfig {
+    pub api_key: String,
+    /// longest one summary request may take (milliseconds, `"30s"` in the file)
+    #[serde(deserialize_with = "de_duration_ms")]
+    pub timeout: u64,
+    /// tries of a request that times out or fails with a network error, 429 or 5xx; the alert
+    /// goes out without the AI analysis when all of them fail
+    pub max_retries: u8,
+    /// wait before the second try, doubled for each further one with jitter (milliseconds,
+    /// `"1s"` in the file)
+    #[serde(deserialize_with = "de_duration_ms")]
+    pub retry_backoff: u64,
+    /// longest wait between tries
+    #[serde(deserialize_with = "de_duration_ms")]
+    pub max_retry_backoff: u64,
+}
+
+impl Default for AiConfig {
+    fn default() -> Self {
+        Self {
+            api_key: String::new(),
+            timeout: 30 * SECONDS,
+            max_retries: 3,
+            retry_backoff: SECONDS,
+            max_retry_backoff: 10 * SECONDS,
+        }
+    }
+}
+
+/// Where api keys and tokens are loaded from
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct SecretsConfig {
+    pub backend: SecretsBackend,
+    /// encrypted secrets file, for the `encrypted_file` backend
+    pub path: String,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SecretsBackend {
+    /// environment variables / `.env`
+    #[default]
+    Env,
+    /// AES-GCM encrypted file unlocked with `SECRETS_PASSPHRASE`
+    EncryptedFile,
+}
+
+impl Config {
+    /// Load the config from `CONFIG_PATH` (default `config.toml`) and apply env overrides.
+    /// The profile is taken from `CONFIG_PROFILE` when set.
+    pub fn load() -> Result<Self> {
+        Self::load_profile(None)
+    }
+
+    /// Load the config with the given profile (falls back to `CONFIG_PROFILE`).
+    pub fn load_profile(profile: Option<&str>) -> Result<Self> {
+        let path = env::var("CONFIG_PATH").unwrap_or_else(|_| DEFAULT_CONFIG_PATH.to_string());
+        let profile = profile
+            .map(|p| p.to_string())
+            .or_else(|| env::var("CONFIG_PROFILE").ok().filter(|p| !p.is_empty()));
+        Self::from_file(&path, profile.as_deref())
+    }
+
+    /// Load the config from the given file. A missing file falls back to
+    /// defaults so an env-only setup still works.
+    pub fn from_file(path: &str, profile: Option<&str>) -> Result<Self> {
+        let mut config = if Path::new(path).exists() {
+            let content = fs::read_to_string(path)
+                .with_context(|| format!("read config file {} error", path))?;
+            Self::from_toml_str(&content, profile)
+                .with_context(|| format!("parse config file {} error", path))?
+        } else if let Some(profile) = profile {
+            bail!("profile `{}` requested but config file {} does not exist", profile, path);
+        } else {
+            Self::default()
+        };
+        config.apply_env_overrides();
+        let store = open_store(&config.secrets)?;
+        config.apply_secrets(store.as_ref())?;
+        Ok(config)
+    }
+
+    /// Parse a config, merging `[profiles.<profile>]` over the base tables
+    pub fn from_toml_str(content: &str, profile: Option<&str>) -> Result<Self> {
+        let mut table = content.parse::<toml::Table>()?;
+        let profiles = match table.remove(PROFILES_KEY) {
+            Some(toml::Value::Table(profiles)) => profiles,
+            Some(_) => bail!("`{}` must be a table", PROFILES_KEY),
+            None => toml::Table::new(),
+        };
+
+        if let Some(name) = profile {
+            match profiles.get(name) {
+                Some(toml::Value::Table(overlay)) => merge_tables(&mut table, overlay.clone()),
+                Some(_) => bail!("profile `{}` must be a table", name),
+                None => bail!(
+                    "unknown profile `{}` (available: {})",
+                    name,
+                    profiles.keys().cloned().collect::<Vec<_>>().join(", ")
+                ),
+            }
+        }
+
+        let mut config: Config = toml::Value::Table(table).try_into()?;
+        config.profile = profile.map(|p| p.to_string());
+        Ok(config)
+    }
+
+    /// Check every required setting and report all problems at once,
+    /// before anything tries to connect.
+    pub fn validate(&self) -> Result<()> {
+        let mut problems = Vec::new();
+
+        check_url(&mut problems, "grpc.url (GRPC_URL)", &self.grpc.url, &["http", "https"]);
+        for (i, feed) in self.grpc.extra_feeds.iter().enumerate() {
+            check_url(&mut problems, &format!("grpc.extra_feeds[{}].url", i), &feed.url, &["http", "https"]);
+        }
+        if !self.grpc.extra_feeds.is_empty() && self.engine.dedup == DedupMode::Off {
+            problems.push("grpc.extra_feeds needs engine.dedup, every transaction arrives once per feed".to_string());
+        }
+        if self.grpc.reconnect.initial_delay == 0 || self.grpc.reconnect.initial_delay > self.grpc.reconnect.max_delay {
+            problems.push("grpc.reconnect.initial_delay must be positive and not above max_delay".to_string());
+        }
+        check_url(&mut problems, "rpc.url (RPC_URL)", &self.rpc.url, &["http", "https"]);
+        check_url(&mut problems, "redis.url (REDIS_URL)", &self.redis.url, &["redis", "rediss", "redis+unix", "unix"]);
+        if matches!(self.redis.db, Some(db) if db < 0) {
+            problems.push("redis.db must not be negative".to_string());
+        }
+
+        match self.redis.mode {
+            RedisMode::Single => {}
+            RedisMode::Sentinel if self.redis.nodes.is_empty() || self.redis.master_name.is_empty() => {
+                problems.push("redis.mode = \"sentinel\" needs redis.nodes and redis.master_name".to_string());
+            }
+            RedisMode::Sentinel => {}
+            RedisMode::Cluster => {
+                if self.redis.nodes.is_empty() {
+                    problems.push("redis.mode = \"cluster\" needs redis.nodes".to_string());
+                }
+                if self.redis.db.is_some_and(|db| db != 0) {
+                    problems.push("redis.db is not supported in cluster mode".to_string());
+                }
+                // the scripts and transactions touch several keys, they have to share a slot
+                if !has_hash_tag(&self.redis.key_prefix) {
+                    problems.push(format!(
+                        "redis.key_prefix must contain a hash tag like \"{{mon1}}:\" in cluster mode, got `{}`",
+                        self.redis.key_prefix
+                    ));
+                }
+            }
+        }
+        if self.storage.backend == StorageBackend::Sqlite {
+            if !cfg!(feature = "sqlite") {
+                problems.push("storage.backend = \"sqlite\" needs a build with the `sqlite` feature".to_string());
+            } else if self.storage.path.is_empty() {
+                problems.push("storage.path is required for the sqlite backend".to_string());
+            }
+        }
+        if self.event_log.enabled && (self.event_log.key.is_empty() || self.event_log.max_len == 0) {
+            problems.push("event_log.key must be set and event_log.max_len at least 1".to_string());
+        }
+        if self.engine.workers == 0 || self.engine.queue_size == 0 {
+            problems.push("engine.workers and engine.queue_size must be at least 1".to_string());
+        }
+        if self.engine.dedup == DedupMode::Memory && self.engine.dedup_capacity == 0 {
+            problems.push("engine.dedup_capacity must be at least 1".to_string());
+        }
+        if self.engine.dedup == DedupMode::Redis && self.engine.dedup_ttl < SECONDS {
+            problems.push("engine.dedup_ttl must be at least 1s".to_string());
+        }
+
+        check_pubkey(&mut problems, "programs.pumpfun", &self.programs.pumpfun);
+        check_pubkey(&mut problems, "programs.pumpamm", &self.programs.pumpamm);
+        check_pubkey(&mut problems, "programs.moonshot", &self.programs.moonshot);
+        check_pubkey(&mut problems, "programs.boop", &self.programs.boop);
+        if self.programs.enabled.is_empty() {
+            problems.push("programs.enabled must list at least one program".to_string());
+        }
+
+        self.alert.check_thresholds(&mut problems);
+        if self.alert.check_interval == 0 {
+            problems.push("alert.check_interval must be greater than 0".to_string());
+        }
+        if self.engine.dev_balance_refresh > 0 && !self.engine.dev_holdings {
+            problems.push("engine.dev_balance_refresh needs engine.dev_holdings = true".to_string());
+        }
+        if (self.alert.min_volume > 0.0 || self.alert.min_buy_ratio > 0.0) && !self.engine.volume {
+            problems.push("alert.min_volume and alert.min_buy_ratio need engine.volume = true".to_string());
+        }
+        if self.alert.followups && self.alert.followup_multiples.iter().any(|multiple| !multiple.is_finite() || *multiple <= 1.0) {
+            problems.push("alert.followup_multiples must all be greater than 1".to_string());
+        }
+        if !(0.0..100.0).contains(&self.alert.rug_floor_percent) || !(0.0..=100.0).contains(&self.alert.rug_withdraw_percent) {
+            problems.push("alert.rug_floor_percent and alert.rug_withdraw_percent must be between 0 and 100".to_string());
+        }
+        if (self.alert.rug_floor_percent > 0.0 || self.alert.rug_withdraw_percent > 0.0) && !self.alert.followups {
+            problems.push("alert.rug_floor_percent and alert.rug_withdraw_percent need alert.followups = true".to_string());
+        }
+        if self.alert.digest_interval > 0 {
+            if self.alert.digest_size == 0 {
+                problems.push("alert.digest_size must be at least 1".to_string());
+            }
+            // 汇总模式没有单条 alert 可改或回复
+            if self.alert.live_updates || self.alert.followups {
+                problems.push("alert.live_updates and alert.followups need single alerts, they don't work with alert.digest_interval".to_string());
+            }
+        }
+
+        let bots = self.telegram.resolved_bots();
+        if self.telegram.routes.is_empty() && !bots.iter().any(|(name, _)| name == "alerts") {
+            problems.push(
+                "telegram: an alerts bot is required (telegram.bot_token/chat_id, TG_BOT_TOKEN/TG_CHAT_ID, [telegram.bots.alerts] or [telegram.routes.<name>])"
+                    .to_string(),
+            );
+        }
+        if self.telegram.trending_interval > 0 {
+            // Telegram rate limits edits of one message
+            if self.telegram.trending_interval < 10 * SECONDS {
+                problems.push("telegram.trending_interval must be 0 or at least 10s".to_string());
+            }
+            if self.telegram.trending_size == 0 {
+                problems.push("telegram.trending_size must be at least 1".to_string());
+            }
+        }
+        if self.telegram.alert_retries > 0 && self.telegram.alert_retry_backoff == 0 {
+            problems.push("telegram.alert_retry_backoff must be greater than 0 with alert_retries".to_string());
+        }
+        if !self.telegram.templates_dir.is_empty() && !Path::new(&self.telegram.templates_dir).is_dir() {
+            problems.push(format!("telegram.templates_dir `{}` is not a directory", self.telegram.templates_dir));
+        }
+        let languages = match locale::languages(&self.telegram.templates_dir) {
+            Ok(languages) => languages,
+            Err(e) => {
+                problems.push(format!("telegram.templates_dir: {:#}", e));
+                vec![DEFAULT_LANGUAGE.to_string()]
+            }
+        };
+        if !languages.contains(&self.telegram.language) {
+            problems.push(format!("telegram.language `{}` has no catalog, one of {}", self.telegram.language, languages.join(", ")));
+        }
+        for (name, route) in self.telegram.resolved_routes() {
+            let prefix = format!("telegram.routes.{}", name);
+            if route.bot_token.is_empty() {
+                problems.push(format!("{}.bot_token is required (or telegram.bot_token / TG_BOT_TOKEN)", prefix));
+            } else if !route.bot_token.contains(':') {
+                problems.push(format!("{}.bot_token is not a valid bot token", prefix));
+            }
+            check_chat_id(&mut problems, &format!("{}.chat_id", prefix), &route.chat_id);
+            if !route.market_cap.is_finite() || route.market_cap < 0.0 {
+                problems.push(format!("{}.market_cap must be 0 or more", prefix));
+            }
+            if !languages.contains(&route.language) {
+                problems.push(format!("{}.language `{}` has no catalog, one of {}", prefix, route.language, languages.join(", ")));
+            }
+            if !route.quiet_hours.is_empty() && parse_time_window(&route.quiet_hours).is_none() {
+                problems.push(format!("{}.quiet_hours `{}` is not a window like \"23:00-07:00\"", prefix, route.quiet_hours));
+            }
+            if route.quiet_hold && route.quiet_hours.is_empty() {
+                problems.push(format!("{}.quiet_hold needs quiet_hours", prefix));
+            }
+            if route.quiet_tz().is_err() {
+                problems.push(format!("{}.quiet_timezone `{}` is not an IANA time zone", prefix, route.quiet_timezone));
+            }
+            if route.topic < 0 || route.amm_topic < 0 {
+                problems.push(format!("{}.topic and amm_topic must be 0 or a topic id", prefix));
+            }
+            if !(0.0..=1.0).contains(&route.min_buy_ratio) {
+                problems.push(format!("{}.min_buy_ratio must be between 0.0 and 1.0", prefix));
+            }
+            if (route.min_volume > 0.0 || route.min_buy_ratio > 0.0) && !self.engine.volume {
+                problems.push(format!("{}.min_volume and min_buy_ratio need engine.volume = true", prefix));
+            }
+            if route.min_buyers > 0 && !self.engine.buyers {
+                problems.push(format!("{}.min_buyers needs engine.buyers = true", prefix));
+            }
+            if (route.max_dev_percent > 0.0 || route.skip_dev_sold) && !self.engine.dev_holdings {
+                problems.push(format!("{}.max_dev_percent and skip_dev_sold need engine.dev_holdings = true", prefix));
+            }
+        }
+        for (name, bot) in &bots {
+            let prefix = if self.telegram.bots.contains_key(name) {
+                format!("telegram.bots.{}", name)
+            } else {
+                "telegram".to_string()
+            };
+            if name.parse::<BotPurpose>().is_err() {
+                problems.push(format!("{}: unknown bot purpose (expected alerts, admin or errors)", prefix));
+            }
+            if bot.bot_token.is_empty() {
+                problems.push(format!("{}.bot_token is required", prefix));
+            } else if !bot.bot_token.contains(':') {
+                problems.push(format!("{}.bot_token is not a valid bot token", prefix));
+            }
+            check_chat_id(&mut problems, &format!("{}.chat_id", prefix), &bot.chat_id);
+        }
+
+        for provider in self.x.providers() {
+            if self.x.credential_of(provider).trim().is_empty() {
+                problems.push(match provider {
+                    XProvider::Apidance => format!("x.api_key (secret {}) is required", X_API_KEY),
+                    XProvider::Official => format!("x.bearer_token (secret {}) is required for the official provider", X_BEARER_TOKEN),
+                });
+            }
+        }
+        check_url(&mut problems, "x.base_url", self.x.base_url_of(self.x.provider), &["http", "https"]);
+        if self.x.max_retries == 0 {
+            problems.push("x.max_retries must be at least 1".to_string());
+        }
+        if self.x.retry_backoff > self.x.max_retry_backoff {
+            problems.push("x.retry_backoff must not be greater than x.max_retry_backoff".to_string());
+        }
+        if self.x.requests_per_minute > 0 && self.x.burst == 0 {
+            problems.push("x.burst must be at least 1 with x.requests_per_minute".to_string());
+        }
+        if !self.x.kol_accounts.is_empty() && self.x.kol_interval < 30 * SECONDS {
+            problems.push("x.kol_interval must be at least 30s with x.kol_accounts".to_string());
+        }
+        for pattern in &self.x.spam_patterns {
+            if let Err(e) = regex::Regex::new(pattern) {
+                problems.push(format!("x.spam_patterns: invalid pattern {:?}: {}", pattern, e));
+            }
+        }
+        if self.ai.api_key.trim().is_empty() {
+            problems.push(format!("ai.api_key (secret {}) is required", AI_API_KEY));
+        }
+        if self.ai.timeout == 0 {
+            problems.push("ai.timeout must be greater than 0".to_string());
+        }
+        if self.ai.max_retries == 0 {
+            problems.push("ai.max_retries must be at least 1".to_string());
+        }
+        if self.ai.retry_backoff > self.ai.max_retry_backoff {
+            problems.push("ai.retry_backoff must not be greater than ai.max_retry_backoff".to_string());
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            bail!("invalid configuration:\n  - {}", problems.join("\n  - "))
+        }
+    }
+
+    /// Environment variables take precedence over values from the file
+    pub fn apply_env_overrides(&mut self) {
+        if let Ok(url) = env::var("GRPC_URL") {
+            self.grpc.url = url;
+        }
+        if let Ok(url) = env::var("RPC_URL") {
+            self.rpc.url = url;
+        }
+        if let Ok(url) = env::var("REDIS_URL") {
+            self.redis.url = url;
+        }
+        if let Some(mk) = env::var("MARKET_CAP").ok().and_then(|v| v.trim().parse::<f32>().ok()) {
+            self.alert.market_cap = mk;
+        }
+        if let Ok(chat_id) = env::var("TG_CHAT_ID") {
+            self.telegram.chat_id = chat_id;
+        }
+    }
+
+    /// Fill api keys and tokens from the secret store
+    pub fn apply_secrets(&mut self, store: &dyn SecretStore) -> Result<()> {
+        if let Some(token) = store.get(TG_BOT_TOKEN)? {
+            self.telegram.bot_token = token;
+        }
+        if let Some(key) = store.get(X_API_KEY)? {
+            self.x.api_key = key;
+        }
+        if let Some(token) = store.get(X_BEARER_TOKEN)? {
+            self.x.bearer_token = token;
+        }
+        if let Some(key) = store.get(AI_API_KEY)? {
+            self.ai.api_key = key;
+        }
+        if let Some(token) = store.get(GRPC_X_TOKEN)? {
+            self.grpc.x_token = Some(token);
+        }
+        Ok(())
+    }
+}
+
+/// Pick `--profile <name>` / `--profile=<name>` out of the command line arguments
+pub fn profile_arg(args: impl IntoIterator<Item = String>) -> Option<String> {
+    flag_value(args, "--profile")
+}
+
+/// Pick `--from-slot <slot>` out of the command line arguments
+pub fn from_slot_arg(args: impl IntoIterator<Item = String>) -> Option<u64> {
+    flag_value(args, "--from-slot").and_then(|slot| slot.parse().ok())
+}
+
+fn flag_value(args: impl IntoIterator<Item = String>, flag: &str) -> Option<String> {
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == flag {
+            return args.next();
+        }
+        if let Some(value) = arg.strip_prefix(flag).and_then(|rest| rest.strip_prefix('=')) {
+            return Some(value.to_string());
+        }
+    }
+    None
+}
+
+/// Parse a duration like `500ms`, `90s`, `10m`, `2h` or `1d` into milliseconds.
+/// A bare number is taken as milliseconds.
+pub fn parse_duration_ms(value: &str) -> Option<u64> {
+    let value = value.trim();
+    let split = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number = number.parse::<u64>().ok()?;
+    let scale = match unit.trim() {
+        "" | "ms" => 1,
+        "s" => SECONDS,
+        "m" => MINUTES,
+        "h" => 60 * MINUTES,
+        "d" => 24 * 60 * MINUTES,
+        _ => return None,
+    };
+    number.checked_mul(scale)
+}
+
+// a non-empty `{...}` section, which redis cluster hashes instead of the whole key
+fn has_hash_tag(prefix: &str) -> bool {
+    match prefix.find('{') {
+        Some(open) => prefix[open + 1..].find('}').is_some_and(|len| len > 0),
+        None => false,
+    }
+}
+
+/// Durations may be written as a number of milliseconds or as a string (`"10m"`)
+fn de_duration_ms<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum RawDuration {
+        Millis(u64),
+        Text(String),
+    }
+
+    match RawDuration::deserialize(deserializer)? {
+        RawDuration::Millis(ms) => Ok(ms),
+        RawDuration::Text(text) => parse_duration_ms(&text)
+            .ok_or_else(|| de::Error::custom(format!("invalid duration `{}`", text))),
+    }
+}
+
+/// Recursively merge `overlay` into `base`, overlay values win
+fn merge_tables(base: &mut toml::Table, overlay: toml::Table) {
+    for (key, value) in overlay {
+        if let (Some(toml::Value::Table(base_table)), toml::Value::Table(overlay_table)) =
+            (base.get_mut(&key), &value)
+        {
+            merge_tables(base_table, overlay_table.clone());
+            continue;
+        }
+        base.insert(key, value);
+    }
+}
+
+fn check_pubkey(problems: &mut Vec<String>, name: &str, value: &str) {
+    if let Err(e) = Pubkey::from_str(value) {
+        problems.push(format!("{} is not a valid pubkey `{}`: {}", name, value, e));
+    }
+}
+
+fn check_url(problems: &mut Vec<String>, name: &str, value: &str, schemes: &[&str]) {
+    if value.is_empty() {
+        problems.push(format!("{} is required", name));
+        return;
+    }
+    match Url::parse(value) {
+        Ok(url) if schemes.contains(&url.scheme()) => {}
+        Ok(url) => problems.push(format!(
+            "{} has unsupported scheme `{}` (expected one of {})",
+            name,
+            url.scheme(),
+            schemes.join(", ")
+        )),
+        Err(e) => problems.push(format!("{} is not a valid url `{}`: {}", name, value, e)),
+    }
+}
+
+/// chat ids are numeric (`-100...` for groups/channels) or a public `@channel` name
+fn check_chat_id(problems: &mut Vec<String>, name: &str, value: &str) {
+    if value.is_empty() {
+        problems.push(format!("{} is required", name));
+    } else if !(value.parse::<i64>().is_ok() || (value.starts_with('@') && value.len() > 1)) {
+        problems.push(format!("{} must be a numeric id or @channel, got `{}`", name, value));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_config_test() {
+        let config = Config::from_toml_str(
+            r#"
+            [grpc]
+            url = "https://solana-yellowstone-grpc.publicnode.com:443"
+
+            [redis]
+            url = "redis://127.0.0.1/"
+
+            [alert]
+            market_cap = 80000.0
+            max_age = "2h"
+            "#,
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(config.grpc.url, "https://solana-yellowstone-grpc.publicnode.com:443");
+        assert_eq!(config.redis.url, "redis://127.0.0.1/");
+        assert_eq!(config.alert.market_cap, 80000.0);
+        assert_eq!(config.alert.max_age, 2 * 60 * MINUTES);
+        // missing fields fall back to defaults
+        assert_eq!(config.alert.min_age, NEW_COIN_MIN_TIME);
+        assert_eq!(config.alert.check_interval, CHECK_INTERVAL);
+        assert_eq!(config.alert.tiers(), vec![80000.0]);
+        assert!(config.rpc.url.is_empty());
+    }
+
+    #[test]
+    fn profile_config_test() {
+        let content = r#"
+            [grpc]
+            url = "https://mainnet.example.com"
+
+            [redis]
+            url = "redis://127.0.0.1/"
+
+            [alert]
+            market_cap = 80000.0
+
+            [profiles.dev.grpc]
+            url = "https://devnet.example.com"
+
+            [profiles.dev.redis]
+            db = 2
+        "#;
+
+        let config = Config::from_toml_str(content, Some("dev")).unwrap();
+        assert_eq!(config.profile.as_deref(), Some("dev"));
+        assert_eq!(config.grpc.url, "https://devnet.example.com");
+        assert_eq!(config.redis.url, "redis://127.0.0.1/");
+        assert_eq!(config.redis.connection_url(), "redis://127.0.0.1/2");
+        assert_eq!(config.alert.market_cap, 80000.0);
+
+        let config = Config::from_toml_str(content, None).unwrap();
+        assert_eq!(config.grpc.url, "https://mainnet.example.com");
+
+        assert!(Config::from_toml_str(content, Some("prod")).is_err());
+        assert_eq!(
+            profile_arg(["sol_new", "--profile", "dev"].map(String::from)),
+            Some("dev".to_string())
+        );
+        assert_eq!(profile_arg(["sol_new", "--profile=prod"].map(String::from)), Some("prod".to_string()));
+        assert_eq!(from_slot_arg(["sol_new", "--from-slot", "1234"].map(String::from)), Some(1234));
+        assert_eq!(from_slot_arg(["sol_new", "--from-slotx=1"].map(String::from)), None);
+    }
+
+    #[test]
+    fn alert_overrides_test() {
+        let alert = AlertConfig::default();
+        let overrides = HashMap::from([
+            ("market_cap".to_string(), "120000".to_string()),
+            ("tiers".to_string(), "500000, 150000".to_string()),
+            ("min_age".to_string(), "10m".to_string()),
+            ("max_age".to_string(), "not a duration".to_string()),
+            ("unknown".to_string(), "1".to_string()),
+        ]);
+
+        let merged = alert.with_overrides(&overrides).unwrap();
+        assert_eq!(merged.market_cap, 120000.0);
+        assert_eq!(merged.tiers(), vec![150000.0, 500000.0]);
+        assert_eq!(merged.min_age, 10 * MINUTES);
+        assert_eq!(merged.max_age, NEW_COIN_MAX_TIME);
+
+        // out of range after merging, nothing is applied
+        let overrides = HashMap::from([
+            ("market_cap".to_string(), "0".to_string()),
+            ("min_age".to_string(), "3h".to_string()),
+            ("max_age".to_string(), "1h".to_string()),
+        ]);
+        let err = alert.with_overrides(&overrides).unwrap_err().to_string();
+        assert!(err.contains("alert.market_cap must be positive"));
+        assert!(err.contains("alert.min_age"));
+    }
+
+    #[test]
+    fn parse_duration_test() {
+        assert_eq!(parse_duration_ms("600000"), Some(600000));
+        assert_eq!(parse_duration_ms("90s"), Some(90 * SECONDS));
+        assert_eq!(parse_duration_ms("10m"), Some(10 * MINUTES));
+        assert_eq!(parse_duration_ms("2h"), Some(120 * MINUTES));
+        assert_eq!(parse_duration_ms("10 m"), Some(10 * MINUTES));
+        assert_eq!(parse_duration_ms("m"), None);
+        assert_eq!(parse_duration_ms("10w"), None);
+    }
+
+    #[test]
+    fn hash_tag_test() {
+        assert!(has_hash_tag("{mon1}:"));
+        assert!(has_hash_tag("app:{a}"));
+        assert!(!has_hash_tag("mon1:"));
+        assert!(!has_hash_tag("{}:"));
+        assert!(!has_hash_tag("{mon1:"));
+    }
+
+    #[test]
+    fn validate_reports_all_problems_test() {
+        let mut config = Config::default();
+        config.grpc.url = "not a url".to_string();
+        config.redis.url = "http://127.0.0.1".to_string();
+        config.alert.min_age = config.alert.max_age;
+        config.telegram.chat_id = "alerts".to_string();
+
+        let err = config.validate().unwrap_err().to_string();
+        assert!(err.contains("grpc.url"));
+        assert!(err.contains("rpc.url (RPC_URL) is required"));
+        assert!(err.contains("redis.url (REDIS_URL) has unsupported scheme `http`"));
+        assert!(err.contains("alert.min_age"));
+        assert!(err.contains("telegram.bot_token is required"));
+        assert!(err.contains("telegram.chat_id"));
+    }
+
+    #[test]
+    fn resolved_bots_test() {
+        let config = Config::from_toml_str(
+            r#"
+            [telegram]
+            bot_token = "1:default"
+            chat_id = "-1001"
+
+            [telegram.bots.admin]
+            chat_id = "-1002"
+
+            [telegram.bots.errors]
+            bot_token = "2:errors"
+            chat_id = "-1003"
+            "#,
+            None,
+        )
+        .unwrap();
+
+        let bots = config.telegram.resolved_bots();
+        let names: Vec<_> = bots.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["admin", "alerts", "errors"]);
+        assert_eq!(bots[0].1.bot_token, "1:default");
+        assert_eq!(bots[1].1.chat_id, "-1001");
+        assert_eq!(bots[2].1.bot_token, "2:errors");
+    }
+
+    #[test]
+    fn route_accepts_test() {
+        let config = Config::from_toml_str(
+            r#"
+            [telegram]
+            bot_token = "1:default"
+
+            [telegram.routes.quality]
+            chat_id = "-1002"
+            market_cap = 200000.0
+            min_buyers = 50
+            max_dev_percent = 5.0
+            skip_dev_sold = true
+
+            [telegram.routes.degens]
+            chat_id = "-1001"
+            market_cap = 30000.0
+            language = "zh"
+            "#,
+            None,
+        )
+        .unwrap();
+
+        let routes = config.telegram.resolved_routes();
+        assert_eq!(routes[0].0, "degens");
+        assert_eq!(routes[1].1.bot_token, "1:default");
+        assert_eq!((routes[0].1.language.as_str(), routes[1].1.language.as_str()), ("zh", "en"));
+
+        let (degens, quality) = (&routes[0].1, &routes[1].1);
+        let volume = TokenVolume::default();
+        let record = TokenRecord { buyers: 80, dev_percent: 3.0, ..Default::default() };
+        assert!(degens.accepts(50000.0, &record, &volume));
+        assert!(!quality.accepts(50000.0, &record, &volume));
+        assert!(quality.accepts(200000.0, &record, &volume));
+        assert!(!degens.accepts(20000.0, &record, &volume));
+
+        let sold = TokenRecord { dev_sold: true, ..record.clone() };
+        assert!(!quality.accepts(200000.0, &sold, &volume));
+        assert!(degens.accepts(200000.0, &sold, &volume));
+        let few_buyers = TokenRecord { buyers: 10, ..record };
+        assert!(!quality.accepts(200000.0, &few_buyers, &volume));
+    }
+
+    #[test]
+    fn route_quiet_hours_test() {
+        let route = RouteConfig {
+            quiet_hours: "23:00-07:00".to_string(),
+            quiet_timezone: "Asia/Shanghai".to_string(),
+            priority_tier: 500000.0,
+            ..Default::default()
+        };
+        // 2024-01-01 16:00 UTC is 00:00 in Shanghai, 04:00 UTC is 12:00
+        let (midnight, noon) = (1_704_124_800_000, 1_704_081_600_000);
+        assert!(route.is_quiet_at(midnight));
+        assert!(!route.is_quiet_at(noon));
+        assert!(route.silences(100000.0, midnight));
+        assert!(!route.silences(500000.0, midnight));
+        assert!(!RouteConfig::default().is_quiet_at(midnight));
+        assert_eq!(parse_time_window("7:30-9:00"), Some((450, 540)));
+        assert_eq!(parse_time_window("25:00-07:00"), None);
+    }
+
+    #[test]
+    fn route_topic_test() {
+        let route = RouteConfig { topic: 12, amm_topic: 34, ..Default::default() };
+        let curve = TokenRecord::default();
+        let amm = TokenRecord { pool: "pool".to_string(), ..Default::default() };
+        assert_eq!(route.topic_for(&curve), Some(12));
+        assert_eq!(route.topic_for(&amm), Some(34));
+
+        let route = RouteConfig { topic: 12, ..Default::default() };
+        assert_eq!(route.topic_for(&amm), Some(12));
+        assert_eq!(RouteConfig::default().topic_for(&amm), None);
+    }
+}
@@ -1,30 +1,14 @@
-use std::env;
-
-use once_cell::sync::Lazy;
 use solana_program::pubkey;
 use solana_sdk::pubkey::Pubkey;
 
-pub static GRPC: Lazy<String> = Lazy::new(|| env::var("GRPC_URL").unwrap());
-pub static RPC: Lazy<String> = Lazy::new(|| env::var("RPC_URL").unwrap());
-
-
-
-pub static REDIS_URL: Lazy<String> = Lazy::new(|| env::var("REDIS_URL").unwrap());
-
-pub static MARKET_CAP: Lazy<f32> = Lazy::new(|| {
-    env::var("MARKET_CAP")
-        .unwrap()
-        .parse::<f32>()
-        .unwrap_or(50000.0)
-}); 
-
-
 // program related
 pub const SYSTEM_PROGRAM_ID: Pubkey = pubkey!("11111111111111111111111111111111");
 pub const SYSTEM_RENT_PROGRAM_ID: Pubkey = pubkey!("SysvarRent111111111111111111111111111111111");
 pub const TOKEN_PROGRAM_ID: Pubkey = pubkey!("TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA");
+pub const TOKEN_2022_PROGRAM_ID: Pubkey = pubkey!("TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb");
 pub const ASSOC_TOKEN_ACC_PROGRAM_ID: Pubkey =
     pubkey!("ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL");
+pub const COMPUTE_BUDGET_PROGRAM_ID: Pubkey = pubkey!("ComputeBudget111111111111111111111111111111");
 pub const EVENT_AUTHORITY: Pubkey = pubkey!("Ce6TQqeHC9p8KetsN6JsjHK7UTZk7nasjjnr7XxXp9F1");
 pub const KEY_PREFIX: &'static str = "token:info:";
 
@@ -38,6 +22,31 @@ pub const INIT_PRICE: f32 = (INIT_SOL_REVERSES as f32 / 1e9) / (INIT_TOKEN_REVER
 pub const PUMPFUN_TOTAL_SUPPLY: u64 = 1_000_000_000_000_000;
 
 pub const PUMPAMM_PROGRAM_ID: Pubkey = pubkey!("pAMMBay6oceH9fJKBRHGP5D4bD4sWpmSwMn52FMfXEA");
+
+/// Constant product curve over virtual reserves, the price follows from the tokens sold
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VirtualCurve {
+    pub init_sol_reserves: u64,
+    pub init_token_reserves: u64,
+    pub total_supply: u64,
+    pub token_decimals: u8,
+}
+
+// 9 decimal tokens, one billion supply; moonshot and boop.fun launch on the same curve
+const NINE_DECIMAL_CURVE: VirtualCurve = VirtualCurve {
+    init_sol_reserves: 30_000_000_000,
+    init_token_reserves: 1_073_000_000_000_000_000,
+    total_supply: 1_000_000_000_000_000_000,
+    token_decimals: 9,
+};
+
+// moonshot
+pub const MOONSHOT_PROGRAM_ID: Pubkey = pubkey!("MoonCVVNZFSYkqNXP6bxHLPL6QQJiMagDL3qcqUQTrG");
+pub const MOONSHOT_CURVE: VirtualCurve = NINE_DECIMAL_CURVE;
+
+// boop.fun
+pub const BOOP_PROGRAM_ID: Pubkey = pubkey!("boop8hVGQGqehUK2iVEMEnMrL5RbjywRzHKBmBE7ry4");
+pub const BOOP_CURVE: VirtualCurve = NINE_DECIMAL_CURVE;
  
 // scalars
 pub const MINUTES: u64 = 60 * 1000;
@@ -48,4 +57,5 @@ pub const WSOL: Pubkey = pubkey!("So11111111111111111111111111111111111111112");
  
 // Time
 pub const NEW_COIN_MIN_TIME: u64 = 10 * 60 * 1000; // 10分钟 (以毫秒为单位)
-pub const NEW_COIN_MAX_TIME: u64 = 15 * 60 * 1000; // 15分钟 (以毫秒为单位)
\ No newline at end of file
+pub const NEW_COIN_MAX_TIME: u64 = 15 * 60 * 1000; // 15分钟 (以毫秒为单位)
+pub const CHECK_INTERVAL: u64 = 40 * SECONDS; // 每40秒检查一次市值, check market cap every 40 seconds
\ No newline at end of file
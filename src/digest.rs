@@ -0,0 +1,287 @@
+//! Digest mode, `alert.digest_interval`.
+//!
+//! The market cap check holds the tokens it would alert (`cache::add_digest_token`) and every
+//! `alert.digest_interval` one summary goes to each route: the alerted tokens by market cap, the
+//! tracked tokens that graduated to PumpSwap since the last digest and the biggest market cap
+//! moves over the interval. Alerted tokens show the current counts of the X post of their alert.
+//!
+//! Routes with `quiet_hold` get the alerts held in their quiet hours as such a summary once the
+//! quiet hours end.
+
+use std::{collections::HashMap, time::Duration};
+
+use solana_sdk::timing::timestamp;
+
+use redis::RedisResult;
+use tokio::{
+    sync::watch,
+    time::{interval_at, Instant, MissedTickBehavior},
+};
+use tracing::{debug, warn};
+
+use crate::{
+    cache::{alert_messages, blocklist, digest_tokens, mark_digest_graduated, price_change, settle_digest_tokens, settle_quiet_alerts, quiet_alerts, token_records, DigestEntry, TokenRecord},
+    constants::MINUTES,
+    config::AlertConfig,
+    redis_conn::RedisConn,
+    social::SocialChain,
+    tg_bot::{locale::text, notifier::Notifier, tg_bot::{escape_markdown, escape_markdown_url}},
+    utils::format_duration_ms,
+};
+
+/// A token in a digest
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DigestToken {
+    pub mint: String,
+    pub name: String,
+    pub symbol: String,
+    pub market_cap: f32,
+    /// token page on its launchpad
+    pub chart_url: String,
+    /// market cap change over the digest interval in percent, movers only
+    pub change: f64,
+    /// routes that took the alert, alerted tokens only
+    pub routes: Vec<String>,
+    /// current likes, retweets and replies of the alert's X post, alerted tokens only
+    pub engagement: String,
+}
+
+impl DigestToken {
+    pub fn new(record: &TokenRecord) -> Self {
+        Self {
+            mint: record.mint.clone(),
+            name: record.name.clone(),
+            symbol: record.symbol.clone(),
+            market_cap: record.market_cap,
+            chart_url: record.launchpad().chart_url(&record.mint),
+            ..Default::default()
+        }
+    }
+
+    /// Name linked to the chart, symbol, market cap and engagement as MarkdownV2
+    pub fn markdown(&self) -> String {
+        let engagement = if self.engagement.is_empty() { String::new() } else { format!(" `{}`", self.engagement) };
+        format!(
+            "[{}]({}) \\(`{}`\\) `{} SOL`{}",
+            escape_markdown(&self.name),
+            escape_markdown_url(&self.chart_url),
+            escape_markdown(&self.symbol),
+            escape_markdown(&format!("{:.0}", self.market_cap)),
+            engagement,
+        )
+    }
+}
+
+/// One summary, the sections sorted and cut to `alert.digest_size`
+#[derive(Debug, Clone, Default)]
+pub struct Digest {
+    /// interval the digest covers (milliseconds), 0 for the alerts held in quiet hours
+    pub period: u64,
+    /// tokens alerted in the interval, highest market cap first
+    pub top: Vec<DigestToken>,
+    /// tokens that graduated since the last digest, highest market cap first
+    pub graduated: Vec<DigestToken>,
+    /// biggest market cap changes over the interval, either way
+    pub movers: Vec<DigestToken>,
+}
+
+impl Digest {
+    /// The digest for the route `route` whose lowest tier is `min_market_cap` as MarkdownV2
+    /// in `language`, empty when it has nothing for the route
+    pub fn markdown(&self, route: &str, min_market_cap: f32, language: &str) -> String {
+        let top: Vec<&DigestToken> = self.top.iter().filter(|token| token.routes.iter().any(|name| name == route)).collect();
+        let graduated: Vec<&DigestToken> = self.graduated.iter().filter(|token| token.market_cap >= min_market_cap).collect();
+        let movers: Vec<&DigestToken> = self.movers.iter().filter(|token| token.market_cap >= min_market_cap).collect();
+        if top.is_empty() && graduated.is_empty() && movers.is_empty() {
+            return String::new();
+        }
+
+        let title = if self.period == 0 {
+            text(language, "quiet_title", &[])
+        } else {
+            text(language, "digest_title", &[("period", &format_duration_ms(self.period))])
+        };
+        let mut sections = vec![format!("🗞 *{}*", escape_markdown(&title))];
+        if !top.is_empty() {
+            let lines: Vec<String> =
+                top.iter().enumerate().map(|(i, token)| format!("{}\\. {}", i + 1, token.markdown())).collect();
+            sections.push(format!("🏆 *{}*\n{}", escape_markdown(&text(language, "digest_top", &[])), lines.join("\n")));
+        }
+        if !graduated.is_empty() {
+            let lines: Vec<String> = graduated.iter().map(|token| format!("• {}", token.markdown())).collect();
+            sections.push(format!("🎓 *{}*\n{}", escape_markdown(&text(language, "digest_graduated", &[])), lines.join("\n")));
+        }
+        if !movers.is_empty() {
+            let lines: Vec<String> = movers
+                .iter()
+                .map(|token| format!("• {} `{}`", token.markdown(), escape_markdown(&format!("{:+.0}%", token.change))))
+                .collect();
+            sections.push(format!("📈 *{}*\n{}", escape_markdown(&text(language, "digest_movers", &[])), lines.join("\n")));
+        }
+        sections.join("\n\n")
+    }
+}
+
+/// Collect the digest of the last `alert.digest_interval` with the alerted tokens it was built
+/// from, for `settle_digest_tokens` once it is sent
+pub async fn collect_digest(
+    conn: &mut RedisConn,
+    alert: &AlertConfig,
+    social: &SocialChain,
+) -> RedisResult<(Digest, HashMap<String, DigestEntry>)> {
+    // muted tokens are left out of every section
+    let blocklist = blocklist(conn).await?;
+    let mut records = token_records(conn).await?;
+    records.retain(|_, record| !blocklist.blocks(record));
+    let size = alert.digest_size;
+
+    // tokens removed meanwhile are left out
+    let alerted = digest_tokens(conn).await?;
+    let mut top: Vec<DigestToken> = alerted
+        .iter()
+        .filter_map(|(mint, entry)| Some(DigestToken { routes: entry.routes.clone(), ..DigestToken::new(records.get(mint)?) }))
+        .collect();
+    top.sort_by(|a, b| b.market_cap.total_cmp(&a.market_cap));
+    top.truncate(size);
+    if social.engagement_interval() > 0 {
+        for token in &mut top {
+            if let Some(messages) = alert_messages(conn, &token.mint).await? {
+                token.engagement = social.tweet_engagement(&messages.details.ai_from_x_url).await.unwrap_or(messages.details.x_engagement);
+            }
+        }
+    }
+
+    let pools: Vec<String> =
+        records.values().filter(|record| !record.pool.is_empty()).map(|record| record.mint.clone()).collect();
+    let mut graduated: Vec<DigestToken> = mark_digest_graduated(conn, &pools)
+        .await?
+        .iter()
+        .filter_map(|mint| Some(DigestToken::new(records.get(mint)?)))
+        .collect();
+    graduated.sort_by(|a, b| b.market_cap.total_cmp(&a.market_cap));
+    graduated.truncate(size);
+
+    // only tokens at an alert tier count as movers, the rest is noise
+    let lowest = alert.tiers().first().copied().unwrap_or(alert.market_cap);
+    let mut movers = Vec::new();
+    for record in records.values().filter(|record| record.market_cap >= lowest) {
+        if let Some(change) = price_change(conn, &record.mint, alert.digest_interval).await? {
+            movers.push(DigestToken { change, ..DigestToken::new(record) });
+        }
+    }
+    movers.sort_by(|a, b| b.change.abs().total_cmp(&a.change.abs()));
+    movers.truncate(size);
+
+    Ok((Digest { period: alert.digest_interval, top, graduated, movers }, alerted))
+}
+
+/// Send a digest every `alert.digest_interval` until shutdown
+pub async fn run_digest(mut conn: RedisConn, alert: AlertConfig, notifier: Notifier, social: SocialChain, mut shutdown: watch::Receiver<bool>) {
+    let period = Duration::from_millis(alert.digest_interval.max(1));
+    let mut ticker = interval_at(Instant::now() + period, period);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        tokio::select! {
+            biased;
+            _ = shutdown.changed() => break,
+            _ = ticker.tick() => {}
+        }
+        match collect_digest(&mut conn, &alert, &social).await {
+            Ok((digest, alerted)) => {
+                debug!("digest: {} top, {} graduated, {} movers", digest.top.len(), digest.graduated.len(), digest.movers.len());
+                // the alerted tokens are dropped only once their routes got the digest
+                let failed = notifier.send_digest(&digest).await;
+                if let Err(e) = settle_digest_tokens(&mut conn, &alerted, &failed).await {
+                    warn!("settle digest tokens error: {}", e);
+                }
+            }
+            Err(e) => warn!("collect digest error: {}", e),
+        }
+    }
+}
+
+/// Send the alerts held for `quiet_hold` routes once their quiet hours end, checked every minute
+/// until shutdown
+pub async fn run_quiet_releases(mut conn: RedisConn, notifier: Notifier, size: usize, mut shutdown: watch::Receiver<bool>) {
+    let period = Duration::from_millis(MINUTES);
+    let mut ticker = interval_at(Instant::now() + period, period);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        tokio::select! {
+            biased;
+            _ = shutdown.changed() => break,
+            _ = ticker.tick() => {}
+        }
+        for route in notifier.routes().iter().filter(|route| route.filter.quiet_hold && !route.filter.is_quiet_at(timestamp())) {
+            let result = match quiet_alerts(&mut conn, &route.name).await {
+                Ok(held) if held.is_empty() => continue,
+                Ok(held) => match blocklist(&mut conn).await {
+                    Ok(blocklist) => token_records(&mut conn).await.map(|mut records| {
+                        records.retain(|_, record| !blocklist.blocks(record));
+                        (held, records)
+                    }),
+                    Err(e) => Err(e),
+                },
+                Err(e) => Err(e),
+            };
+            let (held, records) = match result {
+                Ok(result) => result,
+                Err(e) => {
+                    warn!("release quiet alerts of route {} error: {}", route.name, e);
+                    continue;
+                }
+            };
+            // tokens removed or muted meanwhile are left out
+            let mut top: Vec<DigestToken> = held
+                .keys()
+                .filter_map(|mint| Some(DigestToken { routes: vec![route.name.clone()], ..DigestToken::new(records.get(mint)?) }))
+                .collect();
+            top.sort_by(|a, b| b.market_cap.total_cmp(&a.market_cap));
+            top.truncate(size);
+            debug!("releasing {} quiet alerts of route {}", top.len(), route.name);
+            // 发送失败就留着, the held alerts are dropped only once the summary went out
+            let failed = notifier.send_digest(&Digest { top, ..Default::default() }).await;
+            if failed.contains(&route.name) {
+                continue;
+            }
+            if let Err(e) = settle_quiet_alerts(&mut conn, &route.name, &held).await {
+                warn!("settle quiet alerts of route {} error: {}", route.name, e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn digest_markdown_test() {
+        let token = DigestToken {
+            mint: "Mint1".to_string(),
+            name: "Cool.Token".to_string(),
+            symbol: "CT".to_string(),
+            market_cap: 420.4,
+            chart_url: "https://pump.fun/Mint1".to_string(),
+            change: 0.0,
+            routes: vec!["degens".to_string()],
+            engagement: String::new(),
+        };
+        let digest = Digest {
+            period: 15 * 60_000,
+            top: vec![token.clone()],
+            graduated: vec![],
+            movers: vec![DigestToken { change: 125.0, market_cap: 30.0, routes: vec![], ..token }],
+        };
+        assert_eq!(
+            digest.markdown("degens", 0.0, "en"),
+            "🗞 *Digest of the last 15m*\n\n🏆 *Top tokens*\n1\\. [Cool\\.Token](https://pump.fun/Mint1) \\(`CT`\\) `420 SOL`\
+             \n\n📈 *Biggest movers*\n• [Cool\\.Token](https://pump.fun/Mint1) \\(`CT`\\) `30 SOL` `\\+125%`"
+        );
+        // the mover is below the route's tier and the token went to another route
+        assert_eq!(digest.markdown("quality", 100.0, "en"), "");
+        assert!(digest.markdown("degens", 100.0, "zh").starts_with("🗞 *过去 15m 汇总*\n\n🏆 *市值排行*"));
+        let held = Digest { top: digest.top.clone(), ..Default::default() };
+        assert!(held.markdown("degens", 0.0, "en").starts_with("🗞 *Alerts held during quiet hours*\n\n🏆"));
+    }
+}
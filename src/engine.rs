@@ -1,269 +1,1463 @@
-use std::sync::Arc;
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeMap, HashMap, HashSet, VecDeque},
+    hash::{Hash, Hasher},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
-use futures_util::StreamExt;
-use redis::aio::MultiplexedConnection;
+use futures_util::{stream::select_all, StreamExt};
 use reqwest::Client;
-use solana_client::nonblocking::rpc_client::RpcClient;
-use solana_transaction_status::{option_serializer::OptionSerializer, UiInnerInstructions, UiTransactionStatusMeta};
-use tokio::sync::Mutex;
-use tracing::{debug, info, trace};
-use yellowstone_grpc_proto::geyser::subscribe_update::UpdateOneof;
+use solana_client::{
+    nonblocking::rpc_client::RpcClient, rpc_client::GetConfirmedSignaturesForAddress2Config,
+    rpc_config::RpcTransactionConfig,
+};
+use solana_sdk::{
+    bs58, commitment_config::CommitmentConfig, pubkey::Pubkey, signature::Signature, timing::timestamp,
+    transaction::VersionedTransaction,
+};
+use solana_transaction_status::{
+    option_serializer::OptionSerializer, UiLoadedAddresses, UiTransactionEncoding, UiTransactionStatusMeta,
+    UiTransactionTokenBalance,
+};
+use tokio::{
+    sync::{broadcast, mpsc, watch, Mutex},
+    task::JoinHandle,
+    time::{interval_at, sleep, Instant, MissedTickBehavior},
+};
+use tracing::{debug, info, trace, warn};
+use yellowstone_grpc_proto::{
+    geyser::{subscribe_update::UpdateOneof, CommitmentLevel},
+    prelude::TokenBalance,
+};
 
 use crate::{
-    cache::{
-        add_token_info, check_mk, from_pool_query_token_mint, query_token_info, update_mk
-    }, client::GrpcClient, constants::{
-        GRPC, PUMPAMM_PROGRAM_ID, PUMPFUN_PROGRAM_ID, REDIS_URL, RPC
-    }, tg_bot::tg_bot::get_instance, types::TargetEvent, utils::{
-        cal_pumpamm_marketcap_precise, cal_pumpamm_price, cal_pumpfun_marketcap, cal_pumpfun_price, convert_to_encoded_tx
-    }, x::get_x_instance 
+    ai::{ai_client, AiClient}, bus::{BusEvent, EventBus}, candles::run_candles, digest::{run_digest, run_quiet_releases}, event_log::{create_groups, EventLogWriter}, pubsub::run_event_publisher, cache::{
+        check_mk, dev_balance, token_records, update_dev_balance, DevBalance, get_pumpfun_params, migrate_alert_markers, migrate_token_records, rebuild_token_indexes, get_threshold_overrides, mark_signature_seen, record_feed_latency, record_parse_failures, get_checkpoint, set_block_hash, set_checkpoint, set_key_prefix, set_token_store, BlockhashInfo, Checkpoint
+    }, client::{include_filter, AccountWatchlist, GrpcClient}, config::{AlertConfig, Commitment, Config, DedupMode, EngineConfig}, constants::{MOONSHOT_CURVE, PUMPFUN_TOTAL_SUPPLY, SECONDS, TOKEN_2022_PROGRAM_ID, TOKEN_PROGRAM_ID, WSOL}, handler::{
+        BuyerTracker, DevHoldingsTracker, EventContext, EventHandler, EventLogger, FirstBuyerTracker, HandlerRegistry, HolderTracker, LifecycleAlerts, MarketCapTracker, PriorityFeeTracker, UnknownEventDumper, VolumeTracker
+    }, raw::{InstructionView, RawInstruction, RawTransaction}, redis_conn::RedisConn, stats::EngineStats, tg_bot::{commands::run_bot_commands, notifier::Notifier, registry::BotRegistry}, kol::run_kol_scanner, trending::run_trending, types::{ComputeBudget, ParseFailure, Program, TargetEvent, TokenInstruction, TokenTransfer}, utils::{
+        associated_token_address, backoff_with_jitter, convert_to_encoded_tx
+    }, social::{social_chain, SocialChain}, storage::open_store
 };
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
+
+// recent slots whose block time is kept for transactions arriving after their block meta
+const BLOCK_TIME_SLOTS: u64 = 150;
 
 
 pub struct Monitor {
+    pub config: Config,
+    pub bots: BotRegistry,
+    pub handlers: HandlerRegistry,
+    pub bus: EventBus,
+    pub stats: Arc<EngineStats>,
+    /// accounts watched at runtime, e.g. a pool after graduation
+    pub watchlist: AccountWatchlist,
+    /// primary feed first, then `grpc.extra_feeds`; channels are kept across reconnects
+    pub grpc: Vec<GrpcClient>,
     pub rpc: Arc<RpcClient>,
     pub http: reqwest::Client,
     pub transaction_lock: Arc<Mutex<()>>,
-    pub redis: MultiplexedConnection,
+    pub redis: RedisConn,
+    // enabled programs by decoded id, the raw parsing path compares keys without base58
+    program_keys: Vec<(Pubkey, Program)>,
 }
 
 impl Monitor {
-    pub async fn new() -> Result<Self> {
-        let redis = redis::Client::open(REDIS_URL.to_string())?;
-        let conn = redis
-            .get_multiplexed_async_connection()
-            .await
-            .context("get redis connection error")
-            .unwrap();
+    pub async fn new(config: Config) -> Result<Self> {
+        set_key_prefix(&config.redis.key_prefix);
+        if let Some(store) = open_store(&config.storage).context("open token store error")? {
+            set_token_store(store);
+        }
+        let conn = RedisConn::connect(&config.redis).await.context("get redis connection error")?;
+
+        let bots = BotRegistry::from_config(&config.telegram)?;
+
+        let migrated = migrate_token_records(&mut conn.clone()).await?;
+        if migrated > 0 {
+            info!("migrated {} token records to JSON", migrated);
+        }
+        let tiers = Notifier::new(&config.telegram, bots.clone()).tiers(&config.alert);
+        let markers = migrate_alert_markers(&mut conn.clone(), &tiers).await?;
+        if markers > 0 {
+            info!("migrated {} alert markers to the alert state", markers);
+        }
+        let indexed = rebuild_token_indexes(&mut conn.clone()).await?;
+        debug!("indexed {} token records", indexed);
+
+        let mut handlers = HandlerRegistry::default();
+        let pumpfun_params = get_pumpfun_params(&mut conn.clone()).await?.unwrap_or_default();
+        let tracker = MarketCapTracker::new(conn.clone(), pumpfun_params, config.engine.price_history);
+        if config.engine.write_batch_interval > 0 {
+            handlers.register(tracker.with_write_batch(config.engine.write_batch_size, config.engine.hot_record_ttl));
+        } else {
+            handlers.register(tracker);
+        }
+        handlers.register(EventLogger);
+        if config.engine.priority_fee_samples > 0 {
+            handlers.register(PriorityFeeTracker::new(conn.clone(), config.engine.priority_fee_samples));
+        }
+        if config.engine.volume {
+            handlers.register(VolumeTracker::new(conn.clone()));
+        }
+        if config.engine.buyers {
+            handlers.register(BuyerTracker::new(conn.clone()));
+        }
+        if config.engine.spl_transfers {
+            handlers.register(HolderTracker::new(conn.clone()));
+        }
+        if config.engine.dev_holdings {
+            handlers.register(DevHoldingsTracker::new(conn.clone(), config.engine.spl_transfers));
+        }
+        if config.engine.first_buyers > 0 {
+            handlers.register(FirstBuyerTracker::new(conn.clone(), config.engine.first_buyers));
+        }
+        if config.alert.followups {
+            handlers.register(LifecycleAlerts::new(conn.clone(), Notifier::new(&config.telegram, bots.clone()), &config.alert));
+        }
+        if config.engine.unknown_events && !config.engine.unknown_events_dump.is_empty() {
+            handlers.register(UnknownEventDumper::new(config.engine.unknown_events_dump.clone()));
+        }
+        if config.event_log.enabled {
+            create_groups(&mut conn.clone(), &config.event_log).await;
+            handlers.register(EventLogWriter::new(conn.clone(), config.event_log.clone()));
+        }
 
         Ok(Self {
-            rpc: Arc::new(RpcClient::new(RPC.to_string())),
+            bots,
+            handlers,
+            bus: EventBus::new(config.engine.bus_capacity),
+            stats: Arc::new(EngineStats::default()),
+            watchlist: AccountWatchlist::default(),
+            grpc: config.grpc.clients()?,
+            rpc: Arc::new(RpcClient::new(config.rpc.url.clone())),
             http: Client::new(),
             transaction_lock: Arc::new(Mutex::new(())),
             redis: conn,
+            program_keys: config.programs.enabled_keys(),
+            config,
         })
     } 
 
+    /// Add a handler that is called for every parsed event, after the built-in ones
+    pub fn register_handler(&mut self, handler: impl EventHandler + 'static) {
+        self.handlers.register(handler);
+    }
+
+    /// Receive every parsed event, subscribe before calling `run`
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<BusEvent>> {
+        self.bus.subscribe()
+    }
+
+    /// Run until SIGINT/SIGTERM
     pub async fn run(&self) -> Result<()> {
-        // grpc
-        let grpc_url = GRPC.to_string();
-        let tg_instance = get_instance();
-        let x_instance = get_x_instance();
-        
-        let grpc = GrpcClient::new(grpc_url);
-        let mut stream = grpc
-            .subscribe_transaction(
-                vec![PUMPAMM_PROGRAM_ID.to_string(), PUMPFUN_PROGRAM_ID.to_string()],
-                vec![],
-                vec![],
-                yellowstone_grpc_proto::geyser::CommitmentLevel::Confirmed,
-            )
-            .await?;
-
-        let mut block_times = 0;
-
-        // receive messages
-        while let Some(Ok(sub)) = stream.next().await {
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        tokio::spawn(async move {
+            shutdown_signal().await;
+            info!("shutdown signal received");
+            let _ = shutdown_tx.send(true);
+        });
+        self.run_until(shutdown_rx).await
+    }
+
+    /// Run until `shutdown` flips to true, then drain the workers and persist the checkpoint
+    pub async fn run_until(&self, shutdown: watch::Receiver<bool>) -> Result<()> {
+        let reconnect = &self.config.grpc.reconnect;
+        let checkpoint = if self.config.engine.resume {
+            get_checkpoint(&mut self.redis.clone()).await?.unwrap_or_default()
+        } else {
+            Checkpoint::default()
+        };
+        if checkpoint.slot > 0 {
+            info!("resuming after slot {} (tx {})", checkpoint.slot, checkpoint.signature);
+        }
+        // market cap checks run on their own schedule, even while the stream is down
+        let conn = RedisConn::connect(&self.config.redis)
+            .await
+            .context("get redis connection for market cap checks error")?;
+        // one client per X provider for all tasks, they share its request quota
+        let social = social_chain(&self.config.x);
+        let checker = tokio::spawn(run_market_cap_checks(
+            conn,
+            self.config.alert.clone(),
+            Notifier::new(&self.config.telegram, self.bots.clone()),
+            social.clone(),
+            ai_client(&self.config.ai),
+            self.config.pubsub.alerts,
+            shutdown.clone(),
+        ));
+
+        // digest mode sends one summary every `alert.digest_interval` instead of single alerts
+        let digest = if self.config.alert.digest_interval > 0 {
+            let conn = RedisConn::connect(&self.config.redis)
+                .await
+                .context("get redis connection for digests error")?;
+            Some(tokio::spawn(run_digest(
+                conn,
+                self.config.alert.clone(),
+                Notifier::new(&self.config.telegram, self.bots.clone()),
+                social.clone(),
+                shutdown.clone(),
+            )))
+        } else {
+            None
+        };
+
+        // alerts held in the quiet hours of `quiet_hold` routes go out when the quiet hours end
+        let notifier = Notifier::new(&self.config.telegram, self.bots.clone());
+        let quiet_releases = if notifier.routes().iter().any(|route| route.filter.quiet_hold) {
+            let conn = RedisConn::connect(&self.config.redis)
+                .await
+                .context("get redis connection for quiet hours error")?;
+            Some(tokio::spawn(run_quiet_releases(conn, notifier, self.config.alert.digest_size, shutdown.clone())))
+        } else {
+            None
+        };
+
+        // pinned top tokens per route, edited every `telegram.trending_interval`
+        let trending = if self.config.telegram.trending_interval > 0 {
+            let conn = RedisConn::connect(&self.config.redis)
+                .await
+                .context("get redis connection for trending error")?;
+            Some(tokio::spawn(run_trending(
+                conn,
+                Notifier::new(&self.config.telegram, self.bots.clone()),
+                self.config.telegram.trending_interval,
+                self.config.telegram.trending_size,
+                shutdown.clone(),
+            )))
+        } else {
+            None
+        };
+
+        // tracked tokens mentioned in the timelines of `x.kol_accounts`
+        let kol = if !self.config.x.kol_accounts.is_empty() {
+            let conn = RedisConn::connect(&self.config.redis)
+                .await
+                .context("get redis connection for the KOL scanner error")?;
+            Some(tokio::spawn(run_kol_scanner(
+                conn,
+                social.clone(),
+                Notifier::new(&self.config.telegram, self.bots.clone()),
+                self.config.x.kol_accounts.clone(),
+                self.config.x.kol_interval,
+                self.config.alert.record_ttl,
+                shutdown.clone(),
+            )))
+        } else {
+            None
+        };
+
+        // `/mute` and friends from the admin chat, `/subscribe` in DMs with the alerts bot; one
+        // poller per bot token
+        let telegram = &self.config.telegram;
+        let admin = self.bots.admin().filter(|_| telegram.admin_commands);
+        let subscriptions = self.bots.alerts().filter(|_| telegram.subscriptions);
+        let pollers = match (admin, subscriptions) {
+            (Some(admin), Some(alerts)) if admin.bot_token == alerts.bot_token => vec![(admin, Some(telegram.admin_users.clone()), true)],
+            (admin, alerts) => admin
+                .map(|bot| (bot, Some(telegram.admin_users.clone()), false))
+                .into_iter()
+                .chain(alerts.map(|bot| (bot, None, true)))
+                .collect(),
+        };
+        let mut bot_commands = Vec::new();
+        for (bot, admins, subscriptions) in pollers {
+            let conn = RedisConn::connect(&self.config.redis)
+                .await
+                .context("get redis connection for bot commands error")?;
+            bot_commands.push(tokio::spawn(run_bot_commands(conn, bot.clone(), admins, subscriptions, shutdown.clone())));
+        }
+
+        // events go out on pubsub from the bus like the candles
+        let publisher = if self.config.pubsub.events {
+            let conn = RedisConn::connect(&self.config.redis)
+                .await
+                .context("get redis connection for pubsub error")?;
+            Some(tokio::spawn(run_event_publisher(self.bus.subscribe(), conn, shutdown.clone())))
+        } else {
+            None
+        };
+
+        // candles follow the event bus, subscribed before the first event is published
+        let candles = if self.config.candles.enabled {
+            let conn = RedisConn::connect(&self.config.redis)
+                .await
+                .context("get redis connection for candles error")?;
+            Some(tokio::spawn(run_candles(self.bus.subscribe(), conn, self.config.candles.clone(), shutdown.clone())))
+        } else {
+            None
+        };
+
+        // creator balances of alert candidates are read over rpc every `engine.dev_balance_refresh`
+        let dev_balances = if self.config.engine.dev_balance_refresh > 0 {
+            let conn = RedisConn::connect(&self.config.redis)
+                .await
+                .context("get redis connection for dev balances error")?;
+            let min_market_cap = self.config.alert.tiers().first().copied().unwrap_or(self.config.alert.market_cap);
+            Some(tokio::spawn(run_dev_balance_refresh(
+                self.rpc.clone(),
+                conn,
+                self.config.engine.dev_balance_refresh,
+                min_market_cap,
+                shutdown.clone(),
+            )))
+        } else {
+            None
+        };
+
+        // buffered handler writes go out every `engine.write_batch_interval`
+        let flusher = (self.config.engine.write_batch_interval > 0).then(|| {
+            tokio::spawn(run_handler_flushes(
+                self.handlers.clone(),
+                self.config.engine.write_batch_interval,
+                shutdown.clone(),
+            ))
+        });
+
+        let mut state = self.new_run_state(checkpoint, shutdown);
+        state.replay_from = self.config.engine.from_slot;
+        state.track_confirmations = self.config.grpc.commitment == Commitment::Processed;
+
+        loop {
+            let result = self.process_stream(&mut state).await;
+            self.watchlist.attach(vec![]);
+            let reason = match result {
+                Ok(()) => "stream ended".to_string(),
+                Err(e) => format!("{:#}", e),
+            };
+            if *state.shutdown.borrow() {
+                break;
+            }
+
+            state.failures += 1;
+            let delay = backoff_with_jitter(state.failures, reconnect.initial_delay, reconnect.max_delay);
+            warn!("grpc stream disconnected: {}, reconnecting in {}ms (attempt {})", reason, delay, state.failures);
+            if state.failures == reconnect.notify_after {
+                self.notify_admin(&format!(
+                    "⚠️ gRPC stream failed {} times in a row, last error: {}",
+                    state.failures, reason
+                ))
+                .await;
+            }
+            tokio::select! {
+                _ = sleep(Duration::from_millis(delay)) => {}
+                _ = state.shutdown.changed() => break,
+            }
+        }
+
+        // drain queued events before leaving
+        info!("shutting down, draining workers");
+        // the queues are drained, only events still waiting for confirmation are left out
+        let checkpoint = Checkpoint { slot: state.handled_slot(), signature: state.checkpoint.signature.clone() };
+        state.workers.shutdown().await;
+        if let Some(dev_balances) = dev_balances {
+            let _ = dev_balances.await;
+        }
+        if let Some(flusher) = flusher {
+            let _ = flusher.await;
+        }
+        self.handlers.flush().await;
+        let _ = checker.await;
+        if let Some(digest) = digest {
+            let _ = digest.await;
+        }
+        if let Some(quiet_releases) = quiet_releases {
+            let _ = quiet_releases.await;
+        }
+        if let Some(trending) = trending {
+            let _ = trending.await;
+        }
+        if let Some(kol) = kol {
+            let _ = kol.await;
+        }
+        for bot_commands in bot_commands {
+            let _ = bot_commands.await;
+        }
+        if let Some(candles) = candles {
+            let _ = candles.await;
+        }
+        if let Some(publisher) = publisher {
+            let _ = publisher.await;
+        }
+
+        if checkpoint.slot > 0 {
+            let mut conn = self.redis.clone();
+            set_checkpoint(&mut conn, &checkpoint).await?;
+            info!("checkpoint slot {} saved", checkpoint.slot);
+        }
+        Ok(())
+    }
+
+    /// Rebuild redis state from the program transactions in `from_slot..=to_slot`.
+    ///
+    /// Signatures come from `getSignaturesForAddress`, transactions from `getTransaction`,
+    /// and go through the same handlers as the live stream. Returns the number of transactions.
+    pub async fn replay(&self, from_slot: u64, to_slot: u64) -> Result<usize> {
+        let mut signatures = Vec::new();
+        let mut seen = HashSet::new();
+        for program in self.config.programs.enabled_ids() {
+            let program = Pubkey::from_str(&program)?;
+            // pages go from newest to oldest
+            let mut before = None;
+            'pages: loop {
+                let page = self
+                    .rpc
+                    .get_signatures_for_address_with_config(
+                        &program,
+                        GetConfirmedSignaturesForAddress2Config {
+                            before,
+                            until: None,
+                            limit: Some(1000),
+                            commitment: Some(CommitmentConfig::confirmed()),
+                        },
+                    )
+                    .await
+                    .with_context(|| format!("get signatures for {} error", program))?;
+                let Some(last) = page.last() else { break };
+                before = Some(Signature::from_str(&last.signature)?);
+                for status in page {
+                    if status.slot < from_slot {
+                        break 'pages;
+                    }
+                    if status.slot <= to_slot && status.err.is_none() && seen.insert(status.signature.clone()) {
+                        signatures.push((status.slot, status.signature));
+                    }
+                }
+            }
+        }
+        // oldest first so updates of a token are applied in order
+        signatures.reverse();
+        signatures.sort_by_key(|(slot, _)| *slot);
+        info!("replaying {} transactions in slots {}..={}", signatures.len(), from_slot, to_slot);
+
+        let (_shutdown_tx, shutdown) = watch::channel(false);
+        let mut state = self.new_run_state(Checkpoint::default(), shutdown);
+        for (index, (slot, signature)) in signatures.iter().enumerate() {
+            if !state.dedup.is_new(signature).await? {
+                continue;
+            }
+            let tx = self
+                .rpc
+                .get_transaction_with_config(
+                    &Signature::from_str(signature)?,
+                    RpcTransactionConfig {
+                        encoding: Some(UiTransactionEncoding::Base64),
+                        commitment: Some(CommitmentConfig::confirmed()),
+                        max_supported_transaction_version: Some(0),
+                    },
+                )
+                .await
+                .with_context(|| format!("get transaction {} error", signature))?;
+            let transaction = tx.transaction.transaction.decode();
+            let fee_payer = transaction
+                .as_ref()
+                .and_then(|tx| tx.message.static_account_keys().first().map(|key| key.to_string()))
+                .unwrap_or_default();
+            if let Some(meta) = tx.transaction.meta {
+                let ctx = EventContext {
+                    signature: signature.clone(),
+                    slot: *slot,
+                    block_time: tx.block_time.unwrap_or_default(),
+                    fee_payer,
+                    ..Default::default()
+                };
+                self.update_token_info(ctx, transaction, meta, &mut state).await?;
+            }
+            if (index + 1) % 1000 == 0 {
+                info!("replayed {}/{} transactions", index + 1, signatures.len());
+            }
+        }
+        state.workers.shutdown().await;
+        self.handlers.flush().await;
+        Ok(signatures.len())
+    }
+
+    fn new_run_state(&self, checkpoint: Checkpoint, shutdown: watch::Receiver<bool>) -> RunState {
+        RunState {
+            workers: WorkerPool::spawn(
+                self.config.engine.workers,
+                self.config.engine.queue_size,
+                self.handlers.clone(),
+                self.stats.clone(),
+            ),
+            dedup: SignatureDedup::new(&self.config.engine, self.redis.clone()),
+            failures: 0,
+            stalled: false,
+            saved_slot: checkpoint.slot,
+            checkpoint,
+            block_times: BTreeMap::new(),
+            track_confirmations: false,
+            replay_from: None,
+            pending: PendingConfirmations::default(),
+            shutdown,
+        }
+    }
+
+    /// Slot to replay from, when resuming and the gap is small enough for the gRPC node
+    async fn resume_from_slot(&self, state: &mut RunState) -> Option<u64> {
+        if let Some(slot) = state.replay_from.take() {
+            return Some(slot);
+        }
+        let last_slot = state.checkpoint.slot;
+        if !self.config.engine.resume || last_slot == 0 {
+            return None;
+        }
+        let tip = match self.rpc.get_slot().await {
+            Ok(tip) => tip,
+            Err(e) => {
+                warn!("get current slot error, not replaying: {}", e);
+                return None;
+            }
+        };
+        let gap = tip.saturating_sub(last_slot);
+        if gap > self.config.engine.max_replay_slots {
+            warn!(
+                "{} slots behind the checkpoint, more than max_replay_slots, events in slots {}..{} are missed",
+                gap, last_slot, tip
+            );
+            return None;
+        }
+        Some(last_slot + 1)
+    }
+
+    /// Subscribe and process messages until the stream ends, errors or shutdown is requested
+    async fn process_stream(&self, state: &mut RunState) -> Result<()> {
+        let from_slot = self.resume_from_slot(state).await;
+        if let Some(slot) = from_slot {
+            info!("replaying from slot {}", slot);
+        }
+        let mut accounts = self.config.programs.enabled_ids();
+        accounts.extend(self.watchlist.accounts());
+
+        // every feed gets the same subscription, the first copy of a transaction wins
+        // and later copies are dropped by the signature dedup
+        // by feed index, a feed that failed to subscribe has no handle
+        let mut subscriptions = HashMap::new();
+        let mut streams = Vec::new();
+        let mut last_error = None;
+        for (feed, grpc) in self.grpc.iter().enumerate() {
+            let result = grpc
+                .subscribe_transaction(
+                    accounts.clone(),
+                    vec![],
+                    vec![],
+                    include_filter(self.config.grpc.include_failed),
+                    include_filter(self.config.grpc.include_vote),
+                    from_slot,
+                    self.config.grpc.commitment.into(),
+                )
+                .await
+                .with_context(|| format!("subscribe transaction error (feed {})", feed));
+            match result {
+                Ok((subscription, stream)) => {
+                    subscriptions.insert(feed, subscription);
+                    streams.push(stream.map(move |message| (feed, message)).boxed());
+                }
+                Err(e) => {
+                    warn!("{:#}", e);
+                    last_error = Some(e);
+                }
+            }
+        }
+        if streams.is_empty() {
+            return Err(last_error.unwrap_or_else(|| anyhow!("no grpc feed configured")));
+        }
+        let feeds = streams.len();
+        let mut failed_feeds = HashSet::new();
+        let mut stream = select_all(streams);
+        self.watchlist.attach(subscriptions.values().cloned().collect());
+
+        let stats_interval = self.config.engine.stats_interval;
+        let mut stats_tick = interval_at(
+            Instant::now() + Duration::from_millis(stats_interval.max(1)),
+            Duration::from_millis(stats_interval.max(1)),
+        );
+
+        let ping_interval = self.config.grpc.ping_interval;
+        let mut ping_tick = interval_at(
+            Instant::now() + Duration::from_millis(ping_interval.max(1)),
+            Duration::from_millis(ping_interval.max(1)),
+        );
+        let mut ping_id: i32 = 0;
+
+        // watchdog, reset by every transaction update
+        let idle_timeout = Duration::from_millis(self.config.grpc.idle_timeout.max(1));
+        let idle = sleep(idle_timeout);
+        tokio::pin!(idle);
+
+        // receive messages, a message being processed is always finished before shutdown
+        loop {
+            let message = tokio::select! {
+                biased;
+                _ = state.shutdown.changed() => return Ok(()),
+                _ = &mut idle, if self.config.grpc.idle_timeout > 0 => {
+                    if !state.stalled {
+                        state.stalled = true;
+                        self.notify_admin(&format!(
+                            "⚠️ No transactions received for {}s, reconnecting the gRPC stream",
+                            idle_timeout.as_secs()
+                        ))
+                        .await;
+                    }
+                    return Err(anyhow!("no transaction updates for {}s", idle_timeout.as_secs()));
+                }
+                // keeps reporting while the stream is stalled
+                _ = stats_tick.tick(), if stats_interval > 0 => {
+                    let snapshot = self.stats.snapshot(state.workers.queue_depth());
+                    info!("engine stats: {}", snapshot);
+                    if !snapshot.parse_errors.is_empty() {
+                        warn!("parse failures in the last {}ms: {}", stats_interval, snapshot.parse_failures_display());
+                        if let Err(e) = record_parse_failures(&mut self.redis.clone(), &snapshot.parse_errors).await {
+                            warn!("record parse failures error: {}", e);
+                        }
+                    }
+                    if let Err(e) = record_feed_latency(&mut self.redis.clone(), &snapshot.feed_latency).await {
+                        warn!("record feed latency error: {}", e);
+                    }
+                    continue;
+                }
+                _ = ping_tick.tick(), if ping_interval > 0 => {
+                    ping_id = ping_id.wrapping_add(1);
+                    for subscription in subscriptions.values() {
+                        if let Err(e) = subscription.ping(ping_id) {
+                            debug!("grpc ping error: {}", e);
+                        }
+                    }
+                    continue;
+                }
+                message = stream.next() => match message {
+                    Some(message) => message,
+                    None => return Ok(()),
+                },
+            };
+            let (feed, message) = message;
+            let sub = match message {
+                Ok(sub) => sub,
+                // keep going on the remaining feeds
+                Err(e) if failed_feeds.len() + 1 < feeds => {
+                    if failed_feeds.insert(feed) {
+                        warn!("grpc feed {} error, continuing on the other feeds: {}", feed, e);
+                    }
+                    continue;
+                }
+                Err(e) => return Err(e).context("grpc stream error"),
+            };
+            if let Some(created_at) = &sub.created_at {
+                let created_at_micros = created_at.seconds * 1_000_000 + created_at.nanos as i64 / 1000;
+                self.stats.observe_feed_latency(feed, created_at_micros);
+            }
+            if state.failures > 0 {
+                if state.failures >= self.config.grpc.reconnect.notify_after {
+                    self.notify_admin("✅ gRPC stream reconnected").await;
+                }
+                info!("grpc stream recovered after {} failed attempts", state.failures);
+                state.failures = 0;
+            }
+
             if let Some(update) = sub.update_oneof {
                 match update {
                     UpdateOneof::Transaction(sub_tx) => {
+                        idle.as_mut().reset(Instant::now() + idle_timeout);
+                        if state.stalled {
+                            state.stalled = false;
+                            self.notify_admin("✅ Transactions are flowing again").await;
+                        }
+                        self.stats.observe_processed_slot(sub_tx.slot);
                         if let Some(tx_info) = sub_tx.transaction {
-                            let tx = convert_to_encoded_tx(tx_info)?;
+                            let signature = bs58::encode(&tx_info.signature).into_string();
+                            let fee_payer = tx_info
+                                .transaction
+                                .as_ref()
+                                .and_then(|tx| tx.message.as_ref())
+                                .and_then(|message| message.account_keys.first())
+                                .map(|key| bs58::encode(key).into_string())
+                                .unwrap_or_default();
+                            if !state.dedup.is_new(&signature).await? {
+                                trace!("skip duplicate transaction {}", signature);
+                                continue;
+                            }
+                            let ctx = EventContext {
+                                signature: signature.clone(),
+                                slot: sub_tx.slot,
+                                block_time: state
+                                    .block_times
+                                    .get(&sub_tx.slot)
+                                    .copied()
+                                    .unwrap_or_else(|| (timestamp() / 1000) as i64),
+                                fee_payer,
+                                ..Default::default()
+                            };
+                            if self.config.engine.raw_parsing {
+                                if let Some(raw) = RawTransaction::new(&tx_info) {
+                                    self.update_token_info_raw(ctx, raw, state).await?;
+                                    state.checkpoint.signature = signature;
+                                    continue;
+                                }
+                            }
+                            // one transaction that does not convert must not end the stream
+                            let tx = match convert_to_encoded_tx(tx_info) {
+                                Ok(tx) => tx,
+                                Err(e) => {
+                                    warn!("skip transaction {}: {:#}", signature, e);
+                                    self.stats.observe_decode_error();
+                                    continue;
+                                }
+                            };
+                            let transaction = tx.transaction.decode();
                             if let Some(meta) = tx.meta {
-                                self.update_token_info(meta).await?;
+                                self.update_token_info(ctx, transaction, meta, state).await?;
+                                state.checkpoint.signature = signature;
                             }
                         }
                     }
 
                     UpdateOneof::BlockMeta(meta) => {
-                        block_times += 1;
+                        self.stats.observe_chain_slot(meta.slot);
+                        if let Some(block_time) = &meta.block_time {
+                            self.stats.observe_block_time(block_time.timestamp, timestamp());
+                            state.block_times.insert(meta.slot, block_time.timestamp);
+                            // keep about a minute of slots
+                            state.block_times = state.block_times.split_off(&meta.slot.saturating_sub(BLOCK_TIME_SLOTS));
+                        }
                         let mut conn = self.redis.clone();
-                        redis::cmd("set")
-                            .arg("blockhash")
-                            .arg(&meta.blockhash)
-                            .exec_async(&mut conn)
-                            .await?;
-                        if block_times == 100 {
-                            debug!("check mk!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!");
-                            check_mk(&mut conn, tg_instance.clone(), x_instance.clone()).await?; 
-                            block_times = 0;
+                        // transactions of a slot arrive before its block meta,
+                        // older block metas (e.g. from a slower feed) are skipped
+                        if meta.slot > state.checkpoint.slot {
+                            state.checkpoint.slot = meta.slot;
+                            // saved once every worker handled the events queued before the mark
+                            state.workers.mark(state.handled_slot()).await?;
+                            let acked = state.workers.acked_slot();
+                            if acked > state.saved_slot {
+                                let checkpoint = Checkpoint { slot: acked, signature: state.checkpoint.signature.clone() };
+                                set_checkpoint(&mut conn, &checkpoint).await?;
+                                state.saved_slot = acked;
+                            }
+
+                            let produced_at = meta
+                                .block_time
+                                .as_ref()
+                                .map(|block_time| block_time.timestamp.max(0) as u64 * 1000)
+                                .unwrap_or_else(timestamp);
+                            let block_height = meta.block_height.as_ref().map(|h| h.block_height).unwrap_or_default();
+                            let info = BlockhashInfo::new(meta.blockhash.clone(), meta.slot, block_height, produced_at);
+                            set_block_hash(&mut conn, &info).await?;
+                        }
+                    }
+                    UpdateOneof::Ping(_) => {
+                        // load balancers in front of some providers expect an answer
+                        if let Some(subscription) = subscriptions.get(&feed) {
+                            if let Err(e) = subscription.ping(0) {
+                                warn!("grpc ping answer on feed {} error: {}", feed, e);
+                            }
+                        }
+                    }
+
+                    UpdateOneof::Pong(pong) => {
+                        trace!("grpc pong {}", pong.id);
+                    }
+
+                    UpdateOneof::Slot(slot) => {
+                        self.stats.observe_chain_slot(slot.slot);
+                        // only subscribed at processed commitment
+                        if slot.status == CommitmentLevel::Confirmed as i32 {
+                            for (ctx, event) in state.pending.confirm(slot.slot) {
+                                state.workers.dispatch(ctx, event).await?;
+                            }
+                            let dropped = state
+                                .pending
+                                .expire(slot.slot.saturating_sub(self.config.grpc.confirm_timeout_slots));
+                            if !dropped.is_empty() {
+                                let mints: Vec<Pubkey> = dropped.iter().filter_map(|(_, event)| event.created_mint()).collect();
+                                warn!("{} events of unconfirmed slots dropped, created tokens {:?}", dropped.len(), mints);
+                            }
                         }
                     }
                     _ => {}
                 }
             }
         }
-        Ok(())
+    }
+
+    /// Send a notice to the admin chat, failures are only logged
+    async fn notify_admin(&self, msg: &str) {
+        if let Some(bot) = self.bots.admin() {
+            if let Err(e) = bot.send_message_async(msg, None).await {
+                warn!("send admin notification error: {}", e.msg);
+            }
+        }
     }
 
     // update token info
     async fn update_token_info(
         &self,
+        ctx: EventContext,
+        transaction: Option<VersionedTransaction>,
         meta: UiTransactionStatusMeta,
+        state: &mut RunState,
     ) -> Result<()> {
-        if let OptionSerializer::Some(inner_ixs) = meta.inner_instructions {
-            self.check_instruction(inner_ixs).await
-        } else {
-            Ok(())
-        }
+        let tx = TxView {
+            keys: transaction.as_ref().map(|tx| account_keys(tx, &meta.loaded_addresses)).unwrap_or_default(),
+            instructions: transaction
+                .as_ref()
+                .map(|tx| tx.message.instructions().iter().map(RawInstruction::from).collect())
+                .unwrap_or_default(),
+            inner: match &meta.inner_instructions {
+                OptionSerializer::Some(groups) if !groups.is_empty() => Some(
+                    groups.iter().map(|group| (group.index as usize, group.instructions.as_slice())).collect(),
+                ),
+                _ => None,
+            },
+            pre_balances: ui_balances(&meta.pre_token_balances),
+            post_balances: ui_balances(&meta.post_token_balances),
+        };
+        self.process_transaction(ctx, tx, state).await
     }
 
-    // check instruction
-    async fn check_instruction(
+    // update token info from the protobuf bytes, same events as `update_token_info`
+    async fn update_token_info_raw(
         &self,
-        inner_ixs: Vec<UiInnerInstructions>,
+        ctx: EventContext,
+        mut raw: RawTransaction<'_>,
+        state: &mut RunState,
     ) -> Result<()> {
-        let mut conn = self.redis.clone();
-
-        // let mut temp_price = HashMap::new();
-        for inner in inner_ixs {
-            for ix in inner.instructions {
-                if let Ok(target_event) = TargetEvent::try_from(ix.clone()) {
-                    match target_event {
-                        TargetEvent::PumpfunBuy(buy) => {
-                            let sol_reserves = buy.virtual_sol_reserves;
-                            let token_reserves = buy.virtual_token_reserves;
-                            let price = cal_pumpfun_price(sol_reserves, token_reserves);
-                            let market_cap = cal_pumpfun_marketcap(price);
-                            update_mk(&mut conn, &buy.mint.to_string(), market_cap, &"".to_string()).await?;
-                            // // info!("buy ===========> {:?}, {:?}, {:?}, {:?}, {:?}", buy.mint, sol_reserves, token_reserves, price, market_cap);
-
-                            // temp_price.insert(buy.mint, (price, market_cap));
-                        }
+        let tx = TxView {
+            keys: std::mem::take(&mut raw.keys),
+            instructions: raw.instructions().collect(),
+            inner: raw.inner_instructions().map(|groups| {
+                groups.iter().map(|group| (group.index as usize, group.instructions.as_slice())).collect()
+            }),
+            pre_balances: raw_balances(raw.pre_token_balances()),
+            post_balances: raw_balances(raw.post_token_balances()),
+        };
+        self.process_transaction(ctx, tx, state).await
+    }
 
-                        TargetEvent::PumpfunSell(sell) => {
-                            let sol_reserves = sell.virtual_sol_reserves;
-                            let token_reserves = sell.virtual_token_reserves;
-                            let price = cal_pumpfun_price(sol_reserves, token_reserves);
-                            let market_cap = cal_pumpfun_marketcap(price); 
-                            update_mk(&mut conn, &sell.mint.to_string(), market_cap, &"".to_string()).await?;
+    // parse a transaction of either encoding, the events are published on the bus and
+    // handed to the worker pool
+    async fn process_transaction<I: InstructionView + Sync>(
+        &self,
+        mut ctx: EventContext,
+        tx: TxView<'_, I>,
+        state: &mut RunState,
+    ) -> Result<()> {
+        let keys = tx.keys;
+        ctx.token_program = token_program(&keys, &tx.post_balances).to_string();
+        let budget = ComputeBudget::from_instructions(
+            tx.instructions.iter().filter_map(|ix| Some((keys.get(ix.program_id_index as usize)?, ix.data))),
+        );
+        ctx.compute_unit_price = budget.unit_price;
+        ctx.priority_fee = budget.priority_fee();
 
-                            // temp_price.insert(sell.mint, (price, market_cap));
-                        }
+        let outer_instructions = tx
+            .instructions
+            .iter()
+            .map(|ix| Some((self.program_by_key(&ix.program_id(&keys)?)?, ix.account_keys(&keys)?)))
+            .collect();
+        let token_accounts = if self.config.engine.spl_transfers {
+            token_accounts(&keys, &tx.pre_balances, &tx.post_balances)
+        } else {
+            HashMap::new()
+        };
+        let accounts = TxAccounts { keys, outer_instructions, token_accounts };
 
-                        TargetEvent::PumpfunCreate(create) => {
-                            // let mint = create.mint;
-                            // 默认是没有
-                            // default is false
-                            // if mint.to_string().ends_with("pump") {
-                                // let have_x_or_tg = have_tg_or_x(&self.http, &mint.to_string())
-                                //     .await
-                                //     .unwrap_or(false); 
-                                // todo！ get token info
-                                add_token_info(&mut conn, &create).await?;
-                            // }
-                        }
+        // launches without a create event, ahead of the trades of the same transaction
+        for (ix, outer) in tx.instructions.iter().zip(&accounts.outer_instructions) {
+            let Some((program, ix_accounts)) = outer else {
+                continue;
+            };
+            if let Some(target_event) = TargetEvent::from_launch_instruction(*program, ix_accounts, ix.data) {
+                let mut event_ctx = ctx.clone();
+                event_ctx.accounts = ix_accounts.clone();
+                self.dispatch_event(&event_ctx, target_event, state).await?;
+            }
+        }
 
-                        TargetEvent::PumpfunComplete(_) => {
-                            // safe delete
-                        }
+        let Some(inner_groups) = tx.inner else {
+            // the feed stripped the inner instructions, fall back to the top-level ones
+            for (ix, outer) in tx.instructions.iter().zip(&accounts.outer_instructions) {
+                let Some((program, ix_accounts)) = outer else {
+                    continue;
+                };
+                if let Some(target_event) = TargetEvent::from_outer_instruction(*program, ix_accounts, ix.data) {
+                    let mut event_ctx = ctx.clone();
+                    event_ctx.accounts = ix_accounts.clone();
+                    self.dispatch_event(&event_ctx, target_event, state).await?;
+                }
+            }
+            return Ok(());
+        };
 
-                        TargetEvent::PumpammCreatePool(pool_info) => {
-                            let pool = pool_info;
-                         
-                            // 该池子的base_mint必须在redis中存在
-                            if query_token_info(&mut conn, &pool.base_mint.to_string()).await.is_ok() {    
-                                debug!("create pool: {:?}", pool);
-                                let price = cal_pumpamm_price(pool.pool_base_amount, pool.pool_quote_amount);
-
-                                let market_cap = cal_pumpamm_marketcap_precise(price);
-                                debug!("create pool mint {} pool {} market cap: {}", pool.base_mint.to_string(), pool.pool.to_string(), market_cap);
-                                
-                                update_mk(&mut conn, &pool.base_mint.to_string(), market_cap, &pool.pool.to_string()).await?;
-                            } 
-                        } 
-
-                        TargetEvent::PumpammBuy(buy) => {
-                            // println!("buy ===========> {:?}", buy);
-                            // TODO! AMM buy
-                            let buy_info = buy;
-                            if let Ok(mint) = from_pool_query_token_mint(&mut conn, &buy_info.pool.to_string()).await {   
-                                // 如果毕业的话则更新价格和市场市值
-                                // debug!("have token graduation");
-                                // debug!("buy_info = {:?}", buy_info);
-                                let price = cal_pumpamm_price(buy_info.pool_base_token_reserves, buy_info.pool_quote_token_reserves);
-
-                                let market_cap = cal_pumpamm_marketcap_precise(price);
-                                // debug!("buy mint {} pool {} price {} market cap: {}", mint, buy_info.pool.to_string(), price, market_cap);
-                                 
-                                update_mk(&mut conn, &mint, market_cap, &buy_info.pool.to_string()).await?;
-                            } else {
-                                continue;
-                            }
-                        } 
- 
-                        TargetEvent::PumpammSell(sell) => {
-                            // println!("sell ===========> {:?}", sell);
-                            // TODO! AMM sell
-                            let sell_info = sell; 
-                            if let Ok(mint) = from_pool_query_token_mint(&mut conn, &sell_info.pool.to_string()).await {   
-                                // 如果毕业的话则更新价格和市场市值
-                                // debug!("have token graduation");
-                                // debug!("sell_info = {:?}", sell_info);
-                                let price = cal_pumpamm_price(sell_info.pool_base_token_reserves, sell_info.pool_quote_token_reserves);
-
-                                let market_cap = cal_pumpamm_marketcap_precise(price);
-                                // debug!("sell mint {} pool {} market cap: {}", mint, sell_info.pool.to_string(), market_cap);
-                                 
-                                update_mk(&mut conn, &mint, market_cap, &sell_info.pool.to_string()).await?;
-                            } else {
+        let spl_transfers = self.config.engine.spl_transfers;
+        for (index, instructions) in inner_groups {
+            let outer = accounts.outer_instructions.get(index).and_then(|outer| outer.as_ref());
+            // monitored program the group runs under, for the token transfers it makes
+            let mut group_program = outer.map(|(program, _)| *program);
+            // latest non-event instruction of each program, the one a cpi event is emitted from
+            let mut emitters: HashMap<Program, Vec<Pubkey>> = outer.cloned().into_iter().collect();
+            for ix in instructions {
+                let program_id = ix.program_id(&accounts.keys);
+                let program = program_id.and_then(|id| self.program_by_key(&id));
+                if spl_transfers && group_program.is_none() {
+                    group_program = program;
+                }
+                // events are only taken from the program that emits them
+                let parsed = match program {
+                    Some(program) => ix.parse_event(program),
+                    None => Ok(None),
+                };
+                match parsed {
+                    Ok(Some(target_event)) => {
+                        let mut event_ctx = ctx.clone();
+                        event_ctx.accounts = emitters.get(&target_event.program()).cloned().unwrap_or_default();
+                        self.dispatch_event(&event_ctx, target_event, state).await?
+                    }
+                    Ok(None) => {
+                        if let Some(program) = program {
+                            if let Some(target_event) = ix.unknown_event(program) {
+                                // a cpi event none of the parsers knows, e.g. after a program upgrade
+                                self.stats.observe_parse_error(program, ParseFailure::UnknownDiscriminator);
+                                if self.config.engine.unknown_events {
+                                    self.dispatch_event(&ctx, target_event, state).await?;
+                                }
                                 continue;
                             }
-                        } 
-
-                        TargetEvent::PumpammDeposit(deposit) => {
-                            // TODO! AMM deposit
-                            // println!("deposit ===========> {:?}", deposit);
-                            if let Ok(mint) = from_pool_query_token_mint(&mut conn, &deposit.pool.to_string()).await {   
-                                // 如果毕业的话则更新价格和市场市值
-                                // debug!("have token graduation");
-                                // debug!("deposit_info = {:?}", deposit);
-                                let price = cal_pumpamm_price(deposit.pool_base_token_reserves, deposit.pool_quote_token_reserves);
-
-                                let market_cap = cal_pumpamm_marketcap_precise(price);
-                                // debug!("deposit mint {} pool {} market cap: {}", mint, deposit.pool.to_string(), market_cap);
-                                 
-                                update_mk(&mut conn, &mint, market_cap, &deposit.pool.to_string()).await?;
-                            } else {
-                                continue;
+                            if let Some(ix_accounts) = ix.account_keys(&accounts.keys) {
+                                emitters.insert(program, ix_accounts);
                             }
                         }
-
-                        TargetEvent::PumpammWithdraw(withdraw) => {
-                            // TODO! AMM withdraw
-                            // println!("withdraw ===========> {:?}", withdraw);
-                            if let Ok(mint) = from_pool_query_token_mint(&mut conn, &withdraw.pool.to_string()).await {   
-                                // 如果毕业的话则更新价格和市场市值
-                                // debug!("have token graduation");
-                                // debug!("withdraw_info = {:?}", withdraw);
-                                let price = cal_pumpamm_price(withdraw.pool_base_token_reserves, withdraw.pool_quote_token_reserves);
-
-                                let market_cap = cal_pumpamm_marketcap_precise(price);
-                                // debug!("withdraw mint {} pool {} market cap: {}", mint, withdraw.pool.to_string(), market_cap);
-                                 
-                                update_mk(&mut conn, &mint, market_cap, &withdraw.pool.to_string()).await?;
-                            } else {
-                                continue;
+                        let transfer = match (group_program, program_id) {
+                            (Some(group_program), Some(program_id)) if spl_transfers && is_token_program(&program_id) => {
+                                ix.account_keys(&accounts.keys)
+                                    .and_then(|ix_accounts| ix.token_instruction(&ix_accounts))
+                                    .and_then(|instruction| transfer_event(group_program, instruction, &accounts))
                             }
+                            _ => None,
+                        };
+                        if let Some(target_event) = transfer {
+                            self.dispatch_event(&ctx, target_event, state).await?;
                         }
-                        _ => todo!()
+                    }
+                    Err(e) => {
+                        // one bad instruction must not stop the others
+                        debug!("tx {} {}", ctx.signature, e);
+                        self.stats.observe_parse_error(e.program, e.kind);
                     }
                 }
-                //  else {
-                //     println!("ix ===========> {:?}", ix);
-                // }
             }
         }
+        Ok(())
+    }
 
-        // for (key, (_, mk)) in temp_price {
-        //     // update marketcap
-        //     update_mk(&mut conn, &key.to_string(), mk).await?;
-        // } 
+    // enabled program with the id `key`
+    fn program_by_key(&self, key: &Pubkey) -> Option<Program> {
+        self.program_keys.iter().find(|(id, _)| id == key).map(|(_, program)| *program)
+    }
 
-        Ok(())
+    async fn dispatch_event(&self, ctx: &EventContext, target_event: TargetEvent, state: &mut RunState) -> Result<()> {
+        // a transaction can also touch a program that is not monitored
+        if !self.config.programs.is_enabled(target_event.program()) {
+            return Ok(());
+        }
+        if self.bus.subscriber_count() > 0 {
+            self.bus.publish(ctx.clone(), target_event.clone());
+        }
+        if state.track_confirmations {
+            // every kind of event waits for its slot, so nothing of a dropped fork is ever stored
+            state.pending.add(ctx.clone(), target_event);
+            return Ok(());
+        }
+        state.workers.dispatch(ctx.clone(), target_event).await
+    }
+}
+
+/// State kept across reconnects of the stream loop
+struct RunState {
+    workers: WorkerPool,
+    dedup: SignatureDedup,
+    // 连续失败次数, consecutive failures, reset once a message arrives
+    failures: u32,
+    // no transactions within `grpc.idle_timeout`, the admin was told
+    stalled: bool,
+    // last slot whose block meta was seen, the replay start of a reconnect
+    checkpoint: Checkpoint,
+    // last slot in redis, every event up to it went through the handlers
+    saved_slot: u64,
+    // block time of recent slots, from block meta updates
+    block_times: BTreeMap<u64, i64>,
+    // events wait for slot confirmation before the handlers see them (processed commitment)
+    track_confirmations: bool,
+    // explicit replay start, used by the first subscription only
+    replay_from: Option<u64>,
+    pending: PendingConfirmations,
+    shutdown: watch::Receiver<bool>,
+}
+
+impl RunState {
+    /// Latest slot whose events all went to the workers; at processed commitment the slot
+    /// before the oldest one still waiting for confirmation
+    fn handled_slot(&self) -> u64 {
+        match self.pending.oldest() {
+            Some(oldest) => self.checkpoint.slot.min(oldest.saturating_sub(1)),
+            None => self.checkpoint.slot,
+        }
+    }
+}
+
+// transaction of either encoding: top-level instructions, the inner instruction groups by
+// top-level index (`None` when the feed left them out) and the token balances
+struct TxView<'a, I> {
+    keys: Vec<Pubkey>,
+    instructions: Vec<RawInstruction<'a>>,
+    inner: Option<Vec<(usize, &'a [I])>>,
+    pre_balances: Vec<BalanceView<'a>>,
+    post_balances: Vec<BalanceView<'a>>,
+}
+
+// account keys of a transaction, resolved once for all of its instructions
+struct TxAccounts {
+    keys: Vec<Pubkey>,
+    // enabled program and resolved accounts of each top-level instruction
+    outer_instructions: Vec<Option<(Program, Vec<Pubkey>)>>,
+    // token account -> mint, owner and balance after the transaction, only with `engine.spl_transfers`
+    token_accounts: HashMap<Pubkey, TokenAccount>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct TokenAccount {
+    mint: Pubkey,
+    owner: Pubkey,
+    balance: u64,
+}
+
+// token balance of either meta, the UI and protobuf types carry the same strings
+struct BalanceView<'a> {
+    account_index: usize,
+    mint: &'a str,
+    owner: &'a str,
+    program_id: &'a str,
+    amount: &'a str,
+}
+
+fn ui_balances(balances: &OptionSerializer<Vec<UiTransactionTokenBalance>>) -> Vec<BalanceView<'_>> {
+    let OptionSerializer::Some(balances) = balances else {
+        return vec![];
+    };
+    balances
+        .iter()
+        .map(|balance| BalanceView {
+            account_index: balance.account_index as usize,
+            mint: &balance.mint,
+            owner: match &balance.owner {
+                OptionSerializer::Some(owner) => owner,
+                _ => "",
+            },
+            program_id: match &balance.program_id {
+                OptionSerializer::Some(id) => id,
+                _ => "",
+            },
+            amount: &balance.ui_token_amount.amount,
+        })
+        .collect()
+}
+
+fn raw_balances(balances: &[TokenBalance]) -> Vec<BalanceView<'_>> {
+    balances
+        .iter()
+        .map(|balance| BalanceView {
+            account_index: balance.account_index as usize,
+            mint: &balance.mint,
+            owner: &balance.owner,
+            program_id: &balance.program_id,
+            amount: balance.ui_token_amount.as_ref().map(|amount| amount.amount.as_str()).unwrap_or_default(),
+        })
+        .collect()
+}
+
+// token accounts from the pre and post token balances; closed accounts keep their owner with balance 0
+fn token_accounts(keys: &[Pubkey], pre: &[BalanceView], post: &[BalanceView]) -> HashMap<Pubkey, TokenAccount> {
+    let mut accounts = HashMap::new();
+    for (balances, post) in [(pre, false), (post, true)] {
+        for balance in balances {
+            let (Some(key), Ok(mint)) = (keys.get(balance.account_index), Pubkey::from_str(balance.mint)) else {
+                continue;
+            };
+            let owner = Pubkey::from_str(balance.owner).unwrap_or_default();
+            let amount = if post { balance.amount.parse().unwrap_or_default() } else { 0 };
+            accounts.insert(*key, TokenAccount { mint, owner, balance: amount });
+        }
+    }
+    accounts
+}
+
+// token program of the launched token: the program of the first non-SOL token balance,
+// otherwise Token-2022 when the transaction loads it
+fn token_program(keys: &[Pubkey], post_balances: &[BalanceView]) -> Pubkey {
+    let wsol = WSOL.to_string();
+    let program = post_balances
+        .iter()
+        .filter(|balance| balance.mint != wsol)
+        .find_map(|balance| Pubkey::from_str(balance.program_id).ok());
+    if let Some(program) = program {
+        return program;
+    }
+    if keys.contains(&TOKEN_2022_PROGRAM_ID) {
+        TOKEN_2022_PROGRAM_ID
+    } else {
+        TOKEN_PROGRAM_ID
+    }
+}
+
+// Token-2022 keeps the instruction layouts; transfer fees only show in the balances
+fn is_token_program(program_id: &Pubkey) -> bool {
+    *program_id == TOKEN_PROGRAM_ID || *program_id == TOKEN_2022_PROGRAM_ID
+}
+
+// `TargetEvent::TokenTransfer` for a decoded token instruction
+fn transfer_event(program: Program, instruction: TokenInstruction, accounts: &TxAccounts) -> Option<TargetEvent> {
+    let destination = accounts.token_accounts.get(&instruction.destination)?;
+    let mint = instruction.mint.unwrap_or(destination.mint);
+    if mint == WSOL {
+        return None;
+    }
+    let source = instruction
+        .source
+        .and_then(|source| accounts.token_accounts.get(&source))
+        .copied()
+        .unwrap_or_default();
+    Some(TargetEvent::TokenTransfer(TokenTransfer {
+        program,
+        mint,
+        source_owner: source.owner,
+        source_balance: source.balance,
+        destination_owner: destination.owner,
+        destination_balance: destination.balance,
+        amount: instruction.amount,
+    }))
+}
+
+// static keys, then the writable and readonly keys loaded from lookup tables
+fn account_keys(transaction: &VersionedTransaction, loaded_addresses: &OptionSerializer<UiLoadedAddresses>) -> Vec<Pubkey> {
+    let mut keys = transaction.message.static_account_keys().to_vec();
+    if let OptionSerializer::Some(loaded) = loaded_addresses {
+        keys.extend(
+            loaded
+                .writable
+                .iter()
+                .chain(loaded.readonly.iter())
+                .filter_map(|key| Pubkey::from_str(key).ok()),
+        );
+    }
+    keys
+}
+
+/// Flush the handlers every `period` milliseconds until shutdown
+async fn run_handler_flushes(handlers: HandlerRegistry, period: u64, mut shutdown: watch::Receiver<bool>) {
+    let period = Duration::from_millis(period);
+    let mut ticker = interval_at(Instant::now() + period, period);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        tokio::select! {
+            biased;
+            _ = shutdown.changed() => break,
+            _ = ticker.tick() => handlers.flush().await,
+        }
+    }
+}
+
+/// Read the creator balance of tracked tokens at or above `min_market_cap` over rpc every `period`
+async fn run_dev_balance_refresh(
+    rpc: Arc<RpcClient>,
+    mut conn: RedisConn,
+    period: u64,
+    min_market_cap: f32,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let period = Duration::from_millis(period);
+    let mut ticker = interval_at(Instant::now() + period, period);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        tokio::select! {
+            biased;
+            _ = shutdown.changed() => break,
+            _ = ticker.tick() => {}
+        }
+        let records = match token_records(&mut conn).await {
+            Ok(records) => records,
+            Err(e) => {
+                warn!("dev balance refresh error: {}", e);
+                continue;
+            }
+        };
+        for record in records.into_values().filter(|record| record.market_cap >= min_market_cap) {
+            let (Ok(creator), Ok(mint)) = (Pubkey::from_str(&record.creator), Pubkey::from_str(&record.mint)) else {
+                continue;
+            };
+            let account = associated_token_address(&creator, &mint, &record.token_program());
+            // a missing account is skipped, the trades still follow the balance
+            let balance = match rpc.get_token_account_balance(&account).await {
+                Ok(balance) => balance,
+                Err(e) => {
+                    debug!("dev balance of {} error: {}", record.mint, e);
+                    continue;
+                }
+            };
+            let Ok(amount) = balance.amount.parse::<u64>() else {
+                continue;
+            };
+            // the launchpads mint one billion tokens, with 6 (pump.fun) or 9 decimals
+            let supply = if balance.decimals == MOONSHOT_CURVE.token_decimals { MOONSHOT_CURVE.total_supply } else { PUMPFUN_TOTAL_SUPPLY };
+            let result = match dev_balance(&mut conn, &record.mint).await {
+                // 余额减少视为卖出, a lower balance than the one followed so far counts as sold
+                Ok(known) => {
+                    update_dev_balance(&mut conn, &record.mint, &record.creator, DevBalance::Balance(amount), supply, amount < known)
+                        .await
+                }
+                Err(e) => Err(e),
+            };
+            if let Err(e) = result {
+                warn!("dev balance update of {} error: {}", record.mint, e);
+            }
+        }
+    }
+}
+
+/// Run the market cap check every `alert.check_interval` until shutdown
+async fn run_market_cap_checks(
+    mut conn: RedisConn,
+    alert: AlertConfig,
+    notifier: Notifier,
+    social: SocialChain,
+    ai: AiClient,
+    publish: bool,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let period = Duration::from_millis(alert.check_interval.max(1));
+    let mut ticker = interval_at(Instant::now() + period, period);
+    // a slow check delays the next one instead of firing a burst
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        tokio::select! {
+            biased;
+            _ = shutdown.changed() => break,
+            _ = ticker.tick() => {}
+        }
+        debug!("check mk");
+        // thresholds can be changed at runtime through redis
+        let result = match get_threshold_overrides(&mut conn).await {
+            Ok(overrides) => {
+                // bad overrides are reported every cycle until fixed, the file values apply meanwhile
+                let alert = alert.with_overrides(&overrides).unwrap_or_else(|e| {
+                    warn!("{:#}, using the configured thresholds", e);
+                    alert.clone()
+                });
+                check_mk(&mut conn, notifier.clone(), social.clone(), ai.clone(), &alert, publish).await
+            }
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            warn!("market cap check error: {}", e);
+        }
+    }
+}
+
+/// Events received at processed commitment, by slot in arrival order, until the slot is confirmed
+#[derive(Default)]
+struct PendingConfirmations {
+    slots: BTreeMap<u64, Vec<(EventContext, TargetEvent)>>,
+}
+
+impl PendingConfirmations {
+    fn add(&mut self, ctx: EventContext, event: TargetEvent) {
+        self.slots.entry(ctx.slot).or_default().push((ctx, event));
+    }
+
+    /// Events of a confirmed slot
+    fn confirm(&mut self, slot: u64) -> Vec<(EventContext, TargetEvent)> {
+        self.slots.remove(&slot).unwrap_or_default()
+    }
+
+    /// Oldest slot with events waiting for confirmation
+    fn oldest(&self) -> Option<u64> {
+        self.slots.keys().next().copied()
+    }
+
+    /// Events of slots before `slot` that were never confirmed
+    fn expire(&mut self, slot: u64) -> Vec<(EventContext, TargetEvent)> {
+        let kept = self.slots.split_off(&slot);
+        std::mem::replace(&mut self.slots, kept).into_values().flatten().collect()
+    }
+}
+
+/// Drops transactions that were already processed (feed re-delivery, several instances)
+pub enum SignatureDedup {
+    Off,
+    Memory {
+        seen: HashSet<String>,
+        // insertion order, the oldest signature is evicted first
+        order: VecDeque<String>,
+        capacity: usize,
+    },
+    Redis {
+        conn: RedisConn,
+        ttl_secs: u64,
+    },
+}
+
+impl SignatureDedup {
+    pub fn new(config: &EngineConfig, conn: RedisConn) -> Self {
+        match config.dedup {
+            DedupMode::Off => SignatureDedup::Off,
+            DedupMode::Memory => SignatureDedup::Memory {
+                seen: HashSet::with_capacity(config.dedup_capacity),
+                order: VecDeque::with_capacity(config.dedup_capacity),
+                capacity: config.dedup_capacity.max(1),
+            },
+            DedupMode::Redis => SignatureDedup::Redis {
+                conn,
+                ttl_secs: config.dedup_ttl / SECONDS,
+            },
+        }
+    }
+
+    /// Record the signature, false if it has been seen before
+    pub async fn is_new(&mut self, signature: &str) -> Result<bool> {
+        match self {
+            SignatureDedup::Off => Ok(true),
+            SignatureDedup::Memory { seen, order, capacity } => {
+                if seen.contains(signature) {
+                    return Ok(false);
+                }
+                if order.len() >= *capacity {
+                    if let Some(oldest) = order.pop_front() {
+                        seen.remove(&oldest);
+                    }
+                }
+                seen.insert(signature.to_string());
+                order.push_back(signature.to_string());
+                Ok(true)
+            }
+            SignatureDedup::Redis { conn, ttl_secs } => {
+                Ok(mark_signature_seen(conn, signature, *ttl_secs).await?)
+            }
+        }
+    }
+}
+
+/// Resolves on SIGINT (ctrl-c) or SIGTERM
+pub async fn shutdown_signal() {
+    let ctrl_c = async {
+        let _ = tokio::signal::ctrl_c().await;
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(_) => std::future::pending::<()>().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
     }
 }
 
+/// Pool of worker tasks handling parsed events.
+///
+/// Events are sharded by mint over bounded channels, so updates for the same token
+/// are applied in order, across its graduation too, while different tokens are
+/// handled concurrently. A full queue blocks the stream loop.
+pub struct WorkerPool {
+    senders: Vec<mpsc::Sender<Work>>,
+    handles: Vec<JoinHandle<()>>,
+    // last slot mark each worker reached
+    acked: Vec<Arc<AtomicU64>>,
+}
 
+enum Work {
+    Event(EventContext, TargetEvent),
+    // every event queued before it has been handled
+    Mark(u64),
+}
 
+impl WorkerPool {
+    pub fn spawn(workers: usize, queue_size: usize, handlers: HandlerRegistry, stats: Arc<EngineStats>) -> Self {
+        let handlers = Arc::new(handlers);
+        let mut senders = Vec::new();
+        let mut handles = Vec::new();
+        let mut acked = Vec::new();
+        for _ in 0..workers.max(1) {
+            let (sender, mut receiver) = mpsc::channel::<Work>(queue_size.max(1));
+            let handlers = handlers.clone();
+            let stats = stats.clone();
+            let slot = Arc::new(AtomicU64::new(0));
+            let worker_slot = slot.clone();
+            handles.push(tokio::spawn(async move {
+                while let Some(work) = receiver.recv().await {
+                    match work {
+                        Work::Event(ctx, event) => {
+                            let started = Instant::now();
+                            handlers.handle(&ctx, &event).await;
+                            stats.observe_handle(started.elapsed());
+                        }
+                        Work::Mark(slot) => worker_slot.store(slot, Ordering::Release),
+                    }
+                }
+            }));
+            senders.push(sender);
+            acked.push(slot);
+        }
+        Self { senders, handles, acked }
+    }
+
+    /// Stop accepting events and wait until every queued event is handled
+    pub async fn shutdown(self) {
+        drop(self.senders);
+        for handle in self.handles {
+            let _ = handle.await;
+        }
+    }
+
+    /// Events queued and not yet taken by a worker
+    pub fn queue_depth(&self) -> usize {
+        self.senders
+            .iter()
+            .map(|sender| sender.max_capacity() - sender.capacity())
+            .sum()
+    }
+
+    /// Send an event to the worker owning its token
+    pub async fn dispatch(&self, ctx: EventContext, event: TargetEvent) -> Result<()> {
+        let mut hasher = DefaultHasher::new();
+        // pool or curve when the instruction accounts are unknown
+        event.mint(&ctx.accounts).unwrap_or_else(|| event.key()).hash(&mut hasher);
+        let index = (hasher.finish() % self.senders.len() as u64) as usize;
+        self.senders[index]
+            .send(Work::Event(ctx, event))
+            .await
+            .map_err(|_| anyhow!("worker {} stopped", index))
+    }
+
+    /// Queue a mark for `slot` behind the events every worker has queued
+    pub async fn mark(&self, slot: u64) -> Result<()> {
+        for (index, sender) in self.senders.iter().enumerate() {
+            sender.send(Work::Mark(slot)).await.map_err(|_| anyhow!("worker {} stopped", index))?;
+        }
+        Ok(())
+    }
+
+    /// Highest slot all workers reached, every event dispatched before its mark is handled
+    pub fn acked_slot(&self) -> u64 {
+        self.acked.iter().map(|slot| slot.load(Ordering::Acquire)).min().unwrap_or_default()
+    }
+}
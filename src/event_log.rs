@@ -0,0 +1,216 @@
+//! Capped Redis Stream of every parsed event, for downstream processors.
+//!
+//! Entries carry the event kind (`pumpfun:create`, ...) and the same JSON as the Pub/Sub
+//! messages. Unlike Pub/Sub the stream keeps the last `event_log.max_len` events, and
+//! consumers in a group read with `read_events`, `ack_events` what they processed and
+//! `claim_events` what a crashed consumer left pending. The log is written by a handler
+//! (`EventLogWriter`), not from the lossy event bus, so no event is skipped.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use redis::{
+    streams::{StreamAutoClaimOptions, StreamAutoClaimReply, StreamId, StreamReadOptions, StreamReadReply},
+    AsyncCommands, RedisResult,
+};
+use tracing::warn;
+
+use crate::{
+    config::EventLogConfig,
+    handler::{EventContext, EventHandler},
+    pubsub::event_message,
+    redis_conn::RedisConn,
+    types::TargetEvent,
+};
+
+/// Event read from the log
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoggedEvent {
+    /// stream entry id, acknowledged with `ack_events`
+    pub id: String,
+    /// `<program>:<kind>`, e.g. `pumpfun:create`
+    pub kind: String,
+    /// JSON with the event and its signature, slot, block time and fee payer
+    pub data: String,
+}
+
+impl LoggedEvent {
+    fn from_entry(entry: &StreamId) -> Self {
+        Self {
+            id: entry.id.clone(),
+            kind: entry.get("kind").unwrap_or_default(),
+            data: entry.get("data").unwrap_or_default(),
+        }
+    }
+}
+
+/// Create consumer group `group` on the log, reading the events added from now on;
+/// an existing group is kept as it is
+pub async fn create_group(conn: &mut RedisConn, key: &str, group: &str) -> RedisResult<()> {
+    match conn.xgroup_create_mkstream::<_, _, _, ()>(key, group, "$").await {
+        Err(e) if e.code() == Some("BUSYGROUP") => Ok(()),
+        result => result,
+    }
+}
+
+/// Next events for `consumer` of `group`, waiting up to `block_ms` when there are none (0 returns at once)
+pub async fn read_events(
+    conn: &mut RedisConn,
+    key: &str,
+    group: &str,
+    consumer: &str,
+    count: usize,
+    block_ms: usize,
+) -> RedisResult<Vec<LoggedEvent>> {
+    let mut options = StreamReadOptions::default().group(group, consumer).count(count);
+    if block_ms > 0 {
+        options = options.block(block_ms);
+    }
+    let reply: Option<StreamReadReply> = conn.xread_options(&[key], &[">"], &options).await?;
+    Ok(reply
+        .map(|reply| reply.keys.iter().flat_map(|stream| stream.ids.iter().map(LoggedEvent::from_entry)).collect())
+        .unwrap_or_default())
+}
+
+/// Mark events of `group` as processed
+pub async fn ack_events(conn: &mut RedisConn, key: &str, group: &str, ids: &[String]) -> RedisResult<()> {
+    if ids.is_empty() {
+        return Ok(());
+    }
+    conn.xack(key, group, ids).await
+}
+
+/// Take over up to `count` events that other consumers of `group` read but did not
+/// acknowledge within `min_idle_ms`, e.g. after a crash
+pub async fn claim_events(
+    conn: &mut RedisConn,
+    key: &str,
+    group: &str,
+    consumer: &str,
+    min_idle_ms: u64,
+    count: usize,
+) -> RedisResult<Vec<LoggedEvent>> {
+    let options = StreamAutoClaimOptions::default().count(count);
+    let reply: StreamAutoClaimReply = conn.xautoclaim_options(key, group, consumer, min_idle_ms, "0-0", options).await?;
+    Ok(reply.claimed.iter().map(LoggedEvent::from_entry).collect())
+}
+
+/// Create the consumer groups of `config`, failures are logged
+pub async fn create_groups(conn: &mut RedisConn, config: &EventLogConfig) {
+    for group in &config.groups {
+        if let Err(e) = create_group(conn, &config.key, group).await {
+            warn!("create event log group {} error: {}", group, e);
+        }
+    }
+}
+
+/// Append an event to the log
+pub async fn append_event(
+    conn: &mut RedisConn,
+    config: &EventLogConfig,
+    ctx: &EventContext,
+    event: &TargetEvent,
+) -> RedisResult<()> {
+    let Some((channel, message)) = event_message(ctx, event) else {
+        return Ok(());
+    };
+    let kind = channel.strip_prefix("events:").unwrap_or(&channel);
+    redis::cmd("XADD")
+        .arg(&config.key)
+        .arg("MAXLEN")
+        .arg("~")
+        .arg(config.max_len)
+        .arg("*")
+        .arg("kind")
+        .arg(kind)
+        .arg("data")
+        .arg(message)
+        .query_async(conn)
+        .await
+}
+
+/// Writes every event to the log from the workers, so it gets what the other handlers get
+/// and the checkpoint only moves past events that were logged
+pub struct EventLogWriter {
+    redis: RedisConn,
+    config: EventLogConfig,
+}
+
+impl EventLogWriter {
+    pub fn new(redis: RedisConn, config: EventLogConfig) -> Self {
+        Self { redis, config }
+    }
+}
+
+#[async_trait]
+impl EventHandler for EventLogWriter {
+    fn name(&self) -> &str {
+        "event_log_writer"
+    }
+
+    async fn handle(&self, ctx: &EventContext, event: &TargetEvent) -> Result<()> {
+        append_event(&mut self.redis.clone(), &self.config, ctx, event).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use redis::Value;
+
+    use solana_sdk::timing::timestamp;
+
+    use super::*;
+    use crate::{config::Config, types::Program};
+
+    #[test]
+    fn logged_event_test() {
+        let entry = StreamId {
+            id: "1-0".to_string(),
+            map: HashMap::from([
+                ("kind".to_string(), Value::BulkString(b"pumpfun:unknown".to_vec())),
+                ("data".to_string(), Value::BulkString(b"{}".to_vec())),
+            ]),
+        };
+        assert_eq!(
+            LoggedEvent::from_entry(&entry),
+            LoggedEvent { id: "1-0".to_string(), kind: "pumpfun:unknown".to_string(), data: "{}".to_string() }
+        );
+    }
+
+    #[tokio::test]
+    async fn append_and_replay_test() -> anyhow::Result<()> {
+        dotenv::dotenv().ok();
+        let config = Config::load()?;
+        let mut conn = RedisConn::connect(&config.redis).await?;
+        let log = EventLogConfig {
+            enabled: true,
+            key: format!("events:test:{}", timestamp()),
+            max_len: 100,
+            groups: vec!["processor".to_string()],
+        };
+        create_groups(&mut conn, &log).await;
+
+        let writer = EventLogWriter::new(conn.clone(), log.clone());
+        let ctx = EventContext { signature: "sig".to_string(), slot: 7, ..Default::default() };
+        for program in [Program::Pumpfun, Program::Boop] {
+            let event = TargetEvent::Unknown { program, discriminator: [0; 8], data: vec![] };
+            writer.handle(&ctx, &event).await?;
+        }
+
+        let read = read_events(&mut conn, &log.key, "processor", "a", 10, 0).await?;
+        let kinds: Vec<&str> = read.iter().map(|event| event.kind.as_str()).collect();
+        assert_eq!(kinds, vec!["pumpfun:unknown", "boop:unknown"]);
+        assert!(read[0].data.contains("\"signature\":\"sig\""));
+
+        // `a` crashed before acknowledging, `b` takes the events over
+        let claimed = claim_events(&mut conn, &log.key, "processor", "b", 0, 10).await?;
+        assert_eq!(claimed, read);
+        ack_events(&mut conn, &log.key, "processor", &claimed.iter().map(|event| event.id.clone()).collect::<Vec<_>>()).await?;
+        assert!(claim_events(&mut conn, &log.key, "processor", "b", 0, 10).await?.is_empty());
+
+        conn.del::<_, ()>(&log.key).await?;
+        Ok(())
+    }
+}
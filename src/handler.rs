@@ -0,0 +1,867 @@
+use std::{
+    str::FromStr,
+    sync::{Arc, RwLock},
+};
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use redis::RedisResult;
+use serde_json::json;
+use solana_sdk::{native_token::LAMPORTS_PER_SOL, pubkey::Pubkey, timing::timestamp};
+use tokio::{fs::OpenOptions, io::AsyncWriteExt};
+use tracing::{debug, info, trace, warn};
+
+use crate::{
+    cache::{
+        add_buyer, add_first_buyer, alert_messages, blocklist, lock_alert_messages, mark_followup, remove_alert_messages, add_priority_fee, add_token_info, add_tokens_sold, add_volume,
+        from_curve_query_token_mint, from_pool_query_token_mint, get_token_record, is_tracked, set_pumpfun_params, set_token_pool, update_dev_balance, update_holder, update_market_caps,
+        DevBalance, MarketCapUpdate, TokenRecord,
+    },
+    config::{AlertConfig, RugCleanup},
+    constants::{BOOP_CURVE, MOONSHOT_CURVE, PUMPFUN_TOTAL_SUPPLY},
+    hot_cache::HotRecords,
+    redis_conn::RedisConn,
+    tg_bot::{notifier::Notifier, tg_bot::FollowupAlert},
+    types::{CreateEvent, Program, TargetEvent},
+    utils::{
+        cal_curve_marketcap, cal_curve_price, cal_curve_reserves, cal_pumpamm_marketcap_precise,
+        cal_pumpamm_price, cal_pumpfun_marketcap, cal_pumpfun_price, PumpfunParams,
+    },
+};
+
+/// Transaction context of a parsed event
+#[derive(Debug, Clone, Default)]
+pub struct EventContext {
+    /// base58 transaction signature
+    pub signature: String,
+    pub slot: u64,
+    /// unix seconds, the receive time when the block meta of the slot has not arrived yet
+    pub block_time: i64,
+    /// first signer of the transaction
+    pub fee_payer: String,
+    /// token program of the transaction's tokens, SPL Token or Token-2022, empty when unknown
+    pub token_program: String,
+    /// requested compute unit price in micro-lamports, 0 without a priority fee
+    pub compute_unit_price: u64,
+    /// priority fee in lamports
+    pub priority_fee: u64,
+    /// resolved accounts of the program instruction behind the event (for a cpi event the
+    /// instruction that emitted it), empty when unknown
+    pub accounts: Vec<Pubkey>,
+}
+
+/// Reacts to parsed events.
+///
+/// Handlers are registered on the `Monitor` and called in registration order
+/// for every event, on the worker that owns the event's token.
+///
+/// Coin alerts are not sent from a handler: a token becomes due by its age as well as
+/// its trades, so `cache::check_mk` looks at the stored records on its own schedule.
+#[async_trait]
+pub trait EventHandler: Send + Sync {
+    /// Name used in logs
+    fn name(&self) -> &str;
+
+    async fn handle(&self, ctx: &EventContext, event: &TargetEvent) -> Result<()>;
+
+    /// Write out buffered state, called periodically and on shutdown
+    async fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Ordered list of event handlers
+#[derive(Clone, Default)]
+pub struct HandlerRegistry {
+    handlers: Vec<Arc<dyn EventHandler>>,
+}
+
+impl HandlerRegistry {
+    pub fn register(&mut self, handler: impl EventHandler + 'static) {
+        self.handlers.push(Arc::new(handler));
+    }
+
+    /// Run every handler, a failing handler does not stop the others
+    pub async fn handle(&self, ctx: &EventContext, event: &TargetEvent) {
+        for handler in &self.handlers {
+            if let Err(e) = handler.handle(ctx, event).await {
+                warn!("handler {} error: {:#}", handler.name(), e);
+            }
+        }
+    }
+
+    /// Flush every handler
+    pub async fn flush(&self) {
+        for handler in &self.handlers {
+            if let Err(e) = handler.flush().await {
+                warn!("handler {} flush error: {:#}", handler.name(), e);
+            }
+        }
+    }
+}
+
+/// Keeps token info and market caps in redis up to date
+pub struct MarketCapTracker {
+    redis: RedisConn,
+    // live pump.fun parameters, replaced on `SetParams`
+    pumpfun_params: RwLock<PumpfunParams>,
+    // price history retention in milliseconds, 0 keeps none
+    price_history: u64,
+    // cached records with the updates waiting for the next flush, `None` writes every update directly
+    hot: Option<HotRecords>,
+    batch_size: usize,
+    // one flush at a time, so an older batch never lands after a newer one
+    flushing: tokio::sync::Mutex<()>,
+}
+
+impl MarketCapTracker {
+    /// `pumpfun_params` are the stored parameters, see `cache::get_pumpfun_params`;
+    /// `price_history` is `engine.price_history`
+    pub fn new(redis: RedisConn, pumpfun_params: PumpfunParams, price_history: u64) -> Self {
+        Self { redis, pumpfun_params: RwLock::new(pumpfun_params), price_history, hot: None, batch_size: 0, flushing: Default::default() }
+    }
+
+    /// Keep the records of trading tokens in memory and buffer their updates per mint until
+    /// `flush`, or until `batch_size` mints are waiting; records unused for `hot_ttl` are dropped
+    pub fn with_write_batch(mut self, batch_size: usize, hot_ttl: u64) -> Self {
+        self.hot = Some(HotRecords::new(hot_ttl));
+        self.batch_size = batch_size;
+        self
+    }
+
+    fn pumpfun_params(&self) -> PumpfunParams {
+        self.pumpfun_params.read().map(|params| *params).unwrap_or_default()
+    }
+
+    // store the market cap and, for tracked tokens, a price history point
+    async fn update_market_cap(
+        &self,
+        conn: &mut RedisConn,
+        mint: &str,
+        price: f64,
+        market_cap: f64,
+        pool: &str,
+    ) -> Result<()> {
+        let update = MarketCapUpdate::new(mint, price, market_cap, pool, timestamp());
+        if let Some(hot) = &self.hot {
+            let pending = hot.update(conn, update).await?;
+            if self.batch_size > 0 && pending >= self.batch_size {
+                self.flush().await?;
+            }
+            return Ok(());
+        }
+        update_market_caps(conn, &[update], self.price_history).await?;
+        Ok(())
+    }
+
+    // 新币写入后直接进缓存, cache a created token so its first trades need no lookup
+    fn cache_created(&self, ctx: &EventContext, program: Program, create: &CreateEvent) {
+        if let Some(hot) = &self.hot {
+            hot.insert(TokenRecord::new(ctx, program, create));
+        }
+    }
+
+    // mint of the tracked token traded in `pool`, empty when there is none
+    async fn pool_mint(&self, conn: &mut RedisConn, pool: &str) -> RedisResult<String> {
+        match &self.hot {
+            Some(hot) => hot.mint_by_pool(conn, pool).await,
+            None => from_pool_query_token_mint(conn, pool).await,
+        }
+    }
+
+    async fn is_tracked_mint(&self, conn: &mut RedisConn, mint: &str) -> RedisResult<bool> {
+        match &self.hot {
+            Some(hot) => Ok(hot.record(conn, mint).await?.is_some()),
+            None => Ok(get_token_record(conn, mint).await?.is_some()),
+        }
+    }
+}
+
+#[async_trait]
+impl EventHandler for MarketCapTracker {
+    fn name(&self) -> &str {
+        "market_cap_tracker"
+    }
+
+    async fn flush(&self) -> Result<()> {
+        let Some(hot) = &self.hot else {
+            return Ok(());
+        };
+        let _flushing = self.flushing.lock().await;
+        hot.flush(&mut self.redis.clone(), self.price_history).await?;
+        Ok(())
+    }
+
+    async fn handle(&self, ctx: &EventContext, event: &TargetEvent) -> Result<()> {
+        let mut conn = self.redis.clone();
+        match event {
+            TargetEvent::PumpfunBuy(buy) => {
+                let sol_reserves = buy.virtual_sol_reserves;
+                let token_reserves = buy.virtual_token_reserves;
+                let price = cal_pumpfun_price(sol_reserves, token_reserves);
+                let market_cap = cal_pumpfun_marketcap(price, &self.pumpfun_params());
+                self.update_market_cap(&mut conn, &buy.mint.to_string(), price, market_cap, "").await?;
+                // info!("buy ===========> {:?}, {:?}, {:?}, {:?}, {:?}", buy.mint, sol_reserves, token_reserves, price, market_cap);
+            }
+
+            TargetEvent::PumpfunSell(sell) => {
+                let sol_reserves = sell.virtual_sol_reserves;
+                let token_reserves = sell.virtual_token_reserves;
+                let price = cal_pumpfun_price(sol_reserves, token_reserves);
+                let market_cap = cal_pumpfun_marketcap(price, &self.pumpfun_params());
+                self.update_market_cap(&mut conn, &sell.mint.to_string(), price, market_cap, "").await?;
+            }
+
+            // only parsed from instructions of the pump.fun program itself, see `EVENT_PARSERS`
+            TargetEvent::PumpfunSetParams(event) => {
+                let params = PumpfunParams::from(event);
+                info!("pump.fun params updated: {:?}", params);
+                set_pumpfun_params(&mut conn, &params).await?;
+                if let Ok(mut current) = self.pumpfun_params.write() {
+                    *current = params;
+                }
+            }
+
+            TargetEvent::PumpfunCreate(create) => {
+                // todo！ get token info
+                add_token_info(&mut conn, ctx, Program::Pumpfun, create).await?;
+                self.cache_created(ctx, Program::Pumpfun, create);
+            }
+
+            TargetEvent::PumpfunComplete(_) => {
+                // safe delete
+            }
+
+            TargetEvent::PumpfunTradeIx(trade) => {
+                // 无事件时按成交量推算, without the trade event the curve position follows from the
+                // tokens traded since the create, counted for tracked tokens only
+                let mint = trade.mint.to_string();
+                if let Some((_, _, change)) = instruction_trade(event) {
+                    if self.is_tracked_mint(&mut conn, &mint).await? {
+                        let tokens_sold = add_tokens_sold(&mut conn, &mint, change).await?;
+                        let params = self.pumpfun_params();
+                        let (sol_reserves, token_reserves) = params.virtual_reserves(tokens_sold);
+                        let price = cal_pumpfun_price(sol_reserves, token_reserves);
+                        let market_cap = cal_pumpfun_marketcap(price, &params);
+                        self.update_market_cap(&mut conn, &mint, price, market_cap, "").await?;
+                    }
+                }
+            }
+
+            TargetEvent::PumpammSwapIx(_) => {
+                // the pool reserves are not in the instruction, the market cap follows the pool events
+            }
+
+            TargetEvent::PumpfunMigrate(migrate) => {
+                // 毕业即关联池子, link the pool at graduation instead of waiting for CreatePool
+                set_token_pool(&mut conn, &migrate.mint.to_string(), &migrate.pool.to_string()).await?;
+                if let Some(hot) = &self.hot {
+                    hot.set_pool(&migrate.mint.to_string(), &migrate.pool.to_string());
+                }
+            }
+
+            TargetEvent::PumpammCreatePool(pool) => {
+                // 该池子的base_mint必须在redis中存在
+                if self.is_tracked_mint(&mut conn, &pool.base_mint.to_string()).await? {
+                    debug!("create pool: {:?}", pool);
+                    let price = cal_pumpamm_price(pool.pool_base_amount, pool.pool_quote_amount);
+
+                    let market_cap = cal_pumpamm_marketcap_precise(price);
+                    debug!("create pool mint {} pool {} market cap: {}", pool.base_mint.to_string(), pool.pool.to_string(), market_cap);
+
+                    self.update_market_cap(&mut conn, &pool.base_mint.to_string(), price, market_cap, &pool.pool.to_string()).await?;
+                }
+            }
+
+            TargetEvent::PumpammBuy(buy) => {
+                // 如果毕业的话则更新价格和市场市值
+                if let Ok(mint) = self.pool_mint(&mut conn, &buy.pool.to_string()).await {
+                    let price = cal_pumpamm_price(buy.pool_base_token_reserves, buy.pool_quote_token_reserves);
+                    let market_cap = cal_pumpamm_marketcap_precise(price);
+                    // debug!("buy mint {} pool {} price {} market cap: {}", mint, buy.pool.to_string(), price, market_cap);
+                    self.update_market_cap(&mut conn, &mint, price, market_cap, &buy.pool.to_string()).await?;
+                }
+            }
+
+            TargetEvent::PumpammSell(sell) => {
+                // 如果毕业的话则更新价格和市场市值
+                if let Ok(mint) = self.pool_mint(&mut conn, &sell.pool.to_string()).await {
+                    let price = cal_pumpamm_price(sell.pool_base_token_reserves, sell.pool_quote_token_reserves);
+                    let market_cap = cal_pumpamm_marketcap_precise(price);
+                    self.update_market_cap(&mut conn, &mint, price, market_cap, &sell.pool.to_string()).await?;
+                }
+            }
+
+            TargetEvent::PumpammDeposit(deposit) => {
+                if let Ok(mint) = self.pool_mint(&mut conn, &deposit.pool.to_string()).await {
+                    let price = cal_pumpamm_price(deposit.pool_base_token_reserves, deposit.pool_quote_token_reserves);
+                    let market_cap = cal_pumpamm_marketcap_precise(price);
+                    self.update_market_cap(&mut conn, &mint, price, market_cap, &deposit.pool.to_string()).await?;
+                }
+            }
+
+            TargetEvent::PumpammWithdraw(withdraw) => {
+                if let Ok(mint) = self.pool_mint(&mut conn, &withdraw.pool.to_string()).await {
+                    let price = cal_pumpamm_price(withdraw.pool_base_token_reserves, withdraw.pool_quote_token_reserves);
+                    let market_cap = cal_pumpamm_marketcap_precise(price);
+                    self.update_market_cap(&mut conn, &mint, price, market_cap, &withdraw.pool.to_string()).await?;
+                }
+            }
+
+            TargetEvent::MoonshotBuy(trade) | TargetEvent::MoonshotSell(trade) => {
+                // 曲线位置由累计成交量推算, the curve position follows from the tokens sold so far
+                let delta = if matches!(event, TargetEvent::MoonshotBuy(_)) {
+                    trade.amount as i64
+                } else {
+                    -(trade.amount as i64)
+                };
+                let curve = trade.curve.to_string();
+                // counted from the create on, curves launched before tracking have no baseline
+                let mint = from_curve_query_token_mint(&mut conn, &curve).await?;
+                if !mint.is_empty() {
+                    let tokens_sold = add_tokens_sold(&mut conn, &curve, delta).await?;
+                    let (sol_reserves, token_reserves) = cal_curve_reserves(&MOONSHOT_CURVE, tokens_sold);
+                    let price = cal_curve_price(&MOONSHOT_CURVE, sol_reserves, token_reserves);
+                    let market_cap = cal_curve_marketcap(&MOONSHOT_CURVE, price);
+                    self.update_market_cap(&mut conn, &mint, price, market_cap, "").await?;
+                }
+            }
+
+            TargetEvent::MoonshotCreate(create) => {
+                let create = create.to_create_event();
+                add_token_info(&mut conn, ctx, Program::Moonshot, &create).await?;
+                self.cache_created(ctx, Program::Moonshot, &create);
+            }
+
+            TargetEvent::MoonshotMigration(migration) => {
+                debug!("moonshot migration: {:?}", migration);
+            }
+
+            TargetEvent::BoopCreate(create) => {
+                // the fee payer launches the token
+                let creator = Pubkey::from_str(&ctx.fee_payer).unwrap_or_default();
+                let create = create.to_create_event(creator);
+                add_token_info(&mut conn, ctx, Program::Boop, &create).await?;
+                self.cache_created(ctx, Program::Boop, &create);
+            }
+
+            TargetEvent::BoopBuy(trade) | TargetEvent::BoopSell(trade) => {
+                let is_buy = matches!(event, TargetEvent::BoopBuy(_));
+                let mint = trade.mint.to_string();
+                // only tracked tokens are counted, the sold amounts of others would never be removed
+                if self.is_tracked_mint(&mut conn, &mint).await? {
+                    let tokens_sold = add_tokens_sold(&mut conn, &mint, trade.tokens_bought(is_buy)).await?;
+                    let (sol_reserves, token_reserves) = cal_curve_reserves(&BOOP_CURVE, tokens_sold);
+                    let price = cal_curve_price(&BOOP_CURVE, sol_reserves, token_reserves);
+                    let market_cap = cal_curve_marketcap(&BOOP_CURVE, price);
+                    self.update_market_cap(&mut conn, &mint, price, market_cap, "").await?;
+                }
+            }
+
+            // handled by `HolderTracker`
+            TargetEvent::TokenTransfer(_) => {}
+
+            // counted by the engine, dumped by `UnknownEventDumper`
+            TargetEvent::Unknown { .. } => {}
+        }
+        Ok(())
+    }
+}
+
+/// Logs every event at trace level
+pub struct EventLogger;
+
+#[async_trait]
+impl EventHandler for EventLogger {
+    fn name(&self) -> &str {
+        "event_logger"
+    }
+
+    async fn handle(&self, ctx: &EventContext, event: &TargetEvent) -> Result<()> {
+        trace!("slot {} tx {} event {:?}", ctx.slot, ctx.signature, event);
+        Ok(())
+    }
+}
+
+/// Records the compute unit price buyers of a token paid, see `cache::priority_fee_stats`
+pub struct PriorityFeeTracker {
+    conn: RedisConn,
+    samples: usize,
+}
+
+impl PriorityFeeTracker {
+    pub fn new(conn: RedisConn, samples: usize) -> Self {
+        Self { conn, samples }
+    }
+}
+
+#[async_trait]
+impl EventHandler for PriorityFeeTracker {
+    fn name(&self) -> &str {
+        "priority_fee_tracker"
+    }
+
+    async fn handle(&self, ctx: &EventContext, event: &TargetEvent) -> Result<()> {
+        let mut conn = self.conn.clone();
+        let mint = match event {
+            TargetEvent::PumpfunBuy(trade) => trade.mint.to_string(),
+            TargetEvent::PumpfunTradeIx(trade) if trade.is_buy => trade.mint.to_string(),
+            TargetEvent::PumpammSwapIx(swap) if swap.is_buy => swap.base_mint.to_string(),
+            TargetEvent::BoopBuy(trade) => trade.mint.to_string(),
+            TargetEvent::PumpammBuy(buy) => from_pool_query_token_mint(&mut conn, &buy.pool.to_string()).await?,
+            TargetEvent::MoonshotBuy(trade) => from_curve_query_token_mint(&mut conn, &trade.curve.to_string()).await?,
+            _ => return Ok(()),
+        };
+        if mint.is_empty() {
+            return Ok(());
+        }
+        add_priority_fee(&mut conn, &mint, ctx.compute_unit_price, self.samples).await?;
+        Ok(())
+    }
+}
+
+/// Aggregates the SOL volume of tracked tokens, see `cache::token_volume`
+pub struct VolumeTracker {
+    conn: RedisConn,
+}
+
+impl VolumeTracker {
+    pub fn new(conn: RedisConn) -> Self {
+        Self { conn }
+    }
+}
+
+#[async_trait]
+impl EventHandler for VolumeTracker {
+    fn name(&self) -> &str {
+        "volume_tracker"
+    }
+
+    async fn handle(&self, _ctx: &EventContext, event: &TargetEvent) -> Result<()> {
+        let mut conn = self.conn.clone();
+        let (mint, is_buy, lamports) = match event {
+            TargetEvent::PumpfunBuy(trade) | TargetEvent::PumpfunSell(trade) => {
+                (trade.mint.to_string(), trade.is_buy, trade.sol_amount)
+            }
+            TargetEvent::PumpammBuy(buy) => {
+                (from_pool_query_token_mint(&mut conn, &buy.pool.to_string()).await?, true, buy.quote_amount_in)
+            }
+            TargetEvent::PumpammSell(sell) => {
+                (from_pool_query_token_mint(&mut conn, &sell.pool.to_string()).await?, false, sell.quote_amount_out)
+            }
+            TargetEvent::MoonshotBuy(trade) | TargetEvent::MoonshotSell(trade) => (
+                from_curve_query_token_mint(&mut conn, &trade.curve.to_string()).await?,
+                matches!(event, TargetEvent::MoonshotBuy(_)),
+                trade.collateral_amount,
+            ),
+            // lamports go in on a buy and come out on a sell
+            TargetEvent::BoopBuy(trade) => (trade.mint.to_string(), true, trade.amount_in),
+            TargetEvent::BoopSell(trade) => (trade.mint.to_string(), false, trade.amount_out),
+            // instructions only carry a SOL limit, not the amount traded
+            TargetEvent::PumpfunTradeIx(_) | TargetEvent::PumpammSwapIx(_) => return Ok(()),
+            _ => return Ok(()),
+        };
+        if mint.is_empty() || !is_tracked(&mut conn, &mint).await? {
+            return Ok(());
+        }
+        add_volume(&mut conn, &mint, is_buy, lamports).await?;
+        Ok(())
+    }
+}
+
+/// Counts the distinct wallets buying tracked tokens, see `cache::add_buyer`
+pub struct BuyerTracker {
+    conn: RedisConn,
+}
+
+impl BuyerTracker {
+    pub fn new(conn: RedisConn) -> Self {
+        Self { conn }
+    }
+}
+
+#[async_trait]
+impl EventHandler for BuyerTracker {
+    fn name(&self) -> &str {
+        "buyer_tracker"
+    }
+
+    async fn handle(&self, ctx: &EventContext, event: &TargetEvent) -> Result<()> {
+        let mut conn = self.conn.clone();
+        let mint = bought_mint(&mut conn, event).await?;
+        // the fee payer signs the buy
+        if mint.is_empty() || ctx.fee_payer.is_empty() {
+            return Ok(());
+        }
+        add_buyer(&mut conn, &mint, &ctx.fee_payer).await?;
+        Ok(())
+    }
+}
+
+/// Replies to the alerts of tokens that graduate or have their PumpSwap liquidity withdrawn,
+/// see `alert.followups`; the 2x and market cap rug replies come from the market cap check
+pub struct LifecycleAlerts {
+    conn: RedisConn,
+    notifier: Notifier,
+    withdraw_percent: f32,
+    rug_withdraw_percent: f32,
+    rug_cleanup: RugCleanup,
+    record_ttl: u64,
+}
+
+impl LifecycleAlerts {
+    pub fn new(conn: RedisConn, notifier: Notifier, alert: &AlertConfig) -> Self {
+        Self {
+            conn,
+            notifier,
+            withdraw_percent: alert.followup_withdraw_percent,
+            rug_withdraw_percent: alert.rug_withdraw_percent,
+            rug_cleanup: alert.rug_cleanup,
+            record_ttl: alert.record_ttl,
+        }
+    }
+
+    // reply once per token and kind, in the background so the worker is not held up by the send queue
+    async fn reply(&self, conn: &mut RedisConn, mint: &str, mut followup: FollowupAlert) -> Result<()> {
+        let Some(messages) = alert_messages(conn, mint).await? else {
+            return Ok(());
+        };
+        let record = get_token_record(conn, mint).await?;
+        // muted since the alert
+        if let Some(record) = &record {
+            if blocklist(conn).await?.blocks(record) {
+                return Ok(());
+            }
+        }
+        if let FollowupAlert::Rugged { market_cap, alert_market_cap, .. } = &mut followup {
+            *market_cap = record.map(|record| record.market_cap).unwrap_or_default();
+            *alert_market_cap = messages.alert_market_cap;
+        }
+        if !mark_followup(conn, mint, &followup.kind(), self.record_ttl).await? {
+            return Ok(());
+        }
+        info!("{} follow-up for {}", followup.kind(), mint);
+        let notifier = self.notifier.clone();
+        let mint = mint.to_string();
+        if matches!(followup, FollowupAlert::Rugged { .. }) {
+            // the last word on the token
+            remove_alert_messages(conn, &mint).await?;
+            let cleanup = self.rug_cleanup;
+            tokio::spawn(async move {
+                let _lock = lock_alert_messages(&mint).await;
+                notifier.send_rug_notice(&messages.messages, &followup, &messages.details, cleanup).await;
+            });
+            return Ok(());
+        }
+        // after the edits of the market cap check in flight, one update of the alerts at a time
+        tokio::spawn(async move {
+            let _lock = lock_alert_messages(&mint).await;
+            notifier.send_followup(&messages.messages, &followup, &messages.details).await;
+        });
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl EventHandler for LifecycleAlerts {
+    fn name(&self) -> &str {
+        "lifecycle_alerts"
+    }
+
+    async fn handle(&self, _ctx: &EventContext, event: &TargetEvent) -> Result<()> {
+        let mut conn = self.conn.clone();
+        match event {
+            TargetEvent::PumpfunComplete(complete) => {
+                self.reply(&mut conn, &complete.mint.to_string(), FollowupAlert::Graduated).await?;
+            }
+            TargetEvent::PumpammWithdraw(withdraw) => {
+                // lp_mint_supply is the supply before the withdrawn lp tokens are burned
+                let percent = if withdraw.lp_mint_supply == 0 {
+                    100.0
+                } else {
+                    (withdraw.lp_token_amount_in as f64 / withdraw.lp_mint_supply as f64 * 100.0) as f32
+                };
+                let rugged = self.rug_withdraw_percent > 0.0 && percent >= self.rug_withdraw_percent;
+                if percent < self.withdraw_percent && !rugged {
+                    return Ok(());
+                }
+                let mint = from_pool_query_token_mint(&mut conn, &withdraw.pool.to_string()).await?;
+                if mint.is_empty() {
+                    return Ok(());
+                }
+                let followup = if rugged {
+                    // market caps are filled in from the record and the alert
+                    FollowupAlert::Rugged { market_cap: 0.0, alert_market_cap: 0.0, liquidity_pulled: true }
+                } else {
+                    let sol = withdraw.quote_amount_out as f64 / LAMPORTS_PER_SOL as f64;
+                    FollowupAlert::LiquidityRemoved { percent, sol }
+                };
+                self.reply(&mut conn, &mint, followup).await?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// Records the first buyer wallets of tracked tokens and whether they bought in the create
+/// block, see `cache::first_buyers`
+pub struct FirstBuyerTracker {
+    conn: RedisConn,
+    limit: usize,
+}
+
+impl FirstBuyerTracker {
+    pub fn new(conn: RedisConn, limit: usize) -> Self {
+        Self { conn, limit }
+    }
+}
+
+#[async_trait]
+impl EventHandler for FirstBuyerTracker {
+    fn name(&self) -> &str {
+        "first_buyer_tracker"
+    }
+
+    async fn handle(&self, ctx: &EventContext, event: &TargetEvent) -> Result<()> {
+        let mut conn = self.conn.clone();
+        let mint = bought_mint(&mut conn, event).await?;
+        if mint.is_empty() || ctx.fee_payer.is_empty() {
+            return Ok(());
+        }
+        add_first_buyer(&mut conn, &mint, &ctx.fee_payer, ctx.slot, self.limit).await?;
+        Ok(())
+    }
+}
+
+// mint bought by a buy trade event, empty for other events and untracked pools and curves
+async fn bought_mint(conn: &mut RedisConn, event: &TargetEvent) -> RedisResult<String> {
+    match event {
+        TargetEvent::PumpfunBuy(trade) => Ok(trade.mint.to_string()),
+        TargetEvent::BoopBuy(trade) => Ok(trade.mint.to_string()),
+        TargetEvent::PumpammBuy(buy) => from_pool_query_token_mint(conn, &buy.pool.to_string()).await,
+        TargetEvent::MoonshotBuy(trade) => from_curve_query_token_mint(conn, &trade.curve.to_string()).await,
+        TargetEvent::PumpfunTradeIx(_) | TargetEvent::PumpammSwapIx(_) => Ok(instruction_trade(event)
+            .filter(|(_, _, change)| *change > 0)
+            .map(|(mint, _, _)| mint.to_string())
+            .unwrap_or_default()),
+        _ => Ok(String::new()),
+    }
+}
+
+// mint, trader and token change (negative for a sell) of a top-level buy/sell instruction;
+// the token amount is exact, the SOL side is only a limit
+fn instruction_trade(event: &TargetEvent) -> Option<(Pubkey, Pubkey, i64)> {
+    let signed = |amount: u64, is_buy: bool| if is_buy { amount as i64 } else { -(amount as i64) };
+    match event {
+        TargetEvent::PumpfunTradeIx(trade) => Some((trade.mint, trade.user, signed(trade.token_amount, trade.is_buy))),
+        TargetEvent::PumpammSwapIx(swap) => Some((swap.base_mint, swap.user, signed(swap.base_amount, swap.is_buy))),
+        _ => None,
+    }
+}
+
+/// Follows the creator's balance of tracked tokens, see `cache::update_dev_balance`
+pub struct DevHoldingsTracker {
+    conn: RedisConn,
+    transfers: bool,
+}
+
+impl DevHoldingsTracker {
+    /// `transfers` takes the balances from `TargetEvent::TokenTransfer` events (`engine.spl_transfers`)
+    /// instead of adding up the trades
+    pub fn new(conn: RedisConn, transfers: bool) -> Self {
+        Self { conn, transfers }
+    }
+}
+
+#[async_trait]
+impl EventHandler for DevHoldingsTracker {
+    fn name(&self) -> &str {
+        "dev_holdings_tracker"
+    }
+
+    async fn handle(&self, _ctx: &EventContext, event: &TargetEvent) -> Result<()> {
+        let mut conn = self.conn.clone();
+        if let TargetEvent::TokenTransfer(transfer) = event {
+            if !self.transfers {
+                return Ok(());
+            }
+            let mint = transfer.mint.to_string();
+            let Some(record) = get_token_record(&mut conn, &mint).await? else {
+                return Ok(());
+            };
+            let supply = total_supply(transfer.program);
+            if transfer.source_owner.to_string() == record.creator {
+                // 卖回曲线或池子, tokens going back to the curve or the pool are a sell
+                let sold = record.is_reserve(&transfer.destination_owner.to_string());
+                let balance = DevBalance::Balance(transfer.source_balance);
+                update_dev_balance(&mut conn, &mint, &record.creator, balance, supply, sold).await?;
+            }
+            if transfer.destination_owner.to_string() == record.creator {
+                let balance = DevBalance::Balance(transfer.destination_balance);
+                update_dev_balance(&mut conn, &mint, &record.creator, balance, supply, false).await?;
+            }
+            return Ok(());
+        }
+        // the transfers of a trade carry the same tokens
+        if self.transfers {
+            return Ok(());
+        }
+        let signed = |amount: u64, is_buy: bool| if is_buy { amount as i64 } else { -(amount as i64) };
+        let (mint, wallet, change, supply) = match event {
+            TargetEvent::PumpfunBuy(trade) | TargetEvent::PumpfunSell(trade) => {
+                (trade.mint.to_string(), trade.user, signed(trade.token_amount, trade.is_buy), PUMPFUN_TOTAL_SUPPLY)
+            }
+            TargetEvent::PumpammBuy(buy) => (
+                from_pool_query_token_mint(&mut conn, &buy.pool.to_string()).await?,
+                buy.user,
+                buy.base_amount_out as i64,
+                PUMPFUN_TOTAL_SUPPLY,
+            ),
+            TargetEvent::PumpammSell(sell) => (
+                from_pool_query_token_mint(&mut conn, &sell.pool.to_string()).await?,
+                sell.user,
+                -(sell.base_amount_in as i64),
+                PUMPFUN_TOTAL_SUPPLY,
+            ),
+            TargetEvent::MoonshotBuy(trade) | TargetEvent::MoonshotSell(trade) => (
+                from_curve_query_token_mint(&mut conn, &trade.curve.to_string()).await?,
+                trade.sender,
+                signed(trade.amount, matches!(event, TargetEvent::MoonshotBuy(_))),
+                MOONSHOT_CURVE.total_supply,
+            ),
+            TargetEvent::BoopBuy(trade) | TargetEvent::BoopSell(trade) => (
+                trade.mint.to_string(),
+                trade.user,
+                trade.tokens_bought(matches!(event, TargetEvent::BoopBuy(_))),
+                BOOP_CURVE.total_supply,
+            ),
+            TargetEvent::PumpfunTradeIx(_) | TargetEvent::PumpammSwapIx(_) => match instruction_trade(event) {
+                Some((mint, wallet, change)) => (mint.to_string(), wallet, change, PUMPFUN_TOTAL_SUPPLY),
+                None => return Ok(()),
+            },
+            _ => return Ok(()),
+        };
+        if mint.is_empty() {
+            return Ok(());
+        }
+        update_dev_balance(&mut conn, &mint, &wallet.to_string(), DevBalance::Change(change), supply, change < 0).await?;
+        Ok(())
+    }
+}
+
+/// Total supply in raw units of the tokens launched by `program`
+pub fn total_supply(program: Program) -> u64 {
+    match program {
+        Program::Pumpfun | Program::Pumpamm => PUMPFUN_TOTAL_SUPPLY,
+        Program::Moonshot => MOONSHOT_CURVE.total_supply,
+        Program::Boop => BOOP_CURVE.total_supply,
+    }
+}
+
+/// Keeps the holders of tracked tokens from `TargetEvent::TokenTransfer` events
+pub struct HolderTracker {
+    conn: RedisConn,
+}
+
+impl HolderTracker {
+    pub fn new(conn: RedisConn) -> Self {
+        Self { conn }
+    }
+}
+
+#[async_trait]
+impl EventHandler for HolderTracker {
+    fn name(&self) -> &str {
+        "holder_tracker"
+    }
+
+    async fn handle(&self, _ctx: &EventContext, event: &TargetEvent) -> Result<()> {
+        let TargetEvent::TokenTransfer(transfer) = event else {
+            return Ok(());
+        };
+        let mut conn = self.conn.clone();
+        let mint = transfer.mint.to_string();
+        // only tokens launched while monitoring
+        let Some(record) = get_token_record(&mut conn, &mint).await? else {
+            return Ok(());
+        };
+        // the curve and the pool hold the unsold supply, they are not holders
+        let is_holder = |owner: &Pubkey| *owner != Pubkey::default() && !record.is_reserve(&owner.to_string());
+
+        if is_holder(&transfer.source_owner) {
+            update_holder(&mut conn, &mint, &transfer.source_owner.to_string(), transfer.source_balance).await?;
+        }
+        if is_holder(&transfer.destination_owner) {
+            update_holder(&mut conn, &mint, &transfer.destination_owner.to_string(), transfer.destination_balance).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Appends `TargetEvent::Unknown` events as JSON lines to `engine.unknown_events_dump`
+pub struct UnknownEventDumper {
+    path: String,
+}
+
+impl UnknownEventDumper {
+    pub fn new(path: String) -> Self {
+        Self { path }
+    }
+}
+
+#[async_trait]
+impl EventHandler for UnknownEventDumper {
+    fn name(&self) -> &str {
+        "unknown_event_dumper"
+    }
+
+    async fn handle(&self, ctx: &EventContext, event: &TargetEvent) -> Result<()> {
+        if !matches!(event, TargetEvent::Unknown { .. }) {
+            return Ok(());
+        }
+        let line = json!({ "slot": ctx.slot, "signature": ctx.signature, "event": event });
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await
+            .with_context(|| format!("open unknown events dump {} error", self.path))?;
+        file.write_all(format!("{}\n", line).as_bytes()).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::types::{AMMSwapInstruction, TradeInstruction};
+
+    use super::*;
+
+    #[test]
+    fn instruction_trade_test() {
+        let (mint, bonding_curve, user) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let sell = TargetEvent::PumpfunTradeIx(TradeInstruction {
+            is_buy: false,
+            mint,
+            bonding_curve,
+            user,
+            token_amount: 10,
+            sol_limit: 5,
+        });
+        assert_eq!(instruction_trade(&sell), Some((mint, user, -10)));
+
+        let buy = TargetEvent::PumpammSwapIx(AMMSwapInstruction {
+            is_buy: true,
+            pool: Pubkey::new_unique(),
+            user,
+            base_mint: mint,
+            quote_mint: Pubkey::new_unique(),
+            base_amount: 7,
+            quote_limit: 3,
+        });
+        assert_eq!(instruction_trade(&buy), Some((mint, user, 7)));
+        let unknown = TargetEvent::Unknown { program: Program::Pumpfun, discriminator: [0; 8], data: vec![] };
+        assert_eq!(instruction_trade(&unknown), None);
+    }
+}
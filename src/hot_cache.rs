@@ -0,0 +1,186 @@
+//! In-memory layer in front of Redis for the records of tokens that are trading.
+//!
+//! Lookups by mint and by pool are served from memory after the first Redis read, mints and
+//! pools Redis does not know are remembered for a while too, so the trades of untracked tokens
+//! cost no round trip. Market cap updates change the cached record at once and are written
+//! behind by `flush`; Redis stays the durable view, records it no longer has are dropped here.
+
+use dashmap::DashMap;
+use redis::RedisResult;
+use solana_sdk::timing::timestamp;
+
+use crate::{
+    cache::{from_pool_query_token_mint, get_token_record, update_market_caps, MarketCapUpdate, TokenRecord},
+    redis_conn::RedisConn,
+};
+
+struct HotRecord {
+    record: TokenRecord,
+    // last lookup or update, unix milliseconds
+    used: u64,
+}
+
+/// Cached token records with write-behind market cap updates
+pub struct HotRecords {
+    records: DashMap<String, HotRecord>,
+    // pool -> mint of cached records
+    pools: DashMap<String, String>,
+    // mints and pools redis has no record for, until when that is trusted
+    absent: DashMap<String, u64>,
+    pending: DashMap<String, MarketCapUpdate>,
+    ttl: u64,
+}
+
+impl HotRecords {
+    /// Records unused for `ttl` milliseconds are dropped on flush, untracked mints and pools
+    /// are looked up again after `ttl`
+    pub fn new(ttl: u64) -> Self {
+        Self {
+            records: DashMap::new(),
+            pools: DashMap::new(),
+            absent: DashMap::new(),
+            pending: DashMap::new(),
+            ttl,
+        }
+    }
+
+    /// Record of `mint`, `None` for an untracked token
+    pub async fn record(&self, conn: &mut RedisConn, mint: &str) -> RedisResult<Option<TokenRecord>> {
+        let now = timestamp();
+        if let Some(mut hot) = self.records.get_mut(mint) {
+            hot.used = now;
+            return Ok(Some(hot.record.clone()));
+        }
+        if self.absent.get(mint).is_some_and(|until| *until > now) {
+            return Ok(None);
+        }
+        match get_token_record(conn, mint).await? {
+            Some(record) => {
+                self.insert(record.clone());
+                Ok(Some(record))
+            }
+            None => {
+                self.absent.insert(mint.to_string(), now + self.ttl);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Mint of the tracked token traded in `pool`, empty when there is none
+    pub async fn mint_by_pool(&self, conn: &mut RedisConn, pool: &str) -> RedisResult<String> {
+        let now = timestamp();
+        if let Some(mint) = self.pools.get(pool).map(|mint| mint.clone()) {
+            return Ok(mint);
+        }
+        if self.absent.get(pool).is_some_and(|until| *until > now) {
+            return Ok(String::new());
+        }
+        let mint = from_pool_query_token_mint(conn, pool).await?;
+        if mint.is_empty() || self.record(conn, &mint).await?.is_none() {
+            self.absent.insert(pool.to_string(), now + self.ttl);
+            return Ok(String::new());
+        }
+        self.pools.insert(pool.to_string(), mint.clone());
+        Ok(mint)
+    }
+
+    /// Cache a record, e.g. of a token just created
+    pub fn insert(&self, record: TokenRecord) {
+        self.absent.remove(&record.mint);
+        if !record.pool.is_empty() {
+            self.absent.remove(&record.pool);
+            self.pools.insert(record.pool.clone(), record.mint.clone());
+        }
+        self.records.insert(record.mint.clone(), HotRecord { record, used: timestamp() });
+    }
+
+    /// Link a cached record to `pool`, after the pool was set in redis; an empty pool
+    /// (e.g. from a bonding curve trade) leaves the link unchanged
+    pub fn set_pool(&self, mint: &str, pool: &str) {
+        if pool.is_empty() {
+            return;
+        }
+        self.absent.remove(pool);
+        let old = match self.records.get_mut(mint) {
+            Some(mut hot) => std::mem::replace(&mut hot.record.pool, pool.to_string()),
+            None => return,
+        };
+        if old != pool {
+            self.pools.remove(&old);
+        }
+        self.pools.insert(pool.to_string(), mint.to_string());
+    }
+
+    /// Apply an update to the cached record and queue it for the next flush; returns the
+    /// number of queued tokens, 0 when the token is untracked
+    pub async fn update(&self, conn: &mut RedisConn, update: MarketCapUpdate) -> RedisResult<usize> {
+        if self.record(conn, &update.mint).await?.is_none() {
+            return Ok(0);
+        }
+        if let Some(mut hot) = self.records.get_mut(&update.mint) {
+            hot.record.update_market_cap(update.market_cap as f32, update.high_time);
+            hot.used = update.high_time;
+        }
+        self.set_pool(&update.mint, &update.pool);
+        match self.pending.get_mut(&update.mint) {
+            Some(mut queued) => queued.merge(update),
+            None => {
+                self.pending.insert(update.mint.clone(), update);
+            }
+        }
+        Ok(self.pending.len())
+    }
+
+    /// Write the queued updates, then drop the records redis no longer has and the ones
+    /// unused for `ttl`; returns the number of tokens written
+    pub async fn flush(&self, conn: &mut RedisConn, price_history: u64) -> RedisResult<usize> {
+        let mints: Vec<String> = self.pending.iter().map(|queued| queued.key().clone()).collect();
+        let updates: Vec<MarketCapUpdate> =
+            mints.iter().filter_map(|mint| self.pending.remove(mint).map(|(_, update)| update)).collect();
+        let updated = update_market_caps(conn, &updates, price_history).await?;
+
+        let now = timestamp();
+        // removed from redis in the meantime, e.g. by the market cap check
+        let mut evict: Vec<String> =
+            updates.iter().map(|update| &update.mint).filter(|mint| !updated.contains(mint)).cloned().collect();
+        evict.extend(
+            self.records
+                .iter()
+                .filter(|hot| hot.used + self.ttl <= now && !self.pending.contains_key(hot.key()))
+                .map(|hot| hot.key().clone()),
+        );
+        for mint in evict {
+            if let Some((_, hot)) = self.records.remove(&mint) {
+                self.pools.remove(&hot.record.pool);
+            }
+        }
+        self.absent.retain(|_, until| *until > now);
+        Ok(updated.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_pool_test() {
+        let hot = HotRecords::new(60_000);
+        hot.insert(TokenRecord { mint: "mint".to_string(), pool: "old".to_string(), ..Default::default() });
+        assert_eq!(hot.pools.get("old").map(|mint| mint.clone()), Some("mint".to_string()));
+
+        hot.set_pool("mint", "new");
+        assert!(hot.pools.get("old").is_none());
+        assert_eq!(hot.pools.get("new").map(|mint| mint.clone()), Some("mint".to_string()));
+        assert_eq!(hot.records.get("mint").map(|hot| hot.record.pool.clone()), Some("new".to_string()));
+
+        // bonding curve trades carry no pool
+        hot.set_pool("mint", "");
+        assert_eq!(hot.pools.get("new").map(|mint| mint.clone()), Some("mint".to_string()));
+        assert_eq!(hot.records.get("mint").map(|hot| hot.record.pool.clone()), Some("new".to_string()));
+
+        // untracked tokens are left alone
+        hot.set_pool("other", "pool");
+        assert!(hot.pools.get("pool").is_none());
+    }
+}
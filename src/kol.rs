@@ -0,0 +1,178 @@
+//! KOL mentions, `x.kol_accounts`.
+//!
+//! Every `x.kol_interval` the timelines of the listed influencer accounts are read and the
+//! Solana addresses in their new tweets (text and links) are looked up among the tracked tokens.
+//! A tracked token gets one "KOL mention" message per account, a reply to its alert when it was
+//! alerted. The newest tweet read per account is kept in redis, a restart doesn't announce old
+//! tweets again and the first poll of an account only marks where to start.
+
+use std::{str::FromStr, time::Duration};
+
+use redis::RedisResult;
+use solana_sdk::pubkey::Pubkey;
+use tokio::{
+    sync::watch,
+    time::{interval_at, Instant, MissedTickBehavior},
+};
+use tracing::{debug, info, warn};
+
+use crate::{
+    cache::{alert_messages, blocklist, get_token_record, kol_last_seen, mark_kol_mention, set_kol_last_seen},
+    digest::DigestToken,
+    redis_conn::RedisConn,
+    social::SocialChain,
+    tg_bot::{locale::text, notifier::Notifier, tg_bot::escape_markdown},
+    x::Tweet,
+};
+
+// tweet text quoted in the message, in characters
+const QUOTE_LEN: usize = 280;
+
+/// A tracked token mentioned by a KOL
+#[derive(Debug, Clone, Default)]
+pub struct KolMention {
+    /// screen name without `@`
+    pub account: String,
+    pub tweet_id: String,
+    pub text: String,
+    pub token: DigestToken,
+}
+
+impl KolMention {
+    /// The message as MarkdownV2 in `language`
+    pub fn markdown(&self, language: &str) -> String {
+        let mut quote: String = self.text.chars().take(QUOTE_LEN).collect();
+        if quote.len() < self.text.len() {
+            quote.push('…');
+        }
+        format!(
+            "📣 *{}*\n{}\n\n_{}_\n[{}](https://x.com/{}/status/{})",
+            escape_markdown(&text(language, "kol_title", &[("account", &format!("@{}", self.account))])),
+            self.token.markdown(),
+            escape_markdown(&quote),
+            escape_markdown(&text(language, "kol_view", &[])),
+            self.account,
+            self.tweet_id,
+        )
+    }
+}
+
+/// Solana addresses in `text`, each once in order of appearance
+pub fn solana_addresses(text: &str) -> Vec<String> {
+    let mut found: Vec<String> = Vec::new();
+    // links like pump.fun/coin/<mint> split at the slashes
+    for word in text.split(|c: char| !c.is_ascii_alphanumeric()) {
+        if (32..=44).contains(&word.len()) && Pubkey::from_str(word).is_ok() && !found.iter().any(|f| f == word) {
+            found.push(word.to_string());
+        }
+    }
+    found
+}
+
+// numeric tweet id, newer tweets have higher ids
+fn tweet_number(tweet: &Tweet) -> u64 {
+    tweet.tweet_id.parse().unwrap_or(0)
+}
+
+/// Read the KOL timelines every `interval` (milliseconds) until shutdown; mentions are
+/// remembered for `ttl_ms` like the token records (0 keeps them)
+pub async fn run_kol_scanner(
+    mut conn: RedisConn,
+    social: SocialChain,
+    notifier: Notifier,
+    accounts: Vec<String>,
+    interval: u64,
+    ttl_ms: u64,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let accounts: Vec<String> = accounts.iter().map(|account| account.trim().trim_start_matches('@').to_string()).collect();
+    info!("scanning {} KOL timelines every {}ms", accounts.len(), interval);
+    let period = Duration::from_millis(interval.max(1));
+    let mut ticker = interval_at(Instant::now(), period);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        tokio::select! {
+            biased;
+            _ = shutdown.changed() => break,
+            _ = ticker.tick() => {}
+        }
+        if let Err(e) = scan(&mut conn, &social, &notifier, &accounts, ttl_ms).await {
+            warn!("scan KOL timelines error: {}", e);
+        }
+    }
+}
+
+async fn scan(conn: &mut RedisConn, social: &SocialChain, notifier: &Notifier, accounts: &[String], ttl_ms: u64) -> RedisResult<()> {
+    let last_seen = kol_last_seen(conn).await?;
+    let blocklist = blocklist(conn).await?;
+    for account in accounts {
+        let mut tweets = social.timeline(account).await;
+        let Some(newest) = tweets.iter().map(tweet_number).max() else {
+            continue;
+        };
+        let Some(seen) = last_seen.get(account).copied() else {
+            // 第一次只记位置, old tweets are not news
+            set_kol_last_seen(conn, account, newest).await?;
+            continue;
+        };
+        tweets.retain(|tweet| tweet_number(tweet) > seen);
+        tweets.sort_by_key(tweet_number);
+        for tweet in &tweets {
+            let mut text = tweet.text.clone();
+            for url in tweet.urls.iter().flatten() {
+                text.push(' ');
+                text.push_str(url);
+            }
+            for mint in solana_addresses(&text) {
+                let Some(record) = get_token_record(conn, &mint).await? else {
+                    continue;
+                };
+                if blocklist.blocks(&record) || !mark_kol_mention(conn, &mint, account, ttl_ms).await? {
+                    continue;
+                }
+                debug!("@{} mentioned {} in tweet {}", account, mint, tweet.tweet_id);
+                let mention = KolMention {
+                    account: account.clone(),
+                    tweet_id: tweet.tweet_id.clone(),
+                    text: tweet.text.clone(),
+                    token: DigestToken::new(&record),
+                };
+                let messages = alert_messages(conn, &mint).await?.map(|messages| messages.messages).unwrap_or_default();
+                notifier.send_kol_mention(&mention, &messages).await;
+            }
+        }
+        if newest > seen {
+            set_kol_last_seen(conn, account, newest).await?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn solana_addresses_test() {
+        let mint = "4k3Dyjzvzp8eMZWUXbBCjEvwSkkk59S5iCNLY3QrkX6R";
+        let text = format!("aping {} rn\nhttps://pump.fun/coin/{} and {}", mint, mint, "So11111111111111111111111111111111111111112");
+        assert_eq!(solana_addresses(&text), vec![mint.to_string(), "So11111111111111111111111111111111111111112".to_string()]);
+        // too short, not base58 or not 32 bytes
+        assert!(solana_addresses("gm, 0xdeadbeef and OOOOOOOOOOOOOOOOOOOOOOOOOOOOOOOOOO").is_empty());
+    }
+
+    #[test]
+    fn kol_mention_markdown_test() {
+        let mention = KolMention {
+            account: "cool_kol".to_string(),
+            tweet_id: "42".to_string(),
+            text: "this one. send it!".to_string(),
+            token: DigestToken { mint: "Mint1".to_string(), name: "Cool".to_string(), symbol: "CT".to_string(), market_cap: 420.0, chart_url: "https://pump.fun/Mint1".to_string(), ..Default::default() },
+        };
+        assert_eq!(
+            mention.markdown("en"),
+            "📣 *KOL mention by @cool\\_kol*\n[Cool](https://pump.fun/Mint1) \\(`CT`\\) `420 SOL`\n\n_this one\\. send it\\!_\
+             \n[View post](https://x.com/cool_kol/status/42)"
+        );
+    }
+}
@@ -1,7 +1,24 @@
 pub mod engine;
+pub mod bus;
+pub mod candles;
+pub mod digest;
+pub mod event_log;
+pub mod handler;
+pub mod hot_cache;
+pub mod kol;
+pub mod pubsub;
+pub mod raw;
+pub mod rate_limit;
+pub mod redis_conn;
 pub mod cache;
 pub mod client;
+pub mod config;
 pub mod constants;
+pub mod secrets;
+pub mod social;
+pub mod stats;
+pub mod storage;
+pub mod trending;
 pub mod types;
 pub mod utils;
 pub mod tg_bot;
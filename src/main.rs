@@ -1,6 +1,6 @@
 use std::{env, str::FromStr};
 
-use sol_new::engine::Monitor;
+use sol_new::{config::{from_slot_arg, profile_arg, Config}, engine::Monitor, secrets::{encrypt_file, SECRETS_PASSPHRASE}};
 
 use tracing::Level;
 use tracing_subscriber::{EnvFilter, FmtSubscriber};
@@ -19,7 +19,44 @@ async fn main() -> anyhow::Result<()> {
     tracing::subscriber::set_global_default(subscriber)
         .expect("Failed to set global subscriber");
 
-    let monitor = Monitor::new().await?;
+    // sol_new encrypt-secrets <plain.toml> <secrets.enc>
+    let args: Vec<String> = env::args().collect();
+    if args.get(1).map(String::as_str) == Some("encrypt-secrets") {
+        let (input, output) = match (args.get(2), args.get(3)) {
+            (Some(input), Some(output)) => (input, output),
+            _ => anyhow::bail!("usage: sol_new encrypt-secrets <plain.toml> <secrets.enc>"),
+        };
+        let passphrase = env::var(SECRETS_PASSPHRASE)
+            .map_err(|_| anyhow::anyhow!("{} is required", SECRETS_PASSPHRASE))?;
+        encrypt_file(input, output, &passphrase)?;
+        tracing::info!("encrypted secrets written to {}", output);
+        return Ok(());
+    }
+
+    let mut config = Config::load_profile(profile_arg(env::args()).as_deref())?;
+    if let Some(slot) = from_slot_arg(env::args()) {
+        config.engine.from_slot = Some(slot);
+    }
+    config.validate()?;
+    if let Some(profile) = &config.profile {
+        tracing::info!("using config profile: {}", profile);
+    }
+    let monitor = Monitor::new(config).await?;
+
+    // sol_new backfill <from_slot> <to_slot>
+    if args.get(1).map(String::as_str) == Some("backfill") {
+        let (from_slot, to_slot) = match (
+            args.get(2).and_then(|s| u64::from_str(s).ok()),
+            args.get(3).and_then(|s| u64::from_str(s).ok()),
+        ) {
+            (Some(from_slot), Some(to_slot)) if from_slot <= to_slot => (from_slot, to_slot),
+            _ => anyhow::bail!("usage: sol_new backfill <from_slot> <to_slot>"),
+        };
+        let replayed = monitor.replay(from_slot, to_slot).await?;
+        tracing::info!("backfill done, {} transactions replayed", replayed);
+        return Ok(());
+    }
+
     monitor.run().await?;
     Ok(())
 }
@@ -0,0 +1,152 @@
+//! Redis Pub/Sub output of the monitor.
+//!
+//! Parsed events go to `events:<program>:<kind>` (e.g. `events:pumpfun:create`) and alert
+//! decisions to `alerts:<decision>` (`alerts:sent`, `alerts:held`, `alerts:removed`), as JSON.
+//! Pub/Sub keeps nothing, subscribers only see messages published while they are connected.
+
+use std::sync::Arc;
+
+use redis::RedisResult;
+use serde_json::{json, Value};
+use tokio::sync::{broadcast, watch};
+use tracing::warn;
+
+use crate::{bus::BusEvent, handler::EventContext, redis_conn::RedisConn, types::TargetEvent};
+
+/// Token whose alert is sent
+pub const ALERTS_SENT_CHANNEL: &str = "alerts:sent";
+/// Token that crossed a tier but is held back by the volume rules
+pub const ALERTS_HELD_CHANNEL: &str = "alerts:held";
+/// Token dropped by the market cap check
+pub const ALERTS_REMOVED_CHANNEL: &str = "alerts:removed";
+
+// events sent in one pipeline when the publisher is behind
+const MAX_BATCH: usize = 256;
+
+/// Channel of an event with serde tag `tag`, `events:<program>:<kind>`
+pub fn event_channel(event: &TargetEvent, tag: &str) -> String {
+    let program = event.program().as_str();
+    let kind = tag.strip_prefix(program).and_then(|kind| kind.strip_prefix('_')).unwrap_or(tag);
+    format!("events:{}:{}", program, kind)
+}
+
+/// Channel and JSON message of an event, `None` when it does not serialize. The mint and
+/// bonding curve are resolved from the instruction accounts, `null` when the event has none.
+pub fn event_message(ctx: &EventContext, event: &TargetEvent) -> Option<(String, String)> {
+    let value = serde_json::to_value(event).ok()?;
+    // the serde tag, e.g. `pumpfun_create`
+    let channel = event_channel(event, value.get("type").and_then(Value::as_str)?);
+    let message = json!({
+        "signature": ctx.signature,
+        "slot": ctx.slot,
+        "block_time": ctx.block_time,
+        "fee_payer": ctx.fee_payer,
+        "mint": event.mint(&ctx.accounts).map(|mint| mint.to_string()),
+        "bonding_curve": event.bonding_curve(&ctx.accounts).map(|curve| curve.to_string()),
+        "event": value,
+    });
+    Some((channel, message.to_string()))
+}
+
+/// Publish an alert decision
+pub async fn publish_alert(conn: &mut RedisConn, channel: &str, message: &Value) -> RedisResult<()> {
+    redis::cmd("PUBLISH").arg(channel).arg(message.to_string()).query_async(conn).await
+}
+
+/// Publish every event of the bus until shutdown
+pub async fn run_event_publisher(
+    mut events: broadcast::Receiver<Arc<BusEvent>>,
+    mut conn: RedisConn,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    loop {
+        let first = tokio::select! {
+            biased;
+            _ = shutdown.changed() => break,
+            event = events.recv() => match event {
+                Ok(event) => event,
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    warn!("event publisher lagged, {} events skipped", skipped);
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+        };
+        // take what is already queued so a burst goes out in one round trip
+        let mut batch = vec![first];
+        while batch.len() < MAX_BATCH {
+            match events.try_recv() {
+                Ok(event) => batch.push(event),
+                Err(_) => break,
+            }
+        }
+        let mut pipe = redis::pipe();
+        for event in &batch {
+            if let Some((channel, message)) = event_message(&event.ctx, &event.event) {
+                pipe.cmd("PUBLISH").arg(channel).arg(message).ignore();
+            }
+        }
+        if let Err(e) = pipe.query_async::<()>(&mut conn).await {
+            warn!("publish events error: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::pubkey::Pubkey;
+
+    use crate::types::{AMMSwapInstruction, Program, TradeEvent};
+
+    #[test]
+    fn event_channel_test() {
+        let unknown = |program| TargetEvent::Unknown { program, discriminator: [0; 8], data: vec![] };
+        assert_eq!(event_channel(&unknown(Program::Boop), "unknown"), "events:boop:unknown");
+        assert_eq!(event_channel(&unknown(Program::Pumpfun), "pumpfun_create"), "events:pumpfun:create");
+        assert_eq!(event_channel(&unknown(Program::Pumpamm), "pumpamm_create_pool"), "events:pumpamm:create_pool");
+        assert_eq!(event_channel(&unknown(Program::Pumpfun), "token_transfer"), "events:pumpfun:token_transfer");
+    }
+
+    #[test]
+    fn event_message_test() {
+        let event = TargetEvent::PumpammSwapIx(AMMSwapInstruction {
+            is_buy: true,
+            pool: Pubkey::new_unique(),
+            user: Pubkey::new_unique(),
+            base_mint: Pubkey::new_unique(),
+            quote_mint: Pubkey::new_unique(),
+            base_amount: 1,
+            quote_limit: 2,
+        });
+        let (channel, message) = event_message(&EventContext::default(), &event).unwrap();
+        let message: Value = serde_json::from_str(&message).unwrap();
+        assert_eq!(channel, "events:pumpamm:swap_ix");
+        assert_eq!(message["mint"], json!(event.mint(&[]).unwrap().to_string()));
+        assert_eq!(message["bonding_curve"], Value::Null);
+
+        // the curve of a pump.fun trade event comes from the buy/sell instruction that emitted it
+        let accounts: Vec<Pubkey> = (0..8).map(|_| Pubkey::new_unique()).collect();
+        let ctx = EventContext { accounts: accounts.clone(), ..Default::default() };
+        let trade = TargetEvent::PumpfunBuy(TradeEvent {
+            mint: accounts[2],
+            sol_amount: 1,
+            token_amount: 1,
+            is_buy: true,
+            user: accounts[6],
+            timestamp: 0,
+            virtual_sol_reserves: 0,
+            virtual_token_reserves: 0,
+            real_sol_reserves: 0,
+            real_token_reserves: 0,
+            fee_recipient: accounts[1],
+            fee_basis_points: 0,
+            fee: 0,
+            creator: Pubkey::new_unique(),
+            creator_fee_basis_points: 0,
+            creator_fee: 0,
+        });
+        let (_, message) = event_message(&ctx, &trade).unwrap();
+        assert_eq!(serde_json::from_str::<Value>(&message).unwrap()["bonding_curve"], json!(accounts[3].to_string()));
+    }
+}
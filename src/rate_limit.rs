@@ -0,0 +1,82 @@
+//! Client-side rate limiting.
+//!
+//! A token bucket holds up to `burst` requests and refills at `per_minute`. A caller reserves a
+//! token and sleeps until it is its turn, callers queue in the order they reserved; one that
+//! would have to wait longer than it is willing to gets no token and can skip the request.
+
+use std::{sync::Mutex, time::Duration};
+
+use tokio::time::{sleep_until, Instant};
+
+/// Token bucket shared by all requests to one API
+#[derive(Debug)]
+pub struct TokenBucket {
+    capacity: f64,
+    // tokens per second, 0 for no limit
+    rate: f64,
+    // tokens left (negative for reservations queued) and when they were counted
+    state: Mutex<(f64, Instant)>,
+}
+
+impl TokenBucket {
+    /// `per_minute` of 0 leaves the rate unlimited, a `burst` of 0 is taken as 1
+    pub fn new(per_minute: u32, burst: u32) -> Self {
+        let capacity = burst.max(1) as f64;
+        Self { capacity, rate: per_minute as f64 / 60.0, state: Mutex::new((capacity, Instant::now())) }
+    }
+
+    /// Reserve a token at `now`, returns when it may be used; `None` and nothing reserved when
+    /// that is more than `max_wait` away
+    pub fn reserve(&self, now: Instant, max_wait: Duration) -> Option<Instant> {
+        if self.rate <= 0.0 {
+            return Some(now);
+        }
+        let mut state = self.state.lock().unwrap();
+        let (tokens, counted) = *state;
+        let tokens = (tokens + now.saturating_duration_since(counted).as_secs_f64() * self.rate).min(self.capacity);
+        let wait = if tokens >= 1.0 { Duration::ZERO } else { Duration::from_secs_f64((1.0 - tokens) / self.rate) };
+        if wait > max_wait {
+            *state = (tokens, now);
+            return None;
+        }
+        *state = (tokens - 1.0, now);
+        Some(now + wait)
+    }
+
+    /// Wait for a token, false without waiting when it would take longer than `max_wait`
+    pub async fn acquire(&self, max_wait: Duration) -> bool {
+        match self.reserve(Instant::now(), max_wait) {
+            Some(at) => {
+                sleep_until(at).await;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn token_bucket_test() {
+        // 60 per minute, bursts of 2
+        let bucket = TokenBucket::new(60, 2);
+        let now = Instant::now();
+        let max_wait = Duration::from_secs(2);
+        assert_eq!(bucket.reserve(now, max_wait), Some(now));
+        assert_eq!(bucket.reserve(now, max_wait), Some(now));
+        // queued behind the burst, one second apart
+        assert_eq!(bucket.reserve(now, max_wait), Some(now + Duration::from_secs(1)));
+        assert_eq!(bucket.reserve(now, max_wait), Some(now + Duration::from_secs(2)));
+        assert_eq!(bucket.reserve(now, max_wait), None);
+        // refilled, the queued ones first
+        let later = now + Duration::from_secs(3);
+        assert_eq!(bucket.reserve(later, Duration::ZERO), Some(later));
+        assert_eq!(bucket.reserve(later, Duration::ZERO), None);
+
+        let unlimited = TokenBucket::new(0, 0);
+        assert_eq!(unlimited.reserve(now, Duration::ZERO), Some(now));
+    }
+}
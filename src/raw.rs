@@ -0,0 +1,257 @@
+//! Event parsing straight from the geyser protobuf.
+//!
+//! Account keys and instruction data are read as borrowed byte slices, skipping the
+//! `convert_to_encoded_tx` round trip through the RPC UI types and base58/base64 strings.
+//! `InstructionView` covers both encodings, so the engine parses them in one loop.
+
+use std::str::FromStr;
+
+use solana_sdk::pubkey::Pubkey;
+use solana_transaction_status::{UiInstruction, UiParsedInstruction};
+use yellowstone_grpc_proto::prelude::{
+    CompiledInstruction, InnerInstruction, InnerInstructions, Message, SubscribeUpdateTransactionInfo,
+    TokenBalance, TransactionStatusMeta,
+};
+
+use crate::types::{ParseError, Program, TargetEvent, TokenInstruction};
+
+/// Read access to an instruction of either encoding, account indices point into the
+/// keys of the transaction
+pub trait InstructionView {
+    /// `None` for an index outside `keys`
+    fn program_id(&self, keys: &[Pubkey]) -> Option<Pubkey>;
+
+    /// Keys of the instruction's accounts, `None` for an index outside `keys`
+    fn account_keys(&self, keys: &[Pubkey]) -> Option<Vec<Pubkey>>;
+
+    /// `TargetEvent::parse` of the instruction data, `program` is the one the instruction runs
+    fn parse_event(&self, program: Program) -> Result<Option<TargetEvent>, ParseError>;
+
+    /// `TargetEvent::unknown` of the instruction data
+    fn unknown_event(&self, program: Program) -> Option<TargetEvent>;
+
+    /// Transfer or mint of the token program, `accounts` are the instruction's account keys
+    fn token_instruction(&self, accounts: &[Pubkey]) -> Option<TokenInstruction>;
+}
+
+/// Instruction of a geyser transaction, indices point into `RawTransaction::keys`
+#[derive(Debug, Clone, Copy)]
+pub struct RawInstruction<'a> {
+    pub program_id_index: u32,
+    pub accounts: &'a [u8],
+    pub data: &'a [u8],
+}
+
+impl InstructionView for RawInstruction<'_> {
+    fn program_id(&self, keys: &[Pubkey]) -> Option<Pubkey> {
+        keys.get(self.program_id_index as usize).copied()
+    }
+
+    fn account_keys(&self, keys: &[Pubkey]) -> Option<Vec<Pubkey>> {
+        self.accounts.iter().map(|index| keys.get(*index as usize).copied()).collect()
+    }
+
+    fn parse_event(&self, program: Program) -> Result<Option<TargetEvent>, ParseError> {
+        TargetEvent::parse(program, self.data)
+    }
+
+    fn unknown_event(&self, program: Program) -> Option<TargetEvent> {
+        TargetEvent::unknown(program, self.data)
+    }
+
+    fn token_instruction(&self, accounts: &[Pubkey]) -> Option<TokenInstruction> {
+        TokenInstruction::decode(accounts, self.data)
+    }
+}
+
+// inner instructions of the protobuf are read in place
+impl InstructionView for InnerInstruction {
+    fn program_id(&self, keys: &[Pubkey]) -> Option<Pubkey> {
+        RawInstruction::from(self).program_id(keys)
+    }
+
+    fn account_keys(&self, keys: &[Pubkey]) -> Option<Vec<Pubkey>> {
+        RawInstruction::from(self).account_keys(keys)
+    }
+
+    fn parse_event(&self, program: Program) -> Result<Option<TargetEvent>, ParseError> {
+        RawInstruction::from(self).parse_event(program)
+    }
+
+    fn unknown_event(&self, program: Program) -> Option<TargetEvent> {
+        RawInstruction::from(self).unknown_event(program)
+    }
+
+    fn token_instruction(&self, accounts: &[Pubkey]) -> Option<TokenInstruction> {
+        RawInstruction::from(self).token_instruction(accounts)
+    }
+}
+
+// the RPC types carry base58 or base64 strings, json parsed instructions (system, token)
+// have no data at all
+impl InstructionView for UiInstruction {
+    fn program_id(&self, keys: &[Pubkey]) -> Option<Pubkey> {
+        match self {
+            UiInstruction::Compiled(ix) => keys.get(ix.program_id_index as usize).copied(),
+            UiInstruction::Parsed(UiParsedInstruction::PartiallyDecoded(ix)) => Pubkey::from_str(&ix.program_id).ok(),
+            UiInstruction::Parsed(UiParsedInstruction::Parsed(ix)) => Pubkey::from_str(&ix.program_id).ok(),
+        }
+    }
+
+    fn account_keys(&self, keys: &[Pubkey]) -> Option<Vec<Pubkey>> {
+        match self {
+            UiInstruction::Compiled(ix) => ix.accounts.iter().map(|index| keys.get(*index as usize).copied()).collect(),
+            UiInstruction::Parsed(UiParsedInstruction::PartiallyDecoded(ix)) => {
+                ix.accounts.iter().map(|key| Pubkey::from_str(key).ok()).collect()
+            }
+            UiInstruction::Parsed(UiParsedInstruction::Parsed(_)) => None,
+        }
+    }
+
+    fn parse_event(&self, program: Program) -> Result<Option<TargetEvent>, ParseError> {
+        TargetEvent::parse_instruction(program, self)
+    }
+
+    fn unknown_event(&self, program: Program) -> Option<TargetEvent> {
+        TargetEvent::unknown_instruction(program, self)
+    }
+
+    fn token_instruction(&self, accounts: &[Pubkey]) -> Option<TokenInstruction> {
+        match self {
+            UiInstruction::Compiled(ix) => TokenInstruction::decode_str(accounts, &ix.data),
+            UiInstruction::Parsed(UiParsedInstruction::PartiallyDecoded(ix)) => TokenInstruction::decode_str(accounts, &ix.data),
+            UiInstruction::Parsed(UiParsedInstruction::Parsed(_)) => None,
+        }
+    }
+}
+
+impl<'a> From<&'a CompiledInstruction> for RawInstruction<'a> {
+    fn from(ix: &'a CompiledInstruction) -> Self {
+        Self { program_id_index: ix.program_id_index, accounts: &ix.accounts, data: &ix.data }
+    }
+}
+
+impl<'a> From<&'a InnerInstruction> for RawInstruction<'a> {
+    fn from(ix: &'a InnerInstruction) -> Self {
+        Self { program_id_index: ix.program_id_index, accounts: &ix.accounts, data: &ix.data }
+    }
+}
+
+// top-level instruction of a decoded `VersionedTransaction`
+impl<'a> From<&'a solana_sdk::instruction::CompiledInstruction> for RawInstruction<'a> {
+    fn from(ix: &'a solana_sdk::instruction::CompiledInstruction) -> Self {
+        Self { program_id_index: ix.program_id_index as u32, accounts: &ix.accounts, data: &ix.data }
+    }
+}
+
+/// Borrowed view of a geyser transaction update
+pub struct RawTransaction<'a> {
+    message: &'a Message,
+    meta: &'a TransactionStatusMeta,
+    /// static keys, then the writable and readonly keys loaded from lookup tables
+    pub keys: Vec<Pubkey>,
+}
+
+impl<'a> RawTransaction<'a> {
+    /// `None` for an update without message or meta or with a malformed key
+    pub fn new(info: &'a SubscribeUpdateTransactionInfo) -> Option<Self> {
+        let message = info.transaction.as_ref()?.message.as_ref()?;
+        let meta = info.meta.as_ref()?;
+        let keys = message
+            .account_keys
+            .iter()
+            .chain(&meta.loaded_writable_addresses)
+            .chain(&meta.loaded_readonly_addresses)
+            .map(|key| Pubkey::try_from(key.as_slice()).ok())
+            .collect::<Option<Vec<_>>>()?;
+        Some(Self { message, meta, keys })
+    }
+
+    /// Top-level instructions
+    pub fn instructions(&self) -> impl Iterator<Item = RawInstruction<'a>> {
+        self.message.instructions.iter().map(RawInstruction::from)
+    }
+
+    /// Inner instruction groups, `None` when the feed left them out
+    pub fn inner_instructions(&self) -> Option<&'a [InnerInstructions]> {
+        (!self.meta.inner_instructions_none && !self.meta.inner_instructions.is_empty())
+            .then_some(self.meta.inner_instructions.as_slice())
+    }
+
+    pub fn pre_token_balances(&self) -> &'a [TokenBalance] {
+        &self.meta.pre_token_balances
+    }
+
+    pub fn post_token_balances(&self) -> &'a [TokenBalance] {
+        &self.meta.post_token_balances
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use yellowstone_grpc_proto::prelude::Transaction;
+
+    use super::*;
+
+    #[test]
+    fn raw_transaction_test() {
+        let keys: Vec<Pubkey> = (0..3).map(|_| Pubkey::new_unique()).collect();
+        let info = SubscribeUpdateTransactionInfo {
+            transaction: Some(Transaction {
+                message: Some(Message {
+                    account_keys: keys[..2].iter().map(|key| key.to_bytes().to_vec()).collect(),
+                    instructions: vec![CompiledInstruction {
+                        program_id_index: 1,
+                        accounts: vec![0, 2],
+                        data: vec![7],
+                    }],
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            meta: Some(TransactionStatusMeta {
+                loaded_readonly_addresses: vec![keys[2].to_bytes().to_vec()],
+                inner_instructions_none: true,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let raw = RawTransaction::new(&info).unwrap();
+        assert_eq!(raw.keys, keys);
+        assert!(raw.inner_instructions().is_none());
+
+        let ix = raw.instructions().next().unwrap();
+        assert_eq!(ix.program_id(&raw.keys), Some(keys[1]));
+        assert_eq!(ix.account_keys(&raw.keys), Some(vec![keys[0], keys[2]]));
+        assert_eq!(ix.data, &[7]);
+    }
+
+    #[test]
+    fn instruction_view_test() {
+        let keys: Vec<Pubkey> = (0..3).map(|_| Pubkey::new_unique()).collect();
+        // token transfer of 5: source, destination, authority
+        let mut data = vec![3];
+        data.extend(5u64.to_le_bytes());
+        let inner = InnerInstruction { program_id_index: 0, accounts: vec![1, 2, 0], data: data.clone(), stack_height: None };
+        let ui = UiInstruction::Compiled(solana_transaction_status::UiCompiledInstruction {
+            program_id_index: 0,
+            accounts: vec![1, 2, 0],
+            data: solana_sdk::bs58::encode(&data).into_string(),
+            stack_height: None,
+        });
+
+        fn read(ix: &impl InstructionView, keys: &[Pubkey]) -> (Option<Pubkey>, Option<TokenInstruction>) {
+            let accounts = ix.account_keys(keys).unwrap();
+            assert!(ix.parse_event(Program::Pumpfun).unwrap().is_none());
+            assert!(ix.unknown_event(Program::Pumpfun).is_none());
+            (ix.program_id(keys), ix.token_instruction(&accounts))
+        }
+        for (program_id, transfer) in [read(&inner, &keys), read(&ui, &keys)] {
+            assert_eq!(program_id, Some(keys[0]));
+            let transfer = transfer.unwrap();
+            assert_eq!((transfer.source, transfer.destination, transfer.amount), (Some(keys[1]), keys[2], 5));
+        }
+        assert!(ui.account_keys(&keys[..2]).is_none());
+    }
+}
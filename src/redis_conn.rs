@@ -0,0 +1,135 @@
+//! Redis connection that survives restarts and failovers.
+//!
+//! `redis.mode` picks how the server is found: a single url through a `ConnectionManager`
+//! that reconnects on its own, a Sentinel group whose current master is looked up again
+//! after a connection error or a `READONLY` reply, or a Cluster. Every variant is cheap
+//! to clone and shares its connection.
+
+use std::sync::{Arc, Mutex};
+
+use redis::{
+    aio::{ConnectionLike, ConnectionManager, MultiplexedConnection},
+    cluster::ClusterClient,
+    cluster_async::ClusterConnection,
+    sentinel::{SentinelClient, SentinelNodeConnectionInfo, SentinelServerType},
+    Cmd, Pipeline, RedisConnectionInfo, RedisError, RedisFuture, RedisResult, Value,
+};
+
+use crate::config::{RedisConfig, RedisMode};
+
+#[derive(Clone)]
+pub enum RedisConn {
+    Single(ConnectionManager),
+    Sentinel(SentinelConn),
+    Cluster(ClusterConnection),
+}
+
+impl RedisConn {
+    pub async fn connect(config: &RedisConfig) -> RedisResult<Self> {
+        match config.mode {
+            RedisMode::Single => {
+                let client = redis::Client::open(config.connection_url())?;
+                Ok(RedisConn::Single(ConnectionManager::new(client).await?))
+            }
+            RedisMode::Sentinel => {
+                let node = SentinelNodeConnectionInfo {
+                    tls_mode: None,
+                    redis_connection_info: Some(RedisConnectionInfo {
+                        db: config.db.unwrap_or_default(),
+                        ..Default::default()
+                    }),
+                };
+                let client = SentinelClient::build(
+                    config.nodes.clone(),
+                    config.master_name.clone(),
+                    Some(node),
+                    SentinelServerType::Master,
+                )?;
+                let conn = SentinelConn {
+                    sentinel: Arc::new(tokio::sync::Mutex::new(client)),
+                    master: Arc::new(Mutex::new(None)),
+                    db: config.db.unwrap_or_default(),
+                };
+                // fail on startup instead of on the first command
+                conn.master().await?;
+                Ok(RedisConn::Sentinel(conn))
+            }
+            RedisMode::Cluster => {
+                let client = ClusterClient::new(config.nodes.clone())?;
+                Ok(RedisConn::Cluster(client.get_async_connection().await?))
+            }
+        }
+    }
+}
+
+impl ConnectionLike for RedisConn {
+    fn req_packed_command<'a>(&'a mut self, cmd: &'a Cmd) -> RedisFuture<'a, Value> {
+        match self {
+            RedisConn::Single(conn) => conn.req_packed_command(cmd),
+            RedisConn::Cluster(conn) => conn.req_packed_command(cmd),
+            RedisConn::Sentinel(conn) => Box::pin(async move {
+                let mut master = conn.master().await?;
+                conn.check(master.req_packed_command(cmd).await)
+            }),
+        }
+    }
+
+    fn req_packed_commands<'a>(
+        &'a mut self,
+        cmd: &'a Pipeline,
+        offset: usize,
+        count: usize,
+    ) -> RedisFuture<'a, Vec<Value>> {
+        match self {
+            RedisConn::Single(conn) => conn.req_packed_commands(cmd, offset, count),
+            RedisConn::Cluster(conn) => conn.req_packed_commands(cmd, offset, count),
+            RedisConn::Sentinel(conn) => Box::pin(async move {
+                let mut master = conn.master().await?;
+                conn.check(master.req_packed_commands(cmd, offset, count).await)
+            }),
+        }
+    }
+
+    fn get_db(&self) -> i64 {
+        match self {
+            RedisConn::Single(conn) => conn.get_db(),
+            RedisConn::Cluster(conn) => conn.get_db(),
+            RedisConn::Sentinel(conn) => conn.db,
+        }
+    }
+}
+
+/// Connection to the master of a Sentinel group
+#[derive(Clone)]
+pub struct SentinelConn {
+    sentinel: Arc<tokio::sync::Mutex<SentinelClient>>,
+    // current master, `None` until looked up or after an error
+    master: Arc<Mutex<Option<MultiplexedConnection>>>,
+    db: i64,
+}
+
+impl SentinelConn {
+    async fn master(&self) -> RedisResult<MultiplexedConnection> {
+        let current = self.master.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        if let Some(conn) = current {
+            return Ok(conn);
+        }
+        let conn = self.sentinel.lock().await.get_async_connection().await?;
+        *self.master.lock().unwrap_or_else(|e| e.into_inner()) = Some(conn.clone());
+        Ok(conn)
+    }
+
+    // forget the master after an error that points at a failover
+    fn check<T>(&self, result: RedisResult<T>) -> RedisResult<T> {
+        if let Err(e) = &result {
+            if is_failover(e) {
+                *self.master.lock().unwrap_or_else(|e| e.into_inner()) = None;
+            }
+        }
+        result
+    }
+}
+
+fn is_failover(e: &RedisError) -> bool {
+    e.is_io_error() || e.is_connection_dropped() || e.is_timeout() || e.code() == Some("READONLY")
+}
@@ -0,0 +1,155 @@
+use std::{collections::HashMap, env, fs};
+
+use aes_gcm::{
+    aead::{Aead, KeyInit},
+    Aes256Gcm, Key, Nonce,
+};
+use anyhow::{anyhow, bail, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::config::{SecretsBackend, SecretsConfig};
+
+/// secret names looked up in the store
+pub const TG_BOT_TOKEN: &str = "TG_BOT_TOKEN";
+pub const X_API_KEY: &str = "X_API_KEY";
+pub const X_BEARER_TOKEN: &str = "X_BEARER_TOKEN";
+pub const AI_API_KEY: &str = "AI_API_KEY";
+pub const GRPC_X_TOKEN: &str = "GRPC_X_TOKEN";
+
+/// passphrase used to decrypt the encrypted secrets file
+pub const SECRETS_PASSPHRASE: &str = "SECRETS_PASSPHRASE";
+
+const PBKDF2_ROUNDS: u32 = 600_000;
+
+/// A source of API keys and tokens.
+pub trait SecretStore: Send + Sync {
+    /// Look up a secret, `Ok(None)` when the store does not have it
+    fn get(&self, key: &str) -> Result<Option<String>>;
+}
+
+/// Secrets from environment variables (and the `.env` file)
+pub struct EnvStore;
+
+impl SecretStore for EnvStore {
+    fn get(&self, key: &str) -> Result<Option<String>> {
+        Ok(env::var(key).ok().filter(|v| !v.is_empty()))
+    }
+}
+
+/// On-disk format of the encrypted secrets file
+#[derive(Serialize, Deserialize)]
+struct EncryptedFile {
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+/// Secrets from an AES-256-GCM encrypted file.
+///
+/// The plaintext is a TOML table of `NAME = "value"` pairs; the key is derived
+/// from a passphrase with PBKDF2-HMAC-SHA256.
+pub struct EncryptedFileStore {
+    secrets: HashMap<String, String>,
+}
+
+impl EncryptedFileStore {
+    /// Decrypt the file at `path` with `passphrase`
+    pub fn open(path: &str, passphrase: &str) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("read secrets file {} error", path))?;
+        let plaintext = decrypt(&content, passphrase)
+            .with_context(|| format!("decrypt secrets file {} error", path))?;
+        let secrets = toml::from_str::<HashMap<String, String>>(&plaintext)
+            .with_context(|| format!("parse secrets file {} error", path))?;
+        Ok(Self { secrets })
+    }
+}
+
+impl SecretStore for EncryptedFileStore {
+    fn get(&self, key: &str) -> Result<Option<String>> {
+        Ok(self.secrets.get(key).cloned())
+    }
+}
+
+/// Open the store selected by `[secrets]` config
+pub fn open_store(config: &SecretsConfig) -> Result<Box<dyn SecretStore>> {
+    match config.backend {
+        SecretsBackend::Env => Ok(Box::new(EnvStore)),
+        SecretsBackend::EncryptedFile => {
+            if config.path.is_empty() {
+                bail!("secrets.path is required for the encrypted_file backend");
+            }
+            let passphrase = env::var(SECRETS_PASSPHRASE)
+                .map_err(|_| anyhow!("{} is required to open {}", SECRETS_PASSPHRASE, config.path))?;
+            Ok(Box::new(EncryptedFileStore::open(&config.path, &passphrase)?))
+        }
+    }
+}
+
+/// Encrypt a plaintext TOML secrets file into the format read by `EncryptedFileStore`
+pub fn encrypt_file(input: &str, output: &str, passphrase: &str) -> Result<()> {
+    let plaintext = fs::read_to_string(input)
+        .with_context(|| format!("read plaintext secrets {} error", input))?;
+    // make sure the file is usable before writing it out
+    toml::from_str::<HashMap<String, String>>(&plaintext)
+        .with_context(|| format!("parse plaintext secrets {} error", input))?;
+    fs::write(output, encrypt(&plaintext, passphrase)?)
+        .with_context(|| format!("write secrets file {} error", output))?;
+    Ok(())
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> [u8; 32] {
+    let mut key = [0u8; 32];
+    pbkdf2::pbkdf2_hmac::<Sha256>(passphrase.as_bytes(), salt, PBKDF2_ROUNDS, &mut key);
+    key
+}
+
+pub fn encrypt(plaintext: &str, passphrase: &str) -> Result<String> {
+    let salt: [u8; 16] = rand::random();
+    let nonce: [u8; 12] = rand::random();
+    let key = derive_key(passphrase, &salt);
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key));
+    let ciphertext = cipher
+        .encrypt(Nonce::from_slice(&nonce), plaintext.as_bytes())
+        .map_err(|_| anyhow!("encrypt secrets error"))?;
+
+    Ok(serde_json::to_string_pretty(&EncryptedFile {
+        salt: STANDARD.encode(salt),
+        nonce: STANDARD.encode(nonce),
+        ciphertext: STANDARD.encode(ciphertext),
+    })?)
+}
+
+pub fn decrypt(content: &str, passphrase: &str) -> Result<String> {
+    let file: EncryptedFile = serde_json::from_str(content)?;
+    let salt = STANDARD.decode(file.salt)?;
+    let nonce = STANDARD.decode(file.nonce)?;
+    let ciphertext = STANDARD.decode(file.ciphertext)?;
+    if nonce.len() != 12 {
+        bail!("invalid nonce length {}", nonce.len());
+    }
+
+    let key = derive_key(passphrase, &salt);
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key));
+    let plaintext = cipher
+        .decrypt(Nonce::from_slice(&nonce), ciphertext.as_ref())
+        .map_err(|_| anyhow!("wrong passphrase or corrupted secrets file"))?;
+    Ok(String::from_utf8(plaintext)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encrypt_decrypt_test() {
+        let plaintext = "X_API_KEY = \"x-key\"\nAI_API_KEY = \"ai-key\"\n";
+        let encrypted = encrypt(plaintext, "passphrase").unwrap();
+        assert!(!encrypted.contains("x-key"));
+
+        assert_eq!(decrypt(&encrypted, "passphrase").unwrap(), plaintext);
+        assert!(decrypt(&encrypted, "wrong").is_err());
+    }
+}
@@ -0,0 +1,214 @@
+//! Social context of a token behind a trait, `x.provider` then `x.fallback`.
+//!
+//! A `SocialProvider` finds the tweets the AI summary reads and refreshes the counts of a tweet.
+//! `SocialChain` asks the configured providers in order and moves on when one fails, so an
+//! outage of all of them leaves the alert without social context instead of failing the check.
+
+use std::{sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use reqwest::{redirect::Policy, Client};
+use tracing::{debug, warn};
+
+use crate::{
+    config::{XConfig, XProvider},
+    x::{self, is_short_link, same_site, tweet_links, x_client, Tweet, XClient},
+};
+
+/// A source of tweets about tokens
+#[async_trait]
+pub trait SocialProvider: Send + Sync {
+    /// Name in the logs
+    fn name(&self) -> &'static str;
+
+    /// Tweets for the AI summary of `mint` (see `XClient::token_tweets`), an error when the
+    /// provider could not answer; nothing found is not an error
+    async fn token_tweets(&self, mint: &str, symbol: &str, name: &str, account: Option<&str>) -> x::Result<Vec<Tweet>>;
+
+    /// The tweet `tweet_id` with its current counts
+    async fn tweet(&self, tweet_id: &str) -> x::Result<Tweet>;
+
+    /// Recent tweets of the account `screen_name` (without `@`)
+    async fn timeline(&self, screen_name: &str) -> x::Result<Vec<Tweet>>;
+}
+
+#[async_trait]
+impl SocialProvider for XClient {
+    fn name(&self) -> &'static str {
+        match self.provider() {
+            XProvider::Apidance => "apidance",
+            XProvider::Official => "official",
+        }
+    }
+
+    async fn token_tweets(&self, mint: &str, symbol: &str, name: &str, account: Option<&str>) -> x::Result<Vec<Tweet>> {
+        XClient::token_tweets(self, mint, symbol, name, account).await
+    }
+
+    async fn tweet(&self, tweet_id: &str) -> x::Result<Tweet> {
+        self.get_tweet(tweet_id).await
+    }
+
+    async fn timeline(&self, screen_name: &str) -> x::Result<Vec<Tweet>> {
+        self.user_tweets(screen_name, None).await.map(|response| response.tweets)
+    }
+}
+
+/// The providers in the order they are asked, with the settings of the callers
+#[derive(Clone)]
+pub struct SocialChain {
+    providers: Vec<Arc<dyn SocialProvider>>,
+    // resolves shortened links, not an X request
+    http: Client,
+    // milliseconds between two engagement refreshes of an alert's tweet, 0 for none
+    engagement_interval: u64,
+    // milliseconds the tweets found for a token are reused, 0 for no cache
+    search_cache_ttl: u64,
+}
+
+impl SocialChain {
+    /// Without providers every token is without social context
+    pub fn new(providers: Vec<Arc<dyn SocialProvider>>) -> Self {
+        let http = Client::builder()
+            .timeout(Duration::from_secs(10))
+            .redirect(Policy::limited(5))
+            .build()
+            .expect("Failed to create HTTP client");
+        Self { providers, http, engagement_interval: 0, search_cache_ttl: 0 }
+    }
+
+    /// Set how often (milliseconds) an alert's tweet counts are fetched again, 0 never
+    pub fn with_engagement_interval(mut self, interval_ms: u64) -> Self {
+        self.engagement_interval = interval_ms;
+        self
+    }
+
+    pub fn engagement_interval(&self) -> u64 {
+        self.engagement_interval
+    }
+
+    /// Set how long (milliseconds) the tweets found for a token are reused, 0 never
+    pub fn with_search_cache_ttl(mut self, ttl_ms: u64) -> Self {
+        self.search_cache_ttl = ttl_ms;
+        self
+    }
+
+    pub fn search_cache_ttl(&self) -> u64 {
+        self.search_cache_ttl
+    }
+
+    /// Tweets of the first provider that answers, empty when none does
+    pub async fn token_tweets(&self, mint: &str, symbol: &str, name: &str, account: Option<&str>) -> Vec<Tweet> {
+        for provider in &self.providers {
+            match provider.token_tweets(mint, symbol, name, account).await {
+                Ok(tweets) => return tweets,
+                Err(e) => warn!("{} tweets of {} error, trying the next provider: {}", provider.name(), mint, e),
+            }
+        }
+        Vec::new()
+    }
+
+    /// Recent tweets of `screen_name` from the first provider that answers, empty when none does
+    pub async fn timeline(&self, screen_name: &str) -> Vec<Tweet> {
+        for provider in &self.providers {
+            match provider.timeline(screen_name).await {
+                Ok(tweets) => return tweets,
+                Err(e) => warn!("{} timeline of @{} error, trying the next provider: {}", provider.name(), screen_name, e),
+            }
+        }
+        Vec::new()
+    }
+
+    /// Whether one of `tweets` links to `website` (see `same_site`), shortened links are
+    /// followed to where they go; one that can't be resolved doesn't count
+    pub async fn links_to(&self, tweets: &[Tweet], website: &str) -> bool {
+        for link in tweets.iter().flat_map(tweet_links) {
+            let link = if is_short_link(&link) {
+                match self.http.head(&link).send().await {
+                    Ok(response) => response.url().to_string(),
+                    Err(e) => {
+                        debug!("resolve {} error: {}", link, e);
+                        continue;
+                    }
+                }
+            } else {
+                link
+            };
+            if same_site(&link, website) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Engagement of the tweet `tweet_id` (see `Tweet::engagement`) from the first provider that
+    /// has it, `None` when none does
+    pub async fn tweet_engagement(&self, tweet_id: &str) -> Option<String> {
+        if tweet_id.is_empty() {
+            return None;
+        }
+        for provider in &self.providers {
+            match provider.tweet(tweet_id).await {
+                Ok(tweet) => return Some(tweet.engagement()),
+                Err(e) => warn!("{} tweet {} refresh error: {}", provider.name(), tweet_id, e),
+            }
+        }
+        None
+    }
+}
+
+/// `x.provider` followed by `x.fallback`, each with its own client and request quota
+pub fn social_chain(config: &XConfig) -> SocialChain {
+    let providers = config
+        .providers()
+        .into_iter()
+        .map(|provider| Arc::new(x_client(config, provider)) as Arc<dyn SocialProvider>)
+        .collect();
+    SocialChain::new(providers)
+        .with_engagement_interval(config.engagement_interval)
+        .with_search_cache_ttl(config.search_cache_ttl)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::x::TwitterError;
+
+    // answers with one tweet or fails like an outage
+    struct Fixed(Option<&'static str>);
+
+    #[async_trait]
+    impl SocialProvider for Fixed {
+        fn name(&self) -> &'static str {
+            "fixed"
+        }
+
+        async fn token_tweets(&self, _: &str, _: &str, _: &str, _: Option<&str>) -> x::Result<Vec<Tweet>> {
+            self.tweet("").await.map(|tweet| vec![tweet])
+        }
+
+        async fn tweet(&self, _: &str) -> x::Result<Tweet> {
+            match self.0 {
+                Some(id) => Ok(Tweet { tweet_id: id.to_string(), favorite_count: 1, ..Default::default() }),
+                None => Err(TwitterError::ApiError { status_code: 503, message: String::new() }),
+            }
+        }
+
+        async fn timeline(&self, _: &str) -> x::Result<Vec<Tweet>> {
+            self.tweet("").await.map(|tweet| vec![tweet])
+        }
+    }
+
+    #[tokio::test]
+    async fn social_chain_test() {
+        let chain = SocialChain::new(vec![Arc::new(Fixed(None)), Arc::new(Fixed(Some("2"))), Arc::new(Fixed(Some("3")))]);
+        assert_eq!(chain.token_tweets("Mint1", "", "", None).await[0].tweet_id, "2");
+        assert_eq!(chain.tweet_engagement("2").await.as_deref(), Some("❤️ 1 🔁 0 💬 0"));
+        assert_eq!(chain.tweet_engagement("").await, None);
+
+        let down = SocialChain::new(vec![Arc::new(Fixed(None))]);
+        assert!(down.token_tweets("Mint1", "", "", None).await.is_empty());
+        assert_eq!(down.tweet_engagement("2").await, None);
+        assert!(SocialChain::new(Vec::new()).token_tweets("Mint1", "", "", None).await.is_empty());
+    }
+}
@@ -0,0 +1,296 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::types::{ParseFailure, Program};
+
+// latency samples kept per feed and window, a uniform sample of every message once full
+const MAX_LATENCY_SAMPLES: usize = 10_000;
+
+/// How far behind the chain the engine is.
+///
+/// Updated by the stream loop and the workers, read with `snapshot`.
+#[derive(Debug, Default)]
+pub struct EngineStats {
+    /// highest slot seen on the stream
+    chain_slot: AtomicU64,
+    /// slot of the last transaction taken off the stream
+    processed_slot: AtomicU64,
+    /// receive time minus block time of the last block meta
+    block_lag_ms: AtomicU64,
+    // handler latency since the last snapshot
+    handled: AtomicU64,
+    handle_micros_total: AtomicU64,
+    handle_micros_max: AtomicU64,
+    // feed index -> receive time minus geyser `created_at`, in microseconds
+    feed_latency: Mutex<BTreeMap<usize, LatencySamples>>,
+    // instructions of monitored programs that gave no event, per program and failure
+    parse_errors: Mutex<BTreeMap<(Program, ParseFailure), u64>>,
+    // transactions that could not be converted for parsing, since the last snapshot
+    decode_errors: AtomicU64,
+}
+
+impl EngineStats {
+    pub fn observe_chain_slot(&self, slot: u64) {
+        self.chain_slot.fetch_max(slot, Ordering::Relaxed);
+    }
+
+    pub fn observe_processed_slot(&self, slot: u64) {
+        self.processed_slot.store(slot, Ordering::Relaxed);
+        self.observe_chain_slot(slot);
+    }
+
+    /// `block_time` in unix seconds, `now_ms` in unix milliseconds
+    pub fn observe_block_time(&self, block_time: i64, now_ms: u64) {
+        let lag = now_ms.saturating_sub((block_time.max(0) as u64) * 1000);
+        self.block_lag_ms.store(lag, Ordering::Relaxed);
+    }
+
+    /// Time the handlers took for one event
+    pub fn observe_handle(&self, elapsed: Duration) {
+        let micros = elapsed.as_micros() as u64;
+        self.handled.fetch_add(1, Ordering::Relaxed);
+        self.handle_micros_total.fetch_add(micros, Ordering::Relaxed);
+        self.handle_micros_max.fetch_max(micros, Ordering::Relaxed);
+    }
+
+    /// Feed latency of one message, `created_at` is the geyser timestamp in unix microseconds
+    pub fn observe_feed_latency(&self, feed: usize, created_at_micros: i64) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|now| now.as_micros() as i64)
+            .unwrap_or_default();
+        self.observe_latency_micros(feed, (now - created_at_micros).max(0) as u64);
+    }
+
+    fn observe_latency_micros(&self, feed: usize, latency: u64) {
+        if let Ok(mut feeds) = self.feed_latency.lock() {
+            feeds.entry(feed).or_default().add(latency);
+        }
+    }
+
+    /// A transaction update that could not be decoded and was skipped
+    pub fn observe_decode_error(&self) {
+        self.decode_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn observe_parse_error(&self, program: Program, kind: ParseFailure) {
+        if let Ok(mut errors) = self.parse_errors.lock() {
+            *errors.entry((program, kind)).or_default() += 1;
+        }
+    }
+
+    /// Current values, handler and feed latency and parse errors are reset for the next window
+    pub fn snapshot(&self, queue_depth: usize) -> StatsSnapshot {
+        let chain_slot = self.chain_slot.load(Ordering::Relaxed);
+        let processed_slot = self.processed_slot.load(Ordering::Relaxed);
+        let handled = self.handled.swap(0, Ordering::Relaxed);
+        let feed_latency = self
+            .feed_latency
+            .lock()
+            .map(|mut feeds| std::mem::take(&mut *feeds))
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(feed, latency)| FeedLatency::from_samples(feed, latency.seen, latency.samples))
+            .collect();
+        let parse_errors = self
+            .parse_errors
+            .lock()
+            .map(|mut errors| std::mem::take(&mut *errors))
+            .unwrap_or_default()
+            .into_iter()
+            .map(|((program, kind), count)| (program, kind, count))
+            .collect();
+        let total = self.handle_micros_total.swap(0, Ordering::Relaxed);
+        StatsSnapshot {
+            chain_slot,
+            slot_lag: chain_slot.saturating_sub(processed_slot),
+            block_lag_ms: self.block_lag_ms.load(Ordering::Relaxed),
+            queue_depth,
+            handled,
+            avg_handle_micros: if handled == 0 { 0 } else { total / handled },
+            max_handle_micros: self.handle_micros_max.swap(0, Ordering::Relaxed),
+            decode_errors: self.decode_errors.swap(0, Ordering::Relaxed),
+            feed_latency,
+            parse_errors,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct StatsSnapshot {
+    pub chain_slot: u64,
+    /// slots between the chain tip seen on the stream and the last processed transaction
+    pub slot_lag: u64,
+    pub block_lag_ms: u64,
+    /// events waiting in the worker queues
+    pub queue_depth: usize,
+    /// events handled in the window
+    pub handled: u64,
+    pub avg_handle_micros: u64,
+    pub max_handle_micros: u64,
+    /// transactions skipped in the window because they could not be decoded
+    pub decode_errors: u64,
+    /// per gRPC feed, only feeds that sent messages in the window
+    pub feed_latency: Vec<FeedLatency>,
+    /// parse failures per program and kind in the window, only those that happened
+    pub parse_errors: Vec<(Program, ParseFailure, u64)>,
+}
+
+impl StatsSnapshot {
+    /// `program kind count` of every parse failure in the window
+    pub fn parse_failures_display(&self) -> String {
+        self.parse_errors
+            .iter()
+            .map(|(program, kind, count)| format!("{} {} {}", program, kind, count))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+// reservoir of one feed's latencies in a window, every message has the same chance to be kept
+#[derive(Debug, Default)]
+struct LatencySamples {
+    seen: u64,
+    samples: Vec<u64>,
+}
+
+impl LatencySamples {
+    fn add(&mut self, latency: u64) {
+        self.seen += 1;
+        if self.samples.len() < MAX_LATENCY_SAMPLES {
+            self.samples.push(latency);
+            return;
+        }
+        let index = rand::random_range(0..self.seen) as usize;
+        if index < MAX_LATENCY_SAMPLES {
+            self.samples[index] = latency;
+        }
+    }
+}
+
+/// Time from the geyser plugin creating an update to the engine receiving it
+#[derive(Debug, Clone, Default)]
+pub struct FeedLatency {
+    pub feed: usize,
+    /// messages received in the window, the percentiles come from a uniform sample of them
+    pub samples: u64,
+    pub p50_micros: u64,
+    pub p99_micros: u64,
+}
+
+impl FeedLatency {
+    fn from_samples(feed: usize, seen: u64, mut samples: Vec<u64>) -> Self {
+        samples.sort_unstable();
+        let percentile = |p: usize| samples.get((samples.len().saturating_sub(1)) * p / 100).copied().unwrap_or(0);
+        Self {
+            feed,
+            samples: seen,
+            p50_micros: percentile(50),
+            p99_micros: percentile(99),
+        }
+    }
+}
+
+impl fmt::Display for StatsSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "slot {} slot_lag {} block_lag {}ms queue {} handled {} handle avg {}us max {}us",
+            self.chain_slot,
+            self.slot_lag,
+            self.block_lag_ms,
+            self.queue_depth,
+            self.handled,
+            self.avg_handle_micros,
+            self.max_handle_micros,
+        )?;
+        if self.decode_errors > 0 {
+            write!(f, " decode_errors {}", self.decode_errors)?;
+        }
+        for feed in &self.feed_latency {
+            write!(
+                f,
+                " feed{} p50 {:.1}ms p99 {:.1}ms",
+                feed.feed,
+                feed.p50_micros as f64 / 1000.0,
+                feed.p99_micros as f64 / 1000.0
+            )?;
+        }
+        for (program, kind, errors) in &self.parse_errors {
+            write!(f, " {} {} {}", program, kind, errors)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshot_test() {
+        let stats = EngineStats::default();
+        stats.observe_chain_slot(110);
+        stats.observe_processed_slot(100);
+        stats.observe_handle(Duration::from_micros(100));
+        stats.observe_handle(Duration::from_micros(300));
+        stats.observe_parse_error(Program::Pumpfun, ParseFailure::Borsh);
+        stats.observe_parse_error(Program::Pumpfun, ParseFailure::Borsh);
+        stats.observe_parse_error(Program::Boop, ParseFailure::UnknownDiscriminator);
+        stats.observe_decode_error();
+
+        let snapshot = stats.snapshot(3);
+        assert_eq!(snapshot.slot_lag, 10);
+        assert_eq!(snapshot.queue_depth, 3);
+        assert_eq!(snapshot.handled, 2);
+        assert_eq!(snapshot.avg_handle_micros, 200);
+        assert_eq!(snapshot.max_handle_micros, 300);
+        assert_eq!(snapshot.decode_errors, 1);
+        assert_eq!(
+            snapshot.parse_errors,
+            vec![(Program::Pumpfun, ParseFailure::Borsh, 2), (Program::Boop, ParseFailure::UnknownDiscriminator, 1)]
+        );
+        assert_eq!(snapshot.parse_failures_display(), "pumpfun borsh 2, boop unknown_discriminator 1");
+
+        // latency window is reset
+        let snapshot = stats.snapshot(0);
+        assert_eq!(snapshot.handled, 0);
+        assert_eq!(snapshot.max_handle_micros, 0);
+        assert_eq!(snapshot.decode_errors, 0);
+        assert!(snapshot.parse_errors.is_empty());
+    }
+
+    #[test]
+    fn feed_latency_test() {
+        let latency = FeedLatency::from_samples(1, 100, (1..=100).rev().collect());
+        assert_eq!(latency.samples, 100);
+        assert_eq!(latency.p50_micros, 50);
+        assert_eq!(latency.p99_micros, 99);
+
+        let empty = FeedLatency::from_samples(0, 0, vec![]);
+        assert_eq!(empty.p99_micros, 0);
+    }
+
+    #[test]
+    fn feed_latency_sampling_test() {
+        // fast messages first, then as many slow ones; keeping only the first samples would report the fast half
+        let stats = EngineStats::default();
+        for _ in 0..MAX_LATENCY_SAMPLES {
+            stats.observe_latency_micros(0, 1_000);
+        }
+        for _ in 0..MAX_LATENCY_SAMPLES {
+            stats.observe_latency_micros(0, 9_000);
+        }
+        let snapshot = stats.snapshot(0);
+        let latency = &snapshot.feed_latency[0];
+        assert_eq!(latency.samples, 2 * MAX_LATENCY_SAMPLES as u64);
+        assert_eq!(latency.p99_micros, 9_000);
+    }
+}
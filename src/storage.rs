@@ -0,0 +1,274 @@
+//! Token storage outside Redis, selected with `[storage] backend`.
+//!
+//! `TokenStore` covers the token records, the pool index and the alert state. With the default
+//! `redis` backend `cache` keeps them in Redis itself; `SqliteStore` (cargo feature `sqlite`)
+//! keeps them in a local SQLite file for single-box deployments. `open_store` picks the store on
+//! startup and `cache::set_token_store` routes the record, pool and alert state functions to it.
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use async_trait::async_trait;
+
+use crate::{
+    cache::{AlertState, MarketCapUpdate, TokenRecord},
+    config::{StorageBackend, StorageConfig},
+};
+
+/// Token records, pool index and alert state
+#[async_trait]
+pub trait TokenStore: Send + Sync {
+    /// Insert or replace a record, the pool index follows `record.pool`
+    async fn set_token(&self, record: &TokenRecord) -> Result<()>;
+
+    async fn token(&self, mint: &str) -> Result<Option<TokenRecord>>;
+
+    async fn tokens(&self) -> Result<Vec<TokenRecord>>;
+
+    /// Change the record of `mint` in place, false for an untracked token
+    async fn update_token(&self, mint: &str, update: Box<dyn FnOnce(&mut TokenRecord) + Send>) -> Result<bool>;
+
+    /// Remove the records and alert states of `mints`, returns the records removed
+    async fn remove_tokens(&self, mints: &[String]) -> Result<Vec<TokenRecord>>;
+
+    /// Mint traded in `pool`, `None` for a pool of an untracked token
+    async fn token_by_pool(&self, pool: &str) -> Result<Option<String>>;
+
+    /// Apply market cap updates like `cache::update_market_caps`, returns the mints that have a record
+    async fn update_market_caps(&self, updates: &[MarketCapUpdate]) -> Result<Vec<String>>;
+
+    /// Alerts sent for `mint`, the default when none was
+    async fn alert_state(&self, mint: &str) -> Result<AlertState>;
+
+    /// Replace the alert state of `mint`, kept for `ttl_ms` (0 keeps it)
+    async fn set_alert_state(&self, mint: &str, state: &AlertState, ttl_ms: u64) -> Result<()>;
+}
+
+/// The store selected in `config`, `None` for the `redis` backend
+pub fn open_store(config: &StorageConfig) -> Result<Option<Arc<dyn TokenStore>>> {
+    match config.backend {
+        StorageBackend::Redis => Ok(None),
+        #[cfg(feature = "sqlite")]
+        StorageBackend::Sqlite => Ok(Some(Arc::new(SqliteStore::open(&config.path)?))),
+        #[cfg(not(feature = "sqlite"))]
+        StorageBackend::Sqlite => anyhow::bail!("storage.backend = \"sqlite\" needs the `sqlite` cargo feature"),
+    }
+}
+
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteStore;
+
+#[cfg(feature = "sqlite")]
+mod sqlite {
+    use std::sync::{Arc, Mutex};
+
+    use anyhow::{anyhow, Context, Result};
+    use async_trait::async_trait;
+    use rusqlite::{params, Connection, OptionalExtension};
+    use solana_sdk::timing::timestamp;
+
+    use super::TokenStore;
+    use crate::cache::{AlertState, MarketCapUpdate, TokenRecord};
+
+    const SCHEMA: &str = "
+        CREATE TABLE IF NOT EXISTS tokens (
+            mint TEXT PRIMARY KEY,
+            pool TEXT NOT NULL DEFAULT '',
+            record TEXT NOT NULL
+        );
+        CREATE INDEX IF NOT EXISTS tokens_pool ON tokens (pool) WHERE pool != '';
+        CREATE TABLE IF NOT EXISTS alert_states (
+            mint TEXT PRIMARY KEY,
+            state TEXT NOT NULL,
+            -- unix milliseconds, 0 never expires
+            expires_at INTEGER NOT NULL
+        );
+    ";
+
+    /// Store in a SQLite file, calls run on the blocking thread pool
+    #[derive(Clone)]
+    pub struct SqliteStore {
+        conn: Arc<Mutex<Connection>>,
+    }
+
+    impl SqliteStore {
+        /// Open or create the database at `path`, `:memory:` for a temporary one
+        pub fn open(path: &str) -> Result<Self> {
+            let conn = Connection::open(path).with_context(|| format!("open sqlite database {} error", path))?;
+            conn.pragma_update(None, "journal_mode", "WAL")?;
+            conn.execute_batch(SCHEMA).context("create sqlite schema error")?;
+            Ok(Self { conn: Arc::new(Mutex::new(conn)) })
+        }
+
+        async fn call<T: Send + 'static>(
+            &self,
+            f: impl FnOnce(&mut Connection) -> rusqlite::Result<T> + Send + 'static,
+        ) -> Result<T> {
+            let conn = self.conn.clone();
+            tokio::task::spawn_blocking(move || {
+                let mut conn = conn.lock().map_err(|_| anyhow!("sqlite connection poisoned"))?;
+                Ok(f(&mut conn)?)
+            })
+            .await?
+        }
+    }
+
+    fn read_record(conn: &Connection, mint: &str) -> rusqlite::Result<Option<TokenRecord>> {
+        let record: Option<String> =
+            conn.query_row("SELECT record FROM tokens WHERE mint = ?1", [mint], |row| row.get(0)).optional()?;
+        Ok(record.as_deref().and_then(TokenRecord::decode))
+    }
+
+    fn write_record(conn: &Connection, record: &TokenRecord) -> rusqlite::Result<()> {
+        conn.execute(
+            "INSERT INTO tokens (mint, pool, record) VALUES (?1, ?2, ?3)
+             ON CONFLICT (mint) DO UPDATE SET pool = excluded.pool, record = excluded.record",
+            params![record.mint, record.pool, record.encode()],
+        )?;
+        Ok(())
+    }
+
+    #[async_trait]
+    impl TokenStore for SqliteStore {
+        async fn set_token(&self, record: &TokenRecord) -> Result<()> {
+            let record = record.clone();
+            self.call(move |conn| write_record(conn, &record)).await
+        }
+
+        async fn token(&self, mint: &str) -> Result<Option<TokenRecord>> {
+            let mint = mint.to_string();
+            self.call(move |conn| read_record(conn, &mint)).await
+        }
+
+        async fn tokens(&self) -> Result<Vec<TokenRecord>> {
+            self.call(|conn| {
+                let mut statement = conn.prepare("SELECT record FROM tokens")?;
+                let records = statement.query_map([], |row| row.get::<_, String>(0))?;
+                Ok(records.filter_map(|record| TokenRecord::decode(&record.ok()?)).collect())
+            })
+            .await
+        }
+
+        async fn update_token(&self, mint: &str, update: Box<dyn FnOnce(&mut TokenRecord) + Send>) -> Result<bool> {
+            let mint = mint.to_string();
+            self.call(move |conn| {
+                let tx = conn.transaction()?;
+                let Some(mut record) = read_record(&tx, &mint)? else {
+                    return Ok(false);
+                };
+                update(&mut record);
+                write_record(&tx, &record)?;
+                tx.commit()?;
+                Ok(true)
+            })
+            .await
+        }
+
+        async fn remove_tokens(&self, mints: &[String]) -> Result<Vec<TokenRecord>> {
+            let mints = mints.to_vec();
+            self.call(move |conn| {
+                let tx = conn.transaction()?;
+                let mut removed = Vec::new();
+                for mint in &mints {
+                    removed.extend(read_record(&tx, mint)?);
+                    tx.execute("DELETE FROM tokens WHERE mint = ?1", [mint])?;
+                    tx.execute("DELETE FROM alert_states WHERE mint = ?1", [mint])?;
+                }
+                tx.commit()?;
+                Ok(removed)
+            })
+            .await
+        }
+
+        async fn token_by_pool(&self, pool: &str) -> Result<Option<String>> {
+            let pool = pool.to_string();
+            self.call(move |conn| {
+                conn.query_row("SELECT mint FROM tokens WHERE pool = ?1 LIMIT 1", [pool], |row| row.get(0)).optional()
+            })
+            .await
+        }
+
+        async fn update_market_caps(&self, updates: &[MarketCapUpdate]) -> Result<Vec<String>> {
+            let updates = updates.to_vec();
+            self.call(move |conn| {
+                let tx = conn.transaction()?;
+                let mut updated = Vec::new();
+                for update in &updates {
+                    let Some(mut record) = read_record(&tx, &update.mint)? else {
+                        continue;
+                    };
+                    update.apply(&mut record);
+                    write_record(&tx, &record)?;
+                    updated.push(update.mint.clone());
+                }
+                tx.commit()?;
+                Ok(updated)
+            })
+            .await
+        }
+
+        async fn alert_state(&self, mint: &str) -> Result<AlertState> {
+            let mint = mint.to_string();
+            let state: Option<String> = self
+                .call(move |conn| {
+                    conn.query_row(
+                        "SELECT state FROM alert_states WHERE mint = ?1 AND (expires_at = 0 OR expires_at > ?2)",
+                        params![mint, timestamp() as i64],
+                        |row| row.get(0),
+                    )
+                    .optional()
+                })
+                .await?;
+            Ok(state.and_then(|state| serde_json::from_str(&state).ok()).unwrap_or_default())
+        }
+
+        async fn set_alert_state(&self, mint: &str, state: &AlertState, ttl_ms: u64) -> Result<()> {
+            let (mint, state) = (mint.to_string(), serde_json::to_string(state)?);
+            let expires_at = if ttl_ms == 0 { 0 } else { timestamp() + ttl_ms };
+            self.call(move |conn| {
+                conn.execute(
+                    "INSERT INTO alert_states (mint, state, expires_at) VALUES (?1, ?2, ?3)
+                     ON CONFLICT (mint) DO UPDATE SET state = excluded.state, expires_at = excluded.expires_at",
+                    params![mint, state, expires_at as i64],
+                )?;
+                Ok(())
+            })
+            .await
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[tokio::test]
+        async fn sqlite_store_test() -> Result<()> {
+            let store = SqliteStore::open(":memory:")?;
+            let record = TokenRecord { mint: "mint".to_string(), market_cap: 10.0, ..Default::default() };
+            store.set_token(&record).await?;
+            assert!(store.update_token("mint", Box::new(|record| record.pool = "pool".to_string())).await?);
+            assert!(!store.update_token("other", Box::new(|record| record.buyers = 1)).await?);
+            assert_eq!(store.token_by_pool("pool").await?, Some("mint".to_string()));
+
+            let updates = [MarketCapUpdate::new("mint", 1.0, 50.0, "", 7), MarketCapUpdate::new("other", 1.0, 50.0, "", 7)];
+            assert_eq!(store.update_market_caps(&updates).await?, vec!["mint".to_string()]);
+            let stored = store.token("mint").await?.unwrap();
+            assert_eq!((stored.market_cap, stored.ath_market_cap, stored.ath_time), (50.0, 50.0, 7));
+            // an update without a pool keeps the linked one
+            assert_eq!(stored.pool, "pool");
+
+            assert_eq!(store.alert_state("mint").await?, AlertState::default());
+            let mut state = AlertState::default();
+            state.fire(&[50.0], 50.0, 7, 0);
+            store.set_alert_state("mint", &state, 0).await?;
+            assert_eq!(store.alert_state("mint").await?, state);
+
+            let removed = store.remove_tokens(&["mint".to_string()]).await?;
+            assert_eq!(removed.len(), 1);
+            assert!(store.tokens().await?.is_empty());
+            assert_eq!(store.token_by_pool("pool").await?, None);
+            assert_eq!(store.alert_state("mint").await?, AlertState::default());
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,324 @@
+//! Bot commands: admin commands (`telegram.admin_commands`) and DM subscriptions
+//! (`telegram.subscriptions`).
+//!
+//! A bot long polls `getUpdates`. The admin bot takes admin commands from its own chat only (and
+//! from `telegram.admin_users` when set). The lists live in redis, the market cap check, follow-ups
+//! and digests read them on every run:
+//!
+//! - `/mute <mint>` no more alerts for the token
+//! - `/blacklist_creator <pubkey>` no more alerts for any token of the creator
+//! - `/unblacklist <mint or pubkey>` undo either
+//! - `/blocklist` show both lists
+//!
+//! The alerts bot takes subscriptions in private chats, each user with their own filters:
+//!
+//! - `/subscribe [min_mcap=<SOL>] [socials=yes] [launchpad=pumpfun,moonshot,boop]` get alerts in the DM
+//! - `/settings` show the filters
+//! - `/unsubscribe` stop
+//!
+//! Both kinds share one poller when the admin bot is the alerts bot, Telegram answers only one
+//! `getUpdates` caller per token.
+
+use std::{collections::HashSet, str::{FromStr, SplitWhitespace}, time::Duration};
+
+use redis::RedisResult;
+use solana_sdk::pubkey::Pubkey;
+use tokio::{sync::watch, time::sleep};
+use tracing::{info, warn};
+
+use crate::{
+    cache::{blacklist_creator, blocklist, get_subscription, mute_mint, subscribe, unblock, unsubscribe, Subscription},
+    redis_conn::RedisConn,
+    tg_bot::tg_bot_type::{BotInstance, Message, SendMessageOption},
+    types::Program,
+};
+
+// seconds a `getUpdates` call waits for new messages
+const POLL_TIMEOUT: u64 = 30;
+// wait after a failed poll
+const POLL_RETRY: Duration = Duration::from_secs(5);
+
+const USAGE: &str = "/mute <mint>\n/blacklist_creator <pubkey>\n/unblacklist <mint or pubkey>\n/blocklist";
+const SUBSCRIBE_USAGE: &str = "/subscribe [min_mcap=<SOL>] [socials=yes] [launchpad=pumpfun,moonshot,boop]\n/settings\n/unsubscribe";
+
+// launchpads tokens are tracked from
+const LAUNCHPADS: [Program; 3] = [Program::Pumpfun, Program::Moonshot, Program::Boop];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AdminCommand {
+    Mute(String),
+    BlacklistCreator(String),
+    Unblacklist(String),
+    Blocklist,
+    Help,
+}
+
+impl AdminCommand {
+    /// The command in `text`, `None` when it is no command; a command with a missing or
+    /// invalid argument is an error with its usage
+    pub fn parse(text: &str) -> Option<Result<Self, String>> {
+        // `/mute@SomeBot` in groups with several bots
+        let (command, mut arguments) = split_command(text)?;
+        let argument = arguments.next();
+        let pubkey = |usage: &str| match argument {
+            Some(key) if Pubkey::from_str(key).is_ok() => Ok(key.to_string()),
+            Some(key) => Err(format!("`{}` is not a valid address\nusage: {}", key, usage)),
+            None => Err(format!("usage: {}", usage)),
+        };
+        let command = match command {
+            "mute" => pubkey("/mute <mint>").map(AdminCommand::Mute),
+            "blacklist_creator" => pubkey("/blacklist_creator <pubkey>").map(AdminCommand::BlacklistCreator),
+            "unblacklist" | "unmute" => pubkey("/unblacklist <mint or pubkey>").map(AdminCommand::Unblacklist),
+            "blocklist" => Ok(AdminCommand::Blocklist),
+            "help" | "start" => Ok(AdminCommand::Help),
+            _ => return None,
+        };
+        Some(command)
+    }
+
+    /// Apply the command, returns the reply
+    pub async fn execute(&self, conn: &mut RedisConn) -> RedisResult<String> {
+        let reply = match self {
+            AdminCommand::Mute(mint) => {
+                if mute_mint(conn, mint).await? {
+                    format!("🔇 muted {}", mint)
+                } else {
+                    format!("{} was muted already", mint)
+                }
+            }
+            AdminCommand::BlacklistCreator(creator) => {
+                if blacklist_creator(conn, creator).await? {
+                    format!("⛔ blacklisted creator {}", creator)
+                } else {
+                    format!("{} was blacklisted already", creator)
+                }
+            }
+            AdminCommand::Unblacklist(key) => {
+                if unblock(conn, key).await? {
+                    format!("✅ {} is no longer muted or blacklisted", key)
+                } else {
+                    format!("{} was neither muted nor blacklisted", key)
+                }
+            }
+            AdminCommand::Blocklist => {
+                let blocklist = blocklist(conn).await?;
+                let list = |keys: &HashSet<String>| {
+                    let mut keys: Vec<&str> = keys.iter().map(String::as_str).collect();
+                    keys.sort();
+                    if keys.is_empty() { "none".to_string() } else { keys.join("\n") }
+                };
+                format!("Muted mints:\n{}\n\nBlacklisted creators:\n{}", list(&blocklist.mints), list(&blocklist.creators))
+            }
+            AdminCommand::Help => USAGE.to_string(),
+        };
+        Ok(reply)
+    }
+}
+
+// command name of `text` without the `@SomeBot` suffix, and its arguments
+fn split_command(text: &str) -> Option<(&str, SplitWhitespace<'_>)> {
+    let mut words = text.split_whitespace();
+    let command = words.next()?.strip_prefix('/')?;
+    Some((command.split('@').next().unwrap_or(command), words))
+}
+
+/// A command in a private chat with the alerts bot
+#[derive(Debug, Clone, PartialEq)]
+pub enum SubscriberCommand {
+    Subscribe(Subscription),
+    Unsubscribe,
+    Settings,
+    Help,
+}
+
+impl SubscriberCommand {
+    /// The command in `text`, `None` when it is no command; a bad filter is an error with the usage
+    pub fn parse(text: &str) -> Option<Result<Self, String>> {
+        let (command, arguments) = split_command(text)?;
+        let command = match command {
+            "subscribe" => parse_subscription(arguments).map(SubscriberCommand::Subscribe),
+            "unsubscribe" | "stop" => Ok(SubscriberCommand::Unsubscribe),
+            "settings" => Ok(SubscriberCommand::Settings),
+            "help" | "start" => Ok(SubscriberCommand::Help),
+            _ => return None,
+        };
+        Some(command)
+    }
+
+    /// Apply the command for the chat `chat_id`, returns the reply
+    pub async fn execute(&self, conn: &mut RedisConn, chat_id: i64) -> RedisResult<String> {
+        let reply = match self {
+            SubscriberCommand::Subscribe(subscription) => {
+                subscribe(conn, chat_id, subscription).await?;
+                format!("🔔 subscribed: {}", describe(subscription))
+            }
+            SubscriberCommand::Unsubscribe => {
+                if unsubscribe(conn, chat_id).await? {
+                    "🔕 unsubscribed".to_string()
+                } else {
+                    "you were not subscribed".to_string()
+                }
+            }
+            SubscriberCommand::Settings => match get_subscription(conn, chat_id).await? {
+                Some(subscription) => format!("subscribed: {}", describe(&subscription)),
+                None => format!("not subscribed\n{}", SUBSCRIBE_USAGE),
+            },
+            SubscriberCommand::Help => SUBSCRIBE_USAGE.to_string(),
+        };
+        Ok(reply)
+    }
+}
+
+// `key=value` filters of `/subscribe`, unset ones take every token
+fn parse_subscription<'a>(arguments: impl Iterator<Item = &'a str>) -> Result<Subscription, String> {
+    let mut subscription = Subscription::default();
+    for argument in arguments {
+        let invalid = || format!("`{}` is not a valid filter\nusage: {}", argument, SUBSCRIBE_USAGE);
+        let (key, value) = argument.split_once('=').ok_or_else(invalid)?;
+        match key {
+            "min_mcap" | "mcap" => {
+                subscription.min_market_cap = value.parse().ok().filter(|mcap: &f32| *mcap >= 0.0).ok_or_else(invalid)?;
+            }
+            "socials" => {
+                subscription.socials = match value {
+                    "yes" | "true" | "1" => true,
+                    "no" | "false" | "0" => false,
+                    _ => return Err(invalid()),
+                };
+            }
+            "launchpad" | "launchpads" => {
+                subscription.launchpads = value.split(',').filter(|name| !name.is_empty()).map(str::to_string).collect();
+                if !subscription.launchpads.iter().all(|name| LAUNCHPADS.iter().any(|launchpad| launchpad.as_str() == name)) {
+                    return Err(invalid());
+                }
+            }
+            _ => return Err(invalid()),
+        }
+    }
+    Ok(subscription)
+}
+
+// filters of `subscription` as one line
+fn describe(subscription: &Subscription) -> String {
+    let mut filters = vec![if subscription.min_market_cap > 0.0 {
+        format!("market cap from {} SOL", subscription.min_market_cap)
+    } else {
+        "every tier".to_string()
+    }];
+    if subscription.socials {
+        filters.push("with socials".to_string());
+    }
+    if !subscription.launchpads.is_empty() {
+        filters.push(format!("from {}", subscription.launchpads.join(", ")));
+    }
+    filters.join(", ")
+}
+
+/// Answer the commands sent to `bot` until shutdown: admin commands in its chat with `admins`
+/// set (limited to these user ids when not empty), subscriptions in private chats with
+/// `subscriptions` on
+pub async fn run_bot_commands(
+    mut conn: RedisConn,
+    bot: BotInstance,
+    admins: Option<Vec<i64>>,
+    subscriptions: bool,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let mut offset = 0;
+    loop {
+        let updates = tokio::select! {
+            biased;
+            _ = shutdown.changed() => break,
+            updates = bot.get_updates_async(offset, POLL_TIMEOUT) => updates,
+        };
+        let updates = match updates {
+            Ok(updates) => updates,
+            Err(e) => {
+                // e.g. a webhook is set for the bot or another instance polls it
+                warn!("bot commands getUpdates error: {}", e.msg);
+                tokio::select! {
+                    _ = shutdown.changed() => break,
+                    _ = sleep(POLL_RETRY) => continue,
+                }
+            }
+        };
+        for update in updates {
+            offset = offset.max(update.update_id + 1);
+            let Some(message) = update.message else {
+                continue;
+            };
+            let text = message.text.as_deref().unwrap_or_default();
+            let admin = admins.as_deref().is_some_and(|admins| is_admin(&bot, admins, &message));
+            let reply = match AdminCommand::parse(text).filter(|_| admin) {
+                Some(Ok(command)) => {
+                    info!("admin command {:?}", command);
+                    command.execute(&mut conn).await.unwrap_or_else(|e| format!("error: {}", e))
+                }
+                Some(Err(usage)) => usage,
+                // 私聊订阅, only in DMs so group members don't subscribe the group
+                None => match SubscriberCommand::parse(text).filter(|_| subscriptions && message.chat.kind == "private") {
+                    Some(Ok(command)) => {
+                        info!("subscriber {} command {:?}", message.chat.id, command);
+                        command.execute(&mut conn, message.chat.id).await.unwrap_or_else(|e| format!("error: {}", e))
+                    }
+                    Some(Err(usage)) => usage,
+                    None => continue,
+                },
+            };
+            let chat = BotInstance::new(bot.bot_token.clone(), message.chat.id.to_string());
+            let options = SendMessageOption { reply_to_message_id: Some(message.message_id), ..Default::default() };
+            if let Err(e) = chat.send_message_async(&reply, Some(options)).await {
+                warn!("command reply error: {}", e.msg);
+            }
+        }
+    }
+}
+
+// sent in the admin chat, by one of `admins` when set
+fn is_admin(bot: &BotInstance, admins: &[i64], message: &Message) -> bool {
+    message.chat.id.to_string() == bot.chat_id
+        && (admins.is_empty() || message.from.as_ref().is_some_and(|user| admins.contains(&user.id)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_command_test() {
+        let mint = "So11111111111111111111111111111111111111112";
+        assert_eq!(AdminCommand::parse(&format!("/mute {}", mint)), Some(Ok(AdminCommand::Mute(mint.to_string()))));
+        assert_eq!(
+            AdminCommand::parse(&format!("/blacklist_creator@AlertBot {}", mint)),
+            Some(Ok(AdminCommand::BlacklistCreator(mint.to_string())))
+        );
+        assert_eq!(AdminCommand::parse("/blocklist"), Some(Ok(AdminCommand::Blocklist)));
+        assert!(matches!(AdminCommand::parse("/mute"), Some(Err(_))));
+        assert!(matches!(AdminCommand::parse("/unblacklist nope"), Some(Err(_))));
+        assert_eq!(AdminCommand::parse("/price"), None);
+        assert_eq!(AdminCommand::parse("hello"), None);
+    }
+
+    #[test]
+    fn parse_subscriber_command_test() {
+        assert_eq!(
+            SubscriberCommand::parse("/subscribe min_mcap=80000 socials=yes launchpad=pumpfun,boop"),
+            Some(Ok(SubscriberCommand::Subscribe(Subscription {
+                min_market_cap: 80000.0,
+                socials: true,
+                launchpads: vec!["pumpfun".to_string(), "boop".to_string()],
+            })))
+        );
+        assert_eq!(SubscriberCommand::parse("/subscribe@AlertBot"), Some(Ok(SubscriberCommand::Subscribe(Subscription::default()))));
+        assert_eq!(SubscriberCommand::parse("/unsubscribe"), Some(Ok(SubscriberCommand::Unsubscribe)));
+        assert!(matches!(SubscriberCommand::parse("/subscribe min_mcap=lots"), Some(Err(_))));
+        assert_eq!(
+            SubscriberCommand::parse("/subscribe launchpad=moonshot"),
+            Some(Ok(SubscriberCommand::Subscribe(Subscription { launchpads: vec!["moonshot".to_string()], ..Default::default() })))
+        );
+        assert!(matches!(SubscriberCommand::parse("/subscribe launchpad=pumpamm"), Some(Err(_))));
+        assert!(matches!(SubscriberCommand::parse("/subscribe socials"), Some(Err(_))));
+        assert_eq!(SubscriberCommand::parse("/mute"), None);
+        assert_eq!(describe(&Subscription { socials: true, ..Default::default() }), "every tier, with socials");
+    }
+}
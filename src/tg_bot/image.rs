@@ -0,0 +1,123 @@
+//! Token metadata and images for photo alerts.
+//!
+//! The image URL comes from the `image` field of the token's metadata JSON, the social links
+//! subscribers can require from its `website`, `twitter` and `telegram` fields. IPFS links
+//! (`ipfs://<cid>` or `https://<gateway>/ipfs/<cid>`) are tried on the original gateway first
+//! and then on each of `telegram.ipfs_gateways`, public gateways are often slow or rate limited.
+
+use reqwest::Client;
+use serde_json::Value;
+use tracing::debug;
+
+/// Largest photo `sendPhoto` accepts as an upload
+pub const MAX_IMAGE_SIZE: usize = 10 * 1024 * 1024;
+
+/// URLs to try for `url`: itself, then the same IPFS path on every gateway
+pub fn ipfs_candidates(url: &str, gateways: &[String]) -> Vec<String> {
+    let path = if let Some(path) = url.strip_prefix("ipfs://") {
+        // `ipfs://ipfs/<cid>` shows up in older metadata
+        Some(path.strip_prefix("ipfs/").unwrap_or(path))
+    } else {
+        url.find("/ipfs/").map(|index| &url[index + "/ipfs/".len()..])
+    };
+    let mut candidates = Vec::new();
+    if !url.starts_with("ipfs://") {
+        candidates.push(url.to_string());
+    }
+    if let Some(path) = path.filter(|path| !path.is_empty()) {
+        for gateway in gateways {
+            let candidate = format!("{}/{}", gateway.trim_end_matches('/'), path);
+            if !candidates.contains(&candidate) {
+                candidates.push(candidate);
+            }
+        }
+    }
+    candidates
+}
+
+/// Metadata JSON of the token at `uri`, `None` when it can't be retrieved
+pub async fn fetch_token_metadata(client: &Client, uri: &str, gateways: &[String]) -> Option<Value> {
+    if uri.is_empty() {
+        return None;
+    }
+    serde_json::from_slice(&fetch(client, uri, gateways, |_| true).await?).ok()
+}
+
+/// Image of the token with this `metadata`, `None` when it can't be retrieved
+pub async fn fetch_token_image(client: &Client, metadata: &Value, gateways: &[String]) -> Option<Vec<u8>> {
+    let image = metadata.get("image")?.as_str()?;
+    fetch(client, image, gateways, |content_type| content_type.starts_with("image/")).await
+}
+
+/// Link `key` (`website`, `twitter`, `telegram`) of `metadata`, at the top level or under
+/// `extensions`; `None` when missing or empty
+pub fn social_link<'a>(metadata: &'a Value, key: &str) -> Option<&'a str> {
+    [Some(metadata), metadata.get("extensions")]
+        .into_iter()
+        .flatten()
+        .filter_map(|fields| fields.get(key)?.as_str())
+        .map(str::trim)
+        .find(|link| !link.is_empty())
+}
+
+/// Whether `metadata` links a website, X or Telegram
+pub fn has_socials(metadata: &Value) -> bool {
+    ["website", "twitter", "telegram"].iter().any(|key| social_link(metadata, key).is_some())
+}
+
+// body of the first candidate answering with an accepted content type and a body Telegram takes
+async fn fetch(client: &Client, url: &str, gateways: &[String], accept: impl Fn(&str) -> bool) -> Option<Vec<u8>> {
+    for candidate in ipfs_candidates(url, gateways) {
+        let response = match client.get(&candidate).send().await.and_then(|response| response.error_for_status()) {
+            Ok(response) => response,
+            Err(e) => {
+                debug!("fetch {} error: {}", candidate, e);
+                continue;
+            }
+        };
+        let content_type = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+        if !accept(&content_type) || response.content_length().is_some_and(|len| len as usize > MAX_IMAGE_SIZE) {
+            debug!("fetch {} skipped: {} of {:?} bytes", candidate, content_type, response.content_length());
+            continue;
+        }
+        match response.bytes().await {
+            Ok(body) if !body.is_empty() && body.len() <= MAX_IMAGE_SIZE => return Some(body.to_vec()),
+            Ok(body) => debug!("fetch {} skipped: {} bytes", candidate, body.len()),
+            Err(e) => debug!("fetch {} error: {}", candidate, e),
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ipfs_candidates_test() {
+        let gateways = vec!["https://ipfs.io/ipfs/".to_string(), "https://dweb.link/ipfs".to_string()];
+        assert_eq!(
+            ipfs_candidates("ipfs://bafy/1.png", &gateways),
+            vec!["https://ipfs.io/ipfs/bafy/1.png", "https://dweb.link/ipfs/bafy/1.png"]
+        );
+        assert_eq!(
+            ipfs_candidates("https://cf-ipfs.com/ipfs/bafy", &gateways),
+            vec!["https://cf-ipfs.com/ipfs/bafy", "https://ipfs.io/ipfs/bafy", "https://dweb.link/ipfs/bafy"]
+        );
+        // already on a configured gateway
+        assert_eq!(ipfs_candidates("https://ipfs.io/ipfs/bafy", &gateways), vec!["https://ipfs.io/ipfs/bafy", "https://dweb.link/ipfs/bafy"]);
+        assert_eq!(ipfs_candidates("https://arweave.net/abc", &gateways), vec!["https://arweave.net/abc"]);
+    }
+
+    #[test]
+    fn has_socials_test() {
+        assert!(has_socials(&serde_json::json!({ "name": "A", "twitter": "https://x.com/a" })));
+        assert!(has_socials(&serde_json::json!({ "extensions": { "website": "https://a.fun" } })));
+        assert!(!has_socials(&serde_json::json!({ "name": "A", "website": " ", "telegram": null })));
+    }
+}
@@ -0,0 +1,108 @@
+//! Message catalogs for alert text.
+//!
+//! A catalog maps keys like `market_cap` to plain text in one language, the built-in ones are
+//! `locales/en.toml` and `locales/zh.toml`. `locales/<language>.toml` in `telegram.templates_dir`
+//! overrides keys of a built-in language or adds a new one. Keys missing from a catalog fall back
+//! to English. The coin alert templates get the catalog of the route's `language` as `t`, e.g.
+//! `{{ t.market_cap | md }}`.
+
+use std::{collections::HashMap, fs, path::Path};
+
+use anyhow::{Context as _, Result};
+use once_cell::sync::OnceCell;
+use tracing::info;
+
+/// Language of the built-in catalog every other falls back to
+pub const DEFAULT_LANGUAGE: &str = "en";
+
+const BUILTIN: [(&str, &str); 2] = [("en", include_str!("locales/en.toml")), ("zh", include_str!("locales/zh.toml"))];
+
+/// Key to text of one language
+pub type Catalog = HashMap<String, String>;
+
+static CATALOGS: OnceCell<HashMap<String, Catalog>> = OnceCell::new();
+
+/// Load the catalogs of `templates_dir`, call before the first alert; later calls are ignored
+pub fn set_catalogs(templates_dir: &str) -> Result<()> {
+    if CATALOGS.get().is_none() {
+        let _ = CATALOGS.set(load(templates_dir)?);
+    }
+    Ok(())
+}
+
+/// Built-in catalogs with the ones in `<dir>/locales` merged in, each filled up with English
+pub fn load(dir: &str) -> Result<HashMap<String, Catalog>> {
+    let mut catalogs = builtin();
+    let locales = Path::new(dir).join("locales");
+    if !dir.is_empty() && locales.is_dir() {
+        for entry in fs::read_dir(&locales).with_context(|| format!("read {}", locales.display()))? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("toml") {
+                continue;
+            }
+            let Some(language) = path.file_stem().and_then(|s| s.to_str()) else { continue };
+            let text = fs::read_to_string(&path).with_context(|| format!("read {}", path.display()))?;
+            let catalog: Catalog = toml::from_str(&text).with_context(|| format!("parse catalog {}", path.display()))?;
+            catalogs.entry(language.to_string()).or_default().extend(catalog);
+            info!("alert text for {} from {}", language, path.display());
+        }
+    }
+    let default = catalogs[DEFAULT_LANGUAGE].clone();
+    for catalog in catalogs.values_mut() {
+        for (key, text) in &default {
+            catalog.entry(key.clone()).or_insert_with(|| text.clone());
+        }
+    }
+    Ok(catalogs)
+}
+
+fn builtin() -> HashMap<String, Catalog> {
+    BUILTIN
+        .iter()
+        .map(|(language, text)| (language.to_string(), toml::from_str(text).expect("built-in catalog")))
+        .collect()
+}
+
+/// Languages with a catalog, built-in or in `<templates_dir>/locales`
+pub fn languages(templates_dir: &str) -> Result<Vec<String>> {
+    let mut languages: Vec<String> = load(templates_dir)?.into_keys().collect();
+    languages.sort();
+    Ok(languages)
+}
+
+/// Catalog of `language`, English when there is none
+pub fn catalog(language: &str) -> &'static Catalog {
+    let catalogs = CATALOGS.get_or_init(|| load("").expect("built-in catalogs"));
+    catalogs.get(language).unwrap_or(&catalogs[DEFAULT_LANGUAGE])
+}
+
+/// Text of `key` in `language` with every `{name}` of `args` filled in, the key itself when no
+/// catalog has it
+pub fn text(language: &str, key: &str, args: &[(&str, &str)]) -> String {
+    let mut text = catalog(language).get(key).cloned().unwrap_or_else(|| key.to_string());
+    for (name, value) in args {
+        text = text.replace(&format!("{{{}}}", name), value);
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn catalog_test() {
+        let catalogs = builtin();
+        // 中文和英文的 key 要一致
+        let mut en: Vec<_> = catalogs["en"].keys().collect();
+        let mut zh: Vec<_> = catalogs["zh"].keys().collect();
+        en.sort();
+        zh.sort();
+        assert_eq!(en, zh);
+
+        assert_eq!(text("zh", "market_cap", &[]), "市值");
+        assert_eq!(text("fr", "market_cap", &[]), "Market Cap");
+        assert_eq!(text("en", "followup_multiple", &[("multiple", "2")]), "did 2x since the alert");
+        assert_eq!(text("en", "missing", &[]), "missing");
+    }
+}
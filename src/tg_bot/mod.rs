@@ -1,3 +1,9 @@
 pub mod tg_bot_type;
 pub mod tg_bot;
-
+pub mod registry;
+pub mod image;
+pub mod notifier;
+pub mod send_queue;
+pub mod template;
+pub mod locale;
+pub mod commands;
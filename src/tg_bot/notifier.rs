@@ -0,0 +1,333 @@
+//! Routing of coin alerts to chats.
+//!
+//! Every `[telegram.routes.<name>]` is a chat with its own lowest tier and filters, an alert goes
+//! to each route whose tier it reaches and whose filters the token passes, in the route's forum
+//! topic for the token's market (bonding curve or PumpSwap) and in the route's language. Without
+//! routes every alert goes to the alerts bot. In digest mode the routes get the summaries instead.
+//! In a route's quiet hours alerts below its `priority_tier` go out without a notification sound,
+//! or are held for a summary at the end of the quiet hours with `quiet_hold`.
+//! With `telegram.subscriptions` the alerts bot also sends each alert to the users who subscribed
+//! in a DM and whose filters it passes (see `commands`). Each route can keep a pinned trending
+//! message (see `trending`) and gets the mentions of tracked tokens by KOLs (see `kol`).
+//! A coin alert Telegram fails to take is sent again up to `telegram.alert_retries` times with
+//! backoff; the routes it never reached come back to the caller for the dead letter list, and the
+//! admin is told once `telegram.failure_notify_after` deliveries in a row failed.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use serde_json::Value;
+use solana_sdk::timing::timestamp;
+use tokio::time::sleep;
+use tracing::warn;
+
+use crate::{
+    cache::{Subscription, TokenRecord, TokenVolume},
+    config::{AlertConfig, RouteConfig, RugCleanup, TelegramConfig},
+    digest::Digest,
+    kol::KolMention,
+    trending::Trending,
+    tg_bot::{
+        image::has_socials,
+        registry::BotRegistry,
+        tg_bot::{AlertProgress, FollowupAlert, SentAlert, TokenDetails},
+        tg_bot_type::{BotInstance, ErrorResult, SendMessageOption, SendMessageParseMode},
+    },
+    utils::backoff_with_jitter,
+};
+
+// longest wait between two tries of an alert
+const MAX_RETRY_BACKOFF: u64 = 30_000;
+
+/// A chat alerts are sent to and what it takes
+#[derive(Clone)]
+pub struct AlertRoute {
+    pub name: String,
+    pub bot: BotInstance,
+    pub filter: RouteConfig,
+}
+
+/// Alert routes, built from `[telegram]` config
+#[derive(Clone, Default)]
+pub struct Notifier {
+    bots: BotRegistry,
+    routes: Vec<AlertRoute>,
+    subscriptions: bool,
+    // language of alerts to subscribers
+    language: String,
+    retries: u32,
+    retry_backoff: u64,
+    notify_after: u32,
+    // route deliveries failed in a row, shared by the clones
+    failures: Arc<AtomicU32>,
+}
+
+/// Result of sending a coin alert to its routes
+#[derive(Debug, Default)]
+pub struct AlertDelivery {
+    /// first message sent per route name
+    pub sent: Vec<(String, SentAlert)>,
+    /// routes the alert never reached, with the last error
+    pub failed: Vec<(String, ErrorResult)>,
+}
+
+impl Notifier {
+    pub fn new(config: &TelegramConfig, bots: BotRegistry) -> Self {
+        let mut routes: Vec<AlertRoute> = config
+            .resolved_routes()
+            .into_iter()
+            .map(|(name, route)| AlertRoute {
+                name,
+                bot: BotInstance::new(route.bot_token.clone(), route.chat_id.clone()),
+                filter: route,
+            })
+            .collect();
+        if routes.is_empty() {
+            if let Some(bot) = bots.alerts() {
+                let filter = RouteConfig { language: config.language.clone(), ..Default::default() };
+                routes.push(AlertRoute { name: "alerts".to_string(), bot: bot.clone(), filter });
+            }
+        }
+        Self {
+            bots,
+            routes,
+            subscriptions: config.subscriptions,
+            language: config.language.clone(),
+            retries: config.alert_retries,
+            retry_backoff: config.alert_retry_backoff,
+            notify_after: config.failure_notify_after,
+            failures: Arc::default(),
+        }
+    }
+
+    pub fn routes(&self) -> &[AlertRoute] {
+        &self.routes
+    }
+
+    /// Whether alerts also go to DM subscribers
+    pub fn subscriptions(&self) -> bool {
+        self.subscriptions && self.bots.alerts().is_some()
+    }
+
+    /// The alert tiers with the lowest tier of every route added, ascending
+    pub fn tiers(&self, alert: &AlertConfig) -> Vec<f32> {
+        let mut tiers = alert.tiers();
+        tiers.extend(self.routes.iter().map(|route| route.filter.market_cap).filter(|tier| *tier > 0.0));
+        tiers.sort_by(|a, b| a.total_cmp(b));
+        tiers.dedup();
+        tiers
+    }
+
+    /// Routes an alert at `tier` for `record` with this 5 minute `volume` goes to, with the
+    /// forum topic of each
+    pub fn routes_for(&self, tier: f32, record: &TokenRecord, volume: &TokenVolume) -> Vec<(AlertRoute, Option<i64>)> {
+        self.routes
+            .iter()
+            .filter(|route| route.filter.accepts(tier, record, volume))
+            .map(|route| (route.clone(), route.filter.topic_for(record)))
+            .collect()
+    }
+
+    /// Metadata JSON of the token at `uri`, `None` when it can't be fetched
+    pub async fn token_metadata(&self, uri: &str) -> Option<Value> {
+        self.bots.token_metadata(uri).await
+    }
+
+    /// Send a coin alert at `tier` to `routes` and `subscribers`, the image from the token's
+    /// `metadata` is fetched once for all of them; returns what reached which route
+    pub async fn send_coin_alert(
+        &self,
+        routes: &[(AlertRoute, Option<i64>)],
+        subscribers: &[(i64, Subscription)],
+        details: &TokenDetails,
+        metadata: Option<&Value>,
+        tier: f32,
+    ) -> AlertDelivery {
+        let image = match metadata {
+            Some(metadata) => self.bots.token_image(metadata).await,
+            None => None,
+        };
+        let mut delivery = AlertDelivery::default();
+        // 要求 verified socials 的 route 只收验证过的, checked here as the tweets come after the routing
+        for (route, topic) in routes.iter().filter(|(route, _)| details.socials_verified || !route.filter.verified_socials) {
+            let silent = route.filter.silences(tier, timestamp());
+            let mut attempt = 0;
+            // a retry resumes after the messages that went out, nothing is posted twice
+            let mut progress = AlertProgress::default();
+            let result = loop {
+                match route.bot.resume_coin_alert(details, image.clone(), *topic, &route.filter.language, silent, &mut progress).await {
+                    Err(e) if e.is_retryable() && attempt < self.retries => {
+                        attempt += 1;
+                        let backoff = backoff_with_jitter(attempt, self.retry_backoff, MAX_RETRY_BACKOFF);
+                        warn!("send alert for {} to route {} error, try {} in {}ms: {}", details.mint_address, route.name, attempt + 1, backoff, e.msg);
+                        sleep(Duration::from_millis(backoff)).await;
+                    }
+                    result => break result,
+                }
+            };
+            match result {
+                Ok(message) => {
+                    self.failures.store(0, Ordering::Relaxed);
+                    delivery.sent.push((route.name.clone(), message));
+                }
+                Err(e) => {
+                    warn!("send alert for {} to route {} failed after {} tries: {:?}", details.mint_address, route.name, attempt + 1, e);
+                    self.delivery_failed(&route.name, &e).await;
+                    delivery.failed.push((route.name.clone(), e));
+                }
+            }
+        }
+        // 没取到 metadata 就当没有 socials
+        let socials = metadata.is_some_and(has_socials);
+        if let Some(bot) = self.bots.alerts().filter(|_| self.subscriptions) {
+            for (chat_id, _) in subscribers.iter().filter(|(_, subscription)| socials || !subscription.socials) {
+                let subscriber = BotInstance::new(bot.bot_token.clone(), chat_id.to_string());
+                if let Err(e) = subscriber.send_coin_alert(details, image.clone(), None, &self.language, false).await {
+                    warn!("send alert for {} to subscriber {} error: {:?}", details.mint_address, chat_id, e);
+                }
+            }
+        }
+        delivery
+    }
+
+    // count a failed delivery, the admin hears of it when `notify_after` failed in a row
+    async fn delivery_failed(&self, route: &str, error: &ErrorResult) {
+        let failures = self.failures.fetch_add(1, Ordering::Relaxed) + 1;
+        if self.notify_after == 0 || failures != self.notify_after {
+            return;
+        }
+        let Some(admin) = self.bots.admin() else { return };
+        let msg = format!("⚠️ {} coin alerts in a row failed to send, last to route {}: {}", failures, route, error.msg);
+        if let Err(e) = admin.send_message_async(&msg, None).await {
+            warn!("send delivery failure notice error: {}", e.msg);
+        }
+    }
+
+    /// Edit sent alerts to show `details`, messages of routes no longer configured are skipped
+    pub async fn edit_coin_alert(&self, messages: &[(String, SentAlert)], details: &TokenDetails) {
+        for (route, message) in self.sent_routes(messages) {
+            if let Err(e) = route.bot.edit_coin_alert(details, message, &route.filter.language).await {
+                warn!("edit alert for {} in route {} error: {:?}", details.mint_address, route.name, e);
+            }
+        }
+    }
+
+    /// Reply to sent alerts with `followup`
+    pub async fn send_followup(&self, messages: &[(String, SentAlert)], followup: &FollowupAlert, details: &TokenDetails) {
+        for (route, message) in self.sent_routes(messages) {
+            let silent = route.filter.is_quiet_at(timestamp());
+            if let Err(e) = route.bot.send_followup(followup, details, message, &route.filter.language, silent).await {
+                warn!("send {} follow-up for {} in route {} error: {:?}", followup.kind(), details.mint_address, route.name, e);
+            }
+        }
+    }
+
+    /// Reply to sent alerts with the `Rugged` follow-up, then edit or delete the alerts as `cleanup` says
+    pub async fn send_rug_notice(&self, messages: &[(String, SentAlert)], followup: &FollowupAlert, details: &TokenDetails, cleanup: RugCleanup) {
+        self.send_followup(messages, followup, details).await;
+        for (route, message) in self.sent_routes(messages) {
+            let result = match cleanup {
+                RugCleanup::Keep => Ok(()),
+                RugCleanup::Edit => route.bot.edit_rugged_alert(details, message, &route.filter.language).await,
+                RugCleanup::Delete => route.bot.delete_message_async(message.message_id).await,
+            };
+            if let Err(e) = result {
+                warn!("clean up rugged alert of {} in route {} error: {:?}", details.mint_address, route.name, e);
+            }
+        }
+    }
+
+    /// Send `digest` to every route it has something for, in the route's topic and language; returns the
+    /// routes it could not be sent to
+    pub async fn send_digest(&self, digest: &Digest) -> Vec<String> {
+        let mut failed = Vec::new();
+        for route in &self.routes {
+            let text = digest.markdown(&route.name, route.filter.market_cap, &route.filter.language);
+            if text.is_empty() {
+                continue;
+            }
+            let options = SendMessageOption {
+                parse_mode: Some(SendMessageParseMode::MarkdownV2),
+                message_thread_id: (route.filter.topic > 0).then_some(route.filter.topic),
+                disable_notification: route.filter.is_quiet_at(timestamp()),
+                ..Default::default()
+            };
+            if let Err(e) = route.bot.send_message_async(&text, Some(options)).await {
+                warn!("send digest to route {} error: {:?}", route.name, e);
+                failed.push(route.name.clone());
+            }
+        }
+        failed
+    }
+
+    /// Send `mention` to every route in the route's language, as a reply to the token's alert
+    /// in `messages` where there is one
+    pub async fn send_kol_mention(&self, mention: &KolMention, messages: &[(String, SentAlert)]) {
+        for route in &self.routes {
+            let alert = messages.iter().find(|(name, _)| name == &route.name).map(|(_, alert)| alert);
+            let options = SendMessageOption {
+                parse_mode: Some(SendMessageParseMode::MarkdownV2),
+                message_thread_id: alert.and_then(|alert| alert.topic).or((route.filter.topic > 0).then_some(route.filter.topic)),
+                reply_to_message_id: alert.map(|alert| alert.message_id),
+                disable_notification: route.filter.is_quiet_at(timestamp()),
+            };
+            if let Err(e) = route.bot.send_message_async(&mention.markdown(&route.filter.language), Some(options)).await {
+                warn!("send KOL mention of {} to route {} error: {:?}", mention.token.mint, route.name, e);
+            }
+        }
+    }
+
+    /// Edit the trending message of every route in `messages` (route name to message id) to
+    /// show `trending`, routes without one or whose message was deleted get a new pinned one;
+    /// returns the new messages per route name
+    pub async fn update_trending(&self, trending: &Trending, messages: &HashMap<String, i64>) -> Vec<(String, i64)> {
+        let mut sent = Vec::new();
+        for route in &self.routes {
+            let text = trending.markdown(&route.filter.language);
+            if text.is_empty() {
+                continue;
+            }
+            let options = SendMessageOption { parse_mode: Some(SendMessageParseMode::MarkdownV2), ..Default::default() };
+            if let Some(message_id) = messages.get(&route.name) {
+                match route.bot.edit_message_async(*message_id, &text, false, Some(options.clone())).await {
+                    Ok(()) => continue,
+                    // deleted in the chat, sent again
+                    Err(e) if e.msg.contains("message to edit not found") => {}
+                    Err(e) => {
+                        warn!("edit trending message of route {} error: {:?}", route.name, e);
+                        continue;
+                    }
+                }
+            }
+            let options = SendMessageOption {
+                message_thread_id: (route.filter.topic > 0).then_some(route.filter.topic),
+                disable_notification: true,
+                ..options
+            };
+            match route.bot.send_message_async(&text, Some(options)).await {
+                Ok(message_id) => {
+                    // 需要置顶权限, the message is still edited when the bot can't pin it
+                    if let Err(e) = route.bot.pin_message_async(message_id, true).await {
+                        warn!("pin trending message in route {} error: {:?}", route.name, e);
+                    }
+                    sent.push((route.name.clone(), message_id));
+                }
+                Err(e) => warn!("send trending message to route {} error: {:?}", route.name, e),
+            }
+        }
+        sent
+    }
+
+    // routes of `messages` that are still configured
+    fn sent_routes<'a>(&'a self, messages: &'a [(String, SentAlert)]) -> impl Iterator<Item = (&'a AlertRoute, &'a SentAlert)> {
+        messages
+            .iter()
+            .filter_map(|(name, message)| self.routes.iter().find(|route| &route.name == name).map(|route| (route, message)))
+    }
+}
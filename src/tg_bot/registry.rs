@@ -0,0 +1,130 @@
+use std::{collections::HashMap, fmt, str::FromStr, time::Duration};
+
+use anyhow::{anyhow, Result};
+use reqwest::Client;
+use serde_json::Value;
+
+use crate::{
+    config::TelegramConfig,
+    tg_bot::{image::{fetch_token_image, fetch_token_metadata}, send_queue::set_send_limits, template::set_templates, tg_bot_type::BotInstance},
+};
+
+// per request while fetching token metadata or an image, the alert goes out as text after the last gateway
+const IMAGE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What a bot instance is used for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BotPurpose {
+    /// Coin alerts
+    Alerts,
+
+    /// Operational notices for the operator
+    Admin,
+
+    /// Error reports
+    Errors,
+}
+
+impl BotPurpose {
+    pub const ALL: [BotPurpose; 3] = [BotPurpose::Alerts, BotPurpose::Admin, BotPurpose::Errors];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BotPurpose::Alerts => "alerts",
+            BotPurpose::Admin => "admin",
+            BotPurpose::Errors => "errors",
+        }
+    }
+}
+
+impl FromStr for BotPurpose {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        BotPurpose::ALL
+            .into_iter()
+            .find(|purpose| purpose.as_str() == s)
+            .ok_or_else(|| anyhow!("unknown bot purpose `{}` (expected alerts, admin or errors)", s))
+    }
+}
+
+impl fmt::Display for BotPurpose {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Bot instances keyed by purpose, built from `[telegram]` config.
+#[derive(Clone, Default)]
+pub struct BotRegistry {
+    bots: HashMap<BotPurpose, BotInstance>,
+    // `None` until built from config
+    metadata: Option<TokenMetadata>,
+}
+
+#[derive(Clone)]
+struct TokenMetadata {
+    client: Client,
+    gateways: Vec<String>,
+    images: bool,
+}
+
+impl BotRegistry {
+    /// Build the registry from config, this also sets the send rate limits of all bots and
+    /// loads the alert templates
+    pub fn from_config(config: &TelegramConfig) -> Result<Self> {
+        set_send_limits(config);
+        set_templates(config)?;
+        let mut registry = BotRegistry::default();
+        for (name, bot) in config.resolved_bots() {
+            registry.insert(
+                name.parse()?,
+                BotInstance::new(bot.bot_token.clone(), bot.chat_id.clone()),
+            );
+        }
+        registry.metadata = Some(TokenMetadata {
+            client: Client::builder().timeout(IMAGE_TIMEOUT).build()?,
+            gateways: config.ipfs_gateways.clone(),
+            images: config.alert_images,
+        });
+        Ok(registry)
+    }
+
+    /// Metadata JSON of the token at `uri`, `None` when it can't be fetched
+    pub async fn token_metadata(&self, uri: &str) -> Option<Value> {
+        let metadata = self.metadata.as_ref()?;
+        fetch_token_metadata(&metadata.client, uri, &metadata.gateways).await
+    }
+
+    /// Image for the alert of the token with `metadata`, `None` when it can't be fetched or
+    /// `telegram.alert_images` is off
+    pub async fn token_image(&self, metadata: &Value) -> Option<Vec<u8>> {
+        let images = self.metadata.as_ref().filter(|fetch| fetch.images)?;
+        fetch_token_image(&images.client, metadata, &images.gateways).await
+    }
+
+    pub fn insert(&mut self, purpose: BotPurpose, bot: BotInstance) {
+        self.bots.insert(purpose, bot);
+    }
+
+    pub fn get(&self, purpose: BotPurpose) -> Option<&BotInstance> {
+        self.bots.get(&purpose)
+    }
+
+    /// Bot for `purpose`, falling back to the alerts bot when none is configured
+    pub fn get_or_alerts(&self, purpose: BotPurpose) -> Option<&BotInstance> {
+        self.get(purpose).or_else(|| self.alerts())
+    }
+
+    pub fn alerts(&self) -> Option<&BotInstance> {
+        self.get(BotPurpose::Alerts)
+    }
+
+    pub fn admin(&self) -> Option<&BotInstance> {
+        self.get_or_alerts(BotPurpose::Admin)
+    }
+
+    pub fn errors(&self) -> Option<&BotInstance> {
+        self.get_or_alerts(BotPurpose::Errors)
+    }
+}
@@ -0,0 +1,112 @@
+//! Pacing of Telegram sends.
+//!
+//! Telegram takes about one message per second in a chat (20 per minute in groups) and 30 per
+//! second over all chats, beyond that it answers 429 with a `retry_after`. Every send waits for
+//! its chat's turn, in the order they were queued, and for a global slot; a 429 holds the chat
+//! for `retry_after` before the send is repeated.
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use dashmap::DashMap;
+use once_cell::sync::OnceCell;
+use tokio::{
+    sync::{Mutex as AsyncMutex, OwnedMutexGuard},
+    time::{sleep_until, Instant},
+};
+
+use crate::config::TelegramConfig;
+
+static SEND_QUEUE: OnceCell<SendQueue> = OnceCell::new();
+
+/// Use the limits of `config` for every send, call before the first send; later calls are ignored
+pub fn set_send_limits(config: &TelegramConfig) {
+    let _ = SEND_QUEUE.set(SendQueue::from_config(config));
+}
+
+/// The queue all bots send through
+pub fn send_queue() -> &'static SendQueue {
+    SEND_QUEUE.get_or_init(|| SendQueue::from_config(&TelegramConfig::default()))
+}
+
+/// Per-chat and global send pacing
+pub struct SendQueue {
+    chat_interval: Duration,
+    global_interval: Duration,
+    /// sends repeated after a 429 at most this often
+    pub max_retries: u32,
+    // earliest start of the next send over all chats
+    next_global: Mutex<Instant>,
+    // earliest start of the next send per chat id, the lock is the chat's turn
+    chats: DashMap<String, Arc<AsyncMutex<Instant>>>,
+}
+
+impl SendQueue {
+    /// `per_second` of 0 leaves the global rate unlimited
+    pub fn new(chat_interval: Duration, per_second: u32, max_retries: u32) -> Self {
+        Self {
+            chat_interval,
+            global_interval: if per_second == 0 { Duration::ZERO } else { Duration::from_secs(1) / per_second },
+            max_retries,
+            next_global: Mutex::new(Instant::now()),
+            chats: DashMap::new(),
+        }
+    }
+
+    pub fn from_config(config: &TelegramConfig) -> Self {
+        Self::new(Duration::from_millis(config.chat_send_interval), config.max_sends_per_second, config.send_retries)
+    }
+
+    /// Wait for the turn of `chat_id`, it is held until the returned `ChatTurn` is dropped
+    pub async fn turn(&'static self, chat_id: &str) -> ChatTurn {
+        let chat = self.chats.entry(chat_id.to_string()).or_insert_with(|| Arc::new(AsyncMutex::new(Instant::now()))).clone();
+        ChatTurn { next: chat.lock_owned().await, queue: self }
+    }
+
+    // reserve the next global slot, when it starts
+    fn global_slot(&self) -> Instant {
+        let mut next = self.next_global.lock().unwrap();
+        let at = (*next).max(Instant::now());
+        *next = at + self.global_interval;
+        at
+    }
+}
+
+/// A chat's turn to send
+pub struct ChatTurn {
+    next: OwnedMutexGuard<Instant>,
+    queue: &'static SendQueue,
+}
+
+impl ChatTurn {
+    /// Sleep until the chat and the global rate allow the next send
+    pub async fn ready(&mut self) {
+        sleep_until(*self.next).await;
+        let at = self.queue.global_slot();
+        sleep_until(at).await;
+        *self.next = Instant::now() + self.queue.chat_interval;
+    }
+
+    /// Hold the chat for `retry_after`, from a 429 response
+    pub fn hold(&mut self, retry_after: Duration) {
+        *self.next = Instant::now() + retry_after;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn global_slot_test() {
+        let queue = SendQueue::new(Duration::from_secs(1), 20, 3);
+        let first = queue.global_slot();
+        let second = queue.global_slot();
+        assert_eq!(second - first, Duration::from_millis(50));
+
+        let unlimited = SendQueue::new(Duration::from_secs(1), 0, 3);
+        assert!(unlimited.global_slot() <= unlimited.global_slot());
+    }
+}
@@ -0,0 +1,196 @@
+//! Coin alert templates.
+//!
+//! The alert is rendered with [Tera](https://keats.github.io/tera/) from `coin_alert.md` for the
+//! MarkdownV2 parse mode or `coin_alert.html` for HTML (`telegram.alert_format`), the built-in
+//! templates unless `telegram.templates_dir` has them. The fields of `TokenDetails` are the
+//! template variables; the `md`, `md_url` and `html` filters escape a value for MarkdownV2 text,
+//! a MarkdownV2 link URL and HTML. The labels come from the message catalog of the route's
+//! language as `t` (see `locale`).
+
+use std::{collections::HashMap, path::Path};
+
+use anyhow::{Context as _, Result};
+use once_cell::sync::OnceCell;
+use tera::{Context, Tera, Value};
+use tracing::{info, warn};
+
+use crate::{
+    config::{AlertFormat, TelegramConfig},
+    tg_bot::{
+        locale::{catalog, set_catalogs, Catalog, DEFAULT_LANGUAGE},
+        tg_bot::{escape_html, escape_markdown, escape_markdown_url, TokenDetails},
+        tg_bot_type::SendMessageParseMode,
+    },
+    types::Program,
+};
+
+/// File name of the MarkdownV2 coin alert template
+pub const COIN_ALERT: &str = "coin_alert.md";
+/// File name of the HTML coin alert template
+pub const COIN_ALERT_HTML: &str = "coin_alert.html";
+
+const DEFAULT_COIN_ALERT: &str = include_str!("templates/coin_alert.md");
+const DEFAULT_COIN_ALERT_HTML: &str = include_str!("templates/coin_alert.html");
+
+static TEMPLATES: OnceCell<Tera> = OnceCell::new();
+static ALERT_FORMAT: OnceCell<AlertFormat> = OnceCell::new();
+
+/// Load the templates and message catalogs of `config`, call before the first alert; later
+/// calls are ignored
+pub fn set_templates(config: &TelegramConfig) -> Result<()> {
+    set_catalogs(&config.templates_dir)?;
+    if TEMPLATES.get().is_none() {
+        let _ = TEMPLATES.set(load(&config.templates_dir)?);
+        let _ = ALERT_FORMAT.set(config.alert_format);
+    }
+    Ok(())
+}
+
+/// Parse mode coin alerts are rendered for
+pub fn alert_parse_mode() -> SendMessageParseMode {
+    match ALERT_FORMAT.get().copied().unwrap_or_default() {
+        AlertFormat::Markdown => SendMessageParseMode::MarkdownV2,
+        AlertFormat::Html => SendMessageParseMode::HTML,
+    }
+}
+
+/// Templates from `dir`, the built-in ones for files it doesn't have
+pub fn load(dir: &str) -> Result<Tera> {
+    let mut tera = builtin();
+    for name in [COIN_ALERT, COIN_ALERT_HTML] {
+        let path = Path::new(dir).join(name);
+        if dir.is_empty() || !path.exists() {
+            continue;
+        }
+        tera.add_template_file(&path, Some(name)).with_context(|| format!("load template {}", path.display()))?;
+        // 变量名写错在这里就报出来, unknown variables only fail when rendering
+        tera.render(name, &context(&TokenDetails::default(), DEFAULT_LANGUAGE)?)
+            .with_context(|| format!("render template {}", path.display()))?;
+        info!("coin alerts use template {}", path.display());
+    }
+    Ok(tera)
+}
+
+fn builtin() -> Tera {
+    let mut tera = Tera::default();
+    // escaping is left to the filters, `.html` templates would be HTML escaped twice
+    tera.autoescape_on(vec![]);
+    tera.register_filter("md", filter(escape_markdown));
+    tera.register_filter("md_url", filter(escape_markdown_url));
+    tera.register_filter("html", filter(escape_html));
+    tera.add_raw_template(COIN_ALERT, DEFAULT_COIN_ALERT).expect("built-in coin alert template");
+    tera.add_raw_template(COIN_ALERT_HTML, DEFAULT_COIN_ALERT_HTML).expect("built-in HTML coin alert template");
+    tera
+}
+
+// an escaping function as a filter, for strings and numbers alike
+fn filter(escape: fn(&str) -> String) -> impl Fn(&Value, &HashMap<String, Value>) -> tera::Result<Value> + Send + Sync {
+    move |value, _| {
+        let text = match value {
+            Value::String(text) => text.clone(),
+            other => other.to_string(),
+        };
+        Ok(Value::String(escape(&text)))
+    }
+}
+
+// template variables for `details` with the catalog of `language`, `{launchpad}` filled in;
+// details saved before the launchpad was kept are pump.fun tokens
+fn context(details: &TokenDetails, language: &str) -> tera::Result<Context> {
+    let mut context = Context::from_serialize(details)?;
+    let launchpad = if details.launchpad.is_empty() { Program::Pumpfun.name() } else { details.launchpad.as_str() };
+    if details.chart_url.is_empty() {
+        context.insert("chart_url", &Program::Pumpfun.chart_url(&details.mint_address));
+    }
+    let catalog: Catalog =
+        catalog(language).iter().map(|(key, text)| (key.clone(), text.replace("{launchpad}", launchpad))).collect();
+    context.insert("t", &catalog);
+    Ok(context)
+}
+
+/// `details` rendered in `language` with the coin alert template of the alert format, with the
+/// built-in one when that fails
+pub fn render_coin_alert(details: &TokenDetails, language: &str) -> String {
+    let name = match alert_parse_mode() {
+        SendMessageParseMode::MarkdownV2 => COIN_ALERT,
+        SendMessageParseMode::HTML => COIN_ALERT_HTML,
+    };
+    let rendered = context(details, language).and_then(|context| {
+        let tera = TEMPLATES.get_or_init(builtin);
+        tera.render(name, &context).or_else(|e| {
+            warn!("render coin alert template error, using the built-in one: {:?}", e);
+            builtin().render(name, &context)
+        })
+    });
+    match rendered {
+        Ok(text) => text.trim_end().to_string(),
+        Err(e) => {
+            warn!("render coin alert error: {:?}", e);
+            String::new()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_coin_alert_test() {
+        let details = TokenDetails {
+            name: "Cool.Token".to_string(),
+            market_cap: "420.5".to_string(),
+            volume: "5m 12.40 SOL (70% buys), 1h 80.10 SOL".to_string(),
+            tier: "400".to_string(),
+            ..Default::default()
+        };
+        let text = render_coin_alert(&details, "en");
+        assert!(text.starts_with("🚀 *New Pump\\.fun Token Alert\\!* 🚀\n\n💎 *Token Details*\n• *Name:* `Cool\\.Token`"));
+        assert!(text.contains(
+            "• *Market Cap:* `420\\.5 SOL`\n• *Volume:* `5m 12.40 SOL (70% buys), 1h 80.10 SOL`\n• *Tier Crossed:* `400`"
+        ));
+        assert!(!text.contains("Change:") && !text.contains("Token-2022") && !text.contains("Launch Transaction"));
+        // no tweet found, no empty link
+        assert!(text.contains("• [Chart on Pump\\.fun](https://pump.fun/)\n• _No X posts found_\n\n🤖 *AI Analysis*"));
+        // the AI failed, the alert still goes out
+        assert!(text.contains("🤖 *AI Analysis* \n_AI analysis unavailable_\n\n⚠️"));
+        assert!(text.ends_with("⚠️ *DYOR \\| High Risk Investment*"));
+    }
+
+    #[test]
+    fn render_html_test() {
+        let details = TokenDetails {
+            name: "<b>A&B</b>".to_string(),
+            ai_from_x_url: "123".to_string(),
+            ai_analysis: "1 < 2".to_string(),
+            risk_score: "72/100".to_string(),
+            categories: "meme, ai".to_string(),
+            ..Default::default()
+        };
+        let text = builtin().render(COIN_ALERT_HTML, &context(&details, "en").unwrap()).unwrap();
+        assert!(text.contains("• <b>Name:</b> <code>&lt;b&gt;A&amp;B&lt;/b&gt;</code>"));
+        assert!(text.contains("• <a href=\"https://twitter.com/x/status/123\">Related COIN CA X URL</a>\n\n🤖"));
+        assert!(text.contains("1 &lt; 2\n• <b>Risk Score:</b> <code>72/100</code>\n• <b>Categories:</b> <code>meme, ai</code>\n\n⚠️"));
+    }
+
+    #[test]
+    fn render_zh_test() {
+        let details = TokenDetails { name: "Cool.Token".to_string(), ..Default::default() };
+        let text = render_coin_alert(&details, "zh");
+        assert!(text.starts_with("🚀 *Pump\\.fun 新币提醒\\!* 🚀\n\n💎 *代币信息*\n• *名称:* `Cool\\.Token`"));
+        assert!(text.ends_with("⚠️ *DYOR \\| 高风险投资*"));
+    }
+
+    #[test]
+    fn render_launchpad_test() {
+        let details = TokenDetails {
+            mint_address: "Mint1".to_string(),
+            launchpad: Program::Boop.name().to_string(),
+            chart_url: Program::Boop.chart_url("Mint1"),
+            ..Default::default()
+        };
+        let text = render_coin_alert(&details, "en");
+        assert!(text.starts_with("🚀 *New Boop\\.fun Token Alert\\!* 🚀"));
+        assert!(text.contains("• [Chart on Boop\\.fun](https://boop.fun/tokens/Mint1)\n"));
+    }
+}
@@ -5,25 +5,170 @@ use crate::tg_bot::tg_bot_type::{
     SendMessageOption, 
     SendMessageParseMode, 
     StatusCode, 
-    TelegramErrorResult
+    TelegramErrorResult,
+    Update,
 };
+use crate::tg_bot::{locale::text, send_queue::send_queue, template::{alert_parse_mode, render_coin_alert}};
+use std::time::Duration;
 use url::Url;
-use reqwest::Client;
-use serde_json::json;
+use reqwest::{multipart::{Form, Part}, Client, RequestBuilder};
+use tracing::warn;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 use anyhow::Result;
 
 /// Struct to hold detailed token information
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct TokenDetails {
     pub mint_address: String,
     pub name: String,
     pub symbol: String,
     pub url: String,
+    /// launchpad name, e.g. `Pump.fun`, fills `{launchpad}` in the alert text
+    pub launchpad: String,
+    /// token page on its launchpad
+    pub chart_url: String,
     pub ai_analysis: String,
+    /// risk score of the AI analysis, e.g. `72/100`, empty when the model gave none
+    pub risk_score: String,
+    /// categories of the AI analysis, e.g. `meme, animal`, empty without
+    pub categories: String,
+    /// confidence of the AI analysis, e.g. `80%`, empty when the model gave none
+    pub confidence: String,
     pub ai_from_x_url: String,
+    /// likes, retweets and replies of the X post, e.g. `❤️ 120 🔁 30 💬 12`, empty without one
+    pub x_engagement: String,
+    /// the X posts link to the website in the token's metadata
+    pub socials_verified: bool,
     pub market_cap: String,
+    /// market cap change over `alert.change_window`, e.g. `+340% in 10m`, empty when unknown
+    pub price_change: String,
+    /// all-time high and drawdown when below it, e.g. `420 SOL, -25% from ATH`, empty at the high
+    pub ath: String,
+    /// rolling SOL volume, e.g. `5m 12.40 SOL (70% buys), 1h 80.10 SOL`, empty without trades
+    pub volume: String,
+    /// buyer and holder counts, e.g. `120 buyers, 85 holders`, empty when not tracked
+    pub traders: String,
+    /// first buyers that bought in the create block, e.g. `3 of first 20 in the create block`, empty when not captured
+    pub same_block: String,
+    /// creator's holdings, e.g. `holds 4.2%` or `sold, holds 0.0%`, empty when not tracked
+    pub dev: String,
+    /// compute unit price buyers paid, e.g. `p50 1200, p90 8000 µlamports/CU (40 buys)`, empty when not kept
+    pub priority_fees: String,
+    pub tier: String,
     pub creator: String, 
     pub launch_time: String,
+    /// create transaction signature, empty when unknown
+    pub signature: String,
+    /// minted under Token-2022 instead of SPL Token
+    pub token_2022: bool,
+    /// creator's associated token account for the mint, empty when unknown
+    pub creator_token_account: String,
+    /// when the alert was last edited with live numbers, empty for a new alert
+    pub updated: String,
+}
+
+impl TokenDetails {
+    /// The alert in `language` and the parse mode of `telegram.alert_format`, rendered with its
+    /// template
+    pub fn render(&self, language: &str) -> String {
+        render_coin_alert(self, language)
+    }
+}
+
+/// First message of a sent coin alert
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SentAlert {
+    pub message_id: i64,
+    /// the alert went out as a photo, its text is the caption
+    pub caption: bool,
+    /// forum topic the alert was posted in
+    #[serde(default)]
+    pub topic: Option<i64>,
+}
+
+/// How far a coin alert got, a retry with it resumes after the messages already delivered
+#[derive(Debug, Clone, Default)]
+pub struct AlertProgress {
+    /// first message, set once the photo or the first text message is out
+    pub first: Option<SentAlert>,
+    // text messages still to send, `None` until the photo was tried
+    pending: Option<Vec<String>>,
+}
+
+/// Follow-up on an alerted token, sent as a reply to its alert
+#[derive(Debug, Clone, PartialEq)]
+pub enum FollowupAlert {
+    /// the market cap reached `multiple` times the one of the first alert
+    Multiple { multiple: f32, market_cap: f32, alert_market_cap: f32 },
+    /// the bonding curve completed, the token moves to PumpSwap
+    Graduated,
+    /// `percent` of the PumpSwap pool liquidity was withdrawn at once, `sol` of it quote
+    LiquidityRemoved { percent: f32, sol: f64 },
+    /// the market cap collapsed from `alert_market_cap` to `market_cap`, or the liquidity was
+    /// pulled when `liquidity_pulled`
+    Rugged { market_cap: f32, alert_market_cap: f32, liquidity_pulled: bool },
+}
+
+impl FollowupAlert {
+    /// Key under which the follow-up is sent once per token, e.g. `2x` or `graduated`
+    pub fn kind(&self) -> String {
+        match self {
+            FollowupAlert::Multiple { multiple, .. } => format!("{}x", multiple),
+            FollowupAlert::Graduated => "graduated".to_string(),
+            FollowupAlert::LiquidityRemoved { .. } => "liquidity_removed".to_string(),
+            FollowupAlert::Rugged { .. } => "rugged".to_string(),
+        }
+    }
+
+    /// The follow-up for the token of `details` in `language` as MarkdownV2
+    pub fn markdown(&self, details: &TokenDetails, language: &str) -> String {
+        let token = format!("*{}* \\(`{}`\\)", escape_markdown(&details.name), escape_markdown(&details.symbol));
+        match self {
+            FollowupAlert::Multiple { multiple, market_cap, alert_market_cap } => format!(
+                "📈 {} {}\n• *{}:* `{} → {} SOL`",
+                token,
+                escape_markdown(&text(language, "followup_multiple", &[("multiple", &multiple.to_string())])),
+                escape_markdown(&text(language, "market_cap", &[])),
+                escape_markdown(&alert_market_cap.to_string()),
+                escape_markdown(&market_cap.to_string()),
+            ),
+            FollowupAlert::Graduated => {
+                format!("🎓 {} {}", token, escape_markdown(&text(language, "followup_graduated", &[])))
+            }
+            FollowupAlert::LiquidityRemoved { percent, sol } => format!(
+                "🚨 {}: {}",
+                token,
+                escape_markdown(&text(
+                    language,
+                    "followup_liquidity_removed",
+                    &[("percent", &format!("{:.0}", percent)), ("sol", &format!("{:.2}", sol))],
+                )),
+            ),
+            FollowupAlert::Rugged { market_cap, alert_market_cap, liquidity_pulled } => {
+                let key = if *liquidity_pulled { "followup_rugged_liquidity" } else { "followup_rugged" };
+                format!(
+                    "💀 {} {}\n• *{}:* `{} → {} SOL`",
+                    struck_token(details),
+                    escape_markdown(&text(language, key, &[])),
+                    escape_markdown(&text(language, "market_cap", &[])),
+                    escape_markdown(&alert_market_cap.to_string()),
+                    escape_markdown(&market_cap.to_string()),
+                )
+            }
+        }
+    }
+
+    /// What the original alert of a rugged token is replaced with, MarkdownV2
+    pub fn rug_notice(details: &TokenDetails, language: &str) -> String {
+        format!("💀 {}\n{}", struck_token(details), escape_markdown(&text(language, "rug_notice", &[])))
+    }
+}
+
+// `~Name (SYM)~`, code can't go inside the strikethrough
+fn struck_token(details: &TokenDetails) -> String {
+    format!("~{} \\({}\\)~", escape_markdown(&details.name), escape_markdown(&details.symbol))
 }
 
 impl BotInstance {
@@ -36,12 +181,12 @@ impl BotInstance {
         BotInstance { bot_token, chat_id }
     }
 
-    /// Send a message asynchronously to Telegram
+    /// Send a message asynchronously to Telegram, returns its message id
     pub async fn send_message_async(
         &self,
         msg: &str,
         options: Option<SendMessageOption>,
-    ) -> Result<(), ErrorResult> {
+    ) -> Result<i64, ErrorResult> {
         let raw_url_str = format!(
             "https://api.telegram.org/bot{}/sendMessage",
             self.bot_token
@@ -53,10 +198,8 @@ impl BotInstance {
             }
         })?;
     
-        let parse_mode = options
-            .as_ref()
-            .and_then(|opt| opt.parse_mode.clone())
-            .map(get_send_message_parse_mode_str);
+        let options = options.unwrap_or_default();
+        let parse_mode = options.parse_mode.map(get_send_message_parse_mode_str);
     
         let mut json_body = json!({
             "chat_id": &self.chat_id,
@@ -66,98 +209,374 @@ impl BotInstance {
         if let Some(mode) = parse_mode {
             json_body["parse_mode"] = json!(mode);
         }
+        if let Some(thread) = options.message_thread_id {
+            json_body["message_thread_id"] = json!(thread);
+        }
+        if let Some(message_id) = options.reply_to_message_id {
+            json_body["reply_parameters"] = json!({ "message_id": message_id, "allow_sending_without_reply": true });
+        }
+        if options.disable_notification {
+            json_body["disable_notification"] = json!(true);
+        }
     
         let client = Client::new();
-        let response = client.post(url).json(&json_body).send().await?;
-    
-        if response.status().is_success() {
-            Ok(())
-        } else {
-            let telegram_error: TelegramErrorResult = response.json().await.map_err(|_| {
-                ErrorResult {
-                    code: StatusCode::ErrorInternalError as u16,
-                    msg: "Error converting telegram error response to json".to_string(),
-                }
-            })?;
-            Err(ErrorResult {
+        self.send_queued(|| client.post(url.clone()).json(&json_body)).await
+    }
+
+    /// Send a photo with a caption (up to 1024 characters) asynchronously to Telegram, returns
+    /// its message id
+    pub async fn send_photo_async(
+        &self,
+        photo: Vec<u8>,
+        caption: &str,
+        options: Option<SendMessageOption>,
+    ) -> Result<i64, ErrorResult> {
+        let raw_url_str = format!(
+            "https://api.telegram.org/bot{}/sendPhoto",
+            self.bot_token
+        );
+        let url = Url::parse(&raw_url_str).map_err(|e| {
+            ErrorResult {
+                code: StatusCode::ErrorInternalError as u16,
+                msg: format!("Error parsing Url; err={}", e),
+            }
+        })?;
+
+        let options = options.unwrap_or_default();
+        // a multipart form is consumed by the request, it is built again for a retry
+        let form = || {
+            let mut form = Form::new()
+                .text("chat_id", self.chat_id.clone())
+                .text("caption", caption.to_string())
+                // Telegram detects the format from the bytes
+                .part("photo", Part::bytes(photo.clone()).file_name("token"));
+            if let Some(mode) = options.parse_mode.clone().map(get_send_message_parse_mode_str) {
+                form = form.text("parse_mode", mode);
+            }
+            if let Some(thread) = options.message_thread_id {
+                form = form.text("message_thread_id", thread.to_string());
+            }
+            if options.disable_notification {
+                form = form.text("disable_notification", "true");
+            }
+            form
+        };
+
+        let client = Client::new();
+        self.send_queued(|| client.post(url.clone()).multipart(form())).await
+    }
+
+    /// Replace the text of a sent message, or its caption when `caption` is set; an edit that
+    /// changes nothing succeeds
+    pub async fn edit_message_async(
+        &self,
+        message_id: i64,
+        text: &str,
+        caption: bool,
+        options: Option<SendMessageOption>,
+    ) -> Result<(), ErrorResult> {
+        let method = if caption { "editMessageCaption" } else { "editMessageText" };
+        let raw_url_str = format!("https://api.telegram.org/bot{}/{}", self.bot_token, method);
+        let url = Url::parse(&raw_url_str).map_err(|e| {
+            ErrorResult {
+                code: StatusCode::ErrorInternalError as u16,
+                msg: format!("Error parsing Url; err={}", e),
+            }
+        })?;
+
+        let mut json_body = json!({
+            "chat_id": &self.chat_id,
+            "message_id": message_id,
+        });
+        json_body[if caption { "caption" } else { "text" }] = json!(text);
+        if let Some(mode) = options.and_then(|opt| opt.parse_mode).map(get_send_message_parse_mode_str) {
+            json_body["parse_mode"] = json!(mode);
+        }
+
+        let client = Client::new();
+        match self.send_queued(|| client.post(url.clone()).json(&json_body)).await {
+            Err(e) if e.msg.contains("message is not modified") => Ok(()),
+            result => result.map(|_| ()),
+        }
+    }
+
+    /// Long poll `getUpdates` for up to `timeout_secs`, returns the updates after `offset`
+    /// (the last update id seen plus one)
+    pub async fn get_updates_async(&self, offset: i64, timeout_secs: u64) -> Result<Vec<Update>, ErrorResult> {
+        let raw_url_str = format!("https://api.telegram.org/bot{}/getUpdates", self.bot_token);
+        let url = Url::parse(&raw_url_str).map_err(|e| {
+            ErrorResult {
+                code: StatusCode::ErrorInternalError as u16,
+                msg: format!("Error parsing Url; err={}", e),
+            }
+        })?;
+        let json_body = json!({
+            "offset": offset,
+            "timeout": timeout_secs,
+            "allowed_updates": ["message"],
+        });
+
+        // not paced, polling sends nothing to a chat
+        let response = Client::new()
+            .post(url)
+            .json(&json_body)
+            .timeout(Duration::from_secs(timeout_secs + 10))
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            let telegram_error: Option<TelegramErrorResult> = response.json().await.ok();
+            return Err(ErrorResult {
                 code: StatusCode::ErrorInternalError as u16,
-                msg: telegram_error.description,
-            })
+                msg: telegram_error.map(|e| e.description).unwrap_or_else(|| "getUpdates failed".to_string()),
+            });
         }
+        let body: Value = response.json().await?;
+        Ok(serde_json::from_value(body["result"].clone()).unwrap_or_default())
     }
 
+    // Send `request` when the chat's turn comes, a 429 answer is waited out and sent again
+    // up to `max_retries` times; returns the message id
+    async fn send_queued(&self, request: impl Fn() -> RequestBuilder) -> Result<i64, ErrorResult> {
+        let queue = send_queue();
+        let mut turn = queue.turn(&self.chat_id).await;
+        let mut retries = 0;
+        loop {
+            turn.ready().await;
+            let response = request().send().await?;
+            match check_response(response).await {
+                Err(Failure::RateLimited(retry_after)) if retries < queue.max_retries => {
+                    warn!("telegram rate limited chat {}, retrying in {:?}", self.chat_id, retry_after);
+                    turn.hold(retry_after);
+                    retries += 1;
+                }
+                Err(Failure::RateLimited(retry_after)) => {
+                    return Err(ErrorResult {
+                        code: StatusCode::ErrorRateLimited as u16,
+                        msg: format!("rate limited after {} retries, retry after {:?}", retries, retry_after),
+                    })
+                }
+                Err(Failure::Error(e)) => return Err(e),
+                Ok(message_id) => return Ok(message_id),
+            }
+        }
+    }
+
+    /// Send a coin alert, as a photo with the alert as caption when `image` is given; the text
+    /// beyond the caption limit follows as messages, and a photo Telegram rejects falls back to text.
+    /// `topic` is the forum topic (`message_thread_id`) to post in, `None` for the General topic,
+    /// `language` the one of the alert text and `silent` sends without a notification sound.
+    /// Returns the first message, the one with the market info
     pub async fn send_coin_alert(
         &self,
         token_details: &TokenDetails,
-    ) -> Result<(), ErrorResult> {
-        let markdown_message = format!(
-            r#"🚀 *New Pump\.fun Token Alert\!* 🚀
-
-💎 *Token Details*
-• *Name:* `{token_name}`
-• *Symbol:* `{symbol}`
-• *Mint:* `{mint_address}`
-
-📊 *Market Info*
-• *Market Cap:* `{market_cap} SOL`
-• *Creator:* `{creator}`
-• *Launch:* `{launch_time}`
-
-🔗 *Links*
-• [Chart on Pump\.fun](https://pump.fun/{mint_address})
-• [Related COIN CA X URL]({x_url}) 
-
-🤖 *AI Analysis* 
-{ai_analysis}
-
-⚠️ *DYOR \| High Risk Investment*"#,
-            token_name = escape_markdown(&token_details.name),
-            symbol = escape_markdown(&token_details.symbol),
-            mint_address = escape_markdown(&token_details.mint_address),
-            market_cap = escape_markdown(&token_details.market_cap),
-            creator = escape_markdown(&token_details.creator),
-            launch_time = escape_markdown(&token_details.launch_time),
-            x_url = if token_details.ai_from_x_url.is_empty() { "".to_string() } else { format!("https://twitter.com/x/status/{}", escape_markdown(&token_details.ai_from_x_url)) },
-            ai_analysis = escape_markdown(&token_details.ai_analysis)
-        );
+        image: Option<Vec<u8>>,
+        topic: Option<i64>,
+        language: &str,
+        silent: bool,
+    ) -> Result<SentAlert, ErrorResult> {
+        self.resume_coin_alert(token_details, image, topic, language, silent, &mut AlertProgress::default()).await
+    }
 
-        if markdown_message.len() > 4096 {
-            let chunks: Vec<&str> = markdown_message.split("\n\n").collect();
-            let mut current_chunk = String::new();
-
-            for chunk in chunks {
-                if (current_chunk.len() + chunk.len() + 2) > 4000 {
-                    self.send_message_async(&current_chunk, Some(SendMessageOption { 
-                        parse_mode: Some(SendMessageParseMode::MarkdownV2) 
-                    })).await?;
-                    current_chunk = chunk.to_string();
-                } else {
-                    if !current_chunk.is_empty() {
-                        current_chunk.push_str("\n\n");
+    /// `send_coin_alert` from where `progress` left off: the photo is only tried on the first
+    /// call, and a text message that failed is the first one sent again
+    pub async fn resume_coin_alert(
+        &self,
+        token_details: &TokenDetails,
+        image: Option<Vec<u8>>,
+        topic: Option<i64>,
+        language: &str,
+        silent: bool,
+        progress: &mut AlertProgress,
+    ) -> Result<SentAlert, ErrorResult> {
+        let options = SendMessageOption {
+            parse_mode: Some(alert_parse_mode()),
+            message_thread_id: topic,
+            disable_notification: silent,
+            ..Default::default()
+        };
+        if progress.pending.is_none() {
+            let mut message = token_details.render(language);
+            if let Some(image) = image {
+                let (caption, rest) = split_caption(&message, CAPTION_LIMIT);
+                match self.send_photo_async(image, caption, Some(options.clone())).await {
+                    Ok(message_id) => {
+                        progress.first = Some(SentAlert { message_id, caption: true, topic });
+                        message = rest.to_string();
                     }
-                    current_chunk.push_str(chunk);
+                    Err(e) => warn!("send alert photo error, sending text: {}", e.msg),
                 }
             }
+            progress.pending = Some(if message.is_empty() { vec![] } else { split_text(&message) });
+        }
 
-            if !current_chunk.is_empty() {
-                self.send_message_async(&current_chunk, Some(SendMessageOption { 
-                    parse_mode: Some(SendMessageParseMode::MarkdownV2) 
-                })).await?;
+        let pending = progress.pending.get_or_insert_with(Vec::new);
+        while let Some(chunk) = pending.first() {
+            let message_id = self.send_message_async(chunk, Some(options.clone())).await?;
+            progress.first.get_or_insert(SentAlert { message_id, caption: false, topic });
+            pending.remove(0);
+        }
+        progress.first.ok_or_else(|| ErrorResult {
+            code: StatusCode::ErrorInternalError as u16,
+            msg: "empty alert".to_string(),
+        })
+    }
+
+    /// Send `followup` in `language` as a reply to the alert `sent`, without a notification
+    /// sound when `silent`; returns the message id
+    pub async fn send_followup(
+        &self,
+        followup: &FollowupAlert,
+        token_details: &TokenDetails,
+        sent: &SentAlert,
+        language: &str,
+        silent: bool,
+    ) -> Result<i64, ErrorResult> {
+        let options = SendMessageOption {
+            parse_mode: Some(SendMessageParseMode::MarkdownV2),
+            message_thread_id: sent.topic,
+            reply_to_message_id: Some(sent.message_id),
+            disable_notification: silent,
+        };
+        self.send_message_async(&followup.markdown(token_details, language), Some(options)).await
+    }
+
+    /// Delete a sent message
+    pub async fn delete_message_async(&self, message_id: i64) -> Result<(), ErrorResult> {
+        let raw_url_str = format!("https://api.telegram.org/bot{}/deleteMessage", self.bot_token);
+        let url = Url::parse(&raw_url_str).map_err(|e| {
+            ErrorResult {
+                code: StatusCode::ErrorInternalError as u16,
+                msg: format!("Error parsing Url; err={}", e),
+            }
+        })?;
+        let json_body = json!({
+            "chat_id": &self.chat_id,
+            "message_id": message_id,
+        });
+
+        let client = Client::new();
+        self.send_queued(|| client.post(url.clone()).json(&json_body)).await.map(|_| ())
+    }
+
+    /// Pin a sent message, `silent` pins without notifying the members
+    pub async fn pin_message_async(&self, message_id: i64, silent: bool) -> Result<(), ErrorResult> {
+        let raw_url_str = format!("https://api.telegram.org/bot{}/pinChatMessage", self.bot_token);
+        let url = Url::parse(&raw_url_str).map_err(|e| {
+            ErrorResult {
+                code: StatusCode::ErrorInternalError as u16,
+                msg: format!("Error parsing Url; err={}", e),
             }
+        })?;
+        let json_body = json!({
+            "chat_id": &self.chat_id,
+            "message_id": message_id,
+            "disable_notification": silent,
+        });
+
+        let client = Client::new();
+        self.send_queued(|| client.post(url.clone()).json(&json_body)).await.map(|_| ())
+    }
+
+    /// Replace a coin alert sent with `send_coin_alert` with the struck-through rug notice
+    pub async fn edit_rugged_alert(&self, token_details: &TokenDetails, sent: &SentAlert, language: &str) -> Result<(), ErrorResult> {
+        let options = SendMessageOption { parse_mode: Some(SendMessageParseMode::MarkdownV2), ..Default::default() };
+        let notice = FollowupAlert::rug_notice(token_details, language);
+        self.edit_message_async(sent.message_id, &notice, sent.caption, Some(options)).await
+    }
+
+    /// Edit a coin alert sent with `send_coin_alert` to show `token_details` in `language`, only
+    /// the first message is changed
+    pub async fn edit_coin_alert(&self, token_details: &TokenDetails, sent: &SentAlert, language: &str) -> Result<(), ErrorResult> {
+        let message = token_details.render(language);
+        let text = if sent.caption {
+            split_caption(&message, CAPTION_LIMIT).0.to_string()
+        } else {
+            split_text(&message).swap_remove(0)
+        };
+        let options = SendMessageOption { parse_mode: Some(alert_parse_mode()), ..Default::default() };
+        self.edit_message_async(sent.message_id, &text, sent.caption, Some(options)).await
+    }
+}
+
+// Telegram's caption limit
+const CAPTION_LIMIT: usize = 1024;
+
+// `message` in messages below Telegram's 4096 character limit, split between sections
+fn split_text(message: &str) -> Vec<String> {
+    if message.len() <= 4096 {
+        return vec![message.to_string()];
+    }
+    let mut chunks = Vec::new();
+    let mut current_chunk = String::new();
+    for chunk in message.split("\n\n") {
+        if (current_chunk.len() + chunk.len() + 2) > 4000 {
+            chunks.push(std::mem::replace(&mut current_chunk, chunk.to_string()));
         } else {
-            self.send_message_async(&markdown_message, Some(SendMessageOption { 
-                parse_mode: Some(SendMessageParseMode::MarkdownV2) 
-            })).await?;
+            if !current_chunk.is_empty() {
+                current_chunk.push_str("\n\n");
+            }
+            current_chunk.push_str(chunk);
         }
+    }
+    if !current_chunk.is_empty() {
+        chunks.push(current_chunk);
+    }
+    chunks
+}
 
-        Ok(())
+// Leading `\n\n` separated sections of `message` that fit in `limit` bytes, and the rest
+fn split_caption(message: &str, limit: usize) -> (&str, &str) {
+    if message.len() <= limit {
+        return (message, "");
     }
+    let mut end = 0;
+    for (index, _) in message.match_indices("\n\n") {
+        if index > limit {
+            break;
+        }
+        end = index;
+    }
+    if end == 0 {
+        // no section fits, the caption would cut markup apart
+        return ("", message);
+    }
+    (&message[..end], &message[end + 2..])
+}
 
+// A failed send, rate limited ones can be repeated after the wait
+enum Failure {
+    RateLimited(Duration),
+    Error(ErrorResult),
+}
 
+// message id of a successful answer, 0 when the result is not a message
+async fn check_response(response: reqwest::Response) -> Result<i64, Failure> {
+    if response.status().is_success() {
+        let body: Value = response.json().await.unwrap_or_default();
+        return Ok(body["result"]["message_id"].as_i64().unwrap_or_default());
+    }
+    let status = response.status();
+    let telegram_error: TelegramErrorResult = response.json().await.map_err(|_| {
+        Failure::Error(ErrorResult {
+            code: StatusCode::ErrorInternalError as u16,
+            msg: "Error converting telegram error response to json".to_string(),
+        })
+    })?;
+    if status == reqwest::StatusCode::TOO_MANY_REQUESTS || telegram_error.error_code == 429 {
+        // retry_after is missing from some 429s
+        let retry_after = telegram_error.parameters.and_then(|parameters| parameters.retry_after).unwrap_or(1);
+        return Err(Failure::RateLimited(Duration::from_secs(retry_after)));
+    }
+    // 4xx 是请求本身的问题, e.g. a bad MarkdownV2 entity or a chat the bot is not in
+    let code = if status.is_client_error() { StatusCode::ErrorRejected } else { StatusCode::ErrorInternalError };
+    Err(Failure::Error(ErrorResult {
+        code: code as u16,
+        msg: telegram_error.description,
+    }))
 }
 
 /// Escaping special characters in MarkdownV2
-fn escape_markdown(text: &str) -> String {
+pub fn escape_markdown(text: &str) -> String {
     text.chars().map(|c| {
         match c {
             '_' | '*' | '[' | ']' | '(' | ')' | '~' | '`' | '>' |
@@ -168,6 +587,16 @@ fn escape_markdown(text: &str) -> String {
     }).collect()
 }
 
+/// Escaping the inside of a MarkdownV2 inline link URL, where only `)` and `\` are special
+pub fn escape_markdown_url(text: &str) -> String {
+    text.replace('\\', "\\\\").replace(')', "\\)")
+}
+
+/// Escaping text and attribute values for the HTML parse mode
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
 /// Create a Telegram bot instance
 pub fn create_instance(bot_token: &str, chat_id: &str) -> BotInstance {
     BotInstance { 
@@ -176,38 +605,85 @@ pub fn create_instance(bot_token: &str, chat_id: &str) -> BotInstance {
     }
 }
 
-/// Get a preconfigured Telegram bot instance
-pub fn get_instance() -> BotInstance {
-    let keys: (String, String) = (
-        "7985716563:AAE3RtrPsEnqBHqxFZh8HYdw4qig8n37Ugk".to_string(), 
-        "-4704509264".to_string()
-    );
-    create_instance(&keys.0, &keys.1)
-}
-
 #[cfg(test)]
 mod test {
     use super::*;
     use anyhow::Result;
-    use crate::tg_bot::tg_bot::get_instance;
+    use crate::{config::Config, tg_bot::registry::BotRegistry};
+
+    #[test]
+    fn split_caption_test() {
+        assert_eq!(split_caption("short", 10), ("short", ""));
+        assert_eq!(split_caption("aaaa\n\nbbbb\n\ncccc", 12), ("aaaa\n\nbbbb", "cccc"));
+        assert_eq!(split_caption("aaaaaaaa\n\nbb", 4), ("", "aaaaaaaa\n\nbb"));
+    }
+
+    #[test]
+    fn followup_alert_test() {
+        let details = TokenDetails { name: "Cool.Token".to_string(), symbol: "CT".to_string(), ..Default::default() };
+        let followup = FollowupAlert::Multiple { multiple: 2.0, market_cap: 120.5, alert_market_cap: 60.0 };
+        assert_eq!(followup.kind(), "2x");
+        assert_eq!(
+            followup.markdown(&details, "en"),
+            "📈 *Cool\\.Token* \\(`CT`\\) did 2x since the alert\n• *Market Cap:* `60 → 120\\.5 SOL`"
+        );
+        assert!(followup.markdown(&details, "zh").ends_with("自提醒以来涨了 2 倍\n• *市值:* `60 → 120\\.5 SOL`"));
+        assert_eq!(FollowupAlert::Graduated.kind(), "graduated");
+        let removed = FollowupAlert::LiquidityRemoved { percent: 80.0, sol: 12.345 };
+        assert!(removed.markdown(&details, "en").ends_with("80% of the PumpSwap liquidity was withdrawn \\(12\\.35 SOL\\)"));
+        let rugged = FollowupAlert::Rugged { market_cap: 5.0, alert_market_cap: 60.0, liquidity_pulled: false };
+        assert_eq!(rugged.kind(), "rugged");
+        assert!(rugged.markdown(&details, "en").starts_with("💀 ~Cool\\.Token \\(CT\\)~ rugged"));
+        assert!(rugged.markdown(&details, "en").ends_with("`60 → 5 SOL`"));
+        assert_eq!(FollowupAlert::rug_notice(&details, "en"), "💀 ~Cool\\.Token \\(CT\\)~\nRugged, no longer tracked");
+    }
+
+    #[test]
+    fn split_text_test() {
+        assert_eq!(split_text("short"), vec!["short"]);
+        let section = "a".repeat(3000);
+        let chunks = split_text(&format!("{}\n\n{}\n\nbb", section, section));
+        assert_eq!(chunks, vec![section.clone(), format!("{}\n\nbb", section)]);
+    }
 
     #[tokio::test]
     async fn test_send_coin_alert() -> Result<()> {
-        let instance = get_instance();
+        dotenv::dotenv().ok();
+        let bots = BotRegistry::from_config(&Config::load()?.telegram)?;
+        let instance = bots.alerts().expect("alerts bot not configured");
         
         let token_details = TokenDetails {
             mint_address: "7Gx9DgQnTxnKNuBjDT5LNDRmfJz2kZRjGBKvDQC1Lr1z".to_string(),
             name: "CoolMemeToken".to_string(),
             symbol: "CMT".to_string(),
             url: "https://pump.fun/token".to_string(),
+            launchpad: "Pump.fun".to_string(),
+            chart_url: "https://pump.fun/7Gx9DgQnTxnKNuBjDT5LNDRmfJz2kZRjGBKvDQC1Lr1z".to_string(),
             ai_analysis: "This token shows potential for growth due to its unique market positioning.".to_string(),
+            risk_score: "35/100".to_string(),
+            categories: "meme, animal".to_string(),
+            confidence: "80%".to_string(),
             ai_from_x_url: "https://twitter.com/x/status/1234567890".to_string(),
+            x_engagement: "❤️ 120 🔁 30 💬 12".to_string(),
+            socials_verified: true,
             market_cap: "50,000".to_string(),
+            price_change: "+340% in 10m".to_string(),
+            ath: "".to_string(),
+            volume: "5m 12.40 SOL (70% buys), 1h 80.10 SOL".to_string(),
+            traders: "120 buyers, 85 holders".to_string(),
+            same_block: "3 of first 20 in the create block".to_string(),
+            dev: "holds 4.2%".to_string(),
+            priority_fees: "p50 1200, p90 8000 µlamports/CU (40 buys)".to_string(),
+            tier: "50000".to_string(),
             creator: "0x1234...5678".to_string(),
             launch_time: "2024-04-11 12:00 UTC".to_string(),
+            signature: "".to_string(),
+            token_2022: false,
+            creator_token_account: "".to_string(),
+            updated: "".to_string(),
         };
 
-        instance.send_coin_alert(&token_details).await.expect("send_coin_alert failed");
+        instance.send_coin_alert(&token_details, None, None, "en", false).await.expect("send_coin_alert failed");
         
         Ok(())
     }
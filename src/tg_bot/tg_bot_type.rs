@@ -25,6 +25,15 @@ pub struct TelegramErrorResult {
     pub ok: bool,
     pub error_code: i32,
     pub description: String,
+    #[serde(default)]
+    pub parameters: Option<ResponseParameters>,
+}
+
+/// Why a request failed, sent along with some errors
+#[derive(Debug, Default, serde::Deserialize)]
+pub struct ResponseParameters {
+    /// seconds to wait before repeating a request that was rate limited (429)
+    pub retry_after: Option<u64>,
 }
 
 /// Status code indicating the result of APIs related function call.
@@ -37,6 +46,13 @@ pub enum StatusCode {
     /// Whenever Telegram's related operations occurred with error, then this
     /// value will be used.
     ErrorInternalError,
+
+    /// Telegram kept rejecting the request with 429 Too Many Requests.
+    ErrorRateLimited = 429,
+
+    /// Telegram refused the request itself (400 Bad Request, 403 Forbidden, ...), sending it
+    /// again gives the same answer.
+    ErrorRejected = 400,
 }
 
 /// Parse mode for `sendMessage` API
@@ -50,9 +66,51 @@ pub enum SendMessageParseMode {
 }
 
 /// Options which can be used with `sendMessage` API
+#[derive(Clone, Debug, Default)]
 pub struct SendMessageOption {
     /// Parse mode
     pub parse_mode: Option<SendMessageParseMode>,
+
+    /// Forum topic of the chat to post in, the General topic when `None`
+    pub message_thread_id: Option<i64>,
+
+    /// Message of the chat to reply to, sent without the reply when it is gone
+    pub reply_to_message_id: Option<i64>,
+
+    /// Deliver without a notification sound
+    pub disable_notification: bool,
+}
+
+/// An incoming update from `getUpdates`, only messages are read
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+pub struct Update {
+    pub update_id: i64,
+    #[serde(default)]
+    pub message: Option<Message>,
+}
+
+/// A message the bot received
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+pub struct Message {
+    pub message_id: i64,
+    pub chat: Chat,
+    #[serde(default)]
+    pub from: Option<User>,
+    #[serde(default)]
+    pub text: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+pub struct Chat {
+    pub id: i64,
+    /// `private`, `group`, `supergroup` or `channel`
+    #[serde(rename = "type", default)]
+    pub kind: String,
+}
+
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+pub struct User {
+    pub id: i64,
 }
 
 /// Create an `ErrorResult` from a `reqwest::Error`.
@@ -93,6 +151,14 @@ pub fn get_send_message_parse_mode_str(mode: SendMessageParseMode) -> &'static s
 }
 
 
+impl ErrorResult {
+    /// Whether sending the request again can succeed, network errors and Telegram's own
+    /// failures can; a request Telegram refused can't
+    pub fn is_retryable(&self) -> bool {
+        self.code != StatusCode::ErrorRejected as u16
+    }
+}
+
 impl From<reqwest::Error> for ErrorResult {
     fn from(error: reqwest::Error) -> Self {
         ErrorResult {
@@ -0,0 +1,123 @@
+//! Pinned trending message, `telegram.trending_interval`.
+//!
+//! Every route keeps one pinned message listing the tracked tokens with the highest market caps
+//! (the `market_cap_rank` leaderboard), edited every `telegram.trending_interval` so the chat has
+//! an overview that is never stale. The message ids live in redis, a restart edits the same
+//! messages and one deleted in the chat is sent and pinned again.
+
+use std::time::Duration;
+
+use redis::RedisResult;
+use solana_sdk::timing::timestamp;
+use tokio::{
+    sync::watch,
+    time::{interval_at, Instant, MissedTickBehavior},
+};
+use tracing::{debug, warn};
+
+use crate::{
+    cache::{blocklist, set_trending_message, top_tokens, trending_messages},
+    digest::DigestToken,
+    redis_conn::RedisConn,
+    tg_bot::{locale::text, notifier::Notifier, tg_bot::escape_markdown},
+    utils::format_timestamp_to_et,
+};
+
+/// The leaderboard as shown in the trending message
+#[derive(Debug, Clone, Default)]
+pub struct Trending {
+    /// highest market cap first
+    pub tokens: Vec<DigestToken>,
+    /// unix milliseconds
+    pub updated: u64,
+}
+
+impl Trending {
+    /// The message as MarkdownV2 in `language`, empty without tokens
+    pub fn markdown(&self, language: &str) -> String {
+        if self.tokens.is_empty() {
+            return String::new();
+        }
+        let lines: Vec<String> =
+            self.tokens.iter().enumerate().map(|(i, token)| format!("{}\\. {}", i + 1, token.markdown())).collect();
+        let updated = text(language, "trending_updated", &[("time", &format_timestamp_to_et(self.updated))]);
+        format!(
+            "📊 *{}*\n{}\n\n_{}_",
+            escape_markdown(&text(language, "trending_title", &[])),
+            lines.join("\n"),
+            escape_markdown(&updated)
+        )
+    }
+}
+
+/// The `size` tracked tokens with the highest market caps, muted ones left out
+pub async fn collect_trending(conn: &mut RedisConn, size: usize) -> RedisResult<Trending> {
+    let blocklist = blocklist(conn).await?;
+    // 多取一些, blocked tokens would leave the list short
+    let mut tokens: Vec<DigestToken> = top_tokens(conn, size * 2)
+        .await?
+        .iter()
+        .filter(|record| !blocklist.blocks(record))
+        .map(DigestToken::new)
+        .collect();
+    tokens.truncate(size);
+    Ok(Trending { tokens, updated: timestamp() })
+}
+
+/// Edit the trending message of every route each `interval` (milliseconds) until shutdown
+pub async fn run_trending(mut conn: RedisConn, notifier: Notifier, interval: u64, size: usize, mut shutdown: watch::Receiver<bool>) {
+    let period = Duration::from_millis(interval.max(1));
+    // the first update right away, a restart shows fresh numbers
+    let mut ticker = interval_at(Instant::now(), period);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        tokio::select! {
+            biased;
+            _ = shutdown.changed() => break,
+            _ = ticker.tick() => {}
+        }
+        let result = match collect_trending(&mut conn, size).await {
+            Ok(trending) => trending_messages(&mut conn).await.map(|messages| (trending, messages)),
+            Err(e) => Err(e),
+        };
+        let (trending, messages) = match result {
+            Ok(result) => result,
+            Err(e) => {
+                warn!("collect trending tokens error: {}", e);
+                continue;
+            }
+        };
+        debug!("trending: {} tokens", trending.tokens.len());
+        for (route, message_id) in notifier.update_trending(&trending, &messages).await {
+            if let Err(e) = set_trending_message(&mut conn, &route, message_id).await {
+                warn!("save trending message of route {} error: {}", route, e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trending_markdown_test() {
+        let token = DigestToken {
+            mint: "Mint1".to_string(),
+            name: "Cool.Token".to_string(),
+            symbol: "CT".to_string(),
+            market_cap: 420.4,
+            chart_url: "https://pump.fun/Mint1".to_string(),
+            ..Default::default()
+        };
+        // 2026-01-01 12:00 UTC
+        let trending = Trending { tokens: vec![token], updated: 1_767_268_800_000 };
+        assert_eq!(
+            trending.markdown("en"),
+            "📊 *Trending now*\n1\\. [Cool\\.Token](https://pump.fun/Mint1) \\(`CT`\\) `420 SOL`\
+             \n\n_updated 2026\\-01\\-01 07:00 AM ET_"
+        );
+        assert!(trending.markdown("zh").starts_with("📊 *当前热门*\n1\\."));
+        assert_eq!(Trending::default().markdown("en"), "");
+    }
+}
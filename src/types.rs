@@ -1,116 +1,544 @@
-use std::str::FromStr;
+use std::{collections::HashMap, fmt, str::FromStr};
 
-use anyhow::anyhow;
+use base64::{engine::general_purpose::STANDARD, Engine};
 
 use borsh::{BorshDeserialize, BorshSerialize};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
 use solana_sdk::{bs58, pubkey::Pubkey};
-use solana_transaction_status::{UiCompiledInstruction, UiInstruction};
+use solana_transaction_status::{UiInstruction, UiParsedInstruction};
+use thiserror::Error;
+
+use crate::{
+    constants::{COMPUTE_BUDGET_PROGRAM_ID, PUMPFUN_GLOBAL},
+    utils::find_boop_bonding_curve,
+};
+
+/// Event structs and discriminators generated from `pump-fun-idl.json` / `pump_amm_idl.json` by `build.rs`
+pub mod idl {
+    include!(concat!(env!("OUT_DIR"), "/idl.rs"));
+}
 
 // PUMPFUN EVENT
-const PUMPFUN_CREATE_EVENT: [u8; 8] = [27, 114, 169, 77, 222, 235, 99, 118];
-const PUMPFUN_COMPLETE_EVENT: [u8; 8] = [95, 114, 97, 156, 212, 46, 152, 8];
-const PUMPFUN_TRADE_EVENT: [u8; 8] = [189, 219, 127, 211, 78, 230, 97, 238];
+const PUMPFUN_CREATE_EVENT: [u8; 8] = idl::pump::CREATE_EVENT_DISCRIMINATOR;
+const PUMPFUN_COMPLETE_EVENT: [u8; 8] = idl::pump::COMPLETE_EVENT_DISCRIMINATOR;
+const PUMPFUN_TRADE_EVENT: [u8; 8] = idl::pump::TRADE_EVENT_DISCRIMINATOR;
+const PUMPFUN_SET_PARAMS_EVENT: [u8; 8] = idl::pump::SET_PARAMS_EVENT_DISCRIMINATOR;
+// not in the bundled idl yet
+const PUMPFUN_MIGRATE_EVENT: [u8; 8] = [189, 233, 93, 185, 92, 148, 234, 148];
 
 // AMM EVENT
-pub const PUMPAMM_BUY_EVENT: [u8; 8] = [103, 244, 82, 31, 44, 245, 119, 119];
-pub const PUMPAMM_SELL_EVENT: [u8; 8] = [62, 47, 55, 10, 165, 3, 220, 42];
-pub const PUMPAMM_DEPOSIT_EVENT: [u8; 8] = [120, 248, 61, 83, 31, 142, 107, 144];
-pub const PUMPAMM_WITHDRAW_EVENT: [u8; 8] = [22, 9, 133, 26, 160, 44, 71, 192];
-pub const PUMPAMM_CREATE_POOL_EVENT: [u8; 8] = [177, 49, 12, 210, 160, 118, 167, 116];
+pub const PUMPAMM_BUY_EVENT: [u8; 8] = idl::pump_amm::BUY_EVENT_DISCRIMINATOR;
+pub const PUMPAMM_SELL_EVENT: [u8; 8] = idl::pump_amm::SELL_EVENT_DISCRIMINATOR;
+pub const PUMPAMM_DEPOSIT_EVENT: [u8; 8] = idl::pump_amm::DEPOSIT_EVENT_DISCRIMINATOR;
+pub const PUMPAMM_WITHDRAW_EVENT: [u8; 8] = idl::pump_amm::WITHDRAW_EVENT_DISCRIMINATOR;
+pub const PUMPAMM_CREATE_POOL_EVENT: [u8; 8] = idl::pump_amm::CREATE_POOL_EVENT_DISCRIMINATOR;
+
+// first 8 bytes of every anchor emit_cpi instruction, followed by the event discriminator
+const EVENT_IX_TAG: [u8; 8] = [228, 69, 165, 46, 81, 203, 154, 29];
+
+// INSTRUCTION, `global:buy` / `global:sell` of both pump.fun and PumpSwap
+const BUY_IX: [u8; 8] = [102, 6, 61, 18, 1, 218, 235, 234];
+const SELL_IX: [u8; 8] = [51, 230, 133, 164, 1, 127, 131, 173];
+// `global:create` of pump.fun
+const CREATE_IX: [u8; 8] = [24, 30, 200, 40, 5, 28, 7, 119];
+
+// MOONSHOT EVENT
+// same Anchor event name as pump.fun's TradeEvent, the two are told apart by the emitting program
+const MOONSHOT_TRADE_EVENT: [u8; 8] = PUMPFUN_TRADE_EVENT;
+const MOONSHOT_MIGRATION_EVENT: [u8; 8] = [255, 202, 76, 147, 91, 231, 73, 22];
+// `global:token_mint`, moonshot emits no create event
+const MOONSHOT_TOKEN_MINT_IX: [u8; 8] = [3, 44, 164, 184, 123, 13, 245, 179];
+
+// BOOP EVENT
+const BOOP_TOKEN_CREATED_EVENT: [u8; 8] = [236, 19, 41, 255, 130, 78, 147, 172];
+const BOOP_TOKEN_BOUGHT_EVENT: [u8; 8] = [197, 182, 3, 228, 82, 236, 7, 143];
+const BOOP_TOKEN_SOLD_EVENT: [u8; 8] = [88, 61, 1, 247, 185, 6, 252, 86];
 
 
-#[derive(Debug, Clone)]
+/// Programs the monitor can subscribe to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Program {
+    Pumpfun,
+    Pumpamm,
+    Moonshot,
+    Boop,
+}
+
+impl Program {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Program::Pumpfun => "pumpfun",
+            Program::Pumpamm => "pumpamm",
+            Program::Moonshot => "moonshot",
+            Program::Boop => "boop",
+        }
+    }
+
+    /// Display name for alerts
+    pub fn name(&self) -> &'static str {
+        match self {
+            Program::Pumpfun => "Pump.fun",
+            Program::Pumpamm => "PumpSwap",
+            Program::Moonshot => "Moonshot",
+            Program::Boop => "Boop.fun",
+        }
+    }
+
+    /// Page of a token launched on the program
+    pub fn chart_url(&self, mint: &str) -> String {
+        match self {
+            Program::Pumpfun | Program::Pumpamm => format!("https://pump.fun/{}", mint),
+            // moonshot tokens are listed on dexscreener
+            Program::Moonshot => format!("https://dexscreener.com/solana/{}", mint),
+            Program::Boop => format!("https://boop.fun/tokens/{}", mint),
+        }
+    }
+}
+
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Serialized with a `type` tag next to the event fields, e.g. `{"type": "pumpfun_buy", "mint": ...}`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
 pub enum TargetEvent {
     PumpfunBuy(TradeEvent),
     PumpfunSell(TradeEvent),
     PumpfunCreate(CreateEvent),
     PumpfunComplete(CompleteEvent),
+    PumpfunMigrate(MigrateEvent),
+    /// new global curve parameters
+    PumpfunSetParams(SetParamsEvent),
+    /// top-level buy/sell instruction, only parsed when the feed has no inner instructions;
+    /// a top-level `create` gives `PumpfunCreate`
+    PumpfunTradeIx(TradeInstruction),
     PumpammBuy(AMMBuyEvent),
     PumpammSell(AMMSellEvent),
     PumpammDeposit(AMMDepositEvent),
     PumpammWithdraw(AMMWithdrawEvent),
     PumpammCreatePool(AMMCreatePoolEvent),
+    /// top-level buy/sell instruction, only parsed when the feed has no inner instructions
+    PumpammSwapIx(AMMSwapInstruction),
+    MoonshotBuy(MoonshotTradeEvent),
+    MoonshotSell(MoonshotTradeEvent),
+    MoonshotMigration(MoonshotMigrationEvent),
+    /// top-level `token_mint` instruction, the only record of a Moonshot launch
+    MoonshotCreate(MoonshotCreateInstruction),
+    BoopCreate(BoopCreateEvent),
+    BoopBuy(BoopTradeEvent),
+    BoopSell(BoopTradeEvent),
+    /// SPL Token transfer or mint inside a monitored instruction (`engine.spl_transfers`)
+    TokenTransfer(TokenTransfer),
+    /// cpi event of a monitored program with an unknown discriminator (`engine.unknown_events`)
+    Unknown {
+        program: Program,
+        discriminator: [u8; 8],
+        /// event body after the discriminators
+        #[serde(with = "bytes_b64")]
+        data: Vec<u8>,
+    },
 }
- 
-impl TryFrom<UiInstruction> for TargetEvent {
-    type Error = anyhow::Error;
-
-    fn try_from(inner_instruction: UiInstruction) -> Result<Self, Self::Error> {
-        match inner_instruction {
-            solana_transaction_status::UiInstruction::Compiled(ui_compiled_instruction) => {
-                if let Some(create) =
-                    CreateEvent::try_from_compiled_instruction(&ui_compiled_instruction)
-                {
-                    return Ok(TargetEvent::PumpfunCreate(create));
-                }
-                if let Some(complete) =
-                    CompleteEvent::try_from_compiled_instruction(&ui_compiled_instruction)
-                {
-                    return Ok(Self::PumpfunComplete(complete));
-                }
-                if let Some(trade) =
-                    TradeEvent::try_from_compiled_instruction(&ui_compiled_instruction)
-                {
-                    if trade.is_buy {
-                        return Ok(TargetEvent::PumpfunBuy(trade));
-                    } else {
-                        return Ok(TargetEvent::PumpfunSell(trade));
-                    }
-                }
-                if let Some(amm_buy) = AMMBuyEvent::try_from_compiled_instruction(&ui_compiled_instruction) {
-                    return Ok(TargetEvent::PumpammBuy(amm_buy));
-                }
-                if let Some(amm_sell) = AMMSellEvent::try_from_compiled_instruction(&ui_compiled_instruction) {
-                    return Ok(TargetEvent::PumpammSell(amm_sell));
-                }
-                if let Some(amm_deposit) = AMMDepositEvent::try_from_compiled_instruction(&ui_compiled_instruction) {
-                    return Ok(TargetEvent::PumpammDeposit(amm_deposit));
-                }
-                if let Some(amm_withdraw) = AMMWithdrawEvent::try_from_compiled_instruction(&ui_compiled_instruction) {
-                    return Ok(TargetEvent::PumpammWithdraw(amm_withdraw));
-                }
-                if let Some(amm_create_pool) = AMMCreatePoolEvent::try_from_compiled_instruction(&ui_compiled_instruction) {
-                    return Ok(TargetEvent::PumpammCreatePool(amm_create_pool));
-                }
+
+impl TargetEvent {
+    /// Token the event belongs to: the mint for pump.fun events, the pool for AMM events,
+    /// the curve account for Moonshot trades
+    pub fn key(&self) -> Pubkey {
+        match self {
+            TargetEvent::PumpfunBuy(trade) | TargetEvent::PumpfunSell(trade) => trade.mint,
+            TargetEvent::PumpfunCreate(create) => create.mint,
+            TargetEvent::PumpfunComplete(complete) => complete.mint,
+            TargetEvent::PumpfunMigrate(migrate) => migrate.mint,
+            TargetEvent::PumpfunSetParams(_) => PUMPFUN_GLOBAL,
+            TargetEvent::PumpfunTradeIx(trade) => trade.mint,
+            TargetEvent::PumpammBuy(buy) => buy.pool,
+            TargetEvent::PumpammSell(sell) => sell.pool,
+            TargetEvent::PumpammDeposit(deposit) => deposit.pool,
+            TargetEvent::PumpammWithdraw(withdraw) => withdraw.pool,
+            TargetEvent::PumpammCreatePool(pool) => pool.pool,
+            TargetEvent::PumpammSwapIx(swap) => swap.pool,
+            TargetEvent::MoonshotBuy(trade) | TargetEvent::MoonshotSell(trade) => trade.curve,
+            // the migration event does not name the curve
+            TargetEvent::MoonshotMigration(_) => Pubkey::default(),
+            TargetEvent::MoonshotCreate(create) => create.mint,
+            TargetEvent::BoopCreate(create) => create.mint,
+            TargetEvent::BoopBuy(trade) | TargetEvent::BoopSell(trade) => trade.mint,
+            TargetEvent::TokenTransfer(transfer) => transfer.mint,
+            TargetEvent::Unknown { .. } => Pubkey::default(),
+        }
+    }
+
+    /// Decode a top-level instruction of `program`, `accounts` are the keys of the instruction's accounts
+    pub fn from_outer_instruction(program: Program, accounts: &[Pubkey], data: &[u8]) -> Option<TargetEvent> {
+        match program {
+            Program::Pumpfun => CreateEvent::try_from_instruction(accounts, data)
+                .map(TargetEvent::PumpfunCreate)
+                .or_else(|| TradeInstruction::try_from_instruction(accounts, data).map(TargetEvent::PumpfunTradeIx)),
+            Program::Pumpamm => AMMSwapInstruction::try_from_instruction(accounts, data).map(TargetEvent::PumpammSwapIx),
+            // launches come from `from_launch_instruction`, with or without inner instructions
+            Program::Moonshot | Program::Boop => None,
+        }
+    }
+
+    /// Decode a top-level launch instruction of a launchpad without a create event
+    pub fn from_launch_instruction(program: Program, accounts: &[Pubkey], data: &[u8]) -> Option<TargetEvent> {
+        match program {
+            Program::Moonshot => MoonshotCreateInstruction::try_from_instruction(accounts, data).map(TargetEvent::MoonshotCreate),
+            Program::Pumpfun | Program::Pumpamm | Program::Boop => None,
+        }
+    }
+
+    /// `unknown` for an instruction of `program` in either encoding
+    pub fn unknown_instruction(program: Program, instruction: &UiInstruction) -> Option<TargetEvent> {
+        let data = instruction_data(instruction)?;
+        bs58::decode(data)
+            .into_vec()
+            .ok()
+            .and_then(|bytes| TargetEvent::unknown(program, &bytes))
+            .or_else(|| STANDARD.decode(data).ok().and_then(|bytes| TargetEvent::unknown(program, &bytes)))
+    }
+
+    /// Keep a cpi event of `program` that `parse` did not recognize, `None` for other instructions
+    pub fn unknown(program: Program, data: &[u8]) -> Option<TargetEvent> {
+        if data.len() < 16 || !data[..8].eq(&EVENT_IX_TAG) {
+            return None;
+        }
+        Some(TargetEvent::Unknown {
+            program,
+            discriminator: data[8..16].try_into().ok()?,
+            data: data[16..].to_vec(),
+        })
+    }
+
+    /// Bonding curve the event belongs to, `accounts` are the resolved accounts of the
+    /// instruction behind the event (`EventContext::accounts`)
+    pub fn bonding_curve(&self, accounts: &[Pubkey]) -> Option<Pubkey> {
+        match self {
+            // emitted by buy/sell: global, fee_recipient, mint, bonding_curve, ...
+            TargetEvent::PumpfunBuy(_) | TargetEvent::PumpfunSell(_) | TargetEvent::PumpfunComplete(_) => {
+                accounts.get(3).copied()
             }
-            _ => {}
+            TargetEvent::PumpfunCreate(create) => Some(create.bonding_curve),
+            TargetEvent::PumpfunMigrate(migrate) => Some(migrate.bonding_curve),
+            TargetEvent::PumpfunTradeIx(trade) => Some(trade.bonding_curve),
+            TargetEvent::MoonshotBuy(trade) | TargetEvent::MoonshotSell(trade) => Some(trade.curve),
+            TargetEvent::MoonshotCreate(create) => Some(create.curve),
+            TargetEvent::BoopCreate(create) => Some(find_boop_bonding_curve(&create.mint)),
+            TargetEvent::BoopBuy(trade) | TargetEvent::BoopSell(trade) => Some(find_boop_bonding_curve(&trade.mint)),
+            _ => None,
+        }
+    }
+
+    /// Mint of the token the event belongs to, also for pool and curve events, where it is read
+    /// from the resolved accounts of the instruction behind the event (`EventContext::accounts`)
+    pub fn mint(&self, accounts: &[Pubkey]) -> Option<Pubkey> {
+        match self {
+            TargetEvent::PumpfunBuy(trade) | TargetEvent::PumpfunSell(trade) => Some(trade.mint),
+            TargetEvent::PumpfunCreate(create) => Some(create.mint),
+            TargetEvent::PumpfunComplete(complete) => Some(complete.mint),
+            TargetEvent::PumpfunMigrate(migrate) => Some(migrate.mint),
+            TargetEvent::PumpfunTradeIx(trade) => Some(trade.mint),
+            // buy/sell: pool, user, global_config, base_mint, ...; deposit/withdraw: pool, global_config, user, base_mint, ...
+            TargetEvent::PumpammBuy(_)
+            | TargetEvent::PumpammSell(_)
+            | TargetEvent::PumpammDeposit(_)
+            | TargetEvent::PumpammWithdraw(_) => accounts.get(3).copied(),
+            TargetEvent::PumpammCreatePool(pool) => Some(pool.base_mint),
+            TargetEvent::PumpammSwapIx(swap) => Some(swap.base_mint),
+            // buy/sell: sender, sender_token_account, curve_account, curve_token_account, dex_fee, helio_fee, mint, ...
+            TargetEvent::MoonshotBuy(_) | TargetEvent::MoonshotSell(_) => accounts.get(6).copied(),
+            TargetEvent::MoonshotCreate(create) => Some(create.mint),
+            TargetEvent::BoopCreate(create) => Some(create.mint),
+            TargetEvent::BoopBuy(trade) | TargetEvent::BoopSell(trade) => Some(trade.mint),
+            TargetEvent::TokenTransfer(transfer) => Some(transfer.mint),
+            TargetEvent::MoonshotMigration(_) | TargetEvent::PumpfunSetParams(_) | TargetEvent::Unknown { .. } => None,
+        }
+    }
+
+    /// Mint of a newly launched token, for the create event of any launchpad
+    pub fn created_mint(&self) -> Option<Pubkey> {
+        match self {
+            TargetEvent::PumpfunCreate(create) => Some(create.mint),
+            TargetEvent::MoonshotCreate(create) => Some(create.mint),
+            TargetEvent::BoopCreate(create) => Some(create.mint),
+            _ => None,
+        }
+    }
+
+    /// Program that emitted the event
+    pub fn program(&self) -> Program {
+        match self {
+            TargetEvent::PumpfunBuy(_)
+            | TargetEvent::PumpfunSell(_)
+            | TargetEvent::PumpfunCreate(_)
+            | TargetEvent::PumpfunComplete(_)
+            | TargetEvent::PumpfunMigrate(_)
+            | TargetEvent::PumpfunSetParams(_)
+            | TargetEvent::PumpfunTradeIx(_) => Program::Pumpfun,
+            TargetEvent::PumpammBuy(_)
+            | TargetEvent::PumpammSell(_)
+            | TargetEvent::PumpammDeposit(_)
+            | TargetEvent::PumpammWithdraw(_)
+            | TargetEvent::PumpammCreatePool(_)
+            | TargetEvent::PumpammSwapIx(_) => Program::Pumpamm,
+            TargetEvent::MoonshotBuy(_)
+            | TargetEvent::MoonshotSell(_)
+            | TargetEvent::MoonshotMigration(_)
+            | TargetEvent::MoonshotCreate(_) => Program::Moonshot,
+            TargetEvent::BoopCreate(_) | TargetEvent::BoopBuy(_) | TargetEvent::BoopSell(_) => {
+                Program::Boop
+            }
+            TargetEvent::TokenTransfer(transfer) => transfer.program,
+            TargetEvent::Unknown { program, .. } => *program,
+        }
+    }
+}
+
+impl TargetEvent {
+    /// Parse an inner instruction of `program`.
+    ///
+    /// `Ok(None)` when the instruction is no event of `program`, an error when it carries
+    /// a known discriminator but its data does not decode.
+    pub fn parse_instruction(program: Program, instruction: &UiInstruction) -> Result<Option<TargetEvent>, ParseError> {
+        let Some(data) = instruction_data(instruction) else {
+            return Ok(None);
+        };
+        // base58 is the rpc default, some feeds send base64; a string can be valid in both
+        let base58 = bs58::decode(data).into_vec().map(|bytes| TargetEvent::parse(program, &bytes));
+        if let Ok(Ok(Some(event))) = base58 {
+            return Ok(Some(event));
+        }
+        match STANDARD.decode(data).map(|bytes| TargetEvent::parse(program, &bytes)) {
+            Ok(Ok(Some(event))) => Ok(Some(event)),
+            // neither encoding gave an event, report the base58 result
+            _ => base58.unwrap_or(Ok(None)),
+        }
+    }
+
+    /// Parse raw instruction data of `program`, for callers that do not go through the UI types
+    pub fn parse(program: Program, data: &[u8]) -> Result<Option<TargetEvent>, ParseError> {
+        // emit_cpi data: instruction discriminator, event discriminator, event
+        let Some(discriminator) = data.get(8..16).and_then(|bytes| <[u8; 8]>::try_from(bytes).ok()) else {
+            return Ok(None);
+        };
+        match EVENT_PARSERS.get(&(program, discriminator)) {
+            Some(parser) => parser(data),
+            None => Ok(None),
+        }
+    }
+}
+
+// encoded data of an instruction
+fn instruction_data(instruction: &UiInstruction) -> Option<&String> {
+    match instruction {
+        UiInstruction::Compiled(ix) => Some(&ix.data),
+        UiInstruction::Parsed(UiParsedInstruction::PartiallyDecoded(ix)) => Some(&ix.data),
+        // json parsed instructions only exist for programs the rpc knows, e.g. system or token
+        UiInstruction::Parsed(UiParsedInstruction::Parsed(_)) => None,
+    }
+}
+
+type EventParser = fn(&[u8]) -> Result<Option<TargetEvent>, ParseError>;
+
+// (emitting program, event discriminator) -> parser, one lookup per instruction instead of
+// trying every event; another program emitting a known discriminator gets no parser
+static EVENT_PARSERS: Lazy<HashMap<(Program, [u8; 8]), EventParser>> = Lazy::new(|| {
+    let parsers: [(Program, [u8; 8], EventParser); 15] = [
+        (Program::Pumpfun, PUMPFUN_CREATE_EVENT, |data| Ok(CreateEvent::try_from_data(data)?.map(TargetEvent::PumpfunCreate))),
+        (Program::Pumpfun, PUMPFUN_COMPLETE_EVENT, |data| {
+            Ok(CompleteEvent::try_from_data(data)?.map(TargetEvent::PumpfunComplete))
+        }),
+        (Program::Pumpfun, PUMPFUN_MIGRATE_EVENT, |data| Ok(MigrateEvent::try_from_data(data)?.map(TargetEvent::PumpfunMigrate))),
+        (Program::Pumpfun, PUMPFUN_SET_PARAMS_EVENT, |data| {
+            Ok(SetParamsEvent::try_from_data(data)?.map(TargetEvent::PumpfunSetParams))
+        }),
+        (Program::Pumpfun, PUMPFUN_TRADE_EVENT, |data| {
+            Ok(TradeEvent::try_from_data(data)?.map(|trade| match trade.is_buy {
+                true => TargetEvent::PumpfunBuy(trade),
+                false => TargetEvent::PumpfunSell(trade),
+            }))
+        }),
+        (Program::Pumpamm, PUMPAMM_BUY_EVENT, |data| Ok(AMMBuyEvent::try_from_data(data)?.map(TargetEvent::PumpammBuy))),
+        (Program::Pumpamm, PUMPAMM_SELL_EVENT, |data| Ok(AMMSellEvent::try_from_data(data)?.map(TargetEvent::PumpammSell))),
+        (Program::Pumpamm, PUMPAMM_DEPOSIT_EVENT, |data| {
+            Ok(AMMDepositEvent::try_from_data(data)?.map(TargetEvent::PumpammDeposit))
+        }),
+        (Program::Pumpamm, PUMPAMM_WITHDRAW_EVENT, |data| {
+            Ok(AMMWithdrawEvent::try_from_data(data)?.map(TargetEvent::PumpammWithdraw))
+        }),
+        (Program::Pumpamm, PUMPAMM_CREATE_POOL_EVENT, |data| {
+            Ok(AMMCreatePoolEvent::try_from_data(data)?.map(TargetEvent::PumpammCreatePool))
+        }),
+        (Program::Moonshot, MOONSHOT_TRADE_EVENT, |data| {
+            Ok(MoonshotTradeEvent::try_from_data(data)?.map(|trade| match trade.trade_type {
+                MoonshotTradeType::Buy => TargetEvent::MoonshotBuy(trade),
+                MoonshotTradeType::Sell => TargetEvent::MoonshotSell(trade),
+            }))
+        }),
+        (Program::Moonshot, MOONSHOT_MIGRATION_EVENT, |data| {
+            Ok(MoonshotMigrationEvent::try_from_data(data)?.map(TargetEvent::MoonshotMigration))
+        }),
+        (Program::Boop, BOOP_TOKEN_CREATED_EVENT, |data| Ok(BoopCreateEvent::try_from_data(data)?.map(TargetEvent::BoopCreate))),
+        (Program::Boop, BOOP_TOKEN_BOUGHT_EVENT, BoopTradeEvent::try_from_data),
+        (Program::Boop, BOOP_TOKEN_SOLD_EVENT, BoopTradeEvent::try_from_data),
+    ];
+    parsers.into_iter().map(|(program, discriminator, parser)| ((program, discriminator), parser)).collect()
+});
+
+/// Why an instruction of a monitored program did not give an event
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ParseFailure {
+    /// cpi event with a discriminator no parser knows
+    UnknownDiscriminator,
+    /// known discriminator, the body does not match the layout
+    Borsh,
+    /// known discriminator, the body is shorter than the layout
+    Undersized,
+}
+
+impl ParseFailure {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ParseFailure::UnknownDiscriminator => "unknown_discriminator",
+            ParseFailure::Borsh => "borsh",
+            ParseFailure::Undersized => "undersized",
         }
-        return Err(anyhow!("failed to convert to target tx"));
     }
 }
 
-#[derive(Debug, Clone, BorshSerialize, BorshDeserialize)]
-pub struct CreateEvent {
+impl fmt::Display for ParseFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("parse {program} event {discriminator:?} ({data_len} bytes) {kind} error: {reason}")]
+pub struct ParseError {
+    pub program: Program,
+    pub discriminator: [u8; 8],
+    pub data_len: usize,
+    pub kind: ParseFailure,
+    pub reason: String,
+}
+
+impl ParseError {
+    fn new(program: Program, discriminator: [u8; 8], data: &[u8], kind: ParseFailure, reason: impl ToString) -> Self {
+        Self {
+            program,
+            discriminator,
+            data_len: data.len(),
+            kind,
+            reason: reason.to_string(),
+        }
+    }
+
+    // borsh reports a body that ends early as an unexpected length
+    fn from_borsh(program: Program, discriminator: [u8; 8], data: &[u8], error: std::io::Error) -> Self {
+        let undersized = error.kind() == std::io::ErrorKind::UnexpectedEof
+            || error.to_string().contains("Unexpected length of input");
+        let kind = if undersized { ParseFailure::Undersized } else { ParseFailure::Borsh };
+        Self::new(program, discriminator, data, kind, error)
+    }
+}
+
+// event body of emit_cpi data: instruction discriminator, event discriminator, event
+fn event_body<'a>(data: &'a [u8], discriminator: &[u8; 8]) -> Option<&'a [u8]> {
+    (data.len() >= 16 && data[8..16].eq(discriminator)).then(|| &data[16..])
+}
+
+fn decode_event<T: BorshDeserialize>(
+    program: Program,
+    discriminator: [u8; 8],
+    data: &[u8],
+) -> Result<Option<T>, ParseError> {
+    match event_body(data, &discriminator) {
+        Some(body) => T::try_from_slice(body)
+            .map(Some)
+            .map_err(|e| ParseError::from_borsh(program, discriminator, data, e)),
+        None => Ok(None),
+    }
+}
+
+/// pump.fun create event before the creator fee update
+pub type CreateEventV1 = idl::pump::CreateEvent;
+
+/// pump.fun create event with the coin creator, who receives the creator fees
+#[derive(Debug, Clone, BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
+pub struct CreateEventV2 {
     pub name: String,
     pub symbol: String,
     pub uri: String,
+    #[serde(with = "pubkey_b58")]
     pub mint: Pubkey,
+    #[serde(with = "pubkey_b58")]
     pub bonding_curve: Pubkey,
+    #[serde(with = "pubkey_b58")]
     pub user: Pubkey,
+    #[serde(with = "pubkey_b58")]
+    pub creator: Pubkey,
 }
 
-impl CreateEvent {
-    pub fn try_from_compiled_instruction(
-        ui_compiled_instruction: &UiCompiledInstruction,
-    ) -> Option<CreateEvent> {
-        let data = match bs58::decode(ui_compiled_instruction.data.clone()).into_vec() {
-            Ok(d) => d,
-            Err(_) => return None,
-        }; 
-        
-        if data.len() < 16 {
-            return None;
+/// Latest create event layout, older layouts are converted into it
+pub type CreateEvent = CreateEventV2;
+
+impl From<CreateEventV1> for CreateEventV2 {
+    fn from(event: CreateEventV1) -> Self {
+        Self {
+            name: event.name,
+            symbol: event.symbol,
+            uri: event.uri,
+            mint: event.mint,
+            bonding_curve: event.bonding_curve,
+            // before creator fees the launching user was the creator
+            creator: event.user,
+            user: event.user,
         }
-        
-        if data[8..16].eq(&PUMPFUN_CREATE_EVENT) {
-            if let Ok(event) = CreateEvent::try_from_slice(&data[16..]) {
-                // println!("create event: {:?}", event);
-                return Some(event);
-            }
-            Self::try_manual_parse(&data)
-        } else {
+    }
+}
+
+#[derive(Debug, BorshSerialize, Clone, BorshDeserialize)]
+pub struct CreateArgs {
+    pub name: String,
+    pub symbol: String,
+    pub uri: String,
+}
+
+impl CreateEventV2 {
+    /// The launch from a top-level `create` instruction, for feeds without inner instructions
+    // accounts: mint, mint_authority, bonding_curve, associated_bonding_curve, global, mpl_token_metadata, metadata, user, ...
+    pub fn try_from_instruction(accounts: &[Pubkey], data: &[u8]) -> Option<CreateEvent> {
+        if data.len() < 8 || accounts.len() < 8 || !data[..8].eq(&CREATE_IX) {
             return None;
         }
+        let mut args = &data[8..];
+        let CreateArgs { name, symbol, uri } = <CreateArgs as BorshDeserialize>::deserialize(&mut args).ok()?;
+        let user = accounts[7];
+        // the creator argument came with creator fees, before it the user was the creator
+        let creator = <Pubkey as BorshDeserialize>::deserialize(&mut args).unwrap_or(user);
+        Some(CreateEvent { name, symbol, uri, mint: accounts[0], bonding_curve: accounts[2], user, creator })
+    }
+
+    pub fn try_from_data(data: &[u8]) -> Result<Option<CreateEvent>, ParseError> {
+        let Some(body) = event_body(data, &PUMPFUN_CREATE_EVENT) else {
+            return Ok(None);
+        };
+        if let Ok(event) = CreateEventV2::try_from_slice(body) {
+            return Ok(Some(event));
+        }
+        if let Ok(event) = CreateEventV1::try_from_slice(body) {
+            return Ok(Some(event.into()));
+        }
+        Self::try_manual_parse(data)
+            .map(Some)
+            .ok_or_else(|| {
+                ParseError::new(Program::Pumpfun, PUMPFUN_CREATE_EVENT, data, ParseFailure::Borsh, "no create event layout matches")
+            })
     }
     
     fn try_manual_parse(data: &[u8]) -> Option<Self> {
@@ -150,6 +578,13 @@ impl CreateEvent {
             Ok(u) => u,
             Err(_) => return None,
         };
+        offset += 32;
+
+        // v2 events carry the creator after the user
+        let creator = data
+            .get(offset..offset + 32)
+            .and_then(|bytes| Pubkey::try_from_slice(bytes).ok())
+            .unwrap_or(user);
         
         Some(Self {
             name,
@@ -158,6 +593,7 @@ impl CreateEvent {
             mint,
             bonding_curve,
             user,
+            creator,
         })
     }
     
@@ -185,271 +621,961 @@ impl CreateEvent {
     }
 }
 
-#[derive(Debug, BorshSerialize, Clone, BorshDeserialize, Copy)]
-pub struct CompleteEvent {
+pub type CompleteEvent = idl::pump::CompleteEvent;
+
+impl CompleteEvent {
+    pub fn try_from_data(data: &[u8]) -> Result<Option<CompleteEvent>, ParseError> {
+        decode_event(Program::Pumpfun, PUMPFUN_COMPLETE_EVENT, data)
+    }
+}
+
+pub type SetParamsEvent = idl::pump::SetParamsEvent;
+
+impl SetParamsEvent {
+    pub fn try_from_data(data: &[u8]) -> Result<Option<SetParamsEvent>, ParseError> {
+        decode_event(Program::Pumpfun, PUMPFUN_SET_PARAMS_EVENT, data)
+    }
+}
+
+#[derive(Debug, BorshSerialize, Clone, BorshDeserialize, Serialize, Deserialize)]
+pub struct AMMBuyArgs {
+    pub base_amount_out: u64,
+    pub max_quote_amount_in: u64,
+}
+
+#[derive(Debug, BorshSerialize, Clone, BorshDeserialize, Serialize, Deserialize)]
+pub struct AMMSellArgs {
+    pub base_amount_in: u64,
+    pub min_quote_amount_out: u64,
+}
+
+/// PumpSwap `buy` / `sell` instruction
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct AMMSwapInstruction {
+    pub is_buy: bool,
+    #[serde(with = "pubkey_b58")]
+    pub pool: Pubkey,
+    #[serde(with = "pubkey_b58")]
     pub user: Pubkey,
-    pub mint: Pubkey,
-    pub bonding_curve: Pubkey,
-    pub timestamp: i64,
+    #[serde(with = "pubkey_b58")]
+    pub base_mint: Pubkey,
+    #[serde(with = "pubkey_b58")]
+    pub quote_mint: Pubkey,
+    /// base tokens bought or sold
+    pub base_amount: u64,
+    /// max quote in of a buy, min quote out of a sell
+    pub quote_limit: u64,
 }
 
-impl CompleteEvent {
-    pub fn try_from_compiled_instruction(
-        ui_compiled_instruction: &UiCompiledInstruction,
-    ) -> Option<CompleteEvent> {
-        let data = bs58::decode(ui_compiled_instruction.data.clone())
-            .into_vec()
-            .unwrap();
-        if data.len() > 16 && data[8..16].eq(&PUMPFUN_COMPLETE_EVENT) {
-            match CompleteEvent::try_from_slice(&data[16..]) {
-                Ok(event) => return Some(event),
-                Err(_) => return None,
-            }
-        } else {
+impl AMMSwapInstruction {
+    // accounts: pool, user, global_config, base_mint, quote_mint, ...
+    pub fn try_from_instruction(accounts: &[Pubkey], data: &[u8]) -> Option<AMMSwapInstruction> {
+        if data.len() < 8 || accounts.len() < 5 {
             return None;
         }
+        let mut args = &data[8..];
+        let (is_buy, base_amount, quote_limit) = if data[..8].eq(&BUY_IX) {
+            let args = <AMMBuyArgs as BorshDeserialize>::deserialize(&mut args).ok()?;
+            (true, args.base_amount_out, args.max_quote_amount_in)
+        } else if data[..8].eq(&SELL_IX) {
+            let args = <AMMSellArgs as BorshDeserialize>::deserialize(&mut args).ok()?;
+            (false, args.base_amount_in, args.min_quote_amount_out)
+        } else {
+            return None;
+        };
+        Some(Self {
+            is_buy,
+            pool: accounts[0],
+            user: accounts[1],
+            base_mint: accounts[3],
+            quote_mint: accounts[4],
+            base_amount,
+            quote_limit,
+        })
     }
 }
 
+/// pump.fun `CompletePumpAmmMigrationEvent`, the graduated curve's liquidity moved to a PumpSwap pool
+#[derive(Debug, BorshSerialize, Clone, BorshDeserialize, Copy, Serialize, Deserialize)]
+pub struct MigrateEvent {
+    #[serde(with = "pubkey_b58")]
+    pub user: Pubkey,
+    #[serde(with = "pubkey_b58")]
+    pub mint: Pubkey,
+    pub mint_amount: u64,
+    pub sol_amount: u64,
+    pub pool_migration_fee: u64,
+    #[serde(with = "pubkey_b58")]
+    pub bonding_curve: Pubkey,
+    pub timestamp: i64,
+    #[serde(with = "pubkey_b58")]
+    pub pool: Pubkey,
+}
+
+impl MigrateEvent {
+    pub fn try_from_data(data: &[u8]) -> Result<Option<MigrateEvent>, ParseError> {
+        decode_event(Program::Pumpfun, PUMPFUN_MIGRATE_EVENT, data)
+    }
+}
 
-#[derive(Debug, BorshSerialize, Clone, BorshDeserialize)]
+#[derive(Debug, BorshSerialize, Clone, BorshDeserialize, Serialize, Deserialize)]
 pub struct BuyArgs {
     pub amount: u64,
     pub max_sol_cost: u64,
 }
 
-#[derive(Debug, BorshSerialize, Clone, BorshDeserialize, Copy)]
-pub struct TradeEvent {
+#[derive(Debug, BorshSerialize, Clone, BorshDeserialize, Serialize, Deserialize)]
+pub struct SellArgs {
+    pub amount: u64,
+    pub min_sol_output: u64,
+}
+
+/// pump.fun `buy` / `sell` instruction
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct TradeInstruction {
+    pub is_buy: bool,
+    #[serde(with = "pubkey_b58")]
+    pub mint: Pubkey,
+    #[serde(with = "pubkey_b58")]
+    pub bonding_curve: Pubkey,
+    #[serde(with = "pubkey_b58")]
+    pub user: Pubkey,
+    /// tokens bought or sold
+    pub token_amount: u64,
+    /// max sol cost of a buy, min sol output of a sell
+    pub sol_limit: u64,
+}
+
+impl TradeInstruction {
+    // accounts: global, fee_recipient, mint, bonding_curve, associated_bonding_curve, associated_user, user, ...
+    pub fn try_from_instruction(accounts: &[Pubkey], data: &[u8]) -> Option<TradeInstruction> {
+        if data.len() < 8 || accounts.len() < 7 {
+            return None;
+        }
+        // newer versions append optional args, only the leading ones are read
+        let mut args = &data[8..];
+        let (is_buy, token_amount, sol_limit) = if data[..8].eq(&BUY_IX) {
+            let args = <BuyArgs as BorshDeserialize>::deserialize(&mut args).ok()?;
+            (true, args.amount, args.max_sol_cost)
+        } else if data[..8].eq(&SELL_IX) {
+            let args = <SellArgs as BorshDeserialize>::deserialize(&mut args).ok()?;
+            (false, args.amount, args.min_sol_output)
+        } else {
+            return None;
+        };
+        Some(Self {
+            is_buy,
+            mint: accounts[2],
+            bonding_curve: accounts[3],
+            user: accounts[6],
+            token_amount,
+            sol_limit,
+        })
+    }
+}
+
+/// pump.fun trade event before the creator fee update
+pub type TradeEventV1 = idl::pump::TradeEvent;
+
+/// pump.fun trade event with protocol and creator fees
+#[derive(Debug, BorshSerialize, Clone, BorshDeserialize, Copy, Serialize, Deserialize)]
+pub struct TradeEventV2 {
+    #[serde(with = "pubkey_b58")]
     pub mint: Pubkey,
     pub sol_amount: u64,
     pub token_amount: u64,
     pub is_buy: bool,
+    #[serde(with = "pubkey_b58")]
     pub user: Pubkey,
     pub timestamp: i64,
     pub virtual_sol_reserves: u64,
     pub virtual_token_reserves: u64,
-    pub real_sol_reserves: u64, 
+    pub real_sol_reserves: u64,
     pub real_token_reserves: u64,
+    #[serde(with = "pubkey_b58")]
+    pub fee_recipient: Pubkey,
+    pub fee_basis_points: u64,
+    pub fee: u64,
+    #[serde(with = "pubkey_b58")]
+    pub creator: Pubkey,
+    pub creator_fee_basis_points: u64,
+    pub creator_fee: u64,
 }
 
-impl TradeEvent {
-    pub fn try_from_compiled_instruction(
-        ui_compiled_instruction: &UiCompiledInstruction,
-    ) -> Option<TradeEvent> {
-        let data = bs58::decode(ui_compiled_instruction.data.clone())
-            .into_vec()
-            .unwrap();
-        if data.len() > 16 && data[8..16].eq(&PUMPFUN_TRADE_EVENT) {
-            match TradeEvent::try_from_slice(&data[16..]) {
-                Ok(event) => return Some(event),
-                Err(_) => return None,
-            }
-        } else {
-            return None;
+/// Latest trade event layout, older layouts are converted into it
+pub type TradeEvent = TradeEventV2;
+
+impl From<TradeEventV1> for TradeEventV2 {
+    fn from(event: TradeEventV1) -> Self {
+        // fees were not reported before v2
+        Self {
+            mint: event.mint,
+            sol_amount: event.sol_amount,
+            token_amount: event.token_amount,
+            is_buy: event.is_buy,
+            user: event.user,
+            timestamp: event.timestamp,
+            virtual_sol_reserves: event.virtual_sol_reserves,
+            virtual_token_reserves: event.virtual_token_reserves,
+            real_sol_reserves: event.real_sol_reserves,
+            real_token_reserves: event.real_token_reserves,
+            fee_recipient: Pubkey::default(),
+            fee_basis_points: 0,
+            fee: 0,
+            creator: Pubkey::default(),
+            creator_fee_basis_points: 0,
+            creator_fee: 0,
         }
     }
 }
 
-#[derive(Debug, BorshSerialize, Clone, BorshDeserialize, Copy)]
-pub struct AMMBuyEvent {
-    pub timestamp: i64,
-    pub base_amount_out: u64,
-    pub max_quote_amount_in: u64,
-    pub user_base_token_reserves: u64,
-    pub user_quote_token_reserves: u64,
-    pub pool_base_token_reserves: u64,
-    pub pool_quote_token_reserves: u64,
-    pub quote_amount_in: u64,
-    pub lp_fee_basis_points: u64,
-    pub lp_fee: u64,
-    pub protocol_fee_basis_points: u64,
-    pub protocol_fee: u64,
-    pub quote_amount_in_with_lp_fee: u64,
-    pub user_quote_amount_in: u64,
-    pub pool: Pubkey, 
-    pub user: Pubkey,
-    pub user_base_token_account: Pubkey,
-    pub user_quote_token_account: Pubkey,
-    pub protocol_fee_recipient: Pubkey,
-    pub protocol_fee_recipient_token_account: Pubkey,
-} 
+impl TradeEventV2 {
+    pub fn try_from_data(data: &[u8]) -> Result<Option<TradeEvent>, ParseError> {
+        let Some(body) = event_body(data, &PUMPFUN_TRADE_EVENT) else {
+            return Ok(None);
+        };
+        Self::from_event_data(body)
+            .map(Some)
+            .ok_or_else(|| {
+                ParseError::new(Program::Pumpfun, PUMPFUN_TRADE_EVENT, data, ParseFailure::Borsh, "no trade event layout matches")
+            })
+    }
 
-impl AMMBuyEvent {
-    pub fn try_from_compiled_instruction(
-        ui_compiled_instruction: &UiCompiledInstruction,
-    ) -> Option<AMMBuyEvent> {
-        let data = bs58::decode(ui_compiled_instruction.data.clone())
-            .into_vec()
-            .unwrap();
-        if data.len() > 16 && data[8..16].eq(&PUMPAMM_BUY_EVENT) {
-            match AMMBuyEvent::try_from_slice(&data[16..]) {
-                Ok(event) => return Some(event),
-                Err(_) => return None,
-            }
-        } else {
-            return None;
+    /// Decode the event body, newest layout first so a v2 event is never read as a v1 one;
+    /// bytes after the known fields (added by a program upgrade) are ignored
+    pub fn from_event_data(data: &[u8]) -> Option<TradeEvent> {
+        if let Ok(event) = <TradeEventV2 as BorshDeserialize>::deserialize(&mut &data[..]) {
+            return Some(event);
         }
+        <TradeEventV1 as BorshDeserialize>::deserialize(&mut &data[..]).ok().map(TradeEvent::from)
     }
 }
 
-#[derive(Debug, BorshSerialize, Clone, BorshDeserialize, Copy)]
-pub struct AMMSellEvent {
-    pub timestamp: i64,
-    pub base_amount_in: u64,
-    pub min_quote_amount_out: u64,
-    pub user_base_token_reserves: u64,
-    pub user_quote_token_reserves: u64, 
-    pub pool_base_token_reserves: u64,
-    pub pool_quote_token_reserves: u64,
-    pub quote_amount_out: u64,
-    pub lp_fee_basis_points: u64,
-    pub lp_fee: u64,
-    pub protocol_fee_basis_points: u64,
-    pub protocol_fee: u64,
-    pub quote_amount_out_without_lp_fee: u64,
-    pub user_quote_amount_out: u64,
-    pub pool: Pubkey,
-    pub user: Pubkey,
-    pub user_base_token_account: Pubkey,
-    pub user_quote_token_account: Pubkey,
-    pub protocol_fee_recipient: Pubkey,
-    pub protocol_fee_recipient_token_account: Pubkey,
+pub type AMMBuyEvent = idl::pump_amm::BuyEvent;
+
+impl AMMBuyEvent {
+    pub fn try_from_data(data: &[u8]) -> Result<Option<AMMBuyEvent>, ParseError> {
+        decode_event(Program::Pumpamm, PUMPAMM_BUY_EVENT, data)
+    }
 }
 
+pub type AMMSellEvent = idl::pump_amm::SellEvent;
+
 impl AMMSellEvent {
-    pub fn try_from_compiled_instruction(
-        ui_compiled_instruction: &UiCompiledInstruction,
-    ) -> Option<AMMSellEvent> {
-        let data = bs58::decode(ui_compiled_instruction.data.clone())
-            .into_vec()
-            .unwrap();
-        if data.len() > 16 && data[8..16].eq(&PUMPAMM_SELL_EVENT) {
-            match AMMSellEvent::try_from_slice(&data[16..]) {
-                Ok(event) => return Some(event),
-                Err(_) => return None,
-            }
-        } else {
+    pub fn try_from_data(data: &[u8]) -> Result<Option<AMMSellEvent>, ParseError> {
+        decode_event(Program::Pumpamm, PUMPAMM_SELL_EVENT, data)
+    }
+}
+
+pub type AMMDepositEvent = idl::pump_amm::DepositEvent;
+
+impl AMMDepositEvent {
+    pub fn try_from_data(data: &[u8]) -> Result<Option<AMMDepositEvent>, ParseError> {
+        decode_event(Program::Pumpamm, PUMPAMM_DEPOSIT_EVENT, data)
+    }
+}
+
+pub type AMMWithdrawEvent = idl::pump_amm::WithdrawEvent;
+
+impl AMMWithdrawEvent {
+    pub fn try_from_data(data: &[u8]) -> Result<Option<AMMWithdrawEvent>, ParseError> {
+        decode_event(Program::Pumpamm, PUMPAMM_WITHDRAW_EVENT, data)
+    }
+}
+
+pub type AMMCreatePoolEvent = idl::pump_amm::CreatePoolEvent;
+
+impl AMMCreatePoolEvent {
+    pub fn try_from_data(data: &[u8]) -> Result<Option<AMMCreatePoolEvent>, ParseError> {
+        decode_event(Program::Pumpamm, PUMPAMM_CREATE_POOL_EVENT, data)
+    }
+}   
+
+#[derive(Debug, BorshSerialize, Clone, BorshDeserialize, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MoonshotTradeType {
+    Buy,
+    Sell,
+}
+
+#[derive(Debug, BorshSerialize, Clone, BorshDeserialize, Serialize, Deserialize)]
+pub struct MoonshotTradeEvent {
+    /// tokens bought or sold
+    pub amount: u64,
+    /// lamports paid or received
+    pub collateral_amount: u64,
+    pub dex_fee: u64,
+    pub helio_fee: u64,
+    pub allocation: u64,
+    #[serde(with = "pubkey_b58")]
+    pub curve: Pubkey,
+    #[serde(with = "pubkey_b58")]
+    pub cost_token: Pubkey,
+    #[serde(with = "pubkey_b58")]
+    pub sender: Pubkey,
+    pub trade_type: MoonshotTradeType,
+    pub label: String,
+}
+
+impl MoonshotTradeEvent {
+    pub fn try_from_data(data: &[u8]) -> Result<Option<MoonshotTradeEvent>, ParseError> {
+        decode_event(Program::Moonshot, MOONSHOT_TRADE_EVENT, data)
+    }
+}
+
+/// Curve graduation, liquidity moves to the DEX
+#[derive(Debug, BorshSerialize, Clone, BorshDeserialize, Serialize, Deserialize)]
+pub struct MoonshotMigrationEvent {
+    pub tokens_migrated: u64,
+    pub tokens_burned: u64,
+    pub collateral_migrated: u64,
+    pub fee: u64,
+    pub label: String,
+}
+
+impl MoonshotMigrationEvent {
+    pub fn try_from_data(data: &[u8]) -> Result<Option<MoonshotMigrationEvent>, ParseError> {
+        decode_event(Program::Moonshot, MOONSHOT_MIGRATION_EVENT, data)
+    }
+}
+
+#[derive(Debug, BorshSerialize, Clone, BorshDeserialize)]
+pub struct MoonshotTokenMintArgs {
+    pub name: String,
+    pub symbol: String,
+    pub uri: String,
+    pub decimals: u8,
+    pub collateral_currency: u8,
+    pub amount: u64,
+    pub curve_type: u8,
+    pub migration_target: u8,
+}
+
+/// Moonshot `token_mint` instruction
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MoonshotCreateInstruction {
+    pub name: String,
+    pub symbol: String,
+    pub uri: String,
+    #[serde(with = "pubkey_b58")]
+    pub mint: Pubkey,
+    #[serde(with = "pubkey_b58")]
+    pub curve: Pubkey,
+    #[serde(with = "pubkey_b58")]
+    pub sender: Pubkey,
+    pub decimals: u8,
+    /// total supply minted to the curve
+    pub amount: u64,
+}
+
+impl MoonshotCreateInstruction {
+    // accounts: sender, backend_authority, curve_account, mint, mint_metadata, curve_token_account, config_account, ...
+    pub fn try_from_instruction(accounts: &[Pubkey], data: &[u8]) -> Option<MoonshotCreateInstruction> {
+        if data.len() < 8 || accounts.len() < 6 || !data[..8].eq(&MOONSHOT_TOKEN_MINT_IX) {
             return None;
         }
+        let args = <MoonshotTokenMintArgs as BorshDeserialize>::deserialize(&mut &data[8..]).ok()?;
+        Some(MoonshotCreateInstruction {
+            name: args.name,
+            symbol: args.symbol,
+            uri: args.uri,
+            mint: accounts[3],
+            curve: accounts[2],
+            sender: accounts[0],
+            decimals: args.decimals,
+            amount: args.amount,
+        })
+    }
+
+    /// Launch in the create event layout shared by the token records
+    pub fn to_create_event(&self) -> CreateEvent {
+        CreateEvent {
+            name: self.name.clone(),
+            symbol: self.symbol.clone(),
+            uri: self.uri.clone(),
+            mint: self.mint,
+            bonding_curve: self.curve,
+            user: self.sender,
+            creator: self.sender,
+        }
     }
 }
 
-#[derive(Debug, BorshSerialize, Clone, BorshDeserialize, Copy)]
-pub struct AMMDepositEvent {
-    pub timestamp: i64,
-    pub lp_token_amount_out: u64,
-    pub max_base_amount_in: u64,
-    pub max_quote_amount_in: u64,
-    pub user_base_token_reserves: u64,
-    pub user_quote_token_reserves: u64,
-    pub pool_base_token_reserves: u64,
-    pub pool_quote_token_reserves: u64,
-    pub base_amount_in: u64,
-    pub quote_amount_in: u64,
-    pub lp_mint_supply: u64,
-    pub pool: Pubkey,
+/// Boop.fun `TokenCreated`
+#[derive(Debug, BorshSerialize, Clone, BorshDeserialize, Serialize, Deserialize)]
+pub struct BoopCreateEvent {
+    #[serde(with = "pubkey_b58")]
+    pub mint: Pubkey,
+    pub name: String,
+    pub symbol: String,
+    pub uri: String,
+}
+
+impl BoopCreateEvent {
+    pub fn try_from_data(data: &[u8]) -> Result<Option<BoopCreateEvent>, ParseError> {
+        decode_event(Program::Boop, BOOP_TOKEN_CREATED_EVENT, data)
+    }
+
+    /// The launch as a pump.fun style create event, the event itself names neither creator nor curve
+    pub fn to_create_event(&self, creator: Pubkey) -> CreateEvent {
+        CreateEvent {
+            name: self.name.clone(),
+            symbol: self.symbol.clone(),
+            uri: self.uri.clone(),
+            mint: self.mint,
+            bonding_curve: find_boop_bonding_curve(&self.mint),
+            user: creator,
+            creator,
+        }
+    }
+}
+
+/// Boop.fun `TokenBought` / `TokenSold`, both events share this layout
+#[derive(Debug, BorshSerialize, Clone, BorshDeserialize, Copy, Serialize, Deserialize)]
+pub struct BoopTradeEvent {
+    #[serde(with = "pubkey_b58")]
+    pub mint: Pubkey,
+    /// lamports for a buy, tokens for a sell
+    pub amount_in: u64,
+    /// tokens for a buy, lamports for a sell
+    pub amount_out: u64,
+    pub swap_fee: u64,
+    /// buyer or seller
+    #[serde(with = "pubkey_b58")]
     pub user: Pubkey,
-    pub user_base_token_account: Pubkey,
-    pub user_quote_token_account: Pubkey,
-    pub user_pool_token_account: Pubkey,
+    #[serde(with = "pubkey_b58")]
+    pub recipient: Pubkey,
 }
 
-impl AMMDepositEvent {
-    pub fn try_from_compiled_instruction(
-        ui_compiled_instruction: &UiCompiledInstruction,
-    ) -> Option<AMMDepositEvent> {
-        let data = bs58::decode(ui_compiled_instruction.data.clone())
-            .into_vec()
-            .unwrap();
-        if data.len() > 16 && data[8..16].eq(&PUMPAMM_DEPOSIT_EVENT) {
-            match AMMDepositEvent::try_from_slice(&data[16..]) {
-                Ok(event) => return Some(event),
-                Err(_) => return None,
-            }
+impl BoopTradeEvent {
+    /// Tokens that left the curve, negative for a sell
+    pub fn tokens_bought(&self, is_buy: bool) -> i64 {
+        if is_buy {
+            self.amount_out as i64
         } else {
-            return None;
+            -(self.amount_in as i64)
+        }
+    }
+
+    pub fn try_from_data(data: &[u8]) -> Result<Option<TargetEvent>, ParseError> {
+        if let Some(trade) = decode_event(Program::Boop, BOOP_TOKEN_BOUGHT_EVENT, data)? {
+            return Ok(Some(TargetEvent::BoopBuy(trade)));
         }
+        Ok(decode_event(Program::Boop, BOOP_TOKEN_SOLD_EVENT, data)?.map(TargetEvent::BoopSell))
     }
 }
 
-#[derive(Debug, BorshSerialize, Clone, BorshDeserialize, Copy)]
-pub struct AMMWithdrawEvent {
-    pub timestamp: i64,
-    pub lp_token_amount_in: u64,
-    pub min_base_amount_out: u64,
-    pub min_quote_amount_out: u64,
-    pub user_base_token_reserves: u64,
-    pub user_quote_token_reserves: u64,
-    pub pool_base_token_reserves: u64,
-    pub pool_quote_token_reserves: u64,
-    pub base_amount_out: u64,
-    pub quote_amount_out: u64,
-    pub lp_mint_supply: u64,
-    pub pool: Pubkey,
-    pub user: Pubkey,
-    pub user_base_token_account: Pubkey,
-    pub user_quote_token_account: Pubkey,
-    pub user_pool_token_account: Pubkey,
+// SPL Token instruction tags
+const TOKEN_TRANSFER_IX: u8 = 3;
+const TOKEN_MINT_TO_IX: u8 = 7;
+const TOKEN_TRANSFER_CHECKED_IX: u8 = 12;
+const TOKEN_MINT_TO_CHECKED_IX: u8 = 14;
+
+/// `Transfer`, `TransferChecked`, `MintTo` or `MintToChecked` of the SPL Token program
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TokenInstruction {
+    /// source token account, `None` for mints
+    pub source: Option<Pubkey>,
+    /// destination token account
+    pub destination: Pubkey,
+    /// only the checked and mint instructions name the mint
+    pub mint: Option<Pubkey>,
+    pub amount: u64,
 }
 
-impl AMMWithdrawEvent {
-    pub fn try_from_compiled_instruction(
-        ui_compiled_instruction: &UiCompiledInstruction,
-    ) -> Option<AMMWithdrawEvent> {
-        let data = bs58::decode(ui_compiled_instruction.data.clone())
+impl TokenInstruction {
+    /// Decode raw instruction data, `accounts` are the keys of the instruction's accounts
+    pub fn decode(accounts: &[Pubkey], data: &[u8]) -> Option<Self> {
+        let (tag, rest) = data.split_first()?;
+        let amount = u64::from_le_bytes(rest.get(..8)?.try_into().ok()?);
+        let (source, destination, mint) = match *tag {
+            TOKEN_TRANSFER_IX => (Some(*accounts.first()?), *accounts.get(1)?, None),
+            TOKEN_TRANSFER_CHECKED_IX => (Some(*accounts.first()?), *accounts.get(2)?, Some(*accounts.get(1)?)),
+            TOKEN_MINT_TO_IX | TOKEN_MINT_TO_CHECKED_IX => (None, *accounts.get(1)?, Some(*accounts.first()?)),
+            _ => return None,
+        };
+        Some(Self { source, destination, mint, amount })
+    }
+
+    /// `decode` for base58 or base64 encoded data
+    pub fn decode_str(accounts: &[Pubkey], data: &str) -> Option<Self> {
+        bs58::decode(data)
             .into_vec()
-            .unwrap();
-        if data.len() > 16 && data[8..16].eq(&PUMPAMM_WITHDRAW_EVENT) {
-            match AMMWithdrawEvent::try_from_slice(&data[16..]) {
-                Ok(event) => return Some(event),
-                Err(_) => return None,
+            .ok()
+            .and_then(|bytes| Self::decode(accounts, &bytes))
+            .or_else(|| STANDARD.decode(data).ok().and_then(|bytes| Self::decode(accounts, &bytes)))
+    }
+}
+
+/// Token movement with the owners and balances after the transaction, for holder tracking
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct TokenTransfer {
+    /// launchpad whose instruction moved the tokens
+    pub program: Program,
+    #[serde(with = "pubkey_b58")]
+    pub mint: Pubkey,
+    /// default for mints
+    #[serde(with = "pubkey_b58")]
+    pub source_owner: Pubkey,
+    pub source_balance: u64,
+    #[serde(with = "pubkey_b58")]
+    pub destination_owner: Pubkey,
+    pub destination_balance: u64,
+    pub amount: u64,
+}
+
+// ComputeBudget instruction tags and limits
+const SET_COMPUTE_UNIT_LIMIT_IX: u8 = 2;
+const SET_COMPUTE_UNIT_PRICE_IX: u8 = 3;
+const DEFAULT_INSTRUCTION_COMPUTE_UNITS: u32 = 200_000;
+const MAX_COMPUTE_UNITS: u32 = 1_400_000;
+
+/// Compute unit limit and price a transaction requested
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ComputeBudget {
+    pub unit_limit: u32,
+    /// micro-lamports per compute unit
+    pub unit_price: u64,
+}
+
+impl ComputeBudget {
+    /// Read the budget from the top-level instructions, given as (program id, data)
+    pub fn from_instructions<'a>(instructions: impl IntoIterator<Item = (&'a Pubkey, &'a [u8])>) -> Self {
+        let mut budget = ComputeBudget::default();
+        let mut unit_limit = None;
+        let mut other_instructions = 0u32;
+        for (program_id, data) in instructions {
+            if *program_id != COMPUTE_BUDGET_PROGRAM_ID {
+                other_instructions += 1;
+                continue;
+            }
+            match data.split_first() {
+                Some((&SET_COMPUTE_UNIT_LIMIT_IX, rest)) => {
+                    unit_limit = rest.get(..4).and_then(|b| b.try_into().ok()).map(u32::from_le_bytes);
+                }
+                Some((&SET_COMPUTE_UNIT_PRICE_IX, rest)) => {
+                    budget.unit_price = rest.get(..8).and_then(|b| b.try_into().ok()).map(u64::from_le_bytes).unwrap_or_default();
+                }
+                _ => {}
             }
-        } else {
-            return None;
         }
+        // without a limit every other instruction gets the default units
+        budget.unit_limit = unit_limit
+            .unwrap_or(other_instructions.saturating_mul(DEFAULT_INSTRUCTION_COMPUTE_UNITS))
+            .min(MAX_COMPUTE_UNITS);
+        budget
+    }
+
+    /// Priority fee in lamports on top of the base fee
+    pub fn priority_fee(&self) -> u64 {
+        ((self.unit_limit as u128 * self.unit_price as u128).div_ceil(1_000_000)) as u64
     }
 }
 
-#[derive(Debug, BorshSerialize, Clone, BorshDeserialize, Copy)]
-pub struct AMMCreatePoolEvent {
-    pub timestamp: i64,
-    pub index: u16,
-    pub creator: Pubkey,
-    pub base_mint: Pubkey,
-    pub quote_mint: Pubkey,
-    pub base_mint_decimals: u8,
-    pub quote_mint_decimals: u8,
-    pub base_amount_in: u64,
-    pub quote_amount_in: u64,
-    pub pool_base_amount: u64,
-    pub pool_quote_amount: u64,
-    pub minimum_liquidity: u64,
-    pub initial_liquidity: u64,
-    pub lp_token_amount_out: u64,
-    pub pool_bump: u8,
-    pub pool: Pubkey,
-    pub lp_mint: Pubkey,
-    pub user_base_token_account: Pubkey,
-    pub user_quote_token_account: Pubkey,
+// raw bytes as a base64 string
+mod bytes_b64 {
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&STANDARD.encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        STANDARD.decode(s).map_err(D::Error::custom)
+    }
 }
 
-impl AMMCreatePoolEvent {
-    pub fn try_from_compiled_instruction(
-        ui_compiled_instruction: &UiCompiledInstruction,
-    ) -> Option<AMMCreatePoolEvent> {
-        let data = bs58::decode(ui_compiled_instruction.data.clone())
-            .into_vec()
-            .unwrap();
-        if data.len() > 16 && data[8..16].eq(&PUMPAMM_CREATE_POOL_EVENT) {
-            match AMMCreatePoolEvent::try_from_slice(&data[16..]) {
-                Ok(event) => return Some(event),
-                Err(_) => return None,
+// pubkeys as base58 strings instead of byte arrays
+mod pubkey_b58 {
+    use std::str::FromStr;
+
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+    use solana_sdk::pubkey::Pubkey;
+
+    pub fn serialize<S: Serializer>(pubkey: &Pubkey, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(pubkey)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Pubkey, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Pubkey::from_str(&s).map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use solana_transaction_status::{UiCompiledInstruction, UiPartiallyDecodedInstruction};
+
+    use super::*;
+
+    fn event_ix(discriminator: [u8; 8], event: impl BorshSerialize) -> UiInstruction {
+        // emit_cpi data: instruction discriminator, event discriminator, event
+        let mut data = vec![0u8; 8];
+        data.extend_from_slice(&discriminator);
+        data.extend(borsh::to_vec(&event).unwrap());
+        UiInstruction::Compiled(UiCompiledInstruction {
+            program_id_index: 0,
+            accounts: vec![],
+            data: bs58::encode(data).into_string(),
+            stack_height: None,
+        })
+    }
+
+    #[test]
+    fn moonshot_trade_test() {
+        let trade = MoonshotTradeEvent {
+            amount: 1_000_000_000,
+            collateral_amount: 30_000,
+            dex_fee: 0,
+            helio_fee: 0,
+            allocation: 0,
+            curve: Pubkey::new_unique(),
+            cost_token: Pubkey::new_unique(),
+            sender: Pubkey::new_unique(),
+            trade_type: MoonshotTradeType::Sell,
+            label: "".to_string(),
+        };
+        match TargetEvent::parse_instruction(Program::Moonshot, &event_ix(MOONSHOT_TRADE_EVENT, trade.clone())).unwrap().unwrap() {
+            TargetEvent::MoonshotSell(parsed) => assert_eq!(parsed.curve, trade.curve),
+            other => panic!("unexpected event {:?}", other),
+        }
+
+        // a pump.fun trade with the same discriminator is read by the emitting program
+        let pumpfun = TradeEvent {
+            mint: Pubkey::new_unique(),
+            sol_amount: 1,
+            token_amount: 1,
+            is_buy: true,
+            user: Pubkey::new_unique(),
+            timestamp: 0,
+            virtual_sol_reserves: 1,
+            virtual_token_reserves: 1,
+            real_sol_reserves: 1,
+            real_token_reserves: 1,
+            fee_recipient: Pubkey::new_unique(),
+            fee_basis_points: 95,
+            fee: 1,
+            creator: Pubkey::new_unique(),
+            creator_fee_basis_points: 5,
+            creator_fee: 1,
+        };
+        assert!(matches!(
+            TargetEvent::parse_instruction(Program::Pumpfun, &event_ix(PUMPFUN_TRADE_EVENT, pumpfun)).unwrap().unwrap(),
+            TargetEvent::PumpfunBuy(_)
+        ));
+        // any other program emitting the discriminator is not parsed
+        assert!(TargetEvent::parse_instruction(Program::Boop, &event_ix(PUMPFUN_TRADE_EVENT, pumpfun)).unwrap().is_none());
+    }
+
+    #[test]
+    fn instruction_encoding_test() {
+        let complete = CompleteEvent {
+            user: Pubkey::new_unique(),
+            mint: Pubkey::new_unique(),
+            bonding_curve: Pubkey::new_unique(),
+            timestamp: 0,
+        };
+        let mut data = vec![0u8; 8];
+        data.extend_from_slice(&PUMPFUN_COMPLETE_EVENT);
+        data.extend(borsh::to_vec(&complete).unwrap());
+
+        let partially_decoded = UiInstruction::Parsed(UiParsedInstruction::PartiallyDecoded(
+            UiPartiallyDecodedInstruction {
+                program_id: Pubkey::new_unique().to_string(),
+                accounts: vec![],
+                data: STANDARD.encode(&data),
+                stack_height: None,
+            },
+        ));
+        assert_eq!(
+            TargetEvent::parse_instruction(Program::Pumpfun, &partially_decoded).unwrap().unwrap().key(),
+            complete.mint
+        );
+        assert_eq!(TargetEvent::parse(Program::Pumpfun, &data).unwrap().unwrap().key(), complete.mint);
+        assert!(TargetEvent::parse(Program::Pumpamm, &data).unwrap().is_none());
+
+        // known discriminator, truncated body
+        let error = TargetEvent::parse(Program::Pumpfun, &data[..20]).unwrap_err();
+        assert_eq!((error.program, error.data_len), (Program::Pumpfun, 20));
+        assert_eq!(error.kind, ParseFailure::Undersized);
+        assert!(TargetEvent::parse(Program::Pumpfun, &[0u8; 20]).unwrap().is_none());
+
+        // only cpi events are kept as unknown
+        let mut unknown = EVENT_IX_TAG.to_vec();
+        unknown.extend_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8, 9]);
+        match TargetEvent::unknown(Program::Pumpamm, &unknown) {
+            Some(TargetEvent::Unknown { program, discriminator, data }) => {
+                assert_eq!(program, Program::Pumpamm);
+                assert_eq!(discriminator, [1, 2, 3, 4, 5, 6, 7, 8]);
+                assert_eq!(data, vec![9]);
             }
-        } else {
-            return None;
+            other => panic!("unexpected event {:?}", other),
         }
+        assert!(TargetEvent::unknown(Program::Pumpamm, &data).is_none());
     }
-}   
+
+    #[test]
+    fn set_params_test() {
+        let params = SetParamsEvent {
+            fee_recipient: Pubkey::new_unique(),
+            initial_virtual_token_reserves: 1_073_000_000_000_000,
+            initial_virtual_sol_reserves: 30_000_000_000,
+            initial_real_token_reserves: 793_100_000_000_000,
+            token_total_supply: 1_000_000_000_000_000,
+            fee_basis_points: 95,
+        };
+        let mut data = vec![0u8; 8];
+        data.extend_from_slice(&PUMPFUN_SET_PARAMS_EVENT);
+        data.extend(borsh::to_vec(&params).unwrap());
+
+        match TargetEvent::parse(Program::Pumpfun, &data).unwrap() {
+            Some(TargetEvent::PumpfunSetParams(event)) => {
+                assert_eq!(event.fee_recipient, params.fee_recipient);
+                assert_eq!(event.fee_basis_points, 95);
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+        // the same bytes emitted by any other program must not move the global parameters
+        for program in [Program::Pumpamm, Program::Moonshot, Program::Boop] {
+            assert!(TargetEvent::parse(program, &data).unwrap().is_none());
+        }
+    }
+
+    #[test]
+    fn serde_test() {
+        let complete = CompleteEvent {
+            user: Pubkey::new_unique(),
+            mint: Pubkey::new_unique(),
+            bonding_curve: Pubkey::new_unique(),
+            timestamp: 1,
+        };
+        let json = serde_json::to_value(TargetEvent::PumpfunComplete(complete)).unwrap();
+        assert_eq!(json["type"], "pumpfun_complete");
+        assert_eq!(json["mint"], complete.mint.to_string());
+
+        let event: TargetEvent = serde_json::from_value(json).unwrap();
+        assert_eq!(event.key(), complete.mint);
+    }
+
+    #[test]
+    fn outer_instruction_test() {
+        let accounts: Vec<Pubkey> = (0..12).map(|_| Pubkey::new_unique()).collect();
+        let mut data = SELL_IX.to_vec();
+        data.extend(borsh::to_vec(&SellArgs { amount: 10, min_sol_output: 5 }).unwrap());
+
+        match TargetEvent::from_outer_instruction(Program::Pumpfun, &accounts, &data) {
+            Some(TargetEvent::PumpfunTradeIx(trade)) => {
+                assert!(!trade.is_buy);
+                assert_eq!(TargetEvent::PumpfunTradeIx(trade).bonding_curve(&[]), Some(accounts[3]));
+                assert_eq!(trade.mint, accounts[2]);
+                assert_eq!(trade.user, accounts[6]);
+                assert_eq!((trade.token_amount, trade.sol_limit), (10, 5));
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+        match TargetEvent::from_outer_instruction(Program::Pumpamm, &accounts, &data) {
+            Some(TargetEvent::PumpammSwapIx(swap)) => assert_eq!(swap.pool, accounts[0]),
+            other => panic!("unexpected event {:?}", other),
+        }
+        assert!(TargetEvent::from_outer_instruction(Program::Pumpfun, &accounts[..3], &data).is_none());
+
+        // create, with and without the creator argument
+        let args = CreateArgs { name: "Pump".to_string(), symbol: "PUMP".to_string(), uri: "https://example.com".to_string() };
+        let mut data = CREATE_IX.to_vec();
+        data.extend(borsh::to_vec(&args).unwrap());
+        match TargetEvent::from_outer_instruction(Program::Pumpfun, &accounts, &data) {
+            Some(TargetEvent::PumpfunCreate(create)) => {
+                assert_eq!((create.mint, create.bonding_curve), (accounts[0], accounts[2]));
+                assert_eq!((create.user, create.creator), (accounts[7], accounts[7]));
+                assert_eq!((create.name.as_str(), create.symbol.as_str()), ("Pump", "PUMP"));
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+        let creator = Pubkey::new_unique();
+        data.extend(creator.to_bytes());
+        match TargetEvent::from_outer_instruction(Program::Pumpfun, &accounts, &data) {
+            Some(TargetEvent::PumpfunCreate(create)) => assert_eq!(create.creator, creator),
+            other => panic!("unexpected event {:?}", other),
+        }
+        assert!(TargetEvent::from_outer_instruction(Program::Pumpamm, &accounts, &data).is_none());
+    }
+
+    #[test]
+    fn moonshot_launch_test() {
+        use sha2::{Digest, Sha256};
+        assert_eq!(&Sha256::digest(b"global:token_mint")[..8], &MOONSHOT_TOKEN_MINT_IX);
+        let accounts: Vec<Pubkey> = (0..12).map(|_| Pubkey::new_unique()).collect();
+        let args = MoonshotTokenMintArgs {
+            name: "Moon".to_string(),
+            symbol: "MOON".to_string(),
+            uri: "https://example.com".to_string(),
+            decimals: 9,
+            collateral_currency: 0,
+            amount: 1_000_000_000_000_000_000,
+            curve_type: 1,
+            migration_target: 0,
+        };
+        let mut data = MOONSHOT_TOKEN_MINT_IX.to_vec();
+        data.extend(borsh::to_vec(&args).unwrap());
+
+        let event = TargetEvent::from_launch_instruction(Program::Moonshot, &accounts, &data).unwrap();
+        assert_eq!(event.created_mint(), Some(accounts[3]));
+        assert_eq!(event.bonding_curve(&[]), Some(accounts[2]));
+        match event {
+            TargetEvent::MoonshotCreate(create) => {
+                let create = create.to_create_event();
+                assert_eq!((create.user, create.creator), (accounts[0], accounts[0]));
+                assert_eq!(create.symbol, "MOON");
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+        assert!(TargetEvent::from_launch_instruction(Program::Pumpfun, &accounts, &data).is_none());
+        assert!(TargetEvent::from_launch_instruction(Program::Moonshot, &accounts[..4], &data).is_none());
+    }
+
+    #[test]
+    fn mint_test() {
+        let accounts: Vec<Pubkey> = (0..12).map(|_| Pubkey::new_unique()).collect();
+        let mut data = BUY_IX.to_vec();
+        data.extend(borsh::to_vec(&BuyArgs { amount: 10, max_sol_cost: 5 }).unwrap());
+
+        // a token keeps its mint as shard key before and after graduation
+        let trade = TargetEvent::from_outer_instruction(Program::Pumpfun, &accounts, &data).unwrap();
+        let swap = TargetEvent::from_outer_instruction(Program::Pumpamm, &accounts, &data).unwrap();
+        assert_eq!(trade.mint(&accounts), Some(accounts[2]));
+        assert_eq!(swap.mint(&accounts), Some(accounts[3]));
+        assert_eq!(swap.key(), accounts[0]);
+
+        let moonshot = TargetEvent::MoonshotBuy(MoonshotTradeEvent {
+            amount: 1,
+            collateral_amount: 1,
+            dex_fee: 0,
+            helio_fee: 0,
+            allocation: 0,
+            curve: accounts[2],
+            cost_token: Pubkey::new_unique(),
+            sender: accounts[0],
+            trade_type: MoonshotTradeType::Buy,
+            label: "".to_string(),
+        });
+        assert_eq!(moonshot.mint(&accounts), Some(accounts[6]));
+        assert_eq!(moonshot.mint(&[]), None);
+    }
+
+    #[test]
+    fn compute_budget_test() {
+        let other = Pubkey::new_unique();
+        let mut limit = vec![SET_COMPUTE_UNIT_LIMIT_IX];
+        limit.extend_from_slice(&100_000u32.to_le_bytes());
+        let mut price = vec![SET_COMPUTE_UNIT_PRICE_IX];
+        price.extend_from_slice(&1_500_000u64.to_le_bytes());
+
+        let budget = ComputeBudget::from_instructions([
+            (&COMPUTE_BUDGET_PROGRAM_ID, limit.as_slice()),
+            (&COMPUTE_BUDGET_PROGRAM_ID, price.as_slice()),
+            (&other, &[1u8][..]),
+        ]);
+        assert_eq!(budget, ComputeBudget { unit_limit: 100_000, unit_price: 1_500_000 });
+        assert_eq!(budget.priority_fee(), 150_000);
+
+        // default limit per instruction
+        let budget = ComputeBudget::from_instructions([(&COMPUTE_BUDGET_PROGRAM_ID, price.as_slice()), (&other, &[][..]), (&other, &[][..])]);
+        assert_eq!(budget.unit_limit, 400_000);
+    }
+
+    #[test]
+    fn boop_event_test() {
+        use sha2::{Digest, Sha256};
+
+        // anchor event discriminators are the first bytes of sha256("event:<name>")
+        let discriminator = |name: &str| -> [u8; 8] { Sha256::digest(format!("event:{name}"))[..8].try_into().unwrap() };
+        assert_eq!(discriminator("TokenCreated"), BOOP_TOKEN_CREATED_EVENT);
+        assert_eq!(discriminator("TokenBought"), BOOP_TOKEN_BOUGHT_EVENT);
+        assert_eq!(discriminator("TokenSold"), BOOP_TOKEN_SOLD_EVENT);
+
+        let create = BoopCreateEvent {
+            mint: Pubkey::new_unique(),
+            name: "Boop".to_string(),
+            symbol: "BOOP".to_string(),
+            uri: "https://example.com/boop.json".to_string(),
+        };
+        match TargetEvent::parse_instruction(Program::Boop, &event_ix(BOOP_TOKEN_CREATED_EVENT, create.clone())).unwrap() {
+            Some(event @ TargetEvent::BoopCreate(_)) => {
+                assert_eq!(event.created_mint(), Some(create.mint));
+                assert_eq!(event.bonding_curve(&[]), Some(find_boop_bonding_curve(&create.mint)));
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+
+        let trade = BoopTradeEvent {
+            mint: create.mint,
+            amount_in: 1_000_000_000,
+            amount_out: 35_000_000_000_000_000,
+            swap_fee: 10_000_000,
+            user: Pubkey::new_unique(),
+            recipient: Pubkey::new_unique(),
+        };
+        match TargetEvent::parse_instruction(Program::Boop, &event_ix(BOOP_TOKEN_BOUGHT_EVENT, trade)).unwrap() {
+            Some(TargetEvent::BoopBuy(buy)) => assert_eq!(buy.tokens_bought(true), 35_000_000_000_000_000),
+            other => panic!("unexpected event {:?}", other),
+        }
+        match TargetEvent::parse_instruction(Program::Boop, &event_ix(BOOP_TOKEN_SOLD_EVENT, trade)).unwrap() {
+            Some(TargetEvent::BoopSell(sell)) => assert_eq!(sell.tokens_bought(false), -1_000_000_000),
+            other => panic!("unexpected event {:?}", other),
+        }
+    }
+
+    #[test]
+    fn trade_event_layout_test() {
+        // v1 body of a mainnet trade
+        let data = bs58::decode("2K7nL28PxCW8ejnyCeuMpbYAmP2pnuyvkxEQgp79nsKJzbKfMq82LAVFjwFY1xYhKmuaA8H3M5xLfFnF85Xbai9s9aaCyDETZgWMQJayFp8t1HM9ihUxb1TCcsXYVsNKDqaGANFoxSEAPLvpAXJVQHTNyAMxFcgM9s3knpLcDTYtGe7Ufq3WZ9kvAGdd")
+            .into_vec()
+            .unwrap();
+        let v1 = TradeEvent::from_event_data(&data[16..]).unwrap();
+        assert_eq!(v1.creator, Pubkey::default());
+
+        // a newer layout with more fields still decodes
+        let mut longer = data[16..].to_vec();
+        longer.extend_from_slice(&[7u8; 8]);
+        let decoded = TradeEvent::from_event_data(&longer).unwrap();
+        assert_eq!((decoded.mint, decoded.sol_amount, decoded.token_amount), (v1.mint, v1.sol_amount, v1.token_amount));
+
+        let v2 = TradeEvent { creator: Pubkey::new_unique(), creator_fee: 3, ..v1 };
+        let mut body = borsh::to_vec(&v2).unwrap();
+        body.extend_from_slice(&[1u8; 40]);
+        let decoded = TradeEvent::from_event_data(&body).unwrap();
+        assert_eq!((decoded.creator, decoded.creator_fee), (v2.creator, 3));
+
+        assert!(TradeEvent::from_event_data(&data[16..100]).is_none());
+    }
+
+    #[test]
+    fn token_instruction_test() {
+        let accounts: Vec<Pubkey> = (0..4).map(|_| Pubkey::new_unique()).collect();
+        let mut data = vec![TOKEN_TRANSFER_CHECKED_IX];
+        data.extend_from_slice(&42u64.to_le_bytes());
+        data.push(6);
+
+        let transfer = TokenInstruction::decode(&accounts, &data).unwrap();
+        assert_eq!(transfer.source, Some(accounts[0]));
+        assert_eq!(transfer.mint, Some(accounts[1]));
+        assert_eq!(transfer.destination, accounts[2]);
+        assert_eq!(transfer.amount, 42);
+
+        data[0] = TOKEN_MINT_TO_IX;
+        let mint_to = TokenInstruction::decode_str(&accounts, &bs58::encode(&data[..9]).into_string()).unwrap();
+        assert_eq!(mint_to.source, None);
+        assert_eq!((mint_to.mint, mint_to.destination), (Some(accounts[0]), accounts[1]));
+
+        // approve, and a transfer without accounts
+        data[0] = 4;
+        assert!(TokenInstruction::decode(&accounts, &data).is_none());
+        data[0] = TOKEN_TRANSFER_IX;
+        assert!(TokenInstruction::decode(&[], &data).is_none());
+    }
+}
 
 
 #[tokio::test]
@@ -457,7 +1583,7 @@ async fn test() {
     let data = "2K7nL28PxCW8ejnyCeuMpbYAmP2pnuyvkxEQgp79nsKJzbKfMq82LAVFjwFY1xYhKmuaA8H3M5xLfFnF85Xbai9s9aaCyDETZgWMQJayFp8t1HM9ihUxb1TCcsXYVsNKDqaGANFoxSEAPLvpAXJVQHTNyAMxFcgM9s3knpLcDTYtGe7Ufq3WZ9kvAGdd";
     let data = bs58::decode(data.as_bytes()).into_vec().unwrap();
     println!("data {:?}", data);
-    let result = TradeEvent::try_from_slice(&data[16..]).unwrap();
+    let result = TradeEvent::from_event_data(&data[16..]).unwrap();
     println!("result {:?}", result);
 }
 
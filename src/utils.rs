@@ -1,20 +1,27 @@
 use std::{str::FromStr, time::Duration};
 
 use anyhow::{anyhow, Result};
-use chrono::{TimeZone, Utc};
-use chrono_tz::America::New_York;
+use chrono::{TimeZone, Timelike, Utc};
+use chrono_tz::{America::New_York, Tz};
 use reqwest::Client;
 use serde_json::Value;
 use solana_sdk::pubkey::Pubkey;
 use solana_transaction_status::{EncodedTransactionWithStatusMeta, UiTransactionEncoding};
 use yellowstone_grpc_proto::{convert_from, geyser::SubscribeUpdateTransactionInfo};
 
-use crate::{constants::PUMPFUN_PROGRAM_ID, types::CreateEvent};
+use crate::{
+    constants::{
+        BOOP_PROGRAM_ID, PUMPFUN_PROGRAM_ID, ASSOC_TOKEN_ACC_PROGRAM_ID, VirtualCurve,
+        TOKEN_2022_PROGRAM_ID, TOKEN_PROGRAM_ID, INIT_SOL_REVERSES, INIT_TOKEN_REVERSES,
+        PUMPFUN_FEE_RECIPIENT, PUMPFUN_TOTAL_SUPPLY,
+    },
+    types::{CreateEvent, SetParamsEvent},
+};
 pub fn convert_to_encoded_tx(
     tx_info: SubscribeUpdateTransactionInfo,
 ) -> Result<EncodedTransactionWithStatusMeta> {
     convert_from::create_tx_with_meta(tx_info)
-        .unwrap()
+        .map_err(|e| anyhow!("convert transaction error: {}", e))?
         .encode(UiTransactionEncoding::Base64, Some(u8::MAX), true)
         .map_err(|e| anyhow!("{}", e))
 }
@@ -23,15 +30,66 @@ pub fn cal_pumpfun_price(virtual_sol_reserves: u64, virtual_token_reserves: u64)
     (virtual_sol_reserves as f64 / 10f64.powi(9)) / (virtual_token_reserves as f64 / 10f64.powi(6))
 }
 
-pub fn cal_pumpfun_marketcap(price: f64) -> f64 {
-    price * 1_000_000_000.0
+/// Market cap over the total supply of the live `params`
+pub fn cal_pumpfun_marketcap(price: f64, params: &PumpfunParams) -> f64 {
+    price * (params.token_total_supply as f64 / 10f64.powi(TOKEN_DECIMALS as i32))
+}
+
+/// pump.fun global curve parameters, updated by `SetParams` events
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PumpfunParams {
+    pub fee_recipient: Pubkey,
+    pub initial_virtual_token_reserves: u64,
+    pub initial_virtual_sol_reserves: u64,
+    pub initial_real_token_reserves: u64,
+    pub token_total_supply: u64,
+    pub fee_basis_points: u64,
+}
+
+impl Default for PumpfunParams {
+    // values at launch, used until a `SetParams` event is seen
+    fn default() -> Self {
+        Self {
+            fee_recipient: PUMPFUN_FEE_RECIPIENT,
+            initial_virtual_token_reserves: INIT_TOKEN_REVERSES,
+            initial_virtual_sol_reserves: INIT_SOL_REVERSES,
+            initial_real_token_reserves: 793_100_000_000_000,
+            token_total_supply: PUMPFUN_TOTAL_SUPPLY,
+            fee_basis_points: 100,
+        }
+    }
+}
+
+impl From<&SetParamsEvent> for PumpfunParams {
+    fn from(event: &SetParamsEvent) -> Self {
+        Self {
+            fee_recipient: event.fee_recipient,
+            initial_virtual_token_reserves: event.initial_virtual_token_reserves,
+            initial_virtual_sol_reserves: event.initial_virtual_sol_reserves,
+            initial_real_token_reserves: event.initial_real_token_reserves,
+            token_total_supply: event.token_total_supply,
+            fee_basis_points: event.fee_basis_points,
+        }
+    }
+}
+
+impl PumpfunParams {
+    /// Price of a new token before the first buy
+    pub fn initial_price(&self) -> f64 {
+        cal_pumpfun_price(self.initial_virtual_sol_reserves, self.initial_virtual_token_reserves)
+    }
+
+    /// Virtual (sol, token) reserves of a curve after `tokens_sold` tokens left it
+    pub fn virtual_reserves(&self, tokens_sold: u64) -> (u64, u64) {
+        curve_virtual_reserves(self.initial_virtual_sol_reserves, self.initial_virtual_token_reserves, tokens_sold)
+    }
 }
 
 // base_reserve -> meme
 // quote_reserve -> WSOL
 
 const WSOL_DECIMALS: u8 = 9;
-const TOKEN_DECIMALS: u8 = 6;
+pub const TOKEN_DECIMALS: u8 = 6;
 
 pub fn cal_pumpamm_price(
     base_reserves: u64,
@@ -52,6 +110,27 @@ pub fn cal_pumpamm_marketcap_precise(
 }
 
 
+// virtual (sol, token) reserves of a constant product curve after `tokens_sold` tokens left it
+fn curve_virtual_reserves(init_sol: u64, init_token: u64, tokens_sold: u64) -> (u64, u64) {
+    let token_reserves = init_token.saturating_sub(tokens_sold).max(1);
+    let k = init_sol as u128 * init_token as u128;
+    let sol_reserves = (k / token_reserves as u128).min(u64::MAX as u128) as u64;
+    (sol_reserves, token_reserves)
+}
+
+/// Virtual (sol, token) reserves of `curve` after `tokens_sold` tokens left it
+pub fn cal_curve_reserves(curve: &VirtualCurve, tokens_sold: u64) -> (u64, u64) {
+    curve_virtual_reserves(curve.init_sol_reserves, curve.init_token_reserves, tokens_sold)
+}
+
+pub fn cal_curve_price(curve: &VirtualCurve, virtual_sol_reserves: u64, virtual_token_reserves: u64) -> f64 {
+    (virtual_sol_reserves as f64 / 10f64.powi(WSOL_DECIMALS as i32))
+        / (virtual_token_reserves as f64 / 10f64.powi(curve.token_decimals as i32))
+}
+
+pub fn cal_curve_marketcap(curve: &VirtualCurve, price: f64) -> f64 {
+    price * (curve.total_supply as f64 / 10f64.powi(curve.token_decimals as i32))
+}
 
 pub fn get_pumpamm_base_mint_info(create_event: &CreateEvent) -> Vec<(Pubkey, u8)> {
     // let base_token = if pool.base_mint == WSOL {
@@ -97,9 +176,77 @@ pub fn find_bonding_curve(mint: &Pubkey) -> Pubkey {
     .0
 }
 
+pub fn find_boop_bonding_curve(mint: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&["bonding_curve".as_bytes(), mint.as_ref()], &BOOP_PROGRAM_ID).0
+}
+
+/// Associated token account of `owner`, the address depends on the token program of the mint
+pub fn associated_token_address(owner: &Pubkey, mint: &Pubkey, token_program: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[owner.as_ref(), token_program.as_ref(), mint.as_ref()],
+        &ASSOC_TOKEN_ACC_PROGRAM_ID,
+    )
+    .0
+}
+
+/// Token program from a stored id, SPL Token for records written before it was stored
+pub fn parse_token_program(id: &str) -> Pubkey {
+    match Pubkey::from_str(id) {
+        Ok(id) if id == TOKEN_2022_PROGRAM_ID => TOKEN_2022_PROGRAM_ID,
+        _ => TOKEN_PROGRAM_ID,
+    }
+}
+
+/// `"23:00-07:00"` as the minutes of the day it starts and ends at
+pub fn parse_time_window(value: &str) -> Option<(u32, u32)> {
+    let minutes = |time: &str| {
+        let (hour, minute) = time.trim().split_once(':')?;
+        let (hour, minute) = (hour.parse::<u32>().ok()?, minute.parse::<u32>().ok()?);
+        (hour < 24 && minute < 60).then_some(hour * 60 + minute)
+    };
+    let (start, end) = value.split_once('-')?;
+    Some((minutes(start)?, minutes(end)?))
+}
+
+/// Whether `timestamp_ms` falls in the daily window `start..end` (minutes of the day in `tz`),
+/// a window that ends before it starts runs over midnight
+pub fn in_time_window(timestamp_ms: u64, tz: Tz, (start, end): (u32, u32)) -> bool {
+    let Some(time) = Utc.timestamp_millis_opt(timestamp_ms as i64).single() else {
+        return false;
+    };
+    let time = time.with_timezone(&tz);
+    let minute = time.hour() * 60 + time.minute();
+    if start <= end {
+        (start..end).contains(&minute)
+    } else {
+        minute >= start || minute < end
+    }
+}
+
 pub fn format_timestamp_to_et(timestamp_ms: u64) -> String {
     let seconds = (timestamp_ms / 1000) as i64;
     let dt = Utc.timestamp_opt(seconds, 0).unwrap();
     let et = dt.with_timezone(&New_York);   
     et.format("%Y-%m-%d %I:%M %p ET").to_string()
 }
+
+/// Largest whole unit of a duration, e.g. `10m` or `90s`
+pub fn format_duration_ms(ms: u64) -> String {
+    const UNITS: [(u64, &str); 4] = [(24 * 3_600_000, "d"), (3_600_000, "h"), (60_000, "m"), (1000, "s")];
+    for (scale, unit) in UNITS {
+        if ms >= scale && ms % scale == 0 {
+            return format!("{}{}", ms / scale, unit);
+        }
+    }
+    format!("{}ms", ms)
+}
+
+/// Exponential backoff with jitter.
+///
+/// The base delay doubles every attempt (starting at `initial_ms` for attempt 1)
+/// and is capped at `max_ms`; the returned delay is random in `[base / 2, base]`.
+pub fn backoff_with_jitter(attempt: u32, initial_ms: u64, max_ms: u64) -> u64 {
+    let exp = attempt.saturating_sub(1).min(32);
+    let base = initial_ms.saturating_mul(1u64 << exp).min(max_ms);
+    base / 2 + rand::random_range(0..=base - base / 2)
+}
@@ -1,7 +1,21 @@
-use std::time::Duration;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 use serde::{Deserialize, Serialize};
-use reqwest::Client as ReqwestClient;
+use regex::{Regex, RegexBuilder};
+use reqwest::{header::HeaderMap, Client as ReqwestClient, StatusCode};
 use thiserror::Error;
+use tokio::time::sleep;
+use tracing::{debug, warn};
+use url::Url;
+
+use crate::{
+    config::{XConfig, XProvider},
+    rate_limit::TokenBucket,
+    utils::backoff_with_jitter,
+};
 
 /// Twitter API error types
 #[derive(Error, Debug)]
@@ -23,6 +37,45 @@ pub enum TwitterError {
     
     #[error("Maximum retry attempts ({0}) exceeded")]
     MaxRetriesExceeded(u8),
+
+    #[error("Request quota used up, skipped after waiting {0:?}")]
+    QuotaExceeded(Duration),
+
+    #[error("Rate limited, retry after {retry_after:?}")]
+    RateLimited {
+        /// from the `Retry-After` or `x-rate-limit-reset` header
+        retry_after: Option<Duration>,
+    },
+}
+
+impl TwitterError {
+    /// Whether the request can succeed when sent again: network errors, rate limits, timeouts
+    /// and server errors
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            TwitterError::RequestError(e) => e.is_timeout() || e.is_connect() || e.is_request() || e.is_body(),
+            TwitterError::ApiError { status_code, .. } => is_retryable_status(*status_code),
+            TwitterError::RateLimited { .. } => true,
+            // waiting more would only hold up the alert
+            TwitterError::QuotaExceeded(_) => false,
+            TwitterError::JsonError(_) | TwitterError::AuthError(_) | TwitterError::MaxRetriesExceeded(_) => false,
+        }
+    }
+}
+
+// 408, 429 and 5xx
+fn is_retryable_status(status_code: u16) -> bool {
+    status_code == 408 || status_code == 429 || (500..600).contains(&status_code)
+}
+
+/// Wait a rate limited response asks for: `Retry-After` in seconds, else the
+/// `x-rate-limit-reset` unix time (seconds) less `now_secs`
+pub fn retry_after(headers: &HeaderMap, now_secs: u64) -> Option<Duration> {
+    let header = |name: &str| headers.get(name)?.to_str().ok()?.trim().parse::<u64>().ok();
+    if let Some(seconds) = header("retry-after") {
+        return Some(Duration::from_secs(seconds));
+    }
+    header("x-rate-limit-reset").map(|reset| Duration::from_secs(reset.saturating_sub(now_secs)))
 }
 
 /// Represents a single tweet
@@ -59,6 +112,18 @@ pub struct Tweet {
     pub user: User,
 }
 
+impl Tweet {
+    /// Likes, retweets and replies, e.g. `❤️ 120 🔁 30 💬 12`
+    pub fn engagement(&self) -> String {
+        format!("❤️ {} 🔁 {} 💬 {}", self.favorite_count, self.retweet_count, self.reply_count)
+    }
+
+    /// Ranking weight of the counts, a share spreads further than a like
+    fn score(&self) -> i64 {
+        self.favorite_count as i64 + 2 * (self.retweet_count as i64 + self.quote_count as i64) + self.reply_count as i64
+    }
+}
+
 /// Represents a Twitter user
 #[derive(Default, Debug, Serialize, Deserialize, Clone)]
 pub struct User {
@@ -80,7 +145,7 @@ pub struct User {
 }
 
 /// Represents a Twitter API response
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Default, Debug, Serialize, Deserialize, Clone)]
 pub struct TwitterResponse {
     #[serde(default)]
     pub pinned_tweet: Option<serde_json::Value>,
@@ -89,6 +154,142 @@ pub struct TwitterResponse {
     pub next_cursor_str: String,
 }
 
+/// Answer of the official API v2 (`tweets/search/recent`, `tweets?ids=`), authors in `includes`
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct V2Response {
+    data: Vec<V2Tweet>,
+    includes: V2Includes,
+    meta: V2Meta,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct V2Includes {
+    users: Vec<V2User>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct V2Meta {
+    next_token: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct V2Tweet {
+    id: String,
+    text: String,
+    author_id: String,
+    created_at: String,
+    public_metrics: V2TweetMetrics,
+    referenced_tweets: Vec<V2Reference>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct V2TweetMetrics {
+    like_count: i32,
+    retweet_count: i32,
+    reply_count: i32,
+    quote_count: i32,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct V2Reference {
+    // retweeted, quoted or replied_to
+    #[serde(rename = "type")]
+    kind: String,
+    id: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct V2User {
+    id: String,
+    name: String,
+    username: String,
+    description: String,
+    location: String,
+    created_at: String,
+    verified: bool,
+    profile_image_url: String,
+    public_metrics: V2UserMetrics,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct V2UserMetrics {
+    followers_count: i32,
+    following_count: i32,
+    tweet_count: i32,
+    like_count: i32,
+    media_count: i32,
+}
+
+impl From<V2User> for User {
+    fn from(user: V2User) -> Self {
+        Self {
+            id_str: user.id,
+            name: user.name,
+            screen_name: user.username,
+            location: user.location,
+            description: user.description,
+            followers_count: user.public_metrics.followers_count,
+            friends_count: user.public_metrics.following_count,
+            created_at: user.created_at,
+            favourites_count: user.public_metrics.like_count,
+            verified: user.verified,
+            statuses_count: user.public_metrics.tweet_count,
+            media_count: user.public_metrics.media_count,
+            profile_image_url_https: user.profile_image_url,
+        }
+    }
+}
+
+impl From<V2Response> for TwitterResponse {
+    fn from(response: V2Response) -> Self {
+        let users: HashMap<String, User> =
+            response.includes.users.into_iter().map(|user| (user.id.clone(), User::from(user))).collect();
+        let tweets = response
+            .data
+            .into_iter()
+            .map(|tweet| {
+                let referenced = |kind: &str| tweet.referenced_tweets.iter().find(|r| r.kind == kind);
+                let is_retweet = referenced("retweeted").is_some();
+                let is_quote = referenced("quoted").is_some();
+                let is_reply = referenced("replied_to").is_some();
+                let related_tweet_id = tweet.referenced_tweets.first().map(|r| r.id.clone()).unwrap_or_default();
+                Tweet {
+                    user: users.get(&tweet.author_id).cloned().unwrap_or_default(),
+                    tweet_id: tweet.id,
+                    user_id: tweet.author_id,
+                    text: tweet.text,
+                    is_retweet,
+                    is_quote,
+                    is_reply,
+                    related_tweet_id,
+                    favorite_count: tweet.public_metrics.like_count,
+                    quote_count: tweet.public_metrics.quote_count,
+                    reply_count: tweet.public_metrics.reply_count,
+                    retweet_count: tweet.public_metrics.retweet_count,
+                    created_at: tweet.created_at,
+                    ..Default::default()
+                }
+            })
+            .collect();
+        Self { pinned_tweet: None, tweets, next_cursor_str: response.meta.next_token }
+    }
+}
+
+// fields the v2 answers need to fill a `Tweet` and its `User`
+const V2_FIELDS: [(&str, &str); 3] = [
+    ("tweet.fields", "author_id,created_at,public_metrics,referenced_tweets"),
+    ("expansions", "author_id"),
+    ("user.fields", "created_at,description,location,profile_image_url,public_metrics,verified"),
+];
+
 /// Represents the API error response structure
 #[derive(Debug, Serialize, Deserialize)]
 struct ApiErrorResponse {
@@ -103,27 +304,58 @@ pub type Result<T> = std::result::Result<T, TwitterError>;
 /// Twitter API client
 #[derive(Debug, Clone)]
 pub struct XClient {
+    provider: XProvider,
     base_url: String,
     http_client: ReqwestClient,
     max_retries: u8,
+    // milliseconds before the second try and the most between two tries
+    retry_backoff: u64,
+    max_retry_backoff: u64,
     api_key: Option<String>,
+    // shared by the clones, every request takes a token
+    quota: Arc<TokenBucket>,
+    max_quota_wait: Duration,
+    // tweets of a token's own account given to the AI summary, 0 for the search only
+    account_tweets: usize,
+    // search results given to the AI summary
+    search_tweets: usize,
+    // drops spam from the search results
+    spam_filter: Arc<SpamFilter>,
 }
 
 impl XClient {
     /// Create a new Twitter API client
     pub fn new(base_url: &str) -> Self {
         Self {
+            provider: XProvider::Apidance,
             base_url: base_url.to_string(),
             http_client: ReqwestClient::builder()
                 .timeout(Duration::from_secs(30))
                 .build()
                 .expect("Failed to create HTTP client"),
             max_retries: 3,
+            retry_backoff: 500,
+            max_retry_backoff: 30_000,
             api_key: None,
+            quota: Arc::new(TokenBucket::new(0, 0)),
+            max_quota_wait: Duration::ZERO,
+            account_tweets: 5,
+            search_tweets: 5,
+            spam_filter: Arc::new(SpamFilter::default()),
         }
     }
     
-    /// Set the API key for authentication
+    /// Set the API the client talks to, `base_url` and the key have to be the provider's
+    pub fn with_provider(mut self, provider: XProvider) -> Self {
+        self.provider = provider;
+        self
+    }
+
+    pub fn provider(&self) -> XProvider {
+        self.provider
+    }
+
+    /// Set the API key for authentication, the bearer token for the official API
     pub fn with_api_key(mut self, api_key: &str) -> Self {
         self.api_key = Some(api_key.to_string());
         self
@@ -134,6 +366,39 @@ impl XClient {
         self.max_retries = max_retries;
         self
     }
+
+    /// Limit requests to `per_minute` with bursts of `burst`, a request waits at most
+    /// `max_wait_ms` for its turn and fails with `QuotaExceeded` otherwise
+    pub fn with_quota(mut self, per_minute: u32, burst: u32, max_wait_ms: u64) -> Self {
+        self.quota = Arc::new(TokenBucket::new(per_minute, burst));
+        self.max_quota_wait = Duration::from_millis(max_wait_ms);
+        self
+    }
+
+    /// Set how many tweets of a token's own account `token_tweets` takes, 0 for the search only
+    pub fn with_account_tweets(mut self, account_tweets: usize) -> Self {
+        self.account_tweets = account_tweets;
+        self
+    }
+
+    /// Set how many merged search results `token_tweets` takes
+    pub fn with_search_tweets(mut self, search_tweets: usize) -> Self {
+        self.search_tweets = search_tweets.max(1);
+        self
+    }
+
+    /// Set the filter the search results go through before they are ranked
+    pub fn with_spam_filter(mut self, spam_filter: SpamFilter) -> Self {
+        self.spam_filter = Arc::new(spam_filter);
+        self
+    }
+
+    /// Set the wait before the second try and the longest wait between tries (milliseconds)
+    pub fn with_retry_backoff(mut self, initial_ms: u64, max_ms: u64) -> Self {
+        self.retry_backoff = initial_ms;
+        self.max_retry_backoff = max_ms;
+        self
+    }
     
     /// Search for tweets with query parameters
     pub async fn search_tweets(&self, query: &str, cursor: Option<&str>, sort_by: Option<&str>) -> Result<TwitterResponse> {
@@ -142,6 +407,10 @@ impl XClient {
             return Err(TwitterError::AuthError("API key is required".to_string()));
         }
         
+        if self.provider == XProvider::Official {
+            return self.search_recent(query, cursor, sort_by == Some("Top")).await;
+        }
+
         // For the specific API endpoint structure
         let url = format!("{}/Search", self.base_url);
         
@@ -167,24 +436,118 @@ impl XClient {
             }
         }
         
-        let mut last_error = None;
-        
-        // Try up to max_retries times with immediate retry
-        for _attempt in 0..self.max_retries {
-            match self.do_request_with_params(&url, &params).await {
-                Ok(response) => return Ok(response),
+        self.request_with_retries(&url, &params).await
+    }
+    
+    /// Recent tweets of the account `screen_name` (without `@`), newest first
+    pub async fn user_tweets(&self, screen_name: &str, cursor: Option<&str>) -> Result<TwitterResponse> {
+        if self.api_key.is_none() {
+            return Err(TwitterError::AuthError("API key is required".to_string()));
+        }
+        if self.provider == XProvider::Official {
+            // 官方 api 没有按用户名的时间线, the recent search of the account's own posts saves the id lookup
+            return self.search_recent(&format!("from:{} -is:retweet -is:reply", screen_name), cursor, false).await;
+        }
+        let url = format!("{}/UserTweets", self.base_url);
+        // API requires cursor parameter even if empty
+        let params = vec![("screen_name", screen_name.to_string()), ("cursor", cursor.unwrap_or_default().to_string())];
+        self.request_with_retries(&url, &params).await
+    }
+
+    /// The tweet `tweet_id` with its current counts
+    pub async fn get_tweet(&self, tweet_id: &str) -> Result<Tweet> {
+        if self.api_key.is_none() {
+            return Err(TwitterError::AuthError("API key is required".to_string()));
+        }
+        let (url, params) = match self.provider {
+            XProvider::Apidance => (format!("{}/TweetDetail", self.base_url), vec![("tweet_id", tweet_id.to_string())]),
+            XProvider::Official => (
+                format!("{}/tweets", self.base_url),
+                std::iter::once(("ids", tweet_id.to_string())).chain(V2_FIELDS.iter().map(|(name, value)| (*name, value.to_string()))).collect(),
+            ),
+        };
+        // the answer holds the thread around the tweet as well
+        self.request_with_retries(&url, &params)
+            .await?
+            .tweets
+            .into_iter()
+            .find(|tweet| tweet.tweet_id == tweet_id)
+            .ok_or_else(|| TwitterError::ApiError { status_code: 404, message: format!("tweet {} not found", tweet_id) })
+    }
+
+    /// Official API v2 search over the last 7 days, by relevancy or newest first
+    async fn search_recent(&self, query: &str, cursor: Option<&str>, relevancy: bool) -> Result<TwitterResponse> {
+        let url = format!("{}/tweets/search/recent", self.base_url);
+        let mut params = vec![
+            ("query", query.to_string()),
+            ("max_results", "20".to_string()),
+            ("sort_order", if relevancy { "relevancy" } else { "recency" }.to_string()),
+        ];
+        params.extend(V2_FIELDS.iter().map(|(name, value)| (*name, value.to_string())));
+        if let Some(cursor) = cursor.filter(|cursor| !cursor.is_empty()) {
+            params.push(("next_token", cursor.to_string()));
+        }
+        self.request_with_retries(&url, &params).await
+    }
+
+    /// Tweets about the token `mint` for its AI summary, the first one is linked in the alert:
+    /// the latest ones of the token's own X account `account` when it has tweeted, else the
+    /// best results of `search_token`. An error only when the search failed as well
+    pub async fn token_tweets(&self, mint: &str, symbol: &str, name: &str, account: Option<&str>) -> Result<Vec<Tweet>> {
+        if let Some(account) = account.filter(|_| self.account_tweets > 0) {
+            match self.user_tweets(account, None).await {
+                Ok(response) if !response.tweets.is_empty() => {
+                    // retweets and replies say little about the token
+                    let mut tweets: Vec<Tweet> =
+                        response.tweets.into_iter().filter(|tweet| !tweet.is_retweet && !tweet.is_reply).collect();
+                    tweets.truncate(self.account_tweets);
+                    if !tweets.is_empty() {
+                        return Ok(tweets);
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => warn!("x timeline of @{} for {} error, searching instead: {}", account, mint, e),
+            }
+        }
+        self.search_token(mint, symbol, name).await
+    }
+
+    /// Search the mint, the `$SYMBOL` cashtag and the quoted name at once, the results merged,
+    /// cleaned of spam and ranked by `rank_tweets`; a failed query only leaves its results out,
+    /// the error of the first is returned when all of them failed
+    pub async fn search_token(&self, mint: &str, symbol: &str, name: &str) -> Result<Vec<Tweet>> {
+        let search = |query: Option<String>| async move {
+            let query = query?;
+            let result = self.search_tweets(&query, None, Some("Top")).await;
+            if let Err(e) = &result {
+                warn!("x search {} error: {}", query, e);
+            }
+            Some(result.map(|response| response.tweets))
+        };
+        let [by_mint, by_symbol, by_name] = token_queries(mint, symbol, name);
+        let (by_mint, by_symbol, by_name) = tokio::join!(search(by_mint), search(by_symbol), search(by_name));
+        // one list in query order, the copies of a template count over all of them
+        let mut tweets = Vec::new();
+        let mut error = None;
+        let mut answered = false;
+        for result in [by_mint, by_symbol, by_name].into_iter().flatten() {
+            match result {
+                Ok(found) => {
+                    answered = true;
+                    tweets.extend(found);
+                }
                 Err(e) => {
-                    // Save the last error
-                    last_error = Some(e);
-                    // No waiting between retries - immediate retry
+                    error.get_or_insert(e);
                 }
             }
         }
-        
-        // If all attempts failed, return the last error or max retries exceeded error
-        Err(last_error.unwrap_or_else(|| TwitterError::MaxRetriesExceeded(self.max_retries)))
+        if let (false, Some(e)) = (answered, error) {
+            return Err(e);
+        }
+        let tweets = self.spam_filter.filter(tweets, mint);
+        Ok(rank_tweets(vec![tweets], mint, symbol, self.search_tweets))
     }
-    
+
     /// Fetch tweets with built-in retry logic
     pub async fn fetch_tweets(&self, cursor: Option<&str>) -> Result<TwitterResponse> {
         // Check if API key is set
@@ -204,24 +567,43 @@ impl XClient {
             vec![]
         };
         
-        let mut last_error = None;
-        
-        // Try up to max_retries times with immediate retry
-        for _attempt in 0..self.max_retries {
-            match self.do_request_with_params(&url, &params).await {
+        self.request_with_retries(&url, &params).await
+    }
+    
+    /// Execute the request up to `max_retries` times, retryable failures wait with exponential
+    /// backoff or as long as a rate limited answer asks
+    async fn request_with_retries(&self, url: &str, params: &[(&str, String)]) -> Result<TwitterResponse> {
+        if self.max_retries == 0 {
+            return Err(TwitterError::MaxRetriesExceeded(0));
+        }
+        let mut attempt: u32 = 0;
+        loop {
+            // 额度用完就跳过, a burst of alerts goes out without tweets rather than late
+            if !self.quota.acquire(self.max_quota_wait).await {
+                warn!("x request {} skipped, request quota used up", url);
+                return Err(TwitterError::QuotaExceeded(self.max_quota_wait));
+            }
+            let error = match self.do_request_with_params(url, params).await {
                 Ok(response) => return Ok(response),
-                Err(e) => {
-                    // Save the last error
-                    last_error = Some(e);
-                    // No waiting between retries - immediate retry
-                }
+                Err(e) => e,
+            };
+            attempt += 1;
+            if !error.is_retryable() || attempt >= self.max_retries as u32 {
+                return Err(error);
+            }
+            let wait = match &error {
+                TwitterError::RateLimited { retry_after: Some(retry_after) } => *retry_after,
+                _ => Duration::from_millis(backoff_with_jitter(attempt, self.retry_backoff, self.max_retry_backoff)),
+            };
+            // 限流时间太长就不等了, the alert goes out without the tweet
+            if wait > Duration::from_millis(self.max_retry_backoff) {
+                return Err(error);
             }
+            warn!("x request {} error, try {} in {:?}: {}", url, attempt + 1, wait, error);
+            sleep(wait).await;
         }
-        
-        // If all attempts failed, return the last error or max retries exceeded error
-        Err(last_error.unwrap_or_else(|| TwitterError::MaxRetriesExceeded(self.max_retries)))
     }
-    
+
     /// Execute HTTP request with parameters and parse response
     async fn do_request_with_params(&self, url: &str, params: &[(&str, String)]) -> Result<TwitterResponse> {
         // Create request with query parameters
@@ -229,17 +611,40 @@ impl XClient {
         
         // Add API key as a header
         if let Some(api_key) = &self.api_key {
-            request = request.header("apikey", api_key);
+            request = match self.provider {
+                XProvider::Apidance => request.header("apikey", api_key),
+                XProvider::Official => request.bearer_auth(api_key),
+            };
         }
         
         // Send the request
         let response = request.send().await?;
+        let status = response.status();
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+            return Err(TwitterError::RateLimited { retry_after: retry_after(response.headers(), now) });
+        }
+        if !status.is_success() {
+            let text = response.text().await.unwrap_or_default();
+            let message = match serde_json::from_str::<ApiErrorResponse>(&text) {
+                Ok(error_response) => error_response.msg,
+                Err(_) => text.chars().take(200).collect(),
+            };
+            return Err(TwitterError::ApiError { status_code: status.as_u16(), message });
+        }
         
         // Get the raw response text to debug
         let response_text = response.text().await?;
+        if self.provider == XProvider::Official {
+            // errors come with a non-success status, a missing tweet only leaves `data` empty
+            return Ok(serde_json::from_str::<V2Response>(&response_text)?.into());
+        }
         
         // First check if this is an error response
         if let Ok(error_response) = serde_json::from_str::<ApiErrorResponse>(&response_text) {
+            if error_response.code == 429 {
+                return Err(TwitterError::RateLimited { retry_after: None });
+            }
             if error_response.code != 200 {
                 return Err(TwitterError::ApiError {
                     status_code: error_response.code as u16,
@@ -289,11 +694,291 @@ impl XClient {
     }
 }
 
-pub fn get_x_instance() -> XClient {
-    use std::env;
-    dotenv::dotenv().expect("Failed to load .env file");
-    let api_key = env::var("X_API_KEY").expect("X_API_KEY not found");
-    XClient::new("https://api.apidance.pro/sapi").with_api_key(api_key.as_str())
+/// Screen name of the X account `link` points to: `https://x.com/name`, `twitter.com/name`,
+/// `@name` or a bare name; `None` for links to tweets, communities and other pages
+pub fn x_handle(link: &str) -> Option<String> {
+    let link = link.trim();
+    let path = link
+        .split_once("://")
+        .map_or(link, |(_, rest)| rest)
+        .trim_start_matches("www.")
+        .trim_start_matches("mobile.");
+    let path = ["x.com/", "twitter.com/"].iter().find_map(|host| path.strip_prefix(host)).unwrap_or(path);
+    let path = path.split(['?', '#']).next().unwrap_or_default().trim_end_matches('/');
+    let name = path.strip_prefix('@').unwrap_or(path);
+    let valid = !name.is_empty()
+        && name.len() <= 15
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !["i", "home", "search", "intent", "share", "explore", "hashtag"].contains(&name.to_ascii_lowercase().as_str());
+    valid.then(|| name.to_string())
+}
+
+// words that take a side, matched whole and lowercase; crude but enough for a tone
+const BULLISH_WORDS: [&str; 16] = [
+    "bullish", "moon", "mooning", "pump", "pumping", "gem", "send", "sending", "ape", "aped", "buy", "buying", "lfg", "early", "🚀", "100x",
+];
+const BEARISH_WORDS: [&str; 14] = [
+    "bearish", "rug", "rugged", "rugpull", "scam", "dump", "dumping", "sell", "selling", "dead", "avoid", "honeypot", "jeet", "jeets",
+];
+// tweet text quoted to the AI, in characters
+const EXCERPT_LEN: usize = 280;
+
+// bullish words minus bearish words of `text`
+fn tone(text: &str) -> i32 {
+    text.split(|c: char| c.is_whitespace() || matches!(c, ',' | '.' | '!' | '?' | ':' | ';' | '(' | ')' | '"'))
+        .map(|word| word.trim_start_matches(['$', '#']).to_lowercase())
+        .map(|word| BULLISH_WORDS.contains(&word.as_str()) as i32 - BEARISH_WORDS.contains(&word.as_str()) as i32)
+        .sum()
+}
+
+/// Engagement and tone over the tweets found for a token
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TweetAggregate {
+    pub tweets: usize,
+    /// distinct authors
+    pub accounts: usize,
+    pub likes: i64,
+    pub retweets: i64,
+    pub replies: i64,
+    /// tweets with more bullish than bearish words, and the other way round
+    pub bullish: usize,
+    pub bearish: usize,
+}
+
+impl TweetAggregate {
+    pub fn new(tweets: &[Tweet]) -> Self {
+        let accounts: HashSet<&str> = tweets.iter().map(|tweet| tweet.user_id.as_str()).collect();
+        let tones: Vec<i32> = tweets.iter().map(|tweet| tone(&tweet.text)).collect();
+        Self {
+            tweets: tweets.len(),
+            accounts: accounts.len(),
+            likes: tweets.iter().map(|tweet| tweet.favorite_count as i64).sum(),
+            retweets: tweets.iter().map(|tweet| tweet.retweet_count as i64).sum(),
+            replies: tweets.iter().map(|tweet| tweet.reply_count as i64).sum(),
+            bullish: tones.iter().filter(|tone| **tone > 0).count(),
+            bearish: tones.iter().filter(|tone| **tone < 0).count(),
+        }
+    }
+
+    /// From -1 (every tweet bearish) to 1 (every tweet bullish), 0 without tweets
+    pub fn sentiment(&self) -> f32 {
+        if self.tweets == 0 {
+            return 0.0;
+        }
+        (self.bullish as f32 - self.bearish as f32) / self.tweets as f32
+    }
+
+    /// `bullish`, `bearish`, `mixed` (both sides) or `neutral`
+    pub fn label(&self) -> &'static str {
+        match self.sentiment() {
+            s if s >= 0.3 => "bullish",
+            s if s <= -0.3 => "bearish",
+            _ if self.bullish > 0 && self.bearish > 0 => "mixed",
+            _ => "neutral",
+        }
+    }
+
+    /// One line for the AI prompt, empty without tweets
+    pub fn summary(&self) -> String {
+        if self.tweets == 0 {
+            return String::new();
+        }
+        format!(
+            "{} tweets from {} accounts, {} likes, {} retweets, {} replies; tone {} ({} bullish, {} bearish, score {:+.2})",
+            self.tweets,
+            self.accounts,
+            self.likes,
+            self.retweets,
+            self.replies,
+            self.label(),
+            self.bullish,
+            self.bearish,
+            self.sentiment()
+        )
+    }
+}
+
+/// The tweets as excerpts for the AI prompt, one per line with author and likes
+pub fn tweet_excerpts(tweets: &[Tweet]) -> String {
+    tweets
+        .iter()
+        .map(|tweet| {
+            let mut text: String = tweet.text.split_whitespace().collect::<Vec<_>>().join(" ").chars().take(EXCERPT_LEN).collect();
+            if text.chars().count() == EXCERPT_LEN {
+                text.push('…');
+            }
+            let author = if tweet.user.screen_name.is_empty() { "unknown".to_string() } else { format!("@{}", tweet.user.screen_name) };
+            format!("- {} ({} likes): {}", author, tweet.favorite_count, text)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Links of a tweet: its expanded `urls` and the links in the text (usually `t.co`), each once
+pub fn tweet_links(tweet: &Tweet) -> Vec<String> {
+    let mut links: Vec<String> = Vec::new();
+    let in_text = tweet.text.split_whitespace().filter(|word| word.starts_with("http://") || word.starts_with("https://"));
+    for link in tweet.urls.iter().flatten().map(String::as_str).chain(in_text) {
+        let link = link.trim_end_matches(|c: char| matches!(c, '.' | ',' | ')' | '!' | '?'));
+        if !links.iter().any(|l| l == link) {
+            links.push(link.to_string());
+        }
+    }
+    links
+}
+
+// lowercase host without `www.`, a link without scheme is taken as https
+fn site(link: &str) -> Option<String> {
+    let link = link.trim();
+    let url = if link.contains("://") { Url::parse(link) } else { Url::parse(&format!("https://{}", link)) }.ok()?;
+    let host = url.host_str()?.to_lowercase();
+    Some(host.strip_prefix("www.").map(str::to_string).unwrap_or(host))
+}
+
+/// Whether `link` points to the site `website`, subdomains of it included
+pub fn same_site(link: &str, website: &str) -> bool {
+    match (site(link), site(website)) {
+        (Some(link), Some(website)) => link == website || link.ends_with(&format!(".{}", website)),
+        _ => false,
+    }
+}
+
+/// Whether `link` is a shortened link that has to be resolved to see where it goes
+pub fn is_short_link(link: &str) -> bool {
+    site(link).is_some_and(|host| ["t.co", "bit.ly", "tinyurl.com"].contains(&host.as_str()))
+}
+
+/// Search queries of a token: the mint, the `$SYMBOL` cashtag and the quoted name. The cashtag
+/// needs a symbol X takes as one (letters and digits, not only digits), the name is left out
+/// when it is empty or the same as the symbol
+fn token_queries(mint: &str, symbol: &str, name: &str) -> [Option<String>; 3] {
+    let symbol = symbol.trim().trim_start_matches('$');
+    let cashtag = (!symbol.is_empty()
+        && symbol.len() <= 15
+        && symbol.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !symbol.chars().all(|c| c.is_ascii_digit()))
+    .then(|| format!("${}", symbol));
+    let name = name.trim().replace('"', "");
+    let name = (!name.is_empty() && !name.eq_ignore_ascii_case(symbol)).then(|| format!("\"{}\"", name));
+    [Some(mint.to_string()), cashtag, name]
+}
+
+/// Merge search results without duplicates (retweets count as their original) and keep the
+/// best `limit`: tweets naming the mint first, then the cashtag, then the rest, each by
+/// engagement. Earlier lists win ties
+pub fn rank_tweets(results: Vec<Vec<Tweet>>, mint: &str, symbol: &str, limit: usize) -> Vec<Tweet> {
+    let cashtag = format!("${}", symbol.trim().trim_start_matches('$')).to_lowercase();
+    let mut seen = HashSet::new();
+    let mut tweets: Vec<Tweet> = results
+        .into_iter()
+        .flatten()
+        .filter(|tweet| {
+            let id = if tweet.is_retweet && !tweet.related_tweet_id.is_empty() { &tweet.related_tweet_id } else { &tweet.tweet_id };
+            seen.insert(id.clone())
+        })
+        .collect();
+    let relevance = |tweet: &Tweet| {
+        if tweet.text.contains(mint) {
+            0
+        } else if cashtag.len() > 1 && tweet.text.to_lowercase().contains(&cashtag) {
+            1
+        } else {
+            2
+        }
+    };
+    // stable, ties keep the order of the queries
+    tweets.sort_by_key(|tweet| (relevance(tweet), -tweet.score()));
+    tweets.truncate(limit);
+    tweets
+}
+
+/// Drops obvious spam from search results: tweets matching a configured pattern or keyword,
+/// from accounts with fewer than `min_followers`, that say nothing besides the contract address
+/// (tags, links and mentions aside) and shill templates, the same text posted by
+/// `template_accounts` or more accounts
+#[derive(Debug, Default)]
+pub struct SpamFilter {
+    patterns: Vec<Regex>,
+    // lowercase
+    keywords: Vec<String>,
+    min_followers: i32,
+    // 0 keeps copies
+    template_accounts: usize,
+}
+
+impl SpamFilter {
+    /// Patterns match case-insensitively, an invalid one is an error
+    pub fn new(patterns: &[String], keywords: &[String], min_followers: i32, template_accounts: usize) -> std::result::Result<Self, regex::Error> {
+        Ok(Self {
+            patterns: patterns.iter().map(|p| RegexBuilder::new(p).case_insensitive(true).build()).collect::<std::result::Result<_, _>>()?,
+            keywords: keywords.iter().map(|k| k.to_lowercase()).filter(|k| !k.is_empty()).collect(),
+            min_followers,
+            template_accounts,
+        })
+    }
+
+    /// Whether `tweet` alone is spam, the templates need `filter`
+    pub fn is_spam(&self, tweet: &Tweet, mint: &str) -> bool {
+        let text = tweet.text.to_lowercase();
+        let mint = mint.to_lowercase();
+        tweet.user.followers_count < self.min_followers
+            || self.keywords.iter().any(|keyword| text.contains(keyword.as_str()))
+            || self.patterns.iter().any(|pattern| pattern.is_match(&tweet.text))
+            || tweet_words(&tweet.text).iter().all(|word| *word == mint || word.starts_with('$') || word.starts_with('#'))
+    }
+
+    /// `tweets` without the spam, in their order
+    pub fn filter(&self, tweets: Vec<Tweet>, mint: &str) -> Vec<Tweet> {
+        let total = tweets.len();
+        let mut tweets: Vec<Tweet> = tweets.into_iter().filter(|tweet| !self.is_spam(tweet, mint)).collect();
+        if self.template_accounts > 0 {
+            let mut accounts: HashMap<String, HashSet<&str>> = HashMap::new();
+            for tweet in &tweets {
+                accounts.entry(tweet_words(&tweet.text).join(" ")).or_default().insert(&tweet.user_id);
+            }
+            let templates: HashSet<String> =
+                accounts.into_iter().filter(|(_, users)| users.len() >= self.template_accounts).map(|(text, _)| text).collect();
+            tweets.retain(|tweet| !templates.contains(&tweet_words(&tweet.text).join(" ")));
+        }
+        if tweets.len() < total {
+            debug!("x search for {}: {} of {} tweets dropped as spam", mint, total - tweets.len(), total);
+        }
+        tweets
+    }
+}
+
+/// Lowercase words of a tweet without links, mentions and punctuation around them
+fn tweet_words(text: &str) -> Vec<String> {
+    text.split_whitespace()
+        .filter(|word| !word.starts_with("http") && !word.starts_with('@'))
+        .map(|word| word.trim_matches(|c: char| !c.is_alphanumeric() && c != '$' && c != '#').to_lowercase())
+        .filter(|word| !word.is_empty())
+        .collect()
+}
+
+/// Create a client of `x.provider` from config
+pub fn get_x_instance(config: &XConfig) -> XClient {
+    x_client(config, config.provider)
+}
+
+/// Create a client of `provider` with the settings of `config`
+pub fn x_client(config: &XConfig, provider: XProvider) -> XClient {
+    XClient::new(config.base_url_of(provider))
+        .with_provider(provider)
+        .with_api_key(config.credential_of(provider))
+        .with_max_retries(config.max_retries)
+        .with_retry_backoff(config.retry_backoff, config.max_retry_backoff)
+        .with_quota(config.requests_per_minute, config.burst, config.max_queue_wait)
+        .with_account_tweets(config.account_tweets)
+        .with_search_tweets(config.search_tweets)
+        .with_spam_filter(
+            SpamFilter::new(&config.spam_patterns, &config.spam_keywords, config.min_followers, config.template_accounts)
+                .unwrap_or_else(|e| {
+                    // validate() rejects these, only reached without it
+                    warn!("invalid x.spam_patterns, only the built-in spam checks run: {}", e);
+                    SpamFilter::new(&[], &config.spam_keywords, config.min_followers, config.template_accounts).unwrap_or_default()
+                }),
+        )
 }
 
 #[cfg(test)]
@@ -301,6 +986,172 @@ mod tests {
     use super::*;
     use std::env;
 
+    #[test]
+    fn retry_after_test() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(retry_after(&headers, 1000), None);
+        headers.insert("x-rate-limit-reset", "1030".parse().unwrap());
+        assert_eq!(retry_after(&headers, 1000), Some(Duration::from_secs(30)));
+        assert_eq!(retry_after(&headers, 2000), Some(Duration::ZERO));
+        headers.insert("retry-after", "5".parse().unwrap());
+        assert_eq!(retry_after(&headers, 1000), Some(Duration::from_secs(5)));
+
+        assert!(TwitterError::RateLimited { retry_after: None }.is_retryable());
+        assert!(TwitterError::ApiError { status_code: 503, message: String::new() }.is_retryable());
+        assert!(!TwitterError::ApiError { status_code: 401, message: String::new() }.is_retryable());
+        assert!(!TwitterError::AuthError(String::new()).is_retryable());
+    }
+
+    #[test]
+    fn rank_tweets_test() {
+        let tweet = |id: &str, text: &str, likes: i32| Tweet {
+            tweet_id: id.to_string(),
+            text: text.to_string(),
+            favorite_count: likes,
+            ..Default::default()
+        };
+        let by_mint = vec![tweet("1", "ape Mint1 now", 1)];
+        let by_symbol = vec![tweet("2", "$COOL is pumping", 50), tweet("1", "ape Mint1 now", 1)];
+        let by_name = vec![
+            tweet("3", "cool token, no cashtag", 100),
+            Tweet { is_retweet: true, related_tweet_id: "2".to_string(), ..tweet("4", "RT $COOL", 0) },
+            tweet("5", "$cool chart", 70),
+        ];
+        let ids: Vec<String> =
+            rank_tweets(vec![by_mint, by_symbol, by_name], "Mint1", "COOL", 10).into_iter().map(|t| t.tweet_id).collect();
+        assert_eq!(ids, vec!["1", "5", "2", "3"]);
+        assert_eq!(rank_tweets(vec![vec![tweet("1", "a", 1), tweet("2", "b", 2)]], "Mint1", "", 1)[0].tweet_id, "2");
+
+        assert_eq!(
+            token_queries("Mint1", "COOL", "Cool \"Token\""),
+            [Some("Mint1".to_string()), Some("$COOL".to_string()), Some("\"Cool Token\"".to_string())]
+        );
+        assert_eq!(token_queries("Mint1", "420", "Blaze"), [Some("Mint1".to_string()), None, Some("\"Blaze\"".to_string())]);
+        assert_eq!(token_queries("Mint1", "cool", "COOL"), [Some("Mint1".to_string()), Some("$cool".to_string()), None]);
+    }
+
+    #[test]
+    fn spam_filter_test() {
+        let tweet = |user: &str, text: &str| Tweet {
+            user_id: user.to_string(),
+            text: text.to_string(),
+            user: User { followers_count: 10, ..Default::default() },
+            ..Default::default()
+        };
+        let filter = SpamFilter::new(&["dm (me )?for promo".to_string()], &["Giveaway".to_string()], 1, 3).unwrap();
+        let shill = "🚀 100x gem, ape now! https://t.co/a";
+        let tweets = vec![
+            tweet("1", "Mint1Pump"),
+            tweet("2", "$COOL Mint1Pump #solana https://t.co/x @someone"),
+            tweet("3", "DM me for PROMO, Mint1Pump"),
+            tweet("4", "huge GIVEAWAY for holders"),
+            Tweet { user: User::default(), ..tweet("5", "dev is based, chart looks clean") },
+            tweet("6", shill),
+            tweet("7", "🚀 100x GEM, ape now!! https://t.co/b"),
+            tweet("8", shill),
+            tweet("9", "$COOL community took over Mint1Pump, dev is based"),
+            tweet("10", "100x gem, ape now"),
+        ];
+        let kept: Vec<String> = filter.filter(tweets, "Mint1Pump").into_iter().map(|t| t.user_id).collect();
+        assert_eq!(kept, vec!["9"]);
+
+        // two copies are no template yet
+        let tweets = vec![tweet("1", shill), tweet("2", shill), tweet("2", shill)];
+        assert_eq!(filter.filter(tweets, "Mint1Pump").len(), 3);
+        assert!(SpamFilter::new(&["(".to_string()], &[], 0, 0).is_err());
+    }
+
+    #[test]
+    fn v2_response_test() {
+        let body = r#"{
+            "data": [
+                {"id": "2", "text": "RT @cool: $COOL Mint1", "author_id": "u2", "created_at": "2026-01-01T12:00:00.000Z",
+                 "public_metrics": {"like_count": 0, "retweet_count": 3, "reply_count": 0, "quote_count": 0},
+                 "referenced_tweets": [{"type": "retweeted", "id": "1"}]},
+                {"id": "1", "text": "$COOL Mint1", "author_id": "u1",
+                 "public_metrics": {"like_count": 12, "retweet_count": 3, "reply_count": 1, "quote_count": 0}}
+            ],
+            "includes": {"users": [{"id": "u1", "name": "Cool", "username": "cool", "public_metrics": {"followers_count": 42}}]},
+            "meta": {"result_count": 2, "next_token": "abc"}
+        }"#;
+        let response: TwitterResponse = serde_json::from_str::<V2Response>(body).unwrap().into();
+        assert_eq!(response.next_cursor_str, "abc");
+        let [retweet, tweet] = &response.tweets[..] else { panic!("two tweets expected") };
+        assert!(retweet.is_retweet && !retweet.is_reply);
+        assert_eq!(retweet.related_tweet_id, "1");
+        assert_eq!(retweet.user.screen_name, "");
+        assert_eq!((tweet.tweet_id.as_str(), tweet.favorite_count, tweet.reply_count), ("1", 12, 1));
+        assert_eq!((tweet.user.screen_name.as_str(), tweet.user.followers_count), ("cool", 42));
+        assert_eq!(tweet.engagement(), "❤️ 12 🔁 3 💬 1");
+
+        // nothing found
+        let empty: TwitterResponse = serde_json::from_str::<V2Response>(r#"{"meta": {"result_count": 0}}"#).unwrap().into();
+        assert!(empty.tweets.is_empty());
+    }
+
+    #[test]
+    fn tweet_aggregate_test() {
+        let tweet = |user: &str, text: &str, likes: i32| Tweet {
+            user_id: user.to_string(),
+            text: text.to_string(),
+            favorite_count: likes,
+            retweet_count: 1,
+            user: User { screen_name: format!("user{}", user), ..Default::default() },
+            ..Default::default()
+        };
+        let tweets = vec![
+            tweet("1", "$COOL is a gem, aped early 🚀", 10),
+            tweet("1", "Sending it. LFG!", 5),
+            tweet("2", "dev dumped, looks like a rug", 2),
+            tweet("3", "chart is flat", 0),
+        ];
+        let aggregate = TweetAggregate::new(&tweets);
+        assert_eq!(
+            aggregate,
+            TweetAggregate { tweets: 4, accounts: 3, likes: 17, retweets: 4, replies: 0, bullish: 2, bearish: 1 }
+        );
+        assert_eq!(aggregate.label(), "mixed");
+        assert_eq!(
+            aggregate.summary(),
+            "4 tweets from 3 accounts, 17 likes, 4 retweets, 0 replies; tone mixed (2 bullish, 1 bearish, score +0.25)"
+        );
+        assert_eq!(TweetAggregate::new(&tweets[..2]).label(), "bullish");
+        assert_eq!(TweetAggregate::default().summary(), "");
+
+        assert_eq!(
+            tweet_excerpts(&tweets[2..]),
+            "- @user2 (2 likes): dev dumped, looks like a rug\n- @user3 (0 likes): chart is flat"
+        );
+    }
+
+    #[test]
+    fn tweet_links_test() {
+        let tweet = Tweet {
+            text: "site is live https://t.co/abc, also https://cool.fun!".to_string(),
+            urls: Some(vec!["https://cool.fun".to_string()]),
+            ..Default::default()
+        };
+        assert_eq!(tweet_links(&tweet), vec!["https://cool.fun", "https://t.co/abc"]);
+
+        assert!(same_site("https://www.Cool.fun/about", "cool.fun"));
+        assert!(same_site("https://app.cool.fun", "https://cool.fun/"));
+        assert!(!same_site("https://notcool.fun", "cool.fun"));
+        assert!(!same_site("https://cool.fun", ""));
+        assert!(is_short_link("https://t.co/abc") && !is_short_link("https://cool.fun"));
+    }
+
+    #[test]
+    fn x_handle_test() {
+        assert_eq!(x_handle("https://x.com/CoolToken"), Some("CoolToken".to_string()));
+        assert_eq!(x_handle("https://www.twitter.com/cool_token/?s=21"), Some("cool_token".to_string()));
+        assert_eq!(x_handle("@CoolToken"), Some("CoolToken".to_string()));
+        assert_eq!(x_handle("x.com/CoolToken"), Some("CoolToken".to_string()));
+        assert_eq!(x_handle("https://x.com/CoolToken/status/123"), None);
+        assert_eq!(x_handle("https://x.com/i/communities/123"), None);
+        assert_eq!(x_handle("https://cool.fun"), None);
+        assert_eq!(x_handle(""), None);
+    }
+
     #[tokio::test]
     async fn test_x() {
         println!("running 1 test");